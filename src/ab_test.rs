@@ -0,0 +1,33 @@
+//! Compare engagement of the base prompts of a group agent and its [AbTestSetting] variant.
+//!
+//! Each reply is recorded with its variant. Engagement is derived from history at report time:
+//! messages of members within [FOLLOW_UP_SEC] after a reply, and whether the member who triggered
+//! the reply speaks again in the window.
+//!
+//! [AbTestSetting]: crate::global_state::AbTestSetting
+
+use crate::{exception::PluginResult, store, tr};
+
+/// Name of the base prompts in records.
+pub const BASE_VARIANT: &str = "base";
+pub const FOLLOW_UP_SEC: i64 = 300;
+
+/// Comparison of variants in the group.
+pub async fn report(group_id: i64) -> PluginResult<String> {
+    let stats = store::db_ab_stats(group_id, FOLLOW_UP_SEC).await?;
+    if stats.is_empty() {
+        return Ok(tr!(group_id, "ab_test.empty"));
+    }
+    let mut lines = vec![tr!(group_id, "ab_test.title", minutes = FOLLOW_UP_SEC / 60)];
+    for stat in stats {
+        lines.push(tr!(
+            group_id,
+            "ab_test.entry",
+            variant = stat.variant,
+            replies = stat.replies,
+            follow_ups = format!("{:.1}", stat.follow_ups),
+            rate = format!("{:.0}", stat.asker_rate * 100.0),
+        ));
+    }
+    Ok(lines.join("\n"))
+}
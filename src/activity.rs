@@ -0,0 +1,201 @@
+//! Weekly activity heatmap of a group, messages by hour and weekday rendered as an image.
+
+use std::sync::Arc;
+
+use ab_glyph::{FontVec, PxScale};
+use image::{Rgba, RgbaImage};
+use imageproc::{
+    drawing::{draw_filled_rect_mut, draw_text_mut},
+    rect::Rect,
+};
+use kovi::Message;
+use time::OffsetDateTime;
+
+use crate::{
+    event::GroupMsg, exception::PluginResult, global_state::GroupSetting, std_db_error, std_error,
+    store, tr, util, CONFIG, DATA_PATH,
+};
+
+const CELL: u32 = 32;
+const LEFT: u32 = 48;
+const TOP: u32 = 36;
+const MARGIN: u32 = 12;
+const EMPTY: Rgba<u8> = Rgba([235, 237, 240, 255]);
+const BUSIEST: Rgba<u8> = Rgba([33, 110, 57, 255]);
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const LABEL: Rgba<u8> = Rgba([90, 90, 90, 255]);
+/// Locale keys of rows from top, Monday first.
+const WEEKDAYS: [&str; 7] = [
+    "activity.mon",
+    "activity.tue",
+    "activity.wed",
+    "activity.thu",
+    "activity.fri",
+    "activity.sat",
+    "activity.sun",
+];
+
+/// Message counts, `[weekday][hour]` with Monday as 0.
+type Grid = [[i64; 24]; 7];
+
+fn group_of(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+/// Triggered by `<trigger>`, replies the heatmap of the last `days` days.
+pub async fn activity_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    let Some(setting) = group_of(group_id).and_then(|g| g.activity.as_ref()) else {
+        return;
+    };
+    if text.trim() != setting.trigger {
+        return;
+    }
+    let since = match util::iso8601_from_timestamp(Some(group_id), e.time - setting.days * 86400) {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Activity window error: {err}");
+            return;
+        }
+    };
+    let counts = match store::db_activity_by_hour(group_id, &since).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load activity failed: {err}");
+            return;
+        }
+    };
+    let grid = tally(&counts);
+    let total: i64 = grid.iter().flatten().sum();
+    if total == 0 {
+        e.reply(tr!(group_id, "activity.empty", days = setting.days))
+            .await;
+        return;
+    }
+
+    let labels = WEEKDAYS.map(|key| tr!(group_id, key));
+    let font_path = setting
+        .font_file
+        .as_ref()
+        .map(|f| DATA_PATH.get().unwrap().join(f))
+        .filter(|p| p.is_file());
+    let rendered = kovi::tokio::task::spawn_blocking(move || -> PluginResult<RgbaImage> {
+        let font = match font_path {
+            Some(path) => Some(FontVec::try_from_vec(std::fs::read(path)?)?),
+            None => None,
+        };
+        Ok(render(&grid, &labels, font.as_ref()))
+    })
+    .await;
+    let image = match rendered {
+        Ok(Ok(image)) => image,
+        Ok(Err(err)) => {
+            std_error!("Render activity heatmap failed: {err}");
+            return;
+        }
+        Err(err) => {
+            std_error!("Render activity heatmap task failed: {err}");
+            return;
+        }
+    };
+
+    let millis = OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000;
+    let path = DATA_PATH
+        .get()
+        .unwrap()
+        .join(format!("activity_{group_id}_{millis}.png"));
+    if let Err(err) = image.save(&path) {
+        std_error!("Save activity heatmap failed: {err}");
+        return;
+    }
+    // upload through object storage, fallback to local file
+    let path_str = path.to_string_lossy().to_string();
+    let uploaded = util::call_upload(&path_str).await;
+    let file = if uploaded.starts_with("http") {
+        uploaded
+    } else {
+        format!("file://{path_str}")
+    };
+    let (day, hour) = busiest(&grid);
+    let caption = tr!(
+        group_id,
+        "activity.caption",
+        days = setting.days,
+        total = total,
+        weekday = tr!(group_id, WEEKDAYS[day]),
+        hour = hour,
+    );
+    e.reply(Message::new().add_text(caption).add_image(&file))
+        .await;
+}
+
+/// Counts of (weekday, hour, count) with Sunday as 0 into rows with Monday first.
+fn tally(counts: &[(i64, i64, i64)]) -> Grid {
+    let mut grid = [[0; 24]; 7];
+    for &(weekday, hour, count) in counts {
+        if let (0..=6, 0..=23) = (weekday, hour) {
+            grid[(weekday as usize + 6) % 7][hour as usize] += count;
+        }
+    }
+    grid
+}
+
+/// (row, hour) of the most messages, the earliest one on ties.
+fn busiest(grid: &Grid) -> (usize, usize) {
+    let mut best = (0, 0);
+    for (day, row) in grid.iter().enumerate() {
+        for (hour, &count) in row.iter().enumerate() {
+            if count > grid[best.0][best.1] {
+                best = (day, hour);
+            }
+        }
+    }
+    best
+}
+
+/// Cells shade from [EMPTY] to [BUSIEST] by count, axis labels are drawn only with a font.
+fn render(grid: &Grid, labels: &[String; 7], font: Option<&FontVec>) -> RgbaImage {
+    let width = LEFT + 24 * CELL + MARGIN;
+    let height = TOP + 7 * CELL + MARGIN;
+    let mut canvas = RgbaImage::from_pixel(width, height, BACKGROUND);
+    let max = grid.iter().flatten().copied().max().unwrap_or_default();
+    for (day, row) in grid.iter().enumerate() {
+        for (hour, &count) in row.iter().enumerate() {
+            let color = if count == 0 || max == 0 {
+                EMPTY
+            } else {
+                // a single message is still visibly apart from none
+                shade(0.15 + 0.85 * count as f32 / max as f32)
+            };
+            let rect = Rect::at(
+                (LEFT + hour as u32 * CELL) as i32 + 1,
+                (TOP + day as u32 * CELL) as i32 + 1,
+            )
+            .of_size(CELL - 2, CELL - 2);
+            draw_filled_rect_mut(&mut canvas, rect, color);
+        }
+    }
+    let Some(font) = font else {
+        return canvas;
+    };
+    let scale = PxScale::from(18.0);
+    for (day, name) in labels.iter().enumerate() {
+        let y = (TOP + day as u32 * CELL + 7) as i32;
+        draw_text_mut(&mut canvas, LABEL, 14, y, scale, font, name);
+    }
+    for hour in (0..24).step_by(3) {
+        let x = (LEFT + hour * CELL + 4) as i32;
+        draw_text_mut(&mut canvas, LABEL, x, 10, scale, font, &hour.to_string());
+    }
+    canvas
+}
+
+fn shade(t: f32) -> Rgba<u8> {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    let (Rgba([r0, g0, b0, _]), Rgba([r1, g1, b1, _])) = (EMPTY, BUSIEST);
+    Rgba([mix(r0, r1), mix(g0, g1), mix(b0, b1), 255])
+}
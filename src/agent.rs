@@ -1,23 +1,41 @@
 //! OpenAI module.
 
 use crate::{
+    analytics, calendar, endpoint,
+    event::GroupMsg,
     exception::{PluginError, PluginResult},
-    global_state, std_db_error, std_db_info, std_info,
+    global_state::{ApiUrl, GroupSetting},
+    heat,
+    http::Request,
+    image_safety, language, live, long_message, outbox, spend, std_db_error, std_db_info,
+    std_error, std_info, sticker,
     store::{self, GroupChatSegment},
+    takeover, tr,
     util::{self, TimeRepr},
-    AgentSetting, BOT_QQ, CONFIG,
+    AgentSetting, UnknownGroupPolicy, BOT_QQ, CONFIG,
 };
-use kovi::{Message, MsgEvent};
-use reqwest::header::{AUTHORIZATION, CONTENT_TYPE};
-use serde::Deserialize;
-use serde_json::json;
-use std::sync::Arc;
-
-pub async fn logger(e: Arc<MsgEvent>) {
-    let Some(group_id) = e.group_id else {
-        return;
-    };
-    let sender_id = e.sender.user_id;
+use kovi::Message;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+const LIVE_STATUS: &str = "<!live_status!>";
+const SENDER_ROLE: &str = "<!sender_role!>";
+/// Rounds of function calls answered before giving up on a query.
+const MAX_TOOL_ROUNDS: usize = 3;
+/// Segments read when the history window is measured in tokens.
+const MAX_WINDOW_SEGMENTS: i64 = 500;
+/// Muted replies in a group are at least this far apart.
+const MUTED_REPLY_INTERVAL: Duration = Duration::from_secs(60);
+
+pub async fn logger(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let sender_id = e.sender_id;
     let time = TimeRepr::UnixTimeStamp(e.time);
     store::write_group_msg(
         group_id,
@@ -29,15 +47,32 @@ pub async fn logger(e: Arc<MsgEvent>) {
     .await;
 }
 
-pub async fn at_me_handler(e: Arc<MsgEvent>) {
-    let bot = global_state::get_bot();
-    // no-op if not group message
-    let Some(group_id) = e.group_id else {
-        return;
-    };
+/// Apply [UnknownGroupPolicy] to messages of groups absent from config.
+pub async fn unknown_group_handler(e: Arc<GroupMsg>) {
+    let config = CONFIG.get().unwrap();
+    match config.global.unknown_group.unwrap_or_default() {
+        UnknownGroupPolicy::Ignore => {}
+        UnknownGroupPolicy::Log => logger(e).await,
+        UnknownGroupPolicy::Reply => {
+            logger(Arc::clone(&e)).await;
+            if is_at_me(&e) {
+                util::sleep_rand_time().await;
+                util::send_group_msg(e.group_id, tr!(e.group_id, "agent.unknown_group"));
+            }
+        }
+    }
+}
+
+fn is_at_me(e: &GroupMsg) -> bool {
+    let bot_qq_str = BOT_QQ.get().unwrap().to_string();
+    e.message.get("at").iter().any(|x| {
+        serde_json::from_value::<String>(x.data["qq"].clone()).is_ok_and(|t| t == bot_qq_str)
+    })
+}
+
+pub async fn at_me_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
     let at_segs = e.message.get("at");
-    let bot_qq = BOT_QQ.get().unwrap();
-    let bot_qq_str = bot_qq.to_string();
 
     // this will never fail, good to log if api changes
     let missing_field = at_segs
@@ -55,14 +90,7 @@ pub async fn at_me_handler(e: Arc<MsgEvent>) {
     }
 
     // no-op if not at me
-    let at_me =
-        at_segs.iter().find(
-            |&x| match serde_json::from_value::<String>(x.data["qq"].clone()) {
-                Ok(target) => bot_qq_str == target,
-                Err(_) => false,
-            },
-        );
-    if at_me.is_none() {
+    if !is_at_me(&e) {
         return;
     }
 
@@ -72,7 +100,6 @@ pub async fn at_me_handler(e: Arc<MsgEvent>) {
         return;
     };
     let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
-        bot.send_group_msg(group_id, "该群聊未配置");
         return;
     };
 
@@ -80,21 +107,199 @@ pub async fn at_me_handler(e: Arc<MsgEvent>) {
     let Some(ref agent) = group.agent else {
         return;
     };
-    // no-op if mute
     if agent.is_mute() {
+        muted_fallback(&e, agent).await;
+        return;
+    }
+    // no-op if heated, or the bot cannot speak
+    if heat::is_heated(group_id) || util::is_bot_banned(group_id) {
+        return;
+    }
+    if !e.claim_reply() {
+        std_info!("Message {} in {group_id} already replied.", e.message_id);
         return;
     }
 
+    analytics::count(group_id, "agent").await;
     let time = TimeRepr::UnixTimeStamp(e.time);
-    let sender_id = e.sender.user_id;
+    let sender_id = e.sender_id;
     let content = util::extract_text(&e.message).await;
-    if let Some(answer) = agent
-        .group_query(group_id, Some(time), sender_id, &content)
+    let message = match long_message::digest(group_id, sender_id, &content).await {
+        Some(summary) => long_message::render(&summary),
+        None => content.clone(),
+    };
+    let Some(answer) = agent
+        .group_answer(
+            group_id,
+            Some(time),
+            sender_id,
+            &message,
+            util::reply_id(&e.message),
+        )
         .await
+    else {
+        return;
+    };
+    let prompt = content.trim();
+    if let Some(ref takeover) = group.takeover {
+        if takeover::needs_review(takeover, &answer.text) {
+            takeover::submit(group, e.message_id, sender_id, prompt, &answer).await;
+            return;
+        }
+    }
+    post_answer(group, e.message_id, sender_id, prompt, &answer).await;
+}
+
+/// Tell the member that the bot is muted rather than broken, by reaction or a rate limited reply.
+async fn muted_fallback(e: &GroupMsg, agent: &AgentSetting) {
+    static REPLIED: OnceLock<Mutex<HashMap<i64, Instant>>> = OnceLock::new();
+    if let Some(ref emoji_id) = agent.muted_reaction {
+        util::set_msg_emoji_like(e.message_id, emoji_id);
+    }
+    let Some(ref reply) = agent.muted_reply else {
+        return;
+    };
+    if util::is_bot_banned(e.group_id) {
+        return;
+    }
+    {
+        let now = Instant::now();
+        let mut replied = REPLIED
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(last) = replied.get(&e.group_id) {
+            if now.duration_since(*last) < MUTED_REPLY_INTERVAL {
+                return;
+            }
+        }
+        if !e.claim_reply() {
+            return;
+        }
+        replied.insert(e.group_id, now);
+    }
+    e.reply_and_quote(reply.as_str()).await;
+}
+
+/// Send an answer quoting the message it answers, and record it for feedback of members.
+pub async fn post_answer(
+    group: &GroupSetting,
+    message_id: i32,
+    asker_id: i64,
+    prompt: &str,
+    answer: &GroupAnswer,
+) {
+    let group_id = group.id;
+    if let Some(ref outbox) = group.outbox {
+        if !outbox::hold(group_id, outbox.delay_sec).await {
+            std_info!("Answer to {message_id} in {group_id} cancelled in outbox.");
+            return;
+        }
+    }
+    let message = answer_message(group, &answer.text, Some(message_id)).await;
+    let Some(reply_id) = util::send_group_msg_return(group_id, message).await else {
+        return;
+    };
+    // see [crate::feedback]
+    let now = util::cur_time_iso8601(Some(group_id));
+    if let Err(err) = store::db_add_agent_reply(
+        group_id,
+        reply_id,
+        &now,
+        asker_id,
+        &answer.persona,
+        &answer.model,
+        prompt,
+        &answer.text,
+    )
+    .await
     {
-        let message = Message::from(answer);
-        e.reply_and_quote(message);
+        std_db_error!("Record agent reply failed: {err}");
     }
+    crate::run_hooks(|h| h.on_agent_reply(group_id, reply_id, prompt, answer));
+}
+
+/// Answer of the group agent.
+pub struct GroupAnswer {
+    pub text: String,
+    /// Persona window or A/B test variant of the prompts.
+    pub persona: String,
+    pub model: String,
+}
+
+/// Build the message of an answer with inline tags, `[at:QQ or member name]`, `[image:URL]`,
+/// `[sticker:tag]` and `[reply:message id]`. Answers quote `quote` unless they quote another
+/// message.
+///
+/// Unknown members and malformed quotes are kept as text, while unsafe images and unknown stickers
+/// are dropped.
+pub async fn answer_message(group: &GroupSetting, text: &str, quote: Option<i32>) -> Message {
+    static TAG_REGEX: OnceLock<Regex> = OnceLock::new();
+    let regex = TAG_REGEX
+        .get_or_init(|| Regex::new(r"\[(?<kind>at|image|sticker|reply):(?<arg>[^\]]+)\]").unwrap());
+    let mut quote = quote;
+    let mut body = Message::new();
+    // text between segments, including tags kept as text
+    let mut pending = String::new();
+    let mut last = 0;
+    for caps in regex.captures_iter(text) {
+        let tag = caps.get(0).unwrap();
+        let arg = caps["arg"].trim();
+        pending.push_str(&text[last..tag.start()]);
+        last = tag.end();
+        match &caps["kind"] {
+            "at" => match member_qq(group, arg) {
+                Some(qq) => body = flush_text(body, &mut pending).add_at(&qq),
+                None => pending.push_str(&format!("@{arg}")),
+            },
+            "image" => {
+                if let Some(img) = image_safety::screen(arg).await {
+                    body = flush_text(body, &mut pending).add_image(&img);
+                }
+            }
+            "sticker" => {
+                if let Some(img) = sticker::find(group.id, arg).await {
+                    body = flush_text(body, &mut pending).add_image(&img);
+                }
+            }
+            _ => match arg.parse() {
+                Ok(id) => quote = Some(id),
+                Err(_) => pending.push_str(tag.as_str()),
+            },
+        }
+    }
+    pending.push_str(&text[last..]);
+    body = flush_text(body, &mut pending);
+
+    let mut message = match quote {
+        Some(id) => Message::new().add_reply(id),
+        None => Message::new(),
+    };
+    for seg in body.iter() {
+        message.push(seg.clone());
+    }
+    message
+}
+
+fn flush_text(message: Message, pending: &mut String) -> Message {
+    let text = std::mem::take(pending);
+    if text.trim().is_empty() {
+        return message;
+    }
+    message.add_text(text)
+}
+
+/// QQ number of a member by number or by name in `known_members`.
+fn member_qq(group: &GroupSetting, name: &str) -> Option<String> {
+    if name.parse::<i64>().is_ok() {
+        return Some(name.to_string());
+    }
+    let agent = group.agent.as_ref()?;
+    agent
+        .known_members
+        .iter()
+        .find(|(_, (member, _))| member == name)
+        .map(|(qq, _)| qq.clone())
 }
 
 // Mimic an "at me" as if someone asks agent a question, then send answer to group.
@@ -102,7 +307,7 @@ pub async fn query_with_id_msg(
     group_id: i64,
     sender_id: i64,
     message: String,
-) -> PluginResult<String> {
+) -> PluginResult<Message> {
     let invoke_no_agent = Err(PluginError::AgentRequest(
         "Call query_with_id_msg without agent config".to_string(),
     ));
@@ -119,18 +324,19 @@ pub async fn query_with_id_msg(
         return invoke_no_agent;
     };
 
-    // no-op if mute
+    // no-op if mute, or the bot cannot speak
     let agent_mute = Err(PluginError::AgentRequest("Mute".to_string()));
-    if agent.is_mute() {
+    if agent.is_mute() || heat::is_heated(group_id) || util::is_bot_banned(group_id) {
         return agent_mute;
     }
 
     let query_fail =
         PluginError::AgentRequest("Agent query failed, check log for details.".to_string());
-    agent
-        .group_query(group_id, None, sender_id, &message)
+    let answer = agent
+        .group_query(group_id, None, sender_id, &message, None)
         .await
-        .ok_or(query_fail)
+        .ok_or(query_fail)?;
+    Ok(answer_message(group, &answer, None).await)
 }
 
 impl AgentSetting {
@@ -140,11 +346,25 @@ impl AgentSetting {
         time: Option<TimeRepr>,
         sender_id: i64,
         content: &str,
+        reply_to: Option<i32>,
     ) -> Option<String> {
+        self.group_answer(group_id, time, sender_id, content, reply_to)
+            .await
+            .map(|answer| answer.text)
+    }
+
+    pub async fn group_answer(
+        &self,
+        group_id: i64,
+        time: Option<TimeRepr>,
+        sender_id: i64,
+        content: &str,
+        reply_to: Option<i32>,
+    ) -> Option<GroupAnswer> {
         // obtain iso8601
         let time = match time.unwrap_or_default() {
             TimeRepr::Iso8601(t) => t,
-            TimeRepr::UnixTimeStamp(t) => match util::iso8601_from_timestamp(t) {
+            TimeRepr::UnixTimeStamp(t) => match util::iso8601_from_timestamp(Some(group_id), t) {
                 Ok(t) => t,
                 Err(err) => {
                     std_db_error!("{err}");
@@ -160,16 +380,93 @@ impl AgentSetting {
         };
 
         // load history
-        let n = self.aware_history_segments;
-        let history = match store::db_load_n_group_segment(group_id, n).await {
+        let n = match self.aware_history_tokens {
+            Some(_) => MAX_WINDOW_SEGMENTS,
+            None => self.aware_history_segments,
+        };
+        let mut history = match store::db_load_n_group_segment(group_id, n).await {
             Ok(v) => v,
             Err(err) => {
                 std_db_error!("Load chat history failed: {err}");
                 return None;
             }
         };
-        let message = format!("{time} {sender_name}: {content}");
-        let (dev_prompt, user_prompt) = self.substitute_dev_user(&history, &message, know);
+        if let Some(budget) = self.aware_history_tokens {
+            history = fit_history(history, budget);
+        }
+        // reactions to messages in history, e.g. likes to replies of the bot
+        let since = history.first().map_or("", |seg| seg.time.as_str());
+        let reactions = match store::db_reaction_counts(group_id, since).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_db_error!("Load reactions failed: {err}");
+                HashMap::new()
+            }
+        };
+        let mut message = format!("{time} {sender_name}: {content}");
+        // the thread being replied to, which may have scrolled out of history
+        if let Some(reply_to) = reply_to {
+            match store::db_load_thread(group_id, reply_to).await {
+                Ok(thread) if !thread.is_empty() => {
+                    message = format!("回复的对话:\n{}{message}", format_history(&thread));
+                }
+                Ok(_) => {}
+                Err(err) => std_db_error!("Load reply thread failed: {err}"),
+            }
+        }
+        let (variant, dev_template, user_template) = self.pick_prompts();
+        // queried only if asked for
+        let asked = |var: &str| dev_template.contains(var) || user_template.contains(var);
+        let mut runtime = Vec::new();
+        if asked(LIVE_STATUS) {
+            runtime.push((LIVE_STATUS, live::live_status(group_id).await));
+        }
+        if asked(calendar::DATE) {
+            runtime.push((calendar::DATE, calendar::today(group_id)));
+        }
+        if asked(calendar::FESTIVAL) {
+            runtime.push((calendar::FESTIVAL, calendar::festival(group_id)));
+        }
+        if asked(sticker::STICKERS) {
+            runtime.push((sticker::STICKERS, sticker::tags(group_id).await));
+        }
+        if asked(calendar::WEATHER) {
+            let weather = match self.weather {
+                Some(ref weather) => calendar::weather(weather).await,
+                None => String::new(),
+            };
+            runtime.push((calendar::WEATHER, weather));
+        }
+        let role = if self.role_prompts.is_some() || asked(SENDER_ROLE) {
+            util::get_cached_role(group_id, sender_id).await
+        } else {
+            None
+        };
+        if asked(SENDER_ROLE) {
+            runtime.push((SENDER_ROLE, role_name(role.as_deref()).to_string()));
+        }
+        let role_prompt = role
+            .as_ref()
+            .and_then(|role| self.role_prompts.as_ref()?.get(role));
+        let substitute = |history: &[GroupChatSegment]| {
+            let (mut dev, user) = substitute_dev_user(
+                (dev_template, user_template),
+                history,
+                &reactions,
+                &message,
+                know,
+                &runtime,
+            );
+            if let Some(role_prompt) = role_prompt {
+                dev.push('\n');
+                dev.push_str(role_prompt);
+            }
+            (dev, user)
+        };
+        let (dev_prompt, user_prompt) = match self.max_prompt_tokens {
+            Some(max) => fit_prompt(&history, max, substitute)?,
+            None => substitute(&history),
+        };
         std_info!(
             "
             Developer prompt: {dev_prompt}
@@ -177,105 +474,426 @@ impl AgentSetting {
             "
         );
 
-        match self.api_request(&dev_prompt, &user_prompt).await {
-            Ok(resp) => {
-                let model = resp.model;
-                let tokens = resp.usage.total_tokens;
-                std_db_info!("{model} consumed {tokens} tokens");
-                let Some(answer) = resp.choices.first() else {
-                    std_db_error!("OpenAI API response has no choice");
-                    return None;
-                };
-                let sol = &answer.message.content;
-                Some(sol.to_string())
+        let tools = if live::followed_rooms(group_id).is_empty() {
+            vec![]
+        } else {
+            vec![Tool::LiveStatus]
+        };
+        let model = self.get_model().await;
+        let answer = complete_with_tools(
+            &self.api_url,
+            &self.api_key,
+            &model,
+            &dev_prompt,
+            &user_prompt,
+            group_id,
+            &tools,
+        )
+        .await?;
+        let answer = language::enforce(self, &model, (&dev_prompt, &user_prompt), answer).await;
+        if self.ab_test.is_some() {
+            let now = util::cur_time_iso8601(Some(group_id));
+            if let Err(err) = store::db_add_ab_reply(group_id, variant, sender_id, &now).await {
+                std_db_error!("Record A/B test reply failed: {err}");
             }
-            Err(e) => {
-                std_db_error!("OpenAI request failed: {e}");
-                None
+        }
+        Some(GroupAnswer {
+            text: answer,
+            persona: variant.to_string(),
+            model,
+        })
+    }
+}
+
+/// How `<!sender_role!>` reads, empty if the role is unknown.
+fn role_name(role: Option<&str>) -> &'static str {
+    match role {
+        Some("owner") => "群主",
+        Some("admin") => "管理员",
+        Some(_) => "群员",
+        None => "",
+    }
+}
+
+/// Replace placeholders of (dev, user) templates for know, message, history, and `runtime`
+/// (placeholder, value) pairs such as live status by their runtime value.
+fn substitute_dev_user(
+    (dev_template, user_template): (&str, &str),
+    history: &[GroupChatSegment],
+    reactions: &HashMap<i32, i64>,
+    message: &str,
+    know: bool,
+    runtime: &[(&str, String)],
+) -> (String, String) {
+    let know = if know { "know" } else { "don't know" };
+    let dev_know = dev_template.replace("<!know!>", know);
+    let user_know = user_template.replace("<!know!>", know);
+
+    let dev_msg = dev_know.replace("<!message!>", message);
+    let user_msg = user_know.replace("<!message!>", message);
+
+    let buf = format_history_with_reactions(history, reactions);
+    let mut dev_all = dev_msg.replace("<!history!>", &buf);
+    let mut user_all = user_msg.replace("<!history!>", &buf);
+    for (placeholder, value) in runtime {
+        dev_all = dev_all.replace(placeholder, value);
+        user_all = user_all.replace(placeholder, value);
+    }
+
+    (dev_all, user_all)
+}
+
+/// One line per segment that agent understands, e.g. "time sender: content".
+pub fn format_history(history: &[GroupChatSegment]) -> String {
+    format_history_with_reactions(history, &HashMap::new())
+}
+
+/// [format_history] followed by the number of reactions, message_id -> count, after the first
+/// line of each message that has any.
+pub fn format_history_with_reactions(
+    history: &[GroupChatSegment],
+    reactions: &HashMap<i32, i64>,
+) -> String {
+    let mut buf = String::new();
+    let mut annotated = HashSet::new();
+    for seg in history {
+        let len = buf.len();
+        match seg.seg_type.as_str() {
+            // interpret of a long text is its summary, see [crate::long_message]
+            "text" if seg.interpret != "text" && !seg.interpret.is_empty() => {
+                let time_sender_summary = format!(
+                    "{} {}: {}\n",
+                    seg.time,
+                    seg.sender_name,
+                    long_message::render(&seg.interpret)
+                );
+                buf.push_str(&time_sender_summary);
+            }
+            "text" => {
+                let time_sender_content =
+                    format!("{} {}: {}\n", seg.time, seg.sender_name, seg.content);
+                buf.push_str(&time_sender_content);
+            }
+            "at" => {
+                let time_sender_receiver =
+                    format!("{} {} AT {}\n", seg.time, seg.sender_name, seg.interpret);
+                buf.push_str(&time_sender_receiver);
+            }
+            "ocr" => {
+                let time_sender_ocr = format!(
+                    "{} {}: [图片文字]{}\n",
+                    seg.time, seg.sender_name, seg.interpret
+                );
+                buf.push_str(&time_sender_ocr);
+            }
+            "caption" => {
+                let time_sender_caption = format!(
+                    "{} {}: [图片]{}\n",
+                    seg.time, seg.sender_name, seg.interpret
+                );
+                buf.push_str(&time_sender_caption);
+            }
+            "link" => {
+                let time_sender_link = format!(
+                    "{} {}: [链接]{} {}\n",
+                    seg.time, seg.sender_name, seg.content, seg.interpret
+                );
+                buf.push_str(&time_sender_link);
             }
+            _ => (),
+        }
+        let count = reactions.get(&seg.message_id).copied().unwrap_or(0);
+        if buf.len() > len && count > 0 && annotated.insert(seg.message_id) {
+            buf.pop();
+            buf.push_str(&format!(" [收到{count}个回应]\n"));
         }
     }
+    buf
+}
 
-    async fn api_request(&self, dev_prompt: &str, user_prompt: &str) -> PluginResult<GptResponse> {
-        let model = self.get_model().await;
+/// Latest segments of `history` whose formatted lines take at most `budget` tokens.
+pub fn fit_history(mut history: Vec<GroupChatSegment>, budget: usize) -> Vec<GroupChatSegment> {
+    let start = fitting_start(&history, budget);
+    history.drain(..start);
+    history
+}
+
+/// Index of the oldest segment kept by [fit_history].
+fn fitting_start(history: &[GroupChatSegment], budget: usize) -> usize {
+    let mut used = 0;
+    let mut start = history.len();
+    for (idx, seg) in history.iter().enumerate().rev() {
+        used += estimate_tokens(&format_history(std::slice::from_ref(seg)));
+        if used > budget {
+            break;
+        }
+        start = idx;
+    }
+    start
+}
+
+/// (dev, user) prompts substituted by `substitute` from `history`, the oldest segments of which are
+/// dropped until the prompts take at most `max` tokens. None if they cannot fit even without
+/// history.
+pub fn fit_prompt<F>(
+    history: &[GroupChatSegment],
+    max: usize,
+    substitute: F,
+) -> Option<(String, String)>
+where
+    F: Fn(&[GroupChatSegment]) -> (String, String),
+{
+    let size = |(dev, user): &(String, String)| estimate_tokens(dev) + estimate_tokens(user);
+    let prompts = substitute(history);
+    let total = size(&prompts);
+    if total <= max {
+        return Some(prompts);
+    }
+    let bare = substitute(&[]);
+    let overhead = size(&bare);
+    if overhead > max {
+        std_error!("Prompt takes {overhead} tokens without history, over the limit of {max}");
+        return None;
+    }
+    // history may be substituted into both prompts, shrink the budget until it fits
+    let before = history.len();
+    let mut budget = max - overhead;
+    loop {
+        let fitted = &history[fitting_start(history, budget)..];
+        let prompts = substitute(fitted);
+        let size = size(&prompts);
+        if size <= max || fitted.is_empty() {
+            std_info!(
+                "Prompt of {total} tokens over the limit of {max}, history trimmed from {before} to {} segments",
+                fitted.len()
+            );
+            return Some(prompts);
+        }
+        budget = (budget * max / size).min(budget - 1);
+    }
+}
+
+/// Rough token count without a tokenizer: a CJK character or symbol takes about one token, and
+/// about four ASCII characters make one token.
+pub fn estimate_tokens(text: &str) -> usize {
+    let ascii = text.chars().filter(char::is_ascii).count();
+    let other = text.chars().count() - ascii;
+    other + ascii.div_ceil(4)
+}
+
+/// Functions offered to the model in group chat, answered from runtime data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    LiveStatus,
+}
+
+impl Tool {
+    const ALL: [Tool; 1] = [Tool::LiveStatus];
 
-        let payload = match model.as_ref() {
-            "o1" | "o1-mini" | "o1-preview" => {
-                json!({
-                    "model": model,
-                    "messages": [
-                        {
-                            "role": "user",
-                            "content": format!("{dev_prompt}\n{user_prompt}")
-                        }
-                    ]
-                })
+    fn name(self) -> &'static str {
+        match self {
+            Tool::LiveStatus => "live_status",
+        }
+    }
+
+    fn definition(self) -> Value {
+        let description = match self {
+            Tool::LiveStatus => {
+                "Current status of the bilibili live rooms followed by this group, \
+                 call it before answering whether someone is streaming."
             }
-            _ => {
-                json!({
-                    "model": model,
-                    "messages": [
-                        {
-                            "role": "developer",
-                            "content": dev_prompt
-                        },
-                        {
-                            "role": "user",
-                            "content": user_prompt
-                        }
-                    ]
-                })
+        };
+        json!({
+            "type": "function",
+            "function": {
+                "name": self.name(),
+                "description": description,
+                "parameters": { "type": "object", "properties": {} }
+            }
+        })
+    }
+
+    async fn call(self, group_id: i64) -> String {
+        match self {
+            Tool::LiveStatus => live::live_status(group_id).await,
+        }
+    }
+}
+
+/// Request chat completion and extract the first answer, log and return None on failure.
+pub async fn complete(
+    api_url: &ApiUrl,
+    api_key: &str,
+    model: &str,
+    dev_prompt: &str,
+    user_prompt: &str,
+) -> Option<String> {
+    complete_with_tools(api_url, api_key, model, dev_prompt, user_prompt, 0, &[]).await
+}
+
+/// [complete] that answers function calls of `tools` in the context of `group_id`.
+async fn complete_with_tools(
+    api_url: &ApiUrl,
+    api_key: &str,
+    model: &str,
+    dev_prompt: &str,
+    user_prompt: &str,
+    group_id: i64,
+    tools: &[Tool],
+) -> Option<String> {
+    let model = &spend::model_for(model).await?;
+    let mut messages = initial_messages(model, dev_prompt, user_prompt);
+    // o1 models do not support function calling
+    let tools = if is_o1(model) { &[] } else { tools };
+    for _ in 0..=MAX_TOOL_ROUNDS {
+        let resp = match api_request(api_url, api_key, model, &messages, tools).await {
+            Ok(v) => v,
+            Err(e) => {
+                std_db_error!("OpenAI request failed: {e}");
+                return None;
             }
         };
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&self.api_url)
-            .header(CONTENT_TYPE, "application/json")
-            .header(AUTHORIZATION, format!("Bearer {}", self.api_key))
-            .json(&payload)
-            .send()
-            .await?;
-        Ok(response.json().await?)
-    }
-
-    /// Replace placeholders for know, message, and history by their runtime value.
-    fn substitute_dev_user(
-        &self,
-        history: &Vec<GroupChatSegment>,
-        message: &str,
-        know: bool,
-    ) -> (String, String) {
-        let know = if know { "know" } else { "don't know" };
-        let dev_know = self.dev_prompt.replace("<!know!>", know);
-        let user_know = self.user_prompt.replace("<!know!>", know);
-
-        let dev_msg = dev_know.replace("<!message!>", message);
-        let user_msg = user_know.replace("<!message!>", message);
-
-        let mut buf = String::new();
-        for seg in history {
-            match seg.seg_type.as_str() {
-                "text" => {
-                    let time_sender_content =
-                        format!("{} {}: {}\n", seg.time, seg.sender_name, seg.content);
-                    buf.push_str(&time_sender_content);
-                }
-                "at" => {
-                    let time_sender_receiver =
-                        format!("{} {} AT {}\n", seg.time, seg.sender_name, seg.interpret);
-                    buf.push_str(&time_sender_receiver);
-                }
-                _ => (),
+        let tokens = resp.usage.total_tokens;
+        std_db_info!("{} consumed {tokens} tokens", resp.model);
+        // priced by the requested name, the response names a dated snapshot
+        spend::record(model, tokens).await;
+        let Some(answer) = resp.choices.into_iter().next() else {
+            std_db_error!("OpenAI API response has no choice");
+            return None;
+        };
+        let Answer {
+            content,
+            tool_calls,
+        } = answer.message;
+        if tool_calls.is_empty() {
+            return Some(sanitize(&content.unwrap_or_default()));
+        }
+        messages.push(json!({ "role": "assistant", "content": content, "tool_calls": tool_calls }));
+        for call in tool_calls {
+            let name = &call.function.name;
+            let result = match Tool::ALL.into_iter().find(|t| t.name() == name) {
+                Some(tool) => tool.call(group_id).await,
+                None => format!("Unknown function: {name}"),
+            };
+            std_info!("Agent called {name}: {result}");
+            messages.push(json!({ "role": "tool", "tool_call_id": call.id, "content": result }));
+        }
+    }
+    std_db_error!("OpenAI function calls exceeded {MAX_TOOL_ROUNDS} rounds");
+    None
+}
+
+/// Model output without CQ codes and mentions of all members, so that an injected prompt cannot
+/// make the bot send arbitrary segments or mention everyone. Removal repeats in case it joins the
+/// remaining text into a new CQ code.
+pub fn sanitize(text: &str) -> String {
+    static CQ_REGEX: OnceLock<Regex> = OnceLock::new();
+    static AT_ALL_REGEX: OnceLock<Regex> = OnceLock::new();
+    let cq = CQ_REGEX.get_or_init(|| Regex::new(r"(?i)\[\s*CQ\s*:[^\]]*\]").unwrap());
+    // as text or as inline tag, the ascii ones only as whole words
+    let at_all = AT_ALL_REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?i)[@＠]\s*(全体成员|全体|(?:all|everyone)(?:[^a-z0-9_]|$))|\[at:\s*(全体成员|全体|all|everyone)\s*\]",
+        )
+        .unwrap()
+    });
+    let mut text = text.to_string();
+    while cq.is_match(&text) {
+        text = cq.replace_all(&text, "").into_owned();
+    }
+    at_all
+        .replace_all(&text, |caps: &regex::Captures| {
+            caps.get(1).or(caps.get(2)).unwrap().as_str().to_string()
+        })
+        .into_owned()
+}
+
+fn is_o1(model: &str) -> bool {
+    matches!(model, "o1" | "o1-mini" | "o1-preview")
+}
+
+fn initial_messages(model: &str, dev_prompt: &str, user_prompt: &str) -> Vec<Value> {
+    if is_o1(model) {
+        vec![json!({
+            "role": "user",
+            "content": format!("{dev_prompt}\n{user_prompt}")
+        })]
+    } else {
+        vec![
+            json!({
+                "role": "developer",
+                "content": dev_prompt
+            }),
+            json!({
+                "role": "user",
+                "content": user_prompt
+            }),
+        ]
+    }
+}
+
+/// Send a minimal request to verify endpoint, key and model.
+pub async fn check_api(url: &str, api_key: &str, model: &str) -> PluginResult<()> {
+    let messages = initial_messages(model, "Reply with ok.", "ping");
+    let resp = post(url, api_key, &payload(model, &messages, &[])).await?;
+    if resp.choices.is_empty() {
+        return Err(PluginError::AgentRequest(String::from(
+            "response has no choice",
+        )));
+    }
+    Ok(())
+}
+
+/// Request endpoints in the order of [endpoint::order] until one responds.
+async fn api_request(
+    api_url: &ApiUrl,
+    api_key: &str,
+    model: &str,
+    messages: &[Value],
+    tools: &[Tool],
+) -> PluginResult<GptResponse> {
+    let payload = payload(model, messages, tools);
+    let mut last_err = None;
+    for url in endpoint::order(api_url) {
+        match post(url, api_key, &payload).await {
+            Ok(resp) => {
+                endpoint::report(url, true);
+                return Ok(resp);
+            }
+            Err(err) => {
+                std_error!("OpenAI request to {url} failed: {err}");
+                endpoint::report(url, false);
+                last_err = Some(err);
             }
         }
-        let dev_all = dev_msg.replace("<!history!>", &buf);
-        let user_all = user_msg.replace("<!history!>", &buf);
+    }
+    Err(last_err.unwrap_or_else(|| PluginError::AgentRequest(String::from("no api url"))))
+}
 
-        (dev_all, user_all)
+fn payload(model: &str, messages: &[Value], tools: &[Tool]) -> Value {
+    let mut payload = json!({
+        "model": model,
+        "messages": messages
+    });
+    if !tools.is_empty() {
+        let tools: Vec<Value> = tools.iter().map(|t| t.definition()).collect();
+        payload["tools"] = json!(tools);
     }
+    payload
 }
 
-#[derive(Deserialize, Debug, Default)]
+async fn post(url: &str, api_key: &str, payload: &Value) -> PluginResult<GptResponse> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::inject(crate::chaos::Point::Agent).await?;
+    Request::post(url)
+        .bearer_auth(api_key)
+        .json(payload)
+        .send()
+        .await?
+        .json()
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct GptResponse {
     pub id: String,
     pub model: String,
@@ -283,18 +901,112 @@ pub struct GptResponse {
     pub usage: Usage,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Choice {
     pub message: Answer,
     pub finish_reason: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Answer {
-    pub content: String,
+    /// null if the model calls functions instead
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolCall {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub call_type: String,
+    pub function: FunctionCall,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FunctionCall {
+    pub name: String,
+    pub arguments: String,
 }
 
-#[derive(Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Usage {
     pub total_tokens: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_token_window() {
+        assert_eq!(estimate_tokens("你好"), 2);
+        assert_eq!(estimate_tokens("hello world"), 3);
+        let seg = |message_id: i32, content: &str| GroupChatSegment {
+            message_id,
+            time: String::from("2025-01-01 00:00:00"),
+            sender_id: 42,
+            sender_name: String::from("张三"),
+            seg_type: String::from("text"),
+            content: content.to_string(),
+            interpret: String::from("text"),
+        };
+        let long = "长".repeat(100);
+        let history = || vec![seg(1, "早"), seg(2, &long), seg(3, "晚安"), seg(4, "晚安")];
+        let line = estimate_tokens(&format_history(&history()[2..3]));
+        let fitted = fit_history(history(), line * 2 + 1);
+        let ids: Vec<i32> = fitted.iter().map(|s| s.message_id).collect();
+        assert_eq!(ids, vec![3, 4]);
+        // segments before an oversized one are dropped even if they fit
+        let fitted = fit_history(history(), line * 3 + 10);
+        assert_eq!(fitted.len(), 2);
+        assert!(fit_history(history(), 0).is_empty());
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("[CQ:at,qq=all]大家好"), "大家好");
+        assert_eq!(sanitize("[cq : image,file=x]图"), "图");
+        // removal does not leave a new code behind
+        assert_eq!(sanitize("[C[CQ:face,id=1]Q:at,qq=all]"), "");
+        assert_eq!(sanitize("@全体成员 开会"), "全体成员 开会");
+        assert_eq!(sanitize("＠all，@Everyone"), "all，Everyone");
+        assert_eq!(sanitize("[at:all]看[at:张三]"), "all看[at:张三]");
+        assert_eq!(sanitize("@allen 你好"), "@allen 你好");
+    }
+
+    #[test]
+    fn test_prompt_limit() {
+        let history: Vec<_> = (1..=10)
+            .map(|i| GroupChatSegment {
+                message_id: i,
+                time: String::from("2025-01-01 00:00:00"),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("text"),
+                content: format!("第{i}条消息"),
+                interpret: String::from("text"),
+            })
+            .collect();
+        // history in both prompts, as the limit must account for each copy
+        let substitute = |history: &[GroupChatSegment]| {
+            let history = format_history(history);
+            (format!("系统提示\n{history}"), format!("{history}问题"))
+        };
+        let size = |(dev, user): &(String, String)| estimate_tokens(dev) + estimate_tokens(user);
+        let full = size(&substitute(&history));
+        assert_eq!(
+            fit_prompt(&history, full, substitute),
+            Some(substitute(&history))
+        );
+
+        let max = full / 2;
+        let prompts = fit_prompt(&history, max, substitute).unwrap();
+        assert!(size(&prompts) <= max);
+        assert!(prompts.0.contains("第10条消息") && prompts.1.contains("第10条消息"));
+        assert!(!prompts.0.contains("第1条消息"));
+
+        // too long even without history
+        assert!(fit_prompt(&history, 3, substitute).is_none());
+    }
+}
@@ -0,0 +1,125 @@
+//! Browse images saved in history and files of the group as forward messages.
+
+use kovi::{Message, Segment};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    store::{self, GroupChatSegment},
+    tr, util, BOT_QQ,
+};
+
+/// Forward messages with too many nodes fail to send.
+pub const MAX_ITEMS: i64 = 50;
+
+/// Nodes of the `page`th `count` latest images, one image per node. Empty if there is none.
+pub async fn recent_images(group_id: i64, count: i64, page: i64) -> PluginResult<Message> {
+    let count = count.clamp(1, MAX_ITEMS);
+    let offset = (page.max(1) - 1) * count;
+    let images = store::db_load_recent_images(group_id, count, offset).await?;
+    let mut nodes = Message::new();
+    for image in images {
+        nodes.push(image_node(&image));
+    }
+    Ok(nodes)
+}
+
+/// Uploaded url if any, otherwise the local file, which is readable when OneBot runs on the same
+/// host.
+fn image_node(image: &GroupChatSegment) -> Segment {
+    let file = if image.interpret.starts_with("http") {
+        image.interpret.clone()
+    } else {
+        format!("file://{}", image.content)
+    };
+    let content = json!([
+        { "type": "text", "data": { "text": format!("{}\n", image.time) } },
+        { "type": "image", "data": { "file": file } },
+    ]);
+    let data = json!({
+        "name": image.sender_name,
+        "uin": image.sender_id.to_string(),
+        "content": content,
+    });
+    Segment::new("node", data)
+}
+
+/// Nodes of folders and files in the root directory of group files. Empty if there is none.
+pub async fn group_files(group_id: i64) -> PluginResult<Message> {
+    let api = util::get_group_root_files(group_id)
+        .await
+        .map_err(|err| PluginError::OneBotApi(format!("get_group_root_files: {err}")))?;
+    let root: GroupRootFiles = serde_json::from_value(api.data)?;
+    let mut lines = Vec::new();
+    for folder in root.folders.unwrap_or_default() {
+        lines.push(tr!(
+            group_id,
+            "album.folder",
+            name = folder.folder_name,
+            count = folder.total_file_count,
+        ));
+    }
+    let mut files = root.files.unwrap_or_default();
+    files.sort_by_key(|f| std::cmp::Reverse(f.upload_time));
+    for file in files.into_iter().take(MAX_ITEMS as usize) {
+        let time = util::iso8601_from_timestamp(Some(group_id), file.upload_time)?;
+        lines.push(tr!(
+            group_id,
+            "album.file",
+            name = file.file_name,
+            size = format_size(file.file_size),
+            uploader = file.uploader_name,
+            time = time,
+        ));
+    }
+    let bot_qq = *BOT_QQ.get().unwrap();
+    let mut nodes = Message::new();
+    for line in lines {
+        let data = json!({
+            "name": tr!(group_id, "album.files_sender"),
+            "uin": bot_qq.to_string(),
+            "content": [{ "type": "text", "data": { "text": line } }],
+        });
+        nodes.push(Segment::new("node", data));
+    }
+    Ok(nodes)
+}
+
+/// Human readable size, e.g. "1.5MB".
+fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes}B")
+    } else {
+        format!("{size:.1}{}", UNITS[unit])
+    }
+}
+
+/// Response of `get_group_root_files`, lists are null when empty in some implementations.
+#[derive(Deserialize, Debug)]
+struct GroupRootFiles {
+    files: Option<Vec<GroupFile>>,
+    folders: Option<Vec<GroupFolder>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroupFile {
+    file_name: String,
+    file_size: i64,
+    /// Unix timestamp.
+    upload_time: i64,
+    uploader_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct GroupFolder {
+    folder_name: String,
+    total_file_count: i64,
+}
@@ -0,0 +1,44 @@
+//! Opt-in counters of feature usage per group and day, to tell which features are worth keeping.
+//!
+//! Counted are commands by their config key, e.g. `command.dump_history`, queries to the group
+//! agent as `agent`, and live room queries as `live_query`. Nothing is counted unless
+//! `global.analytics` is enabled.
+
+use crate::{exception::PluginResult, std_db_error, store, tr, util, CONFIG};
+
+/// Days covered by [report].
+const REPORT_DAYS: i64 = 30;
+
+fn enabled() -> bool {
+    CONFIG.get().unwrap().global.analytics.unwrap_or(false)
+}
+
+/// Count a use of `feature` in the group today.
+pub async fn count(group_id: i64, feature: &str) {
+    if !enabled() {
+        return;
+    }
+    let day = util::cur_date_iso8601(Some(group_id));
+    if let Err(err) = store::db_add_feature_use(group_id, &day, feature).await {
+        std_db_error!("Count use of {feature} failed: {err}");
+    }
+}
+
+/// Uses of each feature in the group in the last [REPORT_DAYS] days, most used first.
+pub async fn report(group_id: i64) -> PluginResult<String> {
+    if !enabled() {
+        return Ok(tr!(group_id, "analytics.disabled"));
+    }
+    let since = util::date_iso8601(Some(group_id), 1 - REPORT_DAYS);
+    let usage = store::db_feature_usage_since(group_id, &since).await?;
+    if usage.is_empty() {
+        return Ok(tr!(group_id, "analytics.empty", days = REPORT_DAYS));
+    }
+    let mut lines = vec![tr!(group_id, "analytics.title", days = REPORT_DAYS)];
+    lines.extend(
+        usage
+            .iter()
+            .map(|(feature, count)| format!("{feature}: {count}")),
+    );
+    Ok(lines.join("\n"))
+}
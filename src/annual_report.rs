@@ -0,0 +1,159 @@
+//! Yearly statistics of a member, compiled from chat history.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use crate::{
+    event::GroupMsg, exception::PluginResult, global_state::AnnualReportSetting, std_db_error,
+    store, tr, util, CONFIG,
+};
+
+/// Frequent but meaningless bigrams.
+const STOP_WORDS: [&str; 24] = [
+    "我们", "你们", "他们", "什么", "怎么", "这个", "那个", "一个", "没有", "就是", "不是", "可以",
+    "知道", "还是", "然后", "因为", "所以", "但是", "自己", "真的", "现在", "已经", "这样", "觉得",
+];
+
+/// `<trigger> [year]` replies the report of sender, current year by default.
+pub async fn report_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no annual report config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref setting) = group.annual_report else {
+        return;
+    };
+    let Some(arg) = text.trim().strip_prefix(&setting.trigger) else {
+        return;
+    };
+    let arg = arg.trim();
+    let year = if arg.is_empty() {
+        util::cur_date_iso8601(Some(group_id))[..4]
+            .parse()
+            .unwrap_or_default()
+    } else {
+        match arg.parse::<i32>() {
+            Ok(year) => year,
+            Err(_) => {
+                let usage = tr!(group_id, "annual_report.usage", trigger = setting.trigger);
+                e.reply(usage).await;
+                return;
+            }
+        }
+    };
+    match compile(&e, setting, year).await {
+        Ok(report) => e.reply(report).await,
+        Err(err) => std_db_error!("Compile annual report failed: {err}"),
+    }
+}
+
+async fn compile(e: &GroupMsg, setting: &AnnualReportSetting, year: i32) -> PluginResult<String> {
+    let (group_id, user_id) = (e.group_id, e.sender_id);
+    let name = util::get_name_in_group(group_id, user_id).await;
+    let counts = store::db_count_messages_by_year(group_id, year).await?;
+    let Some(rank) = counts.iter().position(|&(id, _)| id == user_id) else {
+        return Ok(tr!(
+            group_id,
+            "annual_report.silent",
+            name = name,
+            year = year
+        ));
+    };
+    let messages = counts[rank].1;
+
+    let mut lines = vec![
+        tr!(group_id, "annual_report.title", name = name, year = year),
+        tr!(
+            group_id,
+            "annual_report.messages",
+            count = messages,
+            rank = rank + 1,
+            total = counts.len(),
+        ),
+    ];
+    if let Some((hour, count)) = store::db_most_active_hour(group_id, user_id, year).await? {
+        lines.push(tr!(
+            group_id,
+            "annual_report.active_hour",
+            hour = hour,
+            count = count
+        ));
+    }
+    if let Some((at, count)) = store::db_most_at(group_id, user_id, year).await? {
+        lines.push(tr!(
+            group_id,
+            "annual_report.most_at",
+            name = at,
+            count = count
+        ));
+    }
+    let texts = store::db_load_texts_by_year(group_id, user_id, year).await?;
+    let words = favorite_words(&texts, &setting.trigger, setting.favorite_words);
+    if !words.is_empty() {
+        let words: Vec<String> = words
+            .iter()
+            .map(|(word, count)| tr!(group_id, "annual_report.word", word = word, count = count))
+            .collect();
+        let words = words.join(", ");
+        lines.push(tr!(group_id, "annual_report.words", words = words));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Words said in most messages, CJK text is split into bigrams since there is no segmentation.
+fn favorite_words(texts: &[String], exclude_prefix: &str, n: usize) -> Vec<(String, usize)> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for text in texts {
+        if text.trim_start().starts_with(exclude_prefix) {
+            continue;
+        }
+        // once per message, repeated words in a message are likely spam
+        let words: HashSet<String> = tokenize(text).into_iter().collect();
+        for word in words {
+            *counts.entry(word).or_default() += 1;
+        }
+    }
+    let mut words: Vec<(String, usize)> = counts
+        .into_iter()
+        .filter(|(word, count)| *count >= 2 && !STOP_WORDS.contains(&word.as_str()))
+        .collect();
+    words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    words.truncate(n);
+    words
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    let is_cjk = |c: char| ('\u{4e00}'..='\u{9fff}').contains(&c);
+    let mut tokens = Vec::new();
+    let mut cjk_run: Vec<char> = Vec::new();
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if is_cjk(c) {
+            cjk_run.push(c);
+        } else {
+            for pair in cjk_run.windows(2) {
+                tokens.push(pair.iter().collect());
+            }
+            cjk_run.clear();
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c.to_ascii_lowercase());
+        } else {
+            if word.len() >= 2 && !word.chars().all(|c| c.is_ascii_digit()) {
+                tokens.push(word.clone());
+            }
+            word.clear();
+        }
+    }
+    tokens
+}
@@ -0,0 +1,147 @@
+//! Daily variables of agent prompts: `<!date!>`, `<!festival!>` and `<!weather!>`.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use time::Weekday;
+
+use crate::{
+    exception::PluginResult, global_state::WeatherSetting, http::Request, std_db_error, tr, util,
+    DATA_PATH,
+};
+
+pub const DATE: &str = "<!date!>";
+pub const FESTIVAL: &str = "<!festival!>";
+pub const WEATHER: &str = "<!weather!>";
+
+const BUNDLED_CALENDAR: &str = include_str!("../assets/festivals.txt");
+/// `festivals.txt` in data directory replaces the bundled calendar.
+const CUSTOM_CALENDAR: &str = "festivals.txt";
+/// Festivals within this many days are mentioned as upcoming.
+const LOOKAHEAD_DAYS: usize = 7;
+const WEATHER_TTL: Duration = Duration::from_secs(1800);
+const DEFAULT_WEATHER_URL: &str = "https://wttr.in/<!city!>?format=%C+%t&lang=zh";
+
+/// Today in form of "2025-01-29 星期三".
+pub fn today(group_id: i64) -> String {
+    let key = match util::now_in(Some(group_id)).weekday() {
+        Weekday::Monday => "calendar.mon",
+        Weekday::Tuesday => "calendar.tue",
+        Weekday::Wednesday => "calendar.wed",
+        Weekday::Thursday => "calendar.thu",
+        Weekday::Friday => "calendar.fri",
+        Weekday::Saturday => "calendar.sat",
+        Weekday::Sunday => "calendar.sun",
+    };
+    let weekday = tr!(group_id, key);
+    format!("{} {weekday}", util::cur_date_iso8601(Some(group_id)))
+}
+
+/// Festival of today or the nearest upcoming one, e.g. "今天是除夕".
+pub fn festival(group_id: i64) -> String {
+    let dates: Vec<String> = (0..=LOOKAHEAD_DAYS as i64)
+        .map(|shift| util::date_iso8601(Some(group_id), shift))
+        .collect();
+    match upcoming_festival(calendar(), &dates) {
+        Some((0, name)) => tr!(group_id, "calendar.festival_today", name = name),
+        Some((days, name)) => tr!(
+            group_id,
+            "calendar.festival_upcoming",
+            name = name,
+            days = days
+        ),
+        None => tr!(group_id, "calendar.no_festival"),
+    }
+}
+
+/// Loaded once, the custom calendar wins over the bundled one.
+fn calendar() -> &'static str {
+    static CALENDAR: OnceLock<String> = OnceLock::new();
+    CALENDAR.get_or_init(|| {
+        let path = DATA_PATH.get().unwrap().join(CUSTOM_CALENDAR);
+        std::fs::read_to_string(path).unwrap_or_else(|_| BUNDLED_CALENDAR.to_string())
+    })
+}
+
+/// (index into `dates`, festival) of the first date that has a festival in `calendar`. `dates` are
+/// "YYYY-MM-DD", calendar lines are "MM-DD name" every year or "YYYY-MM-DD name" once, festivals of
+/// the same day are joined by "、".
+pub fn upcoming_festival(calendar: &str, dates: &[String]) -> Option<(usize, String)> {
+    let entries: Vec<(&str, &str)> = calendar
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once(char::is_whitespace))
+        .map(|(date, name)| (date, name.trim()))
+        .collect();
+    dates.iter().enumerate().find_map(|(idx, date)| {
+        let names: Vec<&str> = entries
+            .iter()
+            .filter(|(day, _)| date == day || date.get(5..) == Some(*day))
+            .map(|&(_, name)| name)
+            .collect();
+        (!names.is_empty()).then(|| (idx, names.join("、")))
+    })
+}
+
+/// city -> (fetched at, weather)
+type WeatherCache = HashMap<String, (Instant, String)>;
+
+/// Current weather of the configured city, cached for a while. Empty on failure.
+pub async fn weather(setting: &WeatherSetting) -> String {
+    static CACHE: OnceLock<Mutex<WeatherCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some((at, weather)) = cache.lock().unwrap().get(&setting.city) {
+        if at.elapsed() < WEATHER_TTL {
+            return weather.clone();
+        }
+    }
+    let url = setting
+        .api_url
+        .as_deref()
+        .unwrap_or(DEFAULT_WEATHER_URL)
+        .replace("<!city!>", &setting.city);
+    match fetch_weather(&url).await {
+        Ok(weather) => {
+            let weather = weather.trim().to_string();
+            cache
+                .lock()
+                .unwrap()
+                .insert(setting.city.clone(), (Instant::now(), weather.clone()));
+            weather
+        }
+        Err(err) => {
+            std_db_error!("Fetch weather of {} failed: {err}", setting.city);
+            String::new()
+        }
+    }
+}
+
+async fn fetch_weather(url: &str) -> PluginResult<String> {
+    let resp = Request::get(url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?;
+    Ok(resp.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upcoming_festival() {
+        let table = "# comment\n01-01 元旦\n2026-02-16 除夕\n2026-02-17 春节\n02-17 测试节\n";
+        let dates = |dates: &[&str]| dates.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        let found = upcoming_festival(table, &dates(&["2026-02-16"]));
+        assert_eq!(found, Some((0, String::from("除夕"))));
+        let found = upcoming_festival(table, &dates(&["2026-02-15", "2027-02-17"]));
+        assert_eq!(found, Some((1, String::from("测试节"))));
+        let found = upcoming_festival(table, &dates(&["2025-12-30", "2026-02-17"]));
+        assert_eq!(found, Some((1, String::from("春节、测试节"))));
+        assert!(upcoming_festival(table, &dates(&["2026-03-01"])).is_none());
+    }
+}
@@ -0,0 +1,52 @@
+//! Optional captioning of group images, so that image-heavy history reads meaningfully.
+
+use std::time::Duration;
+
+use kovi::tokio::time::timeout;
+
+use crate::{
+    exception::PluginResult,
+    global_state::CaptionSetting,
+    http::{Body, Request},
+    std_error, CONFIG,
+};
+
+#[derive(serde::Deserialize, Debug)]
+struct CaptionResponse {
+    text: String,
+}
+
+/// Short description of a local image, None if captioning is disabled or failed.
+pub async fn describe(path: &str) -> Option<String> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no caption config
+    let caption = config.caption.as_ref()?;
+    let text = match timeout(
+        Duration::from_secs(caption.timeout_sec),
+        request(caption, path),
+    )
+    .await
+    {
+        Ok(Ok(text)) => text,
+        Ok(Err(err)) => {
+            std_error!("Caption {path} failed: {err}");
+            return None;
+        }
+        Err(_) => {
+            std_error!("Caption {path} timed out");
+            return None;
+        }
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let text: String = text.chars().take(caption.max_chars).collect();
+    (!text.is_empty()).then_some(text)
+}
+
+async fn request(caption: &CaptionSetting, path: &str) -> PluginResult<String> {
+    let mut req = Request::post(&caption.endpoint).body(Body::File(path.to_string()));
+    if let Some(ref key) = caption.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp: CaptionResponse = req.send().await?.json()?;
+    Ok(resp.text)
+}
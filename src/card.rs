@@ -0,0 +1,62 @@
+//! Group card of the bot itself, set by command or rotated through configured names.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use crate::{
+    global_state::CardSetting,
+    std_info,
+    util::{self, schedule_task_blocking},
+    BOT_QQ, CONFIG,
+};
+
+/// Spawn a rotation task for each group with `rotate_interval_sec`.
+pub async fn subscribe_rotation() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let rotations = groups.iter().filter_map(|g| {
+        let card = g.card.as_ref()?;
+        let interval = card.rotate_interval_sec?;
+        (!card.names.is_empty()).then_some((g.id, card, interval))
+    });
+    for (group_id, card, interval) in rotations {
+        kovi::spawn(async move {
+            let duration = Duration::from_secs(interval);
+            schedule_task_blocking(duration, move || async move {
+                rotate(group_id, card);
+            })
+            .await;
+        });
+    }
+}
+
+/// Set the next name in `names` as card of the bot, returns the name. None if there is no name.
+pub fn rotate(group_id: i64, card: &CardSetting) -> Option<String> {
+    static CURSORS: OnceLock<Mutex<HashMap<i64, usize>>> = OnceLock::new();
+    if card.names.is_empty() {
+        return None;
+    }
+    let idx = {
+        let mut cursors = CURSORS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .unwrap();
+        let cursor = cursors.entry(group_id).or_default();
+        let idx = *cursor % card.names.len();
+        *cursor = idx + 1;
+        idx
+    };
+    let name = card.names[idx].clone();
+    std_info!("Rotate card of bot in {group_id} to {name}.");
+    set_own_card(group_id, &name);
+    Some(name)
+}
+
+pub fn set_own_card(group_id: i64, card: &str) {
+    util::set_group_card(group_id, *BOT_QQ.get().unwrap(), card);
+}
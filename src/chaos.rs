@@ -0,0 +1,136 @@
+//! Artificial failures and latency for resilience testing, compiled only with the `chaos` feature.
+//!
+//! Read from environment variables at first use:
+//! - `MOMO_CHAOS_POINTS`: comma separated points to disturb, any of `agent`, `live`, `store`,
+//!   `upload`, or `all`. Nothing is disturbed if absent.
+//! - `MOMO_CHAOS_FAILURE_RATE`: probability from 0 to 1 that a call fails, 0 by default.
+//! - `MOMO_CHAOS_LATENCY_MS`: a call is delayed by up to this many milliseconds, 0 by default.
+
+use std::{sync::OnceLock, time::Duration};
+
+use kovi::tokio::time::sleep;
+use rand::{thread_rng, Rng};
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    std_warn,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Point {
+    /// Chat completion requests.
+    Agent,
+    /// Bilibili room queries.
+    Live,
+    /// Group message writes, which trips the write buffer of [crate::store].
+    Store,
+    /// Upload script, which falls back to local paths.
+    Upload,
+}
+
+impl Point {
+    fn name(self) -> &'static str {
+        match self {
+            Point::Agent => "agent",
+            Point::Live => "live",
+            Point::Store => "store",
+            Point::Upload => "upload",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chaos {
+    pub points: Vec<Point>,
+    pub failure_rate: f64,
+    pub max_latency: Duration,
+}
+
+impl Chaos {
+    /// Parse values of the environment variables, malformed values are ignored.
+    pub fn parse(
+        points: Option<&str>,
+        failure_rate: Option<&str>,
+        latency_ms: Option<&str>,
+    ) -> Self {
+        let all = [Point::Agent, Point::Live, Point::Store, Point::Upload];
+        let mut parsed = Vec::new();
+        for name in points.unwrap_or_default().split(',').map(str::trim) {
+            if name == "all" {
+                parsed = all.to_vec();
+                break;
+            }
+            match all.into_iter().find(|p| p.name() == name) {
+                Some(point) => parsed.push(point),
+                None if name.is_empty() => {}
+                None => std_warn!("Unknown chaos point: {name}"),
+            }
+        }
+        let failure_rate = failure_rate
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite())
+            .unwrap_or(0.0)
+            .clamp(0.0, 1.0);
+        let latency_ms = latency_ms.and_then(|v| v.parse().ok()).unwrap_or(0);
+        Self {
+            points: parsed,
+            failure_rate,
+            max_latency: Duration::from_millis(latency_ms),
+        }
+    }
+
+    fn from_env() -> Self {
+        let var = |key: &str| std::env::var(key).ok();
+        Self::parse(
+            var("MOMO_CHAOS_POINTS").as_deref(),
+            var("MOMO_CHAOS_FAILURE_RATE").as_deref(),
+            var("MOMO_CHAOS_LATENCY_MS").as_deref(),
+        )
+    }
+}
+
+fn chaos() -> &'static Chaos {
+    static CHAOS: OnceLock<Chaos> = OnceLock::new();
+    CHAOS.get_or_init(Chaos::from_env)
+}
+
+/// Delay and possibly fail a call at `point`.
+pub async fn inject(point: Point) -> PluginResult<()> {
+    let chaos = chaos();
+    if !chaos.points.contains(&point) {
+        return Ok(());
+    }
+    // rng is not Send, do not hold it across await
+    let (delay, fail) = {
+        let mut rng = thread_rng();
+        let delay = chaos.max_latency.mul_f64(rng.gen::<f64>());
+        (delay, rng.gen_bool(chaos.failure_rate))
+    };
+    sleep(delay).await;
+    if fail {
+        std_warn!("Chaos failure injected at {}", point.name());
+        return Err(PluginError::Chaos(point.name()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chaos_parse() {
+        let chaos = Chaos::parse(None, None, None);
+        assert!(chaos.points.is_empty());
+        assert_eq!(chaos.failure_rate, 0.0);
+
+        let chaos = Chaos::parse(Some("agent, store,typo"), Some("1.5"), Some("200"));
+        assert_eq!(chaos.points, vec![Point::Agent, Point::Store]);
+        assert_eq!(chaos.failure_rate, 1.0);
+        assert_eq!(chaos.max_latency, Duration::from_millis(200));
+
+        let chaos = Chaos::parse(Some("live,all"), Some("NaN"), None);
+        assert_eq!(chaos.points.len(), 4);
+        assert_eq!(chaos.failure_rate, 0.0);
+    }
+}
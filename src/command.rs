@@ -1,23 +1,30 @@
 //! Detect group message and respond to commands.
 
-use kovi::{tokio::fs, Message, MsgEvent};
-use std::{ sync::Arc,
+use kovi::{tokio::fs, Message, Segment};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    sync::{Arc, OnceLock},
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
-    global_state, std_db_error, store,
+    ab_test, album, analytics, card, config_view, dataset, db_check, error_stat,
+    event::{GroupMsg, PrivateMsg},
+    global_state::{self, GroupCommand, GroupSetting, QuotaKind},
+    history_import, migration, outbox, quota, std_db_error, std_db_info, std_error,
+    store::{self, GroupChatSegment},
+    stt, tr, uptime,
     util::{self, call_upload},
-    CONFIG, DATA_PATH,
+    ADMIN_QQ, BOT_QQ, CONFIG, DATA_PATH,
 };
 
-pub async fn act(e: Arc<MsgEvent>) {
-    let Some(text) = e.borrow_text() else {
-        return;
-    };
-    let Some(group_id) = e.group_id else {
-        return;
-    };
+/// Forward messages with too many nodes fail to send.
+const MAX_RECAP: i64 = 100;
+
+pub async fn act(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
     let config = CONFIG.get().unwrap();
     let Some(ref groups) = config.groups else {
         return;
@@ -28,51 +35,165 @@ pub async fn act(e: Arc<MsgEvent>) {
     let Some(ref command) = group.command else {
         return;
     };
-    if !command.admin_ids.contains(&e.sender.user_id) {
+    let record = e.message.get("record");
+    let spoken = record
+        .first()
+        .and_then(|seg| seg.data["file"].as_str())
+        .filter(|_| command.voice.unwrap_or(false));
+    if e.text.is_none() && spoken.is_none() {
         return;
     }
-    let Some(cmd) = command.parse_command(text) else {
+    if !command.is_admin(group_id, e.sender_id).await {
         return;
+    }
+    // voice is transcribed after admin check, since STT is costly
+    let text = match (e.borrow_text(), spoken) {
+        (Some(text), _) => text.to_string(),
+        (None, Some(file)) => match stt::transcribe(file).await {
+            Some(text) => text,
+            None => return,
+        },
+        (None, None) => return,
+    };
+    let Some(cmd) = command.parse_command(&text) else {
+        return;
+    };
+    if cmd.is_global() && !command.is_global_admin(e.sender_id) {
+        return;
+    }
+    let feedback = match cmd {
+        GroupCommand::DumpHistory(_) | GroupCommand::DumpLog(_)
+            if command.private_dump.unwrap_or(false) =>
+        {
+            util::send_group_and_log(group_id, tr!(group_id, "command.private_dump")).await;
+            Feedback::Private(e.sender_id)
+        }
+        _ => Feedback::Group(group_id),
+    };
+    execute(group, cmd, feedback, e.sender_id).await;
+}
+
+/// Run group commands from private chat of admin, in form of "群<group_id> <command>".
+///
+/// Returns true if the message is taken as a console command.
+pub async fn private_console(e: Arc<PrivateMsg>) -> bool {
+    static CONSOLE_REGEX: OnceLock<Regex> = OnceLock::new();
+    if e.user_id != *ADMIN_QQ.get().unwrap() {
+        return false;
+    }
+    let Some(text) = e.borrow_text() else {
+        return false;
+    };
+    let regex =
+        CONSOLE_REGEX.get_or_init(|| Regex::new(r"^群(?<group>\d+)\s+(?<command>.+)$").unwrap());
+    let Some(caps) = regex.captures(text.trim()) else {
+        return false;
+    };
+    let Ok(group_id) = caps["group"].parse::<i64>() else {
+        return false;
+    };
+    let feedback = Feedback::Private(e.user_id);
+    let config = CONFIG.get().unwrap();
+    let group = config
+        .groups
+        .as_ref()
+        .and_then(|groups| groups.iter().find(|g| g.id == group_id));
+    let Some(group) = group else {
+        feedback
+            .send(tr!(None, "command.unknown_group", group = group_id))
+            .await;
+        return true;
+    };
+    let Some(ref command) = group.command else {
+        feedback
+            .send(tr!(group_id, "command.no_command", group = group_id))
+            .await;
+        return true;
+    };
+    let Some(cmd) = command.parse_command(&caps["command"]) else {
+        feedback.send(tr!(group_id, "command.unrecognized")).await;
+        return true;
     };
+    execute(group, cmd, feedback, e.user_id).await;
+    true
+}
+
+/// Where the result of a command goes.
+#[derive(Debug, Clone, Copy)]
+enum Feedback {
+    Group(i64),
+    Private(i64),
+}
+
+impl Feedback {
+    async fn send<T>(self, message: T)
+    where
+        T: Into<Message>,
+        T: Serialize,
+    {
+        match self {
+            Feedback::Group(group_id) => util::send_group_and_log(group_id, message).await,
+            Feedback::Private(user_id) => {
+                util::send_private_msg::<Message>(user_id, message.into())
+            }
+        }
+    }
 
+    fn send_forward(self, nodes: Message) {
+        match self {
+            Feedback::Group(group_id) => util::send_group_forward_msg(group_id, nodes),
+            Feedback::Private(user_id) => util::send_private_forward_msg(user_id, nodes),
+        }
+    }
+}
+
+async fn execute(group: &GroupSetting, cmd: GroupCommand, feedback: Feedback, requester: i64) {
+    let group_id = group.id;
+    crate::run_hooks(|h| h.on_command(group_id, requester, &cmd));
+    analytics::count(group_id, &format!("command.{}", cmd.name())).await;
     match cmd {
-        crate::GroupCommand::Mute => {
+        GroupCommand::Mute => {
             let Some(ref agent) = group.agent else {
-                util::send_group_and_log(group_id, "未配置agent").await;
+                feedback.send(tr!(group_id, "command.no_agent")).await;
                 return;
             };
             if agent.is_mute() {
-                util::send_group_and_log(group_id, "...").await;
+                feedback.send(tr!(group_id, "command.unchanged")).await;
                 return;
             }
             agent.mute();
-            util::send_group_and_log(group_id, "接下来我将冷暴力你们所有人,直到主人哀求我").await;
+            feedback.send(tr!(group_id, "command.mute")).await;
         }
-        crate::GroupCommand::Unmute => {
+        GroupCommand::Unmute => {
             let Some(ref agent) = group.agent else {
-                util::send_group_and_log(group_id, "未配置agent").await;
+                feedback.send(tr!(group_id, "command.no_agent")).await;
                 return;
             };
             if !agent.is_mute() {
-                util::send_group_and_log(group_id, "...").await;
+                feedback.send(tr!(group_id, "command.unchanged")).await;
                 return;
             }
             agent.unmute();
-            util::send_group_and_log(group_id, "我勉为其难地同意和你们聊天").await;
+            feedback.send(tr!(group_id, "command.unmute")).await;
         }
-        crate::GroupCommand::SwitchModel(model) => {
+        GroupCommand::SwitchModel(model) => {
             let Some(ref agent) = group.agent else {
-                util::send_group_and_log(group_id, "未配置agent").await;
+                feedback.send(tr!(group_id, "command.no_agent")).await;
                 return;
             };
             agent.set_model(model.clone()).await;
-            let msg = format!("我的脑子被换成了{model}");
-            util::send_group_and_log(group_id, msg).await;
+            feedback
+                .send(tr!(group_id, "command.switch_model", model = model))
+                .await;
         }
-        crate::GroupCommand::DumpHistory(count) => {
+        GroupCommand::DumpHistory(count) => {
             if count < 1 {
                 return;
             }
+            if let Err(reply) = quota::take(group_id, requester, QuotaKind::Dump).await {
+                feedback.send(reply).await;
+                return;
+            }
             let timestamp = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
@@ -82,8 +203,8 @@ pub async fn act(e: Arc<MsgEvent>) {
             match history {
                 Ok(csv_path) => {
                     let url = call_upload(&csv_path).await;
-                    let msg = format!("导出了{count}条聊天记录: {url}");
-                    util::send_group_and_log(group_id, msg).await;
+                    let msg = tr!(group_id, "command.dump_history", count = count, url = url);
+                    feedback.send(msg).await;
                 }
                 Err(err) => {
                     std_db_error!(
@@ -95,10 +216,14 @@ pub async fn act(e: Arc<MsgEvent>) {
                 }
             }
         }
-        crate::GroupCommand::DumpLog(count) => {
+        GroupCommand::DumpLog(count) => {
             if count < 1 {
                 return;
             }
+            if let Err(reply) = quota::take(group_id, requester, QuotaKind::Dump).await {
+                feedback.send(reply).await;
+                return;
+            }
             let timestamp = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
@@ -108,8 +233,8 @@ pub async fn act(e: Arc<MsgEvent>) {
             match history {
                 Ok(csv_path) => {
                     let url = call_upload(&csv_path).await;
-                    let msg = format!("导出了{count}条日志: {url}");
-                    util::send_group_and_log(group_id, msg).await;
+                    let msg = tr!(group_id, "command.dump_log", count = count, url = url);
+                    feedback.send(msg).await;
                 }
                 Err(err) => {
                     std_db_error!(
@@ -121,14 +246,270 @@ pub async fn act(e: Arc<MsgEvent>) {
                 }
             }
         }
+        GroupCommand::ErrorStat => match error_stat::today_stat().await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => {
+                std_db_error!(
+                    "
+                    Load error stat failed.
+                    Cause: {err}
+                    "
+                );
+            }
+        },
+        GroupCommand::ReloadConfig => match global_state::reload_config().await {
+            Ok(()) => feedback.send(tr!(group_id, "command.reload")).await,
+            Err(err) => {
+                std_db_error!(
+                    "
+                    Reload config failed.
+                    Cause: {err}
+                    "
+                );
+                feedback
+                    .send(tr!(group_id, "command.reload_failed", error = err))
+                    .await;
+            }
+        },
+        GroupCommand::Announce(content) => {
+            announce(group, &content, requester).await;
+            if let Feedback::Private(_) = feedback {
+                feedback.send(tr!(group_id, "command.announced")).await;
+            }
+        }
+        GroupCommand::Recap(count) => {
+            if count < 1 {
+                return;
+            }
+            let segs = match store::db_load_n_group_segment(group_id, count.min(MAX_RECAP)).await {
+                Ok(segs) => segs,
+                Err(err) => {
+                    std_db_error!("Load recap history failed: {err}");
+                    return;
+                }
+            };
+            if segs.is_empty() {
+                feedback.send(tr!(group_id, "command.recap_empty")).await;
+                return;
+            }
+            feedback.send_forward(forward_nodes(group_id, &segs));
+        }
+        GroupCommand::ImportHistory(file) => {
+            match history_import::import_file(group_id, &file).await {
+                Ok((parsed, inserted)) => {
+                    let skipped = parsed - inserted;
+                    let msg = tr!(
+                        group_id,
+                        "command.import_history",
+                        inserted = inserted,
+                        skipped = skipped
+                    );
+                    feedback.send(msg).await;
+                }
+                Err(err) => {
+                    std_db_error!("Import history {file} failed: {err}");
+                    feedback
+                        .send(tr!(group_id, "command.import_failed", error = err))
+                        .await;
+                }
+            }
+        }
+        GroupCommand::ExportState => {
+            // the archive holds history of all groups and secrets in config
+            let admin_qq = *ADMIN_QQ.get().unwrap();
+            if requester != admin_qq {
+                feedback
+                    .send(tr!(group_id, "command.main_admin_only"))
+                    .await;
+                return;
+            }
+            let feedback = Feedback::Private(admin_qq);
+            match migration::export_state().await {
+                // never uploaded, the shared bucket is usually public
+                Ok((path, media)) => {
+                    let msg = tr!(group_id, "command.export_state", path = path, media = media);
+                    feedback.send(msg).await;
+                }
+                Err(err) => {
+                    std_db_error!("Export state failed: {err}");
+                    feedback
+                        .send(tr!(group_id, "command.export_failed", error = err))
+                        .await;
+                }
+            }
+        }
+        GroupCommand::ExportDataset => {
+            // chat history is not posted to the group even if anonymized
+            let feedback = Feedback::Private(requester);
+            match dataset::export(group_id).await {
+                Ok((path, count)) => {
+                    let url = call_upload(&path).await;
+                    let msg = tr!(group_id, "command.export_dataset", count = count, url = url);
+                    feedback.send(msg).await;
+                }
+                Err(err) => {
+                    std_db_error!("Export dataset failed: {err}");
+                    feedback
+                        .send(tr!(group_id, "command.export_failed", error = err))
+                        .await;
+                }
+            }
+        }
+        GroupCommand::AbReport => match ab_test::report(group_id).await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => std_db_error!("Load A/B test stats failed: {err}"),
+        },
+        GroupCommand::FeedbackReport => match crate::feedback::report(group_id).await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => std_db_error!("Load feedback stats failed: {err}"),
+        },
+        GroupCommand::DbHealth => match db_check::health().await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => std_db_error!("Load database health failed: {err}"),
+        },
+        GroupCommand::UsageStat => match analytics::report(group_id).await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => std_db_error!("Load feature usage failed: {err}"),
+        },
+        GroupCommand::ShowConfig => match config_view::effective(group).await {
+            Ok(nodes) => feedback.send_forward(nodes),
+            Err(err) => std_error!("Show config of {group_id} failed: {err}"),
+        },
+        GroupCommand::RunStatus => match uptime::status(group_id).await {
+            Ok(msg) => feedback.send(msg).await,
+            Err(err) => std_db_error!("Load run status failed: {err}"),
+        },
+        GroupCommand::CancelSend => {
+            if outbox::cancel_latest(group_id) {
+                std_db_info!("Queued answer in {group_id} cancelled by {requester}.");
+                feedback.send(tr!(group_id, "command.cancel_send")).await;
+            } else {
+                feedback
+                    .send(tr!(group_id, "command.cancel_send_empty"))
+                    .await;
+            }
+        }
+        GroupCommand::RecentImages(count, page) => {
+            if count < 1 {
+                return;
+            }
+            match album::recent_images(group_id, count, page).await {
+                Ok(nodes) if nodes.iter().len() == 0 => {
+                    feedback.send(tr!(group_id, "command.images_empty")).await;
+                }
+                Ok(nodes) => feedback.send_forward(nodes),
+                Err(err) => std_db_error!("Load recent images failed: {err}"),
+            }
+        }
+        GroupCommand::GroupFiles => match album::group_files(group_id).await {
+            Ok(nodes) if nodes.iter().len() == 0 => {
+                feedback.send(tr!(group_id, "command.files_empty")).await;
+            }
+            Ok(nodes) => feedback.send_forward(nodes),
+            Err(err) => {
+                std_db_error!("Load group files failed: {err}");
+                feedback
+                    .send(tr!(group_id, "command.files_failed", error = err))
+                    .await;
+            }
+        },
+        GroupCommand::SetCard(name) => {
+            card::set_own_card(group_id, &name);
+            std_db_info!("Card of bot in {group_id} set to {name} by {requester}.");
+            feedback
+                .send(tr!(group_id, "command.set_card", card = name))
+                .await;
+        }
+        GroupCommand::GrantQuota(user_id, kind, count) => {
+            if group.quota.is_none() {
+                feedback.send(tr!(group_id, "command.no_quota")).await;
+                return;
+            }
+            if let Err(err) = quota::grant(group_id, user_id, kind, count).await {
+                std_db_error!("Grant quota failed: {err}");
+                return;
+            }
+            std_db_info!("{requester} granted {user_id} {count} {kind:?} quota in {group_id}.");
+            feedback
+                .send(tr!(
+                    group_id,
+                    "command.grant_quota",
+                    user = user_id,
+                    kind = kind.as_str(),
+                    count = count,
+                ))
+                .await;
+        }
     }
 }
 
-pub async fn dump_history(e: Arc<MsgEvent>, n: i64) {
-    let Some(group_id) = e.group_id else {
-        return;
+/// One node per message, consecutive segments of a message are merged.
+fn forward_nodes(group_id: i64, segs: &[GroupChatSegment]) -> Message {
+    let mut nodes = Message::new();
+    for msg in segs.chunk_by(|a, b| a.message_id == b.message_id && a.time == b.time) {
+        let content: Vec<Value> = msg
+            .iter()
+            .filter_map(|s| node_content(group_id, s))
+            .collect();
+        if content.is_empty() {
+            continue;
+        }
+        let first = &msg[0];
+        let data = json!({
+            "name": first.sender_name,
+            "uin": first.sender_id.to_string(),
+            "content": content,
+        });
+        nodes.push(Segment::new("node", data));
+    }
+    nodes
+}
+
+/// Segment to resend, None for those derived from others, e.g. ocr of an image.
+fn node_content(group_id: i64, seg: &GroupChatSegment) -> Option<Value> {
+    let text = |text: String| json!({ "type": "text", "data": { "text": text } });
+    let value = match seg.seg_type.as_str() {
+        "text" => text(seg.content.clone()),
+        "at" => text(format!("@{}", seg.interpret)),
+        // uploaded url, local files are not reachable from clients
+        "image" if seg.interpret.starts_with("http") => {
+            json!({ "type": "image", "data": { "file": seg.interpret } })
+        }
+        "image" => text(tr!(group_id, "command.recap_image")),
+        "record" => text(tr!(group_id, "command.recap_record")),
+        "ocr" | "caption" | "link" => return None,
+        other => text(format!("[{other}]")),
     };
-    let bot = global_state::get_bot();
+    Some(value)
+}
+
+/// Post announcement to group, pin it as group notice if supported, and record it.
+async fn announce(group: &GroupSetting, content: &str, requester: i64) {
+    let group_id = group.id;
+    let at_all = group
+        .command
+        .as_ref()
+        .and_then(|c| c.announce_at_all)
+        .unwrap_or(false);
+    let mut message = Message::new();
+    if at_all {
+        let bot_qq = *BOT_QQ.get().unwrap();
+        let role = util::get_role_in_group(group_id, bot_qq).await;
+        // members cannot at all
+        if matches!(role.as_deref(), Some("owner" | "admin")) {
+            message = message.add_at("all").add_text("\n");
+        }
+    }
+    let text = tr!(group_id, "command.announcement", content = content);
+    util::send_group_and_log(group_id, message.add_text(text)).await;
+    let pinned = util::send_group_notice(group_id, content).await;
+    if let Err(err) = store::db_add_announcement(group_id, requester, content, pinned).await {
+        std_db_error!("Record announcement failed: {err}");
+    }
+}
+
+pub async fn dump_history(e: Arc<GroupMsg>, n: i64) {
+    let group_id = e.group_id;
     let data_path = DATA_PATH.get().unwrap();
     let now = SystemTime::now();
     let timestamp = now
@@ -146,7 +527,7 @@ pub async fn dump_history(e: Arc<MsgEvent>, n: i64) {
         );
     }
     let url = call_upload(&csv_path_str).await;
-    let message = Message::from(format!("导出{n}条记录: {url}"));
+    let message = Message::from(tr!(group_id, "command.dump_history", count = n, url = url));
     if let Err(err) = fs::remove_file(csv_path).await {
         std_db_error!(
             "
@@ -155,5 +536,5 @@ pub async fn dump_history(e: Arc<MsgEvent>, n: i64) {
             "
         );
     }
-    bot.send_group_msg(group_id, message);
+    util::send_group_msg(group_id, message);
 }
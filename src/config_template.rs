@@ -0,0 +1,395 @@
+//! Commented config template written on first run, so that operators can fill it without reading
+//! the source.
+//!
+//! The template is [Config::default] with a comment above each documented section and field.
+//! Whether a section or field may be removed is found by removing it and deserializing again, so
+//! the optional marks never go stale.
+
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::{
+    exception::{PluginError::SerializeToml, PluginResult},
+    global_state::Config,
+};
+
+/// Dotted path of a section or field, without array indices, and its description.
+pub const DOCS: &[(&str, &str)] = &[
+    ("version", "配置格式版本，插件更新后自动升级，请勿修改"),
+    ("global", "全局设置"),
+    (
+        "global.max_sleep_sec",
+        "事件（聊天记录除外）处理前随机睡眠0到N秒，为0时不睡眠",
+    ),
+    (
+        "global.unknown_group",
+        "未配置的群聊: ignore完全忽略，log只记录聊天记录，reply记录并在被艾特时提示",
+    ),
+    (
+        "global.locale",
+        "回复语言，非zh-CN时读取数据目录下的locales/<语言>.toml",
+    ),
+    (
+        "global.dry_run",
+        "为true时不发送消息、不执行群管理操作，只写入日志",
+    ),
+    (
+        "global.timezone",
+        "固定的UTC偏移，如+08:00，用于聊天记录时间和定时任务",
+    ),
+    (
+        "global.analytics",
+        "为true时按天统计各功能的使用次数，用usage_stat命令查看",
+    ),
+    ("database", "Sqlite数据库"),
+    ("database.max_connections", "连接池最大连接数"),
+    ("database.log_table_name", "持久化日志的表名"),
+    (
+        "database.group_table_prefix",
+        "聊天记录表名前缀，群1234的记录写入<前缀>1234",
+    ),
+    (
+        "database.write_buffer_cap",
+        "数据库不可用时内存中暂存的消息段数上限",
+    ),
+    ("database.write_retry_sec", "重试写入暂存消息段的间隔秒数"),
+    (
+        "database.compress_min_bytes",
+        "聊天记录中达到此字节数的内容以zstd压缩存储",
+    ),
+    (
+        "object_storage",
+        "上传脚本，导出的文件、聊天记录中的图片和语音以本地路径为参数调用，标准输出为链接",
+    ),
+    ("object_storage.script_path", "可执行文件路径"),
+    ("error_digest", "每天将前一天的错误统计私聊发送给主人"),
+    (
+        "db_check",
+        "数据库每日完整性检查和索引维护，建议设在群内不活跃的时间",
+    ),
+    ("disk", "数据目录所在磁盘的剩余空间监控"),
+    (
+        "disk.min_free_mb",
+        "剩余空间低于该值时暂停下载图片和语音并私聊通知主人",
+    ),
+    ("disk.prune", "空间不足时删除已上传的图片和语音的本地文件"),
+    ("media", "下载聊天记录中的图片和语音"),
+    (
+        "media.timeout_sec",
+        "单次下载的最长秒数，包括排队等待，超时则只记录文件名",
+    ),
+    ("media.max_file_mb", "超过该大小的文件只记录文件名"),
+    ("media.max_concurrent", "同时下载的文件数，修改后重启生效"),
+    ("maintenance", "主人私聊发送的维护命令"),
+    (
+        "maintenance.restart",
+        "处理完待发送的回复后退出进程，需要进程管理器自动重新启动",
+    ),
+    ("maintenance.check_update", "查询crates.io上的最新版本"),
+    (
+        "maintenance.drain_timeout_sec",
+        "重启前等待待发送的回复的最长秒数",
+    ),
+    ("private_agent", "私聊助理，仅回复好友私聊"),
+    (
+        "private_agent.api_url",
+        "OpenAI兼容接口地址，也可以是地址列表，失败时轮换",
+    ),
+    ("private_agent.api_key", "接口密钥"),
+    ("private_agent.allow_ids", "仅回复这些QQ号的好友"),
+    ("private_agent.personas", "QQ号到人设的映射"),
+    ("temp_session", "群成员发起的临时会话（非好友私聊）"),
+    (
+        "temp_session.policy",
+        "ignore不处理，agent由私聊助理回复，forward转发给主人",
+    ),
+    (
+        "temp_session.rate_limit_sec",
+        "同一用户在该秒数内只处理一条消息",
+    ),
+    ("private_live", "好友私聊订阅直播间开播通知"),
+    ("news", "每日早报，从RSS或Atom源汇总标题推送到群"),
+    ("news.sources", "RSS或Atom链接"),
+    ("news.group_ids", "推送的群号"),
+    ("github", "轮询GitHub仓库动态并推送到群"),
+    ("github.repos", "仓库列表，每个仓库一节"),
+    ("ocr", "群聊图片文字识别，结果写入聊天记录"),
+    ("ocr.engine", "tesseract调用本机命令，endpoint调用HTTP服务"),
+    ("caption", "群聊图片描述，供助理理解聊天记录中的图片"),
+    ("unfurl", "链接预览，链接的标题和描述写入聊天记录"),
+    (
+        "image_safety",
+        "发送图片前调用接口检查，接口返回{\"score\": 0.97}",
+    ),
+    (
+        "image_safety.action",
+        "不安全图片的处理: skip不发送，blur模糊后发送",
+    ),
+    ("stt", "语音转文字，目前用于管理员的语音命令"),
+    (
+        "bilibili",
+        "哔哩哔哩登录账号，查询直播间时避免匿名请求被限流",
+    ),
+    ("bilibili.cookie", "从浏览器复制的Cookie请求头"),
+    ("spend", "所有助理共用的OpenAI月度花费上限"),
+    ("spend.prices", "各模型每百万token的单价"),
+    (
+        "spend.fallback_model",
+        "超出上限后改用的模型，删除则停止回复直到下个月",
+    ),
+    ("stats_api", "供外部看板读取统计数据的JSON接口"),
+    (
+        "stats_api.bind",
+        "监听地址，对外开放时请放在HTTPS反向代理之后",
+    ),
+    (
+        "stats_api.token",
+        "请求头Authorization: Bearer <token>，少于16个字符时不启动",
+    ),
+    (
+        "groups",
+        "群设置，每个群一节[[groups]]，删除某一小节即关闭该功能",
+    ),
+    ("groups.id", "QQ群号"),
+    (
+        "groups.pipeline",
+        "消息处理环节及顺序，删除则按默认顺序全部执行",
+    ),
+    ("groups.live", "本群主播的直播间开播下播通知和查询"),
+    ("groups.live.room_id", "哔哩哔哩直播间号"),
+    ("groups.live.notify_group_ids", "同时通知的其他群号"),
+    ("groups.agent", "群助理，被艾特时由OpenAI兼容接口回复"),
+    (
+        "groups.agent.api_url",
+        "OpenAI兼容接口地址，也可以是地址列表，失败时轮换",
+    ),
+    ("groups.agent.api_key", "接口密钥"),
+    ("groups.agent.model", "模型名称"),
+    ("groups.agent.dev_prompt", "系统提示词，可用占位符见README"),
+    (
+        "groups.agent.user_prompt",
+        "用户提示词，<!message!>替换为艾特时发送的消息",
+    ),
+    (
+        "groups.agent.known_members",
+        "QQ号到成员介绍的映射，提示词中的<!members!>",
+    ),
+    (
+        "groups.agent.ab_test",
+        "备选人设，与原提示词随机使用并记录效果",
+    ),
+    ("groups.agent.weather", "提示词中<!weather!>的城市"),
+    (
+        "groups.agent.persona_schedule",
+        "按时段切换人设，每个时段一节",
+    ),
+    (
+        "groups.agent.language",
+        "检查回复语言，不符时重新提问或翻译",
+    ),
+    (
+        "groups.agent.long_message",
+        "超过min_chars字的消息先概括再放入提示词",
+    ),
+    (
+        "groups.agent.role_prompts",
+        "按提问者身份(owner或admin)附加在系统提示词末尾的要求",
+    ),
+    (
+        "groups.command",
+        "管理员命令，启动日志中包含每个命令的正则表达式",
+    ),
+    ("groups.command.admin_ids", "有权限调用命令的QQ号"),
+    (
+        "groups.command.admin_source",
+        "管理员来源: config仅admin_ids，platform群主和群管理员，both两者",
+    ),
+    ("groups.report", "回复消息并发送触发词举报，转发给管理员"),
+    ("groups.verify", "新成员入群验证"),
+    ("groups.moderation", "反刷屏，管理员不受限制"),
+    ("groups.repeat", "复读"),
+    ("groups.points", "签到与积分"),
+    ("groups.level", "发言等级"),
+    ("groups.quiz", "答题游戏"),
+    ("groups.idiom", "成语接龙"),
+    ("groups.feed", "RSS/Atom订阅，管理员可用"),
+    ("groups.meme", "做表情"),
+    (
+        "groups.meme.font_file",
+        "数据目录下的字体文件，需包含中文字符",
+    ),
+    ("groups.search", "聊天记录检索和群史问答"),
+    ("groups.annual_report", "年度报告"),
+    ("groups.activity", "按星期和小时统计消息的活跃热力图"),
+    (
+        "groups.quota",
+        "每个成员每天使用做表情、搜索和导出的次数，删除某项则不限次数",
+    ),
+    (
+        "groups.guild",
+        "并入本群的QQ频道子频道，需要OneBot实现支持频道",
+    ),
+    ("groups.summary", "由本群助理总结最近的聊天记录"),
+    ("groups.mention", "艾特统计"),
+    ("groups.card", "机器人自己的群名片"),
+    (
+        "groups.join_request",
+        "入群申请审核，需要机器人是群主或管理员",
+    ),
+    ("groups.word_filter", "屏蔽词"),
+    ("groups.reminder", "群成员设置提醒"),
+    ("groups.blacklist", "黑名单，这些成员的消息只记录不处理"),
+    ("groups.rate_limit", "按成员限流"),
+    ("groups.keyword", "关键词自动回复"),
+    ("groups.feedback", "助理回复的好评差评反馈"),
+    ("groups.heat", "群内激烈争吵时暂停助理和群事件播报"),
+    ("groups.takeover", "助理回复包含敏感词时交由管理员审核"),
+    ("groups.sticker", "群表情库"),
+    ("groups.outbox", "助理回复等待一段时间再发送，期间可撤掉"),
+    ("groups.ignore", "忽略其他机器人等的消息，不记录也不处理"),
+    ("groups.repost", "识别重复发送的图片（火星图）"),
+];
+
+const OPTIONAL: &str = "可选，删除即关闭或使用缺省值";
+/// Entries of these maps are examples, not fields, so they are not commented.
+const MAPS: [&str; 3] = [
+    "private_agent.personas",
+    "spend.prices",
+    "groups.agent.known_members",
+];
+
+/// Step in a path of the default config.
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+/// Config template with comments.
+pub fn generate() -> PluginResult<String> {
+    let config = Config::default();
+    let plain = toml::to_string_pretty(&config).map_err(|e| SerializeToml(e.to_string()))?;
+    let root = toml::Value::try_from(&config).map_err(|e| SerializeToml(e.to_string()))?;
+    let mut doc: DocumentMut = plain.parse().map_err(|e| SerializeToml(format!("{e}")))?;
+    annotate(doc.as_table_mut(), &root, &mut Vec::new());
+    Ok(doc.to_string())
+}
+
+fn annotate(table: &mut Table, root: &toml::Value, steps: &mut Vec<Step>) {
+    let keys: Vec<String> = table.iter().map(|(k, _)| k.to_string()).collect();
+    for key in keys {
+        steps.push(Step::Key(key.clone()));
+        let comment = comment_of(root, steps);
+        match table.get_mut(&key) {
+            Some(Item::Table(sub)) => {
+                prepend(sub.decor_mut(), &comment);
+                if !MAPS.contains(&doc_path(steps).as_str()) {
+                    annotate(sub, root, steps);
+                }
+            }
+            Some(Item::ArrayOfTables(array)) => {
+                for (i, sub) in array.iter_mut().enumerate() {
+                    if i == 0 {
+                        prepend(sub.decor_mut(), &comment);
+                    }
+                    steps.push(Step::Index(i));
+                    annotate(sub, root, steps);
+                    steps.pop();
+                }
+            }
+            Some(_) => {
+                if let Some(mut key) = table.key_mut(&key) {
+                    prepend(key.leaf_decor_mut(), &comment);
+                }
+            }
+            None => {}
+        }
+        steps.pop();
+    }
+}
+
+fn prepend(decor: &mut toml_edit::Decor, comment: &str) {
+    if comment.is_empty() {
+        return;
+    }
+    let prefix = decor.prefix().and_then(|p| p.as_str()).unwrap_or_default();
+    // keep the blank line before a header above the comment
+    let (blank, rest) = prefix.split_at(prefix.len() - prefix.trim_start_matches('\n').len());
+    decor.set_prefix(format!("{blank}{comment}{rest}"));
+}
+
+/// `# ` lines describing the path, empty if neither documented nor optional.
+fn comment_of(root: &toml::Value, steps: &[Step]) -> String {
+    let path = doc_path(steps);
+    let doc = DOCS.iter().find(|(p, _)| *p == path).map(|(_, d)| *d);
+    let optional = is_optional(root, steps);
+    match (doc, optional) {
+        (Some(doc), true) => format!("# {doc}\n# {OPTIONAL}\n"),
+        (Some(doc), false) => format!("# {doc}\n"),
+        (None, true) => format!("# {OPTIONAL}\n"),
+        (None, false) => String::new(),
+    }
+}
+
+fn doc_path(steps: &[Step]) -> String {
+    let keys: Vec<&str> = steps
+        .iter()
+        .filter_map(|s| match s {
+            Step::Key(k) => Some(k.as_str()),
+            Step::Index(_) => None,
+        })
+        .collect();
+    keys.join(".")
+}
+
+/// Whether the config still deserializes without the value at `steps`.
+fn is_optional(root: &toml::Value, steps: &[Step]) -> bool {
+    let Some((Step::Key(last), parents)) = steps.split_last() else {
+        return false;
+    };
+    let mut value = root.clone();
+    let mut cur = &mut value;
+    for step in parents {
+        let next = match (step, cur) {
+            (Step::Key(k), toml::Value::Table(t)) => t.get_mut(k),
+            (Step::Index(i), toml::Value::Array(a)) => a.get_mut(*i),
+            _ => None,
+        };
+        let Some(next) = next else {
+            return false;
+        };
+        cur = next;
+    }
+    let toml::Value::Table(table) = cur else {
+        return false;
+    };
+    table.remove(last);
+    value.try_into::<Config>().is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::global_state::GroupSetting;
+
+    #[test]
+    fn test_config_template() {
+        let template = generate().unwrap();
+        let config: Config = toml::from_str(&template).unwrap();
+        assert_eq!(config.groups.unwrap()[0].id, GroupSetting::default().id);
+        assert!(template.contains("# QQ群号\nid = "));
+        assert!(template.contains("\n# 群助理，被艾特时由OpenAI兼容接口回复\n# 可选"));
+        // required fields are not marked optional
+        assert!(template.contains("# 接口密钥\napi_key = "));
+
+        // every documented path exists in the template
+        let root = toml::Value::try_from(Config::default()).unwrap();
+        for (path, _) in DOCS {
+            let mut value = &root;
+            for key in path.split('.') {
+                if let toml::Value::Array(array) = value {
+                    value = &array[0];
+                }
+                value = value.get(key).unwrap_or_else(|| panic!("stale doc {path}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Effective configuration of a group for the `show_config` command, so that drift between
+//! `config.toml` and runtime state, e.g. a model switched by command, is visible.
+//!
+//! Secrets are redacted by key wherever they appear in the group section.
+
+use kovi::{Message, Segment};
+use serde_json::json;
+
+use crate::{
+    exception::{PluginError::SerializeToml, PluginResult},
+    global_state::{GroupSetting, LiveSwitch},
+    live, tr, BOT_QQ, CONFIG,
+};
+
+const SECRET_KEYS: [&str; 3] = ["api_key", "token", "cookie"];
+const REDACTED: &str = "******";
+
+/// Forward nodes, runtime state first and then one per section of the group config.
+pub async fn effective(group: &GroupSetting) -> PluginResult<Message> {
+    let mut texts = vec![runtime_state(group).await];
+    let mut root = toml::Value::try_from(group).map_err(|e| SerializeToml(e.to_string()))?;
+    redact(&mut root);
+    let toml::Value::Table(root) = root else {
+        return Err(SerializeToml(String::from("group setting is not a table")));
+    };
+    let (scalars, sections): (toml::Table, toml::Table) =
+        root.into_iter().partition(|(_, v)| !v.is_table());
+    texts.push(toml::to_string_pretty(&scalars).map_err(|e| SerializeToml(e.to_string()))?);
+    for (key, value) in sections {
+        let section = toml::Table::from_iter([(key, value)]);
+        texts.push(toml::to_string_pretty(&section).map_err(|e| SerializeToml(e.to_string()))?);
+    }
+
+    let bot_qq = *BOT_QQ.get().unwrap();
+    let name = tr!(group.id, "config_view.sender");
+    let mut nodes = Message::new();
+    for text in texts {
+        let data = json!({
+            "name": name,
+            "uin": bot_qq.to_string(),
+            "content": [{ "type": "text", "data": { "text": text.trim_end() } }],
+        });
+        nodes.push(Segment::new("node", data));
+    }
+    Ok(nodes)
+}
+
+/// State changed at runtime or derived from several settings.
+async fn runtime_state(group: &GroupSetting) -> String {
+    let config = CONFIG.get().unwrap();
+    let group_id = group.id;
+    let mut lines = vec![tr!(group_id, "config_view.title", group = group_id)];
+    let locale = group.locale.as_ref().or(config.global.locale.as_ref());
+    let timezone = group.timezone.as_ref().or(config.global.timezone.as_ref());
+    lines.push(tr!(
+        group_id,
+        "config_view.locale",
+        locale = locale.map_or("zh-CN", String::as_str),
+        timezone = timezone.map_or("+08:00", String::as_str),
+    ));
+    // names as in config
+    let stages: Vec<String> = group
+        .stages()
+        .iter()
+        .filter_map(|s| serde_json::to_value(s).ok()?.as_str().map(str::to_string))
+        .collect();
+    lines.push(tr!(
+        group_id,
+        "config_view.stages",
+        stages = stages.join(", "),
+    ));
+    match group.agent {
+        Some(ref agent) => {
+            let persona = agent
+                .active_persona
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .and_then(|idx| agent.persona_schedule.as_ref()?.get(idx))
+                .map(|w| w.name.clone())
+                .unwrap_or_else(|| tr!(group_id, "config_view.no_persona"));
+            let state = if agent.is_mute() {
+                tr!(group_id, "config_view.agent_muted")
+            } else {
+                tr!(group_id, "config_view.agent_enabled")
+            };
+            let model = agent.get_model().await;
+            lines.push(tr!(
+                group_id,
+                "config_view.agent",
+                state = state,
+                model = model,
+                configured = agent.model,
+                persona = persona,
+            ));
+        }
+        None => lines.push(tr!(group_id, "config_view.no_agent")),
+    }
+    if let Some(ref setting) = group.live {
+        let key = match setting.get_switch() {
+            LiveSwitch::On => "config_view.live_on",
+            LiveSwitch::Off => "config_view.live_off",
+            LiveSwitch::Init => "config_view.live_init",
+            LiveSwitch::Trap => "config_view.live_trap",
+        };
+        lines.push(tr!(
+            group_id,
+            "config_view.live",
+            room = setting.room_id,
+            status = tr!(group_id, key),
+        ));
+    }
+    let rooms = live::followed_rooms(group_id);
+    if !rooms.is_empty() {
+        lines.push(tr!(
+            group_id,
+            "config_view.followed_rooms",
+            rooms = rooms.join(", "),
+        ));
+    }
+    if let Some(ref command) = group.command {
+        let admins: Vec<String> = command
+            .admins(group_id)
+            .await
+            .iter()
+            .map(i64::to_string)
+            .collect();
+        lines.push(tr!(
+            group_id,
+            "config_view.admins",
+            admins = admins.join(", "),
+        ));
+    }
+    lines.join("\n")
+}
+
+fn redact(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                match value {
+                    toml::Value::String(s) if SECRET_KEYS.contains(&key.as_str()) => {
+                        if !s.is_empty() {
+                            *s = REDACTED.to_string();
+                        }
+                    }
+                    _ => redact(value),
+                }
+            }
+        }
+        toml::Value::Array(array) => array.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
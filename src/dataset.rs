@@ -0,0 +1,195 @@
+//! Fine-tuning dataset in OpenAI chat format, built from history of a group.
+//!
+//! Each text reply of the bot is an assistant turn, preceded by recent history as the user turn.
+//! Members are renamed to `成员N`, QQ numbers and links in text are masked.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+use crate::{
+    agent,
+    exception::PluginResult,
+    store::{self, GroupChatSegment},
+    BOT_QQ, DATA_PATH,
+};
+
+/// Segments of history before a reply taken as its context.
+const CONTEXT_SEGMENTS: usize = 30;
+/// Shorter replies carry little of the persona.
+const MIN_REPLY_CHARS: usize = 2;
+const SYSTEM_PROMPT: &str = "You are a member of a QQ group chat. \
+    Reply to the latest messages in the history.";
+
+/// Write the dataset of the group as JSONL. Returns path of the file and number of examples.
+pub async fn export(group_id: i64) -> PluginResult<(String, usize)> {
+    let segs = store::db_load_group_segment_since(group_id, "").await?;
+    let examples = build_examples(&segs, *BOT_QQ.get().unwrap());
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let path = DATA_PATH
+        .get()
+        .unwrap()
+        .join(format!("dataset-{group_id}-{timestamp}.jsonl"));
+    let mut buf = String::new();
+    for example in &examples {
+        buf.push_str(&example.to_string());
+        buf.push('\n');
+    }
+    kovi::tokio::fs::write(&path, buf).await?;
+    Ok((path.to_string_lossy().to_string(), examples.len()))
+}
+
+/// Examples of `{"messages": [system, user, assistant]}`, identical replies are kept once.
+pub fn build_examples(segs: &[GroupChatSegment], bot_qq: i64) -> Vec<Value> {
+    let anonymizer = Anonymizer::new(segs, bot_qq);
+    let mut seen = HashSet::new();
+    let mut examples = Vec::new();
+    let mut start = 0;
+    for msg in segs.chunk_by(|a, b| a.message_id == b.message_id && a.time == b.time) {
+        let end = start + msg.len();
+        let context = &segs[start.saturating_sub(CONTEXT_SEGMENTS)..start];
+        start = end;
+        if msg[0].sender_id != bot_qq || context.is_empty() {
+            continue;
+        }
+        let reply: String = msg
+            .iter()
+            .filter(|s| s.seg_type == "text")
+            .map(|s| s.content.as_str())
+            .collect();
+        let reply = anonymizer.mask(reply.trim());
+        if reply.chars().count() < MIN_REPLY_CHARS || !seen.insert(reply.clone()) {
+            continue;
+        }
+        let context: Vec<GroupChatSegment> = context.iter().map(|s| anonymizer.apply(s)).collect();
+        examples.push(json!({
+            "messages": [
+                { "role": "system", "content": SYSTEM_PROMPT },
+                { "role": "user", "content": agent::format_history(&context) },
+                { "role": "assistant", "content": reply },
+            ]
+        }));
+    }
+    examples
+}
+
+/// Consistent aliases of members across the dataset.
+struct Anonymizer {
+    aliases: HashMap<i64, String>,
+    // (real name, alias), longer names first so that a name containing another is replaced whole
+    names: Vec<(String, String)>,
+}
+
+impl Anonymizer {
+    fn new(segs: &[GroupChatSegment], bot_qq: i64) -> Self {
+        let mut aliases = HashMap::from([(bot_qq, String::from("我"))]);
+        let mut names = Vec::new();
+        for seg in segs {
+            let next = aliases.len();
+            let alias = aliases
+                .entry(seg.sender_id)
+                .or_insert_with(|| format!("成员{next}"));
+            // single characters are too common to be replaced in text
+            if seg.sender_name.chars().count() > 1
+                && names.iter().all(|(n, _)| n != &seg.sender_name)
+            {
+                names.push((seg.sender_name.clone(), alias.clone()));
+            }
+        }
+        names.sort_by_key(|(n, _)| std::cmp::Reverse(n.chars().count()));
+        Self { aliases, names }
+    }
+
+    fn alias(&self, user_id: i64) -> String {
+        self.aliases
+            .get(&user_id)
+            .cloned()
+            .unwrap_or_else(|| String::from("成员"))
+    }
+
+    fn apply(&self, seg: &GroupChatSegment) -> GroupChatSegment {
+        let interpret = match seg.seg_type.as_str() {
+            "at" => seg
+                .content
+                .parse()
+                .map(|id| self.alias(id))
+                .unwrap_or_default(),
+            _ => self.mask(&seg.interpret),
+        };
+        GroupChatSegment {
+            message_id: seg.message_id,
+            time: seg.time.clone(),
+            sender_id: 0,
+            sender_name: self.alias(seg.sender_id),
+            seg_type: seg.seg_type.clone(),
+            content: self.mask(&seg.content),
+            interpret,
+        }
+    }
+
+    /// Replace names, links, and QQ numbers in text.
+    fn mask(&self, text: &str) -> String {
+        static LINK: OnceLock<Regex> = OnceLock::new();
+        static NUMBER: OnceLock<Regex> = OnceLock::new();
+        let link = LINK.get_or_init(|| Regex::new(r"https?://\S+").unwrap());
+        let number = NUMBER.get_or_init(|| Regex::new(r"\d{5,11}").unwrap());
+        let mut text = link.replace_all(text, "[链接]").to_string();
+        text = number.replace_all(&text, "[号码]").to_string();
+        for (name, alias) in &self.names {
+            text = text.replace(name.as_str(), alias);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT_ID: i64 = 10000;
+
+    #[test]
+    fn test_dataset_examples() {
+        let seg = |message_id: i32, sender_id: i64, name: &str, seg_type: &str, content: &str| {
+            GroupChatSegment {
+                message_id,
+                time: format!("2025-01-01 00:00:{message_id:02}"),
+                sender_id,
+                sender_name: name.to_string(),
+                seg_type: seg_type.to_string(),
+                content: content.to_string(),
+                interpret: String::from("text"),
+            }
+        };
+        let mut at = seg(2, 42, "张三", "at", &BOT_ID.to_string());
+        at.interpret = String::from("momo");
+        let segs = vec![
+            seg(1, 42, "张三", "text", "李四在吗, 加我12345678"),
+            at,
+            seg(2, 42, "张三", "text", "看看 https://example.com/a"),
+            seg(3, BOT_ID, "momo", "text", "张三你好"),
+            seg(4, 43, "李四", "text", "我来了"),
+            seg(5, BOT_ID, "momo", "text", "嗯"),
+            seg(6, BOT_ID, "momo", "text", "张三你好"),
+        ];
+        let examples = build_examples(&segs, BOT_ID);
+        // too short and duplicate replies are dropped
+        assert_eq!(examples.len(), 1);
+        let messages = examples[0]["messages"].as_array().unwrap();
+        assert_eq!(messages[2]["role"], "assistant");
+        assert_eq!(messages[2]["content"], "成员1你好");
+        let user = messages[1]["content"].as_str().unwrap();
+        assert!(user.contains("成员1: 成员2在吗, 加我[号码]"));
+        assert!(user.contains("成员1 AT 我"));
+        assert!(user.contains("[链接]"));
+        assert!(!user.contains("张三"));
+    }
+}
@@ -0,0 +1,75 @@
+//! Daily integrity check of the database, followed by rebuilding indexes and refreshing
+//! statistics. Problems are reported to the main admin, and the last result is shown by the
+//! `db_health` command.
+
+use crate::{
+    exception::PluginResult,
+    std_error, std_info,
+    store::{self, DbCheck},
+    tr,
+    util::{self, schedule_daily_blocking},
+    CONFIG,
+};
+
+/// Problems beyond this are left out of the report.
+const MAX_PROBLEMS: usize = 10;
+
+pub async fn subscribe_check() {
+    let config = CONFIG.get().unwrap();
+    // no-op if no check config
+    let Some(ref setting) = config.db_check else {
+        return;
+    };
+
+    kovi::spawn(async move {
+        schedule_daily_blocking(setting.hour, || async {
+            check().await;
+        })
+        .await;
+    });
+}
+
+/// Check and maintain the database, then record the result.
+pub async fn check() {
+    let detail = match store::db_integrity_check().await {
+        Ok(problems) if problems.is_empty() => None,
+        Ok(problems) => Some(problems[..problems.len().min(MAX_PROBLEMS)].join("\n")),
+        Err(err) => Some(err.to_string()),
+    };
+    // indexes are rebuilt only if intact, maintenance would not fix a corrupted database
+    let detail = match detail {
+        None => store::db_optimize().await.err().map(|err| err.to_string()),
+        problems => problems,
+    };
+    let check = DbCheck {
+        time: util::cur_time_iso8601(None),
+        ok: detail.is_none(),
+        detail: detail.unwrap_or_default(),
+    };
+    if check.ok {
+        std_info!("Database check passed.");
+    } else {
+        std_error!("Database check failed: {}", check.detail);
+        util::notify_admin(tr!(None, "db_check.failed", detail = check.detail));
+    }
+    if let Err(err) = store::db_add_check(&check).await {
+        std_error!("Record database check failed: {err}");
+    }
+}
+
+/// Size of the database and result of the last check.
+pub async fn health() -> PluginResult<String> {
+    let size = store::db_size().await? as f64 / (1024.0 * 1024.0);
+    let size = tr!(None, "db_check.size", size = format!("{size:.1}"));
+    let last = match store::db_last_check().await? {
+        None => tr!(None, "db_check.never"),
+        Some(check) if check.ok => tr!(None, "db_check.ok", time = check.time),
+        Some(check) => tr!(
+            None,
+            "db_check.problem",
+            time = check.time,
+            detail = check.detail,
+        ),
+    };
+    Ok(format!("{size}\n{last}"))
+}
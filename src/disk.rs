@@ -0,0 +1,165 @@
+//! Watch free space of the disk holding the data path.
+//!
+//! Below the threshold, images and records of group history are no longer downloaded, and local
+//! copies of media uploaded already may be pruned, oldest first. The main admin is told once when
+//! space runs low and once when it recovers.
+
+use std::{
+    future::Future,
+    path::Path,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    global_state::DiskSetting,
+    std_error, std_info, store, tr,
+    util::{self, schedule_task_blocking},
+    CONFIG, DATA_PATH,
+};
+
+const MB: u64 = 1024 * 1024;
+
+static LOW: AtomicBool = AtomicBool::new(false);
+
+pub type SpaceFuture<'a> = Pin<Box<dyn Future<Output = PluginResult<u64>> + Send + 'a>>;
+
+/// Free space of the filesystem holding a path, `df` in production.
+pub trait SpaceProbe: Send + Sync {
+    fn free_bytes<'a>(&'a self, path: &'a Path) -> SpaceFuture<'a>;
+}
+
+static PROBE: OnceLock<Box<dyn SpaceProbe>> = OnceLock::new();
+
+/// Measure free space through `probe` instead of `df`, e.g. a mock in tests. Only the first call
+/// before any check takes effect.
+pub fn set_probe(probe: impl SpaceProbe + 'static) {
+    let _ = PROBE.set(Box::new(probe));
+}
+
+fn probe() -> &'static dyn SpaceProbe {
+    PROBE.get_or_init(|| Box::new(Df)).as_ref()
+}
+
+pub async fn subscribe_disk() {
+    let config = CONFIG.get().unwrap();
+    // no-op if no disk config
+    let Some(ref setting) = config.disk else {
+        return;
+    };
+
+    kovi::spawn(async move {
+        let duration = Duration::from_secs(setting.interval_sec);
+        schedule_task_blocking(duration, || check(setting)).await;
+    });
+}
+
+/// Whether free space is below the threshold as of the last check.
+pub fn is_low() -> bool {
+    LOW.load(Ordering::Relaxed)
+}
+
+pub async fn check(setting: &DiskSetting) {
+    let data_path = DATA_PATH.get().unwrap();
+    let mut free = match probe().free_bytes(data_path).await {
+        Ok(free) => free,
+        Err(err) => {
+            std_error!("Check free space of {} failed: {err}", data_path.display());
+            return;
+        }
+    };
+    let min = setting.min_free_mb * MB;
+    if free < min && setting.prune {
+        free += prune(min - free).await;
+    }
+    let low = free < min;
+    let was_low = LOW.swap(low, Ordering::Relaxed);
+    let free_mb = free / MB;
+    if low && !was_low {
+        std_error!("Free space is {free_mb}MB, pause media downloads.");
+        util::notify_admin(tr!(
+            None,
+            "disk.low",
+            free = free_mb,
+            min = setting.min_free_mb,
+        ));
+    } else if !low && was_low {
+        std_info!("Free space is {free_mb}MB, resume media downloads.");
+        util::notify_admin(tr!(None, "disk.recovered", free = free_mb));
+    }
+}
+
+/// Delete local copies of uploaded media in group history, oldest first, until `needed` bytes
+/// are freed. Returns bytes freed.
+pub async fn prune(needed: u64) -> u64 {
+    let mut media = Vec::new();
+    let group_ids = match store::db_group_table_ids().await {
+        Ok(ids) => ids,
+        Err(err) => {
+            std_error!("Load groups to prune failed: {err}");
+            return 0;
+        }
+    };
+    for group_id in group_ids {
+        match store::db_load_media(group_id).await {
+            Ok(entries) => media.extend(entries),
+            Err(err) => std_error!("Load media of group {group_id} to prune failed: {err}"),
+        }
+    }
+    media.sort_by(|a, b| a.time.cmp(&b.time));
+
+    let mut freed = 0;
+    for entry in media {
+        if freed >= needed {
+            break;
+        }
+        // files not uploaded are the only copy
+        if !entry.url.starts_with("http") || !entry.path.starts_with('/') {
+            continue;
+        }
+        let Ok(meta) = kovi::tokio::fs::metadata(&entry.path).await else {
+            continue;
+        };
+        match kovi::tokio::fs::remove_file(&entry.path).await {
+            Ok(()) => freed += meta.len(),
+            Err(err) => std_error!("Prune {} failed: {err}", entry.path),
+        }
+    }
+    std_info!("Pruned {}MB of uploaded media.", freed / MB);
+    freed
+}
+
+struct Df;
+
+impl SpaceProbe for Df {
+    fn free_bytes<'a>(&'a self, path: &'a Path) -> SpaceFuture<'a> {
+        Box::pin(df(path))
+    }
+}
+
+async fn df(path: &Path) -> PluginResult<u64> {
+    let output = kovi::tokio::process::Command::new("df")
+        .arg("-Pk")
+        .arg(path)
+        .output()
+        .await?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Filesystem 1024-blocks Used Available Capacity Mounted on
+    let available = stdout
+        .lines()
+        .nth(1)
+        .and_then(|line| line.split_whitespace().nth(3))
+        .and_then(|kb| kb.parse::<u64>().ok());
+    match available {
+        Some(kb) if output.status.success() => Ok(kb * 1024),
+        _ => {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            Err(PluginError::ChildProcess("df".into(), stderr))
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Health of OpenAI compatible endpoints, so that requests rotate among those configured by
+//! [ApiUrl] and skip the ones failing lately.
+//!
+//! An endpoint failing a request is benched for a cooldown doubling with consecutive failures,
+//! and is back once a request to it succeeds. Benched endpoints are still tried last, so requests
+//! go out even when every endpoint is benched.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex, MutexGuard, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::global_state::ApiUrl;
+
+const BASE_COOLDOWN: Duration = Duration::from_secs(30);
+/// Cooldown stops doubling after this many consecutive failures.
+const MAX_DOUBLING: u32 = 5;
+
+#[derive(Debug, Default)]
+struct Health {
+    failures: u32,
+    benched_until: Option<Instant>,
+}
+
+fn health() -> MutexGuard<'static, HashMap<String, Health>> {
+    static HEALTH: OnceLock<Mutex<HashMap<String, Health>>> = OnceLock::new();
+    HEALTH
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Endpoints in the order to try, round robin among healthy ones, benched ones last.
+pub fn order(api_url: &ApiUrl) -> Vec<&str> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    let urls = api_url.urls();
+    if urls.len() <= 1 {
+        return urls.iter().map(String::as_str).collect();
+    }
+    let start = NEXT.fetch_add(1, Ordering::Relaxed) % urls.len();
+    let now = Instant::now();
+    let health = health();
+    let benched = |url: &str| {
+        health
+            .get(url)
+            .and_then(|h| h.benched_until)
+            .is_some_and(|until| now < until)
+    };
+    let rotated = urls[start..]
+        .iter()
+        .chain(&urls[..start])
+        .map(String::as_str);
+    let (mut healthy, benched): (Vec<_>, Vec<_>) = rotated.partition(|url| !benched(url));
+    healthy.extend(benched);
+    healthy
+}
+
+/// Record the result of a request to the endpoint.
+pub fn report(url: &str, ok: bool) {
+    let mut health = health();
+    if ok {
+        health.remove(url);
+        return;
+    }
+    let entry = health.entry(url.to_string()).or_default();
+    entry.failures += 1;
+    let cooldown = BASE_COOLDOWN * 2u32.pow(entry.failures.min(MAX_DOUBLING) - 1);
+    entry.benched_until = Some(Instant::now() + cooldown);
+}
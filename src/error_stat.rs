@@ -0,0 +1,71 @@
+//! Daily aggregation of error logs.
+
+use crate::{
+    exception::PluginResult,
+    std_error, std_info,
+    store::{self, ErrorStat},
+    tr,
+    util::{self, schedule_daily_blocking},
+    CONFIG,
+};
+
+/// Send yesterday's error digest to the main admin every day at the configured hour.
+pub async fn subscribe_digest() {
+    let config = CONFIG.get().unwrap();
+    // no-op if no digest config
+    let Some(ref digest) = config.error_digest else {
+        return;
+    };
+
+    kovi::spawn(async move {
+        schedule_daily_blocking(digest.hour, || async {
+            let day = util::date_iso8601(None, -1);
+            let stats = match store::db_load_error_stat(&day).await {
+                Ok(v) => v,
+                Err(err) => {
+                    std_error!("Load error stat failed: {err}");
+                    return;
+                }
+            };
+            if stats.is_empty() {
+                std_info!("No error on {day}, skip digest.");
+                return;
+            }
+            util::notify_admin(format_stat(&day, &stats));
+        })
+        .await;
+    });
+}
+
+/// Summary of errors happened today, used by the group command.
+pub async fn today_stat() -> PluginResult<String> {
+    let day = util::cur_date_iso8601(None);
+    let stats = store::db_load_error_stat(&day).await?;
+    if stats.is_empty() {
+        return Ok(tr!(None, "error_stat.none", day = day));
+    }
+    Ok(format_stat(&day, &stats))
+}
+
+fn format_stat(day: &str, stats: &[ErrorStat]) -> String {
+    let total: i64 = stats.iter().map(|s| s.count).sum();
+    let mut buf = tr!(None, "error_stat.title", day = day, total = total);
+    buf.push('\n');
+    for stat in stats {
+        // first line of template is descriptive enough
+        let entry = tr!(
+            None,
+            "error_stat.entry",
+            count = stat.count,
+            template = first_line(&stat.template),
+            last = first_line(&stat.last_content),
+        );
+        buf.push_str(&entry);
+        buf.push('\n');
+    }
+    buf
+}
+
+fn first_line(s: &str) -> &str {
+    s.lines().next().unwrap_or_default()
+}
@@ -0,0 +1,337 @@
+//! Plugin-owned view of kovi events.
+//!
+//! Handlers consume these instead of [kovi::MsgEvent] so that they can be driven without a OneBot
+//! connection, see [crate::testing].
+
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
+
+use kovi::{
+    bot::BotInformation,
+    event::{Event, InternalEvent},
+    tokio::sync::mpsc,
+    types::ApiAndOneshot,
+    Message, MsgEvent,
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::util;
+
+const DEDUP_CAPACITY: usize = 4096;
+const DEDUP_WINDOW: Duration = Duration::from_secs(600);
+/// Handlers racing to reply finish within seconds, so fewer claims than events are kept.
+const REPLY_CLAIM_CAPACITY: usize = 1024;
+
+/// Ring buffer of recently seen keys.
+#[derive(Default)]
+struct SeenEvents {
+    /// Oldest first.
+    order: VecDeque<(Instant, String)>,
+    keys: HashSet<String>,
+}
+
+impl SeenEvents {
+    /// Returns false if the key is seen within [DEDUP_WINDOW]. Otherwise records it, dropping the
+    /// oldest beyond capacity, and returns true.
+    fn insert(&mut self, key: String, capacity: usize) -> bool {
+        let now = Instant::now();
+        while let Some((at, _)) = self.order.front() {
+            if now.duration_since(*at) < DEDUP_WINDOW && self.order.len() < capacity {
+                break;
+            }
+            let (_, key) = self.order.pop_front().unwrap();
+            self.keys.remove(&key);
+        }
+        if !self.keys.insert(key.clone()) {
+            return false;
+        }
+        self.order.push_back((now, key));
+        true
+    }
+}
+
+fn lock(seen: &'static OnceLock<Mutex<SeenEvents>>) -> MutexGuard<'static, SeenEvents> {
+    seen.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns false if the key is seen within a window, e.g. an event replayed after OneBot
+/// reconnects. Otherwise records it and returns true.
+pub fn first_seen(key: String) -> bool {
+    static SEEN: OnceLock<Mutex<SeenEvents>> = OnceLock::new();
+    lock(&SEEN).insert(key, DEDUP_CAPACITY)
+}
+
+/// Identity of a raw notice by type, ids and time.
+pub fn notice_key(json: &Value) -> String {
+    let fields = [
+        "notice_type",
+        "sub_type",
+        "group_id",
+        "user_id",
+        "operator_id",
+        "target_id",
+        "message_id",
+        "time",
+    ];
+    let values: Vec<String> = fields.iter().map(|f| json[f].to_string()).collect();
+    values.join(":")
+}
+
+/// A message received in group chat.
+#[derive(Debug, Clone)]
+pub struct GroupMsg {
+    pub group_id: i64,
+    pub message_id: i32,
+    /// Unix timestamp.
+    pub time: i64,
+    pub sender_id: i64,
+    pub message: Message,
+    pub text: Option<String>,
+}
+
+impl GroupMsg {
+    /// Returns None if the event is not from a group.
+    pub fn from_event(e: &MsgEvent) -> Option<Self> {
+        Some(Self {
+            group_id: e.group_id?,
+            message_id: e.message_id,
+            time: e.time,
+            sender_id: e.sender.user_id,
+            message: e.message.clone(),
+            text: e.borrow_text().map(str::to_string),
+        })
+    }
+
+    pub fn borrow_text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn dedup_key(&self) -> String {
+        format!("group:{}:{}:{}", self.group_id, self.message_id, self.time)
+    }
+
+    /// Returns true only for the first handler about to reply, so that a message triggering e.g.
+    /// both a keyword rule and the agent is answered once.
+    pub fn claim_reply(&self) -> bool {
+        // kept apart from [first_seen], claims must not push out keys of events
+        static CLAIMED: OnceLock<Mutex<SeenEvents>> = OnceLock::new();
+        lock(&CLAIMED).insert(self.dedup_key(), REPLY_CLAIM_CAPACITY)
+    }
+
+    /// Send to the group where message comes from, and write it to history.
+    pub async fn reply<T>(&self, msg: T)
+    where
+        Message: From<T>,
+        T: Serialize,
+    {
+        util::send_group_and_log(self.group_id, Message::from(msg)).await;
+    }
+
+    /// Send to the group where message comes from quoting the message, and write it to history.
+    pub async fn reply_and_quote<T>(&self, msg: T)
+    where
+        Message: From<T>,
+        T: Serialize,
+    {
+        let mut message = Message::new().add_reply(self.message_id);
+        for seg in Message::from(msg).iter() {
+            message.push(seg.clone());
+        }
+        util::send_group_and_log(self.group_id, message).await;
+    }
+}
+
+/// A message received in private chat.
+#[derive(Debug, Clone)]
+pub struct PrivateMsg {
+    pub user_id: i64,
+    /// "friend" for friends, "group" for temporary session from group.
+    pub sub_type: String,
+    /// Group the temporary session is started from, if the implementation reports it.
+    pub source_group: Option<i64>,
+    pub sender_name: String,
+    pub message_id: i32,
+    /// Unix timestamp.
+    pub time: i64,
+    pub message: Message,
+    pub text: Option<String>,
+}
+
+impl PrivateMsg {
+    /// Returns None if the event is not private.
+    pub fn from_event(e: &MsgEvent) -> Option<Self> {
+        if e.group_id.is_some() {
+            return None;
+        }
+        let sender_name = e
+            .sender
+            .nickname
+            .clone()
+            .filter(|x| !x.is_empty())
+            .unwrap_or_else(|| e.sender.user_id.to_string());
+        Some(Self {
+            user_id: e.sender.user_id,
+            sub_type: e.sub_type.clone(),
+            source_group: e.original_json["sender"]["group_id"].as_i64(),
+            sender_name,
+            message_id: e.message_id,
+            time: e.time,
+            message: e.message.clone(),
+            text: e.borrow_text().map(str::to_string),
+        })
+    }
+
+    pub fn borrow_text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn dedup_key(&self) -> String {
+        format!("private:{}:{}:{}", self.user_id, self.message_id, self.time)
+    }
+
+    /// Send to the user where message comes from.
+    pub fn reply<T>(&self, msg: T)
+    where
+        Message: From<T>,
+        T: Serialize,
+    {
+        util::send_private_msg(self.user_id, msg);
+    }
+}
+
+/// A message received in a guild (频道) channel, not delivered by kovi as [MsgEvent] since its
+/// ids are strings.
+#[derive(Debug, Clone)]
+pub struct GuildMsg {
+    pub guild_id: String,
+    pub channel_id: String,
+    pub message_id: String,
+    /// Unix timestamp.
+    pub time: i64,
+    /// Tiny id of the sender in guilds, unrelated to QQ numbers.
+    pub sender_id: i64,
+    pub sender_name: String,
+    /// Tiny id of the bot, which is what at segments to the bot carry.
+    pub self_tiny_id: String,
+    pub message: Message,
+    pub text: Option<String>,
+}
+
+impl GuildMsg {
+    /// Returns None if the raw event is not a guild message.
+    pub fn from_json(json: &Value) -> Option<Self> {
+        if json["post_type"] != "message" || json["message_type"] != "guild" {
+            return None;
+        }
+        // implementations differ in serializing ids as strings or numbers
+        let id = |v: &Value| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        };
+        let sender = &json["sender"];
+        let sender_id: i64 = id(&sender["tiny_id"])
+            .or_else(|| id(&json["user_id"]))?
+            .parse()
+            .ok()?;
+        let sender_name = sender["nickname"]
+            .as_str()
+            .filter(|x| !x.is_empty())
+            .map_or_else(|| sender_id.to_string(), str::to_string);
+        let message: Message = serde_json::from_value(json["message"].clone()).ok()?;
+        let text: String = message
+            .iter()
+            .filter(|seg| seg.type_ == "text")
+            .filter_map(|seg| seg.data["text"].as_str())
+            .collect();
+        Some(Self {
+            guild_id: id(&json["guild_id"])?,
+            channel_id: id(&json["channel_id"])?,
+            message_id: id(&json["message_id"])?,
+            time: json["time"].as_i64()?,
+            sender_id,
+            sender_name,
+            self_tiny_id: id(&json["self_tiny_id"]).unwrap_or_default(),
+            message,
+            text: Some(text).filter(|t| !t.is_empty()),
+        })
+    }
+
+    pub fn borrow_text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn dedup_key(&self) -> String {
+        format!(
+            "guild:{}:{}:{}",
+            self.guild_id, self.channel_id, self.message_id
+        )
+    }
+
+    pub fn is_at_me(&self) -> bool {
+        !self.self_tiny_id.is_empty()
+            && self
+                .message
+                .get("at")
+                .iter()
+                .any(|seg| match &seg.data["qq"] {
+                    Value::String(s) => *s == self.self_tiny_id,
+                    Value::Number(n) => n.to_string() == self.self_tiny_id,
+                    _ => false,
+                })
+    }
+}
+
+/// Raw events are offered to every handler registered by [kovi::PluginBuilder::on].
+impl Event for GuildMsg {
+    fn de(
+        event: &InternalEvent,
+        _bot_info: &BotInformation,
+        _api_tx: &mpsc::Sender<ApiAndOneshot>,
+    ) -> Option<Self> {
+        match event {
+            InternalEvent::OneBotEvent(json) => Self::from_json(json),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen_events_capacity() {
+        let mut seen = SeenEvents::default();
+        assert!(seen.insert(String::from("a"), 2));
+        assert!(!seen.insert(String::from("a"), 2));
+        assert!(seen.insert(String::from("b"), 2));
+        // the oldest is dropped beyond capacity
+        assert!(seen.insert(String::from("c"), 2));
+        assert!(seen.insert(String::from("a"), 2));
+    }
+
+    #[test]
+    fn test_claim_reply_apart_from_events() {
+        let msg = |message_id: i32| GroupMsg {
+            group_id: -1,
+            message_id,
+            time: 0,
+            sender_id: 42,
+            message: Message::new(),
+            text: None,
+        };
+        assert!(first_seen(msg(0).dedup_key()));
+        for message_id in 0..DEDUP_CAPACITY as i32 {
+            assert!(msg(message_id).claim_reply());
+        }
+        // claims did not push out the event
+        assert!(!first_seen(msg(0).dedup_key()));
+    }
+}
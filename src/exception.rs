@@ -14,8 +14,18 @@ pub enum PluginError {
     TimeFormat(#[from] time::error::Format),
     #[error("Reqwest error: {0}.")]
     HttpRequest(#[from] reqwest::Error),
+    #[error("Json error: {0}.")]
+    Json(#[from] serde_json::Error),
     #[error("Regex error: {0}.")]
     Regex(#[from] regex::Error),
+    #[error("Feed parse error: {0}.")]
+    FeedParse(String),
+    #[error("Image error: {0}.")]
+    Image(#[from] image::ImageError),
+    #[error("Font error: {0}.")]
+    Font(#[from] ab_glyph::InvalidFont),
+    #[error("Bilibili rejected request: {0}.")]
+    BilibiliRejected(String),
     #[error("Agent request error: {0}.")]
     AgentRequest(String),
     #[error("Serialize to toml failed, cause: {0}")]
@@ -28,6 +38,11 @@ pub enum PluginError {
     ChildProcess(String, String),
     #[error("Initialize global state failed, cause: {0}")]
     InitGlobalState(String),
+    #[error("OneBot api error: {0}.")]
+    OneBotApi(String),
+    #[cfg(feature = "chaos")]
+    #[error("Chaos failure injected at {0}.")]
+    Chaos(&'static str),
     #[error("Trap to logically unreachable control.")]
     Unreachable,
 }
@@ -0,0 +1,385 @@
+//! RSS/Atom feed subscription per group.
+
+use std::{sync::Arc, time::Duration};
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    Reader,
+};
+use time::OffsetDateTime;
+
+use crate::{
+    event::GroupMsg,
+    exception::{PluginError, PluginResult},
+    global_state::FeedSetting,
+    http::Request,
+    std_db_error, std_error, std_info,
+    store::{self, FeedSubscription},
+    tr,
+    util::{self, schedule_task_blocking},
+    CONFIG,
+};
+
+const SWEEP_INTERVAL_SEC: u64 = 60;
+/// Older unseen entries are dropped silently to avoid flooding the group.
+const MAX_NOTIFY_PER_POLL: usize = 5;
+
+#[derive(Debug, Default)]
+pub struct Feed {
+    pub title: String,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug, Default)]
+pub struct Entry {
+    /// `guid` of RSS or `id` of Atom, falls back to link and title.
+    pub id: String,
+    pub title: String,
+    pub link: String,
+}
+
+/// Parse RSS 2.0, RSS 1.0 or Atom, entries keep the order in document.
+pub fn parse_feed(xml: &str) -> PluginResult<Feed> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+    let mut feed = Feed::default();
+    let mut entry: Option<Entry> = None;
+    let mut path: Vec<String> = Vec::new();
+    let parse_err = |err: quick_xml::Error| PluginError::FeedParse(err.to_string());
+    loop {
+        match reader.read_event().map_err(parse_err)? {
+            Event::Start(tag) => {
+                let name = local_name(&tag);
+                match name.as_str() {
+                    "item" | "entry" => entry = Some(Entry::default()),
+                    "link" => set_link_href(&tag, entry.as_mut()),
+                    _ => {}
+                }
+                path.push(name);
+            }
+            Event::Empty(tag) if local_name(&tag) == "link" => {
+                set_link_href(&tag, entry.as_mut());
+            }
+            Event::End(_) => {
+                let name = path.pop().unwrap_or_default();
+                if name == "item" || name == "entry" {
+                    if let Some(mut e) = entry.take() {
+                        if e.id.is_empty() {
+                            e.id = if e.link.is_empty() {
+                                e.title.clone()
+                            } else {
+                                e.link.clone()
+                            };
+                        }
+                        if !e.id.is_empty() {
+                            feed.entries.push(e);
+                        }
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(parse_err)?;
+                assign_text(&path, &mut feed, entry.as_mut(), &text);
+            }
+            Event::CData(data) => {
+                let text = String::from_utf8_lossy(&data.into_inner()).to_string();
+                assign_text(&path, &mut feed, entry.as_mut(), &text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if feed.title.is_empty() && feed.entries.is_empty() {
+        return Err(PluginError::FeedParse(String::from("not a feed")));
+    }
+    Ok(feed)
+}
+
+fn local_name(tag: &BytesStart) -> String {
+    String::from_utf8_lossy(tag.local_name().as_ref()).to_string()
+}
+
+/// Atom puts link in `href`, only the alternate one is taken.
+fn set_link_href(tag: &BytesStart, entry: Option<&mut Entry>) {
+    let Some(entry) = entry else {
+        return;
+    };
+    if !entry.link.is_empty() {
+        return;
+    }
+    let attr = |key: &str| {
+        tag.try_get_attribute(key)
+            .ok()
+            .flatten()
+            .and_then(|a| a.unescape_value().ok())
+            .map(|v| v.to_string())
+    };
+    if attr("rel").is_some_and(|rel| rel != "alternate") {
+        return;
+    }
+    if let Some(href) = attr("href") {
+        entry.link = href;
+    }
+}
+
+fn assign_text(path: &[String], feed: &mut Feed, entry: Option<&mut Entry>, text: &str) {
+    let Some(current) = path.last() else {
+        return;
+    };
+    match entry {
+        Some(entry) => {
+            let field = match current.as_str() {
+                "title" => &mut entry.title,
+                "link" => &mut entry.link,
+                "guid" | "id" => &mut entry.id,
+                _ => return,
+            };
+            field.push_str(text);
+        }
+        None => {
+            let parent = path.len().checked_sub(2).map(|i| path[i].as_str());
+            if current == "title" && matches!(parent, Some("channel" | "feed")) {
+                feed.title.push_str(text);
+            }
+        }
+    }
+}
+
+pub(crate) async fn fetch_feed(url: &str) -> PluginResult<Feed> {
+    let resp = Request::get(url)
+        .timeout(Duration::from_secs(20))
+        .send()
+        .await?;
+    parse_feed(&resp.text())
+}
+
+/// Subscribe, unsubscribe and list feeds of the group, command admins only.
+pub async fn feed_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no feed config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref feed) = group.feed else {
+        return;
+    };
+    // no-op if not command admin
+    let Some(ref command) = group.command else {
+        return;
+    };
+    if !command.is_admin(group_id, e.sender_id).await {
+        return;
+    }
+
+    let text = text.trim();
+    if let Some(url) = text.strip_prefix(&feed.unsubscribe) {
+        unsubscribe(&e, url.trim()).await;
+    } else if text == feed.list {
+        list(&e).await;
+    } else if let Some(args) = text.strip_prefix(&feed.subscribe) {
+        subscribe(&e, feed, args.trim()).await;
+    }
+}
+
+/// Arguments are `<url> [interval in minutes]`, entries existing at the moment are not notified.
+async fn subscribe(e: &GroupMsg, setting: &FeedSetting, args: &str) {
+    let group_id = e.group_id;
+    let mut args = args.split_whitespace();
+    let Some(url) = args.next() else {
+        e.reply(tr!(group_id, "feed.usage", command = setting.subscribe))
+            .await;
+        return;
+    };
+    let interval_sec = match args.next().map(str::parse::<i64>) {
+        None => setting.poll_interval_sec,
+        Some(Ok(min)) if min > 0 => min * 60,
+        Some(_) => {
+            e.reply(tr!(group_id, "feed.invalid_interval")).await;
+            return;
+        }
+    };
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        e.reply(tr!(group_id, "feed.invalid_url")).await;
+        return;
+    }
+    let subs = match store::db_load_feed_subscriptions(group_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load feed subscriptions failed: {err}");
+            return;
+        }
+    };
+    if subs.len() as i64 >= setting.max_feeds {
+        e.reply(tr!(group_id, "feed.max_feeds", max = setting.max_feeds))
+            .await;
+        return;
+    }
+
+    let feed = match fetch_feed(url).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Fetch feed {url} failed: {err}");
+            e.reply(tr!(group_id, "feed.fetch_failed", url = url)).await;
+            return;
+        }
+    };
+    let title = if feed.title.is_empty() {
+        url
+    } else {
+        feed.title.as_str()
+    };
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    match store::db_add_feed_subscription(group_id, url, title, interval_sec, now + interval_sec)
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            e.reply(tr!(group_id, "feed.already_subscribed", title = title))
+                .await;
+            return;
+        }
+        Err(err) => {
+            std_db_error!("Add feed subscription failed: {err}");
+            return;
+        }
+    }
+    for entry in &feed.entries {
+        if let Err(err) = store::db_mark_feed_entry(group_id, url, &entry.id).await {
+            std_db_error!("Mark feed entry failed: {err}");
+        }
+    }
+    e.reply(tr!(
+        group_id,
+        "feed.subscribed",
+        title = title,
+        minutes = interval_sec / 60,
+    ))
+    .await;
+}
+
+async fn unsubscribe(e: &GroupMsg, url: &str) {
+    match store::db_remove_feed_subscription(e.group_id, url).await {
+        Ok(true) => {
+            e.reply(tr!(e.group_id, "feed.unsubscribed", url = url))
+                .await
+        }
+        Ok(false) => {
+            e.reply(tr!(e.group_id, "feed.not_subscribed", url = url))
+                .await
+        }
+        Err(err) => std_db_error!("Remove feed subscription failed: {err}"),
+    }
+}
+
+async fn list(e: &GroupMsg) {
+    let subs = match store::db_load_feed_subscriptions(e.group_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load feed subscriptions failed: {err}");
+            return;
+        }
+    };
+    if subs.is_empty() {
+        e.reply(tr!(e.group_id, "feed.empty")).await;
+        return;
+    }
+    let lines: Vec<String> = subs
+        .iter()
+        .map(|sub| {
+            tr!(
+                e.group_id,
+                "feed.entry",
+                title = sub.title,
+                minutes = sub.interval_sec / 60,
+                url = sub.url,
+            )
+        })
+        .collect();
+    e.reply(lines.join("\n")).await;
+}
+
+/// Sweep subscriptions every minute, each feed is polled on its own interval.
+pub async fn subscribe_feeds() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    if groups.iter().all(|g| g.feed.is_none()) {
+        return;
+    }
+    kovi::spawn(async {
+        let duration = Duration::from_secs(SWEEP_INTERVAL_SEC);
+        schedule_task_blocking(duration, || {
+            poll_due(OffsetDateTime::now_utc().unix_timestamp())
+        })
+        .await;
+    });
+}
+
+/// Poll feeds due at `now`, in unix timestamp, and notify groups of unseen entries.
+pub async fn poll_due(now: i64) {
+    let due = match store::db_load_due_feeds(now).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load due feeds failed: {err}");
+            return;
+        }
+    };
+    let config = CONFIG.get().unwrap();
+    for sub in due {
+        let next_poll = now + sub.interval_sec;
+        if let Err(err) = store::db_set_feed_next_poll(sub.group_id, &sub.url, next_poll).await {
+            std_db_error!("Update feed next poll failed: {err}");
+        }
+        // subscriptions are kept if feed config is removed later
+        let setting = config
+            .groups
+            .iter()
+            .flatten()
+            .find(|g| g.id == sub.group_id)
+            .and_then(|g| g.feed.as_ref());
+        let Some(setting) = setting else {
+            continue;
+        };
+        let feed = match fetch_feed(&sub.url).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Fetch feed {} failed: {err}", sub.url);
+                continue;
+            }
+        };
+        notify_unseen(&sub, setting, &feed).await;
+    }
+}
+
+async fn notify_unseen(sub: &FeedSubscription, setting: &FeedSetting, feed: &Feed) {
+    let mut unseen = Vec::new();
+    for entry in &feed.entries {
+        match store::db_mark_feed_entry(sub.group_id, &sub.url, &entry.id).await {
+            Ok(true) => unseen.push(entry),
+            Ok(false) => {}
+            Err(err) => std_db_error!("Mark feed entry failed: {err}"),
+        }
+    }
+    if unseen.is_empty() {
+        return;
+    }
+    std_info!("Feed {} has {} new entries", sub.url, unseen.len());
+    // feeds list newest first, notify the latest few in chronological order
+    unseen.truncate(MAX_NOTIFY_PER_POLL);
+    for entry in unseen.into_iter().rev() {
+        let message = setting
+            .template
+            .replace("<!feed!>", &sub.title)
+            .replace("<!title!>", &entry.title)
+            .replace("<!link!>", &entry.link);
+        util::send_group_msg(sub.group_id, message);
+    }
+}
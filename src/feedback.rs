@@ -0,0 +1,77 @@
+//! Members rate answers of the group agent by replying `反馈 好` or `反馈 差` to them.
+//!
+//! Answers are recorded with the persona and model that produced them, so the report tells which
+//! of them performs poorly.
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg, exception::PluginResult, global_state::FeedbackSetting, std_db_error, store,
+    tr, util, CONFIG,
+};
+
+fn setting_of(group_id: i64) -> Option<&'static FeedbackSetting> {
+    let config = CONFIG.get().unwrap();
+    let groups = config.groups.as_ref()?;
+    let group = groups.iter().find(|g| g.id == group_id)?;
+    group.feedback.as_ref()
+}
+
+pub async fn feedback_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(setting) = setting_of(group_id) else {
+        return;
+    };
+    let Some(rest) = e
+        .borrow_text()
+        .and_then(|t| t.trim().strip_prefix(setting.trigger.as_str()))
+    else {
+        return;
+    };
+    let good = match rest.trim() {
+        r if r == setting.good => true,
+        r if r == setting.bad => false,
+        _ => return,
+    };
+    let Some(reply_id) = util::reply_id(&e.message) else {
+        e.reply_and_quote(tr!(
+            group_id,
+            "feedback.usage",
+            trigger = setting.trigger,
+            good = setting.good,
+            bad = setting.bad,
+        ))
+        .await;
+        return;
+    };
+    let now = util::cur_time_iso8601(Some(group_id));
+    match store::db_add_feedback(group_id, reply_id, e.sender_id, good, &now).await {
+        Ok(true) => e.reply_and_quote(tr!(group_id, "feedback.thanks")).await,
+        Ok(false) => {
+            e.reply_and_quote(tr!(group_id, "feedback.not_agent_reply"))
+                .await
+        }
+        Err(err) => std_db_error!("Record feedback failed: {err}"),
+    }
+}
+
+/// Ratings of answers in the group per persona and model, worst first.
+pub async fn report(group_id: i64) -> PluginResult<String> {
+    let stats = store::db_feedback_stats(group_id).await?;
+    if stats.is_empty() {
+        return Ok(tr!(group_id, "feedback.empty"));
+    }
+    let mut lines = vec![tr!(group_id, "feedback.title")];
+    for stat in stats {
+        lines.push(tr!(
+            group_id,
+            "feedback.entry",
+            persona = stat.persona,
+            model = stat.model,
+            replies = stat.replies,
+            good = stat.good,
+            bad = stat.bad,
+        ));
+    }
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,240 @@
+//! Idiom solitaire (成语接龙), the next idiom starts with the last character of the current one.
+//!
+//! Game state and scores are persisted, so that a game survives restarts.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, OnceLock},
+};
+
+use kovi::{tokio::sync::Mutex, Message};
+use rand::{seq::SliceRandom, thread_rng};
+
+use super::Scoreboard;
+use crate::{
+    agent,
+    event::GroupMsg,
+    exception::PluginResult,
+    global_state::{GroupSetting, IdiomSetting},
+    std_db_error, std_error,
+    store::{self, IdiomGame},
+    tr, util, DATA_PATH,
+};
+
+const BUNDLED_DICTIONARY: &str = include_str!("../../assets/idioms.txt");
+
+// moves are serialized so that two players cannot take the same turn
+static MOVE_LOCK: Mutex<()> = Mutex::const_new(());
+
+struct Dictionary {
+    idioms: HashSet<String>,
+    by_first: HashMap<char, Vec<String>>,
+}
+
+impl Dictionary {
+    fn parse(content: &str) -> Self {
+        let mut idioms = HashSet::new();
+        let mut by_first: HashMap<char, Vec<String>> = HashMap::new();
+        for idiom in content.lines().map(str::trim).filter(|x| !x.is_empty()) {
+            if !idioms.insert(idiom.to_string()) {
+                continue;
+            }
+            if let Some(first) = idiom.chars().next() {
+                by_first.entry(first).or_default().push(idiom.to_string());
+            }
+        }
+        Self { idioms, by_first }
+    }
+
+    /// Idioms following the given one that have not been used.
+    fn candidates<'a>(&'a self, idiom: &str, used: &'a str) -> impl Iterator<Item = &'a String> {
+        let list = idiom
+            .chars()
+            .last()
+            .and_then(|last| self.by_first.get(&last));
+        list.into_iter()
+            .flatten()
+            .filter(move |x| !used.split(',').any(|u| u == x.as_str()))
+    }
+}
+
+/// Loaded once, the configured file of the first game wins over the bundled dictionary.
+fn dictionary(setting: &IdiomSetting) -> &'static Dictionary {
+    static DICTIONARY: OnceLock<Dictionary> = OnceLock::new();
+    DICTIONARY.get_or_init(|| {
+        let custom = setting.dictionary_file.as_ref().and_then(|file| {
+            let path = DATA_PATH.get().unwrap().join(file);
+            match std::fs::read_to_string(path) {
+                Ok(content) => Some(content),
+                Err(err) => {
+                    std_error!("Read idiom dictionary failed, fallback to bundled: {err}");
+                    None
+                }
+            }
+        });
+        Dictionary::parse(custom.as_deref().unwrap_or(BUNDLED_DICTIONARY))
+    })
+}
+
+fn last_char(idiom: &str) -> char {
+    idiom.chars().last().unwrap_or_default()
+}
+
+pub async fn idiom_handler(e: Arc<GroupMsg>, group: &GroupSetting) {
+    let Some(ref setting) = group.idiom else {
+        return;
+    };
+    let Some(text) = e.borrow_text().map(str::trim) else {
+        return;
+    };
+    let res = if text == setting.start {
+        start(&e, setting).await
+    } else if text == setting.stop {
+        stop(e.group_id).await
+    } else if text == setting.hint {
+        hint(&e, group, setting).await
+    } else if dictionary(setting).idioms.contains(text) {
+        play(&e, setting, text).await
+    } else {
+        Ok(())
+    };
+    if let Err(err) = res {
+        std_db_error!("Idiom game failed: {err}");
+    }
+}
+
+async fn start(e: &GroupMsg, setting: &IdiomSetting) -> PluginResult<()> {
+    let _guard = MOVE_LOCK.lock().await;
+    if let Some(game) = store::db_load_idiom_game(e.group_id).await? {
+        let msg = tr!(e.group_id, "idiom.running", current = game.current);
+        e.reply(msg).await;
+        return Ok(());
+    }
+    let dictionary = dictionary(setting);
+    // start from an idiom that can be followed
+    let starters: Vec<&String> = dictionary
+        .idioms
+        .iter()
+        .filter(|x| dictionary.candidates(x, x).next().is_some())
+        .collect();
+    let Some(&current) = starters.choose(&mut thread_rng()) else {
+        e.reply(tr!(e.group_id, "idiom.empty_dictionary")).await;
+        return Ok(());
+    };
+    let game = IdiomGame {
+        group_id: e.group_id,
+        current: current.clone(),
+        used: current.clone(),
+        last_player: 0,
+    };
+    store::db_delete_idiom_game(e.group_id).await?;
+    store::db_save_idiom_game(&game).await?;
+    let msg = tr!(
+        e.group_id,
+        "idiom.started",
+        current = current,
+        next = last_char(current),
+    );
+    util::send_group_and_log(e.group_id, msg).await;
+    Ok(())
+}
+
+async fn stop(group_id: i64) -> PluginResult<()> {
+    let _guard = MOVE_LOCK.lock().await;
+    if store::db_load_idiom_game(group_id).await?.is_none() {
+        return Ok(());
+    }
+    finish(group_id, &tr!(group_id, "idiom.stopped")).await
+}
+
+/// Announce scores and delete the game.
+async fn finish(group_id: i64, reason: &str) -> PluginResult<()> {
+    let scores = store::db_load_idiom_scores(group_id).await?;
+    store::db_delete_idiom_game(group_id).await?;
+    let mut scoreboard = Scoreboard::default();
+    for (user_id, score) in scores {
+        scoreboard.add(user_id, score);
+    }
+    let board = scoreboard.render(group_id).await;
+    let msg = if board.is_empty() {
+        tr!(group_id, "idiom.finished_empty", reason = reason)
+    } else {
+        tr!(group_id, "idiom.finished", reason = reason, board = board)
+    };
+    util::send_group_and_log(group_id, msg).await;
+    Ok(())
+}
+
+async fn play(e: &GroupMsg, setting: &IdiomSetting, idiom: &str) -> PluginResult<()> {
+    let _guard = MOVE_LOCK.lock().await;
+    let Some(mut game) = store::db_load_idiom_game(e.group_id).await? else {
+        return Ok(());
+    };
+    let expected = last_char(&game.current);
+    if !idiom.starts_with(expected) {
+        e.reply_and_quote(tr!(e.group_id, "idiom.wrong_start", next = expected))
+            .await;
+        return Ok(());
+    }
+    if game.used.split(',').any(|x| x == idiom) {
+        e.reply_and_quote(tr!(e.group_id, "idiom.used", idiom = idiom))
+            .await;
+        return Ok(());
+    }
+    if game.last_player == e.sender_id {
+        e.reply_and_quote(tr!(e.group_id, "idiom.same_player"))
+            .await;
+        return Ok(());
+    }
+
+    game.current = idiom.to_string();
+    game.used = format!("{},{idiom}", game.used);
+    game.last_player = e.sender_id;
+    store::db_save_idiom_game(&game).await?;
+    store::db_add_idiom_score(e.group_id, e.sender_id).await?;
+
+    let has_next = dictionary(setting)
+        .candidates(&game.current, &game.used)
+        .next()
+        .is_some();
+    if !has_next {
+        return finish(e.group_id, &tr!(e.group_id, "idiom.no_candidate")).await;
+    }
+    let msg = Message::new()
+        .add_at(&e.sender_id.to_string())
+        .add_text(tr!(e.group_id, "idiom.accepted", next = last_char(idiom)));
+    util::send_group_and_log(e.group_id, msg).await;
+    Ok(())
+}
+
+/// Hint by agent of the group, fallback to number of candidates.
+async fn hint(e: &GroupMsg, group: &GroupSetting, setting: &IdiomSetting) -> PluginResult<()> {
+    let Some(game) = store::db_load_idiom_game(e.group_id).await? else {
+        return Ok(());
+    };
+    let candidates: Vec<&String> = dictionary(setting)
+        .candidates(&game.current, &game.used)
+        .collect();
+    let Some(&answer) = candidates.choose(&mut thread_rng()) else {
+        return Ok(());
+    };
+    if let Some(ref agent) = group.agent {
+        let dev_prompt = "You give a short hint in Chinese for an idiom without saying it or any \
+                          of its characters.";
+        let model = agent.get_model().await;
+        let tip = agent::complete(&agent.api_url, &agent.api_key, &model, dev_prompt, answer).await;
+        if let Some(tip) = tip {
+            e.reply(tr!(e.group_id, "idiom.hint", tip = tip)).await;
+            return Ok(());
+        }
+    }
+    e.reply(tr!(
+        e.group_id,
+        "idiom.hint_count",
+        count = candidates.len(),
+        next = last_char(&game.current),
+    ))
+    .await;
+
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! Multi-round group games.
+//!
+//! Game states live in memory, timeouts are driven by a shared ticker instead of a timer per
+//! round.
+
+use std::{
+    cmp::Reverse,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{event::GroupMsg, util, CONFIG};
+
+pub mod idiom;
+pub mod quiz;
+
+const TICK_INTERVAL_SEC: u64 = 1;
+
+pub async fn game_handler(e: Arc<GroupMsg>) {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == e.group_id) else {
+        return;
+    };
+    quiz::quiz_handler(Arc::clone(&e), group).await;
+    idiom::idiom_handler(e, group).await;
+}
+
+/// Spawn the ticker, no-op if no group has a game configured.
+pub async fn subscribe_ticker() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    if groups.iter().all(|g| g.quiz.is_none()) {
+        return;
+    }
+    kovi::spawn(async {
+        let duration = Duration::from_secs(TICK_INTERVAL_SEC);
+        util::schedule_task_blocking(duration, || tick(Instant::now())).await;
+    });
+}
+
+/// Time out rounds whose deadline is before `now`.
+pub async fn tick(now: Instant) {
+    quiz::tick(now).await;
+}
+
+/// Points of players in a game.
+#[derive(Debug, Default, Clone)]
+pub struct Scoreboard(Vec<(i64, i64)>);
+
+impl Scoreboard {
+    pub fn add(&mut self, user_id: i64, points: i64) {
+        match self.0.iter_mut().find(|(id, _)| *id == user_id) {
+            Some((_, total)) => *total += points,
+            None => self.0.push((user_id, points)),
+        }
+    }
+
+    /// Lines of "rank. name: points", highest first, ties ordered by who scored first.
+    pub async fn render(&self, group_id: i64) -> String {
+        let mut ranks = self.0.clone();
+        ranks.sort_by_key(|&(_, points)| Reverse(points));
+        let mut lines = Vec::with_capacity(ranks.len());
+        for (idx, (user_id, points)) in ranks.iter().enumerate() {
+            let name = util::get_name_in_group(group_id, *user_id).await;
+            lines.push(format!("{}. {name}: {points}", idx + 1));
+        }
+        lines.join("\n")
+    }
+}
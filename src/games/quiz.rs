@@ -0,0 +1,284 @@
+//! Quiz game, questions come from a file or are generated by agent.
+//!
+//! A round ends when someone answers correctly or times out, the first correct answer scores.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
+
+use kovi::Message;
+use rand::{seq::SliceRandom, thread_rng};
+use serde::Deserialize;
+
+use super::Scoreboard;
+use crate::{
+    agent,
+    event::GroupMsg,
+    global_state::{GroupSetting, QuizSetting},
+    std_error, std_info, tr, util, DATA_PATH,
+};
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Question {
+    pub question: String,
+    pub answers: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct QuestionFile {
+    questions: Vec<Question>,
+}
+
+#[derive(Debug)]
+struct Quiz {
+    questions: Vec<Question>,
+    round: usize,
+    deadline: Instant,
+    round_timeout: Duration,
+    scoreboard: Scoreboard,
+}
+
+impl Quiz {
+    fn current(&self) -> &Question {
+        &self.questions[self.round]
+    }
+
+    /// Returns false if there is no more question.
+    fn next_round(&mut self) -> bool {
+        self.round += 1;
+        self.deadline = Instant::now() + self.round_timeout;
+        self.round < self.questions.len()
+    }
+
+    fn ask(&self, group_id: i64) -> String {
+        tr!(
+            group_id,
+            "quiz.ask",
+            round = self.round + 1,
+            question = self.current().question,
+        )
+    }
+}
+
+/// Messages to send and games finished, collected under lock and sent after it is released.
+#[derive(Default)]
+struct Outcome {
+    messages: Vec<(i64, Message)>,
+    finished: Vec<(i64, Scoreboard)>,
+}
+
+impl Outcome {
+    async fn send(self) {
+        for (group_id, message) in self.messages {
+            util::send_group_and_log(group_id, message).await;
+        }
+        for (group_id, scoreboard) in self.finished {
+            let board = scoreboard.render(group_id).await;
+            let msg = if board.is_empty() {
+                tr!(group_id, "quiz.finished_empty")
+            } else {
+                tr!(group_id, "quiz.finished", board = board)
+            };
+            util::send_group_and_log(group_id, msg).await;
+        }
+    }
+}
+
+fn quizzes() -> MutexGuard<'static, HashMap<i64, Quiz>> {
+    static QUIZZES: OnceLock<Mutex<HashMap<i64, Quiz>>> = OnceLock::new();
+    QUIZZES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+pub async fn quiz_handler(e: Arc<GroupMsg>, group: &GroupSetting) {
+    let Some(ref quiz) = group.quiz else {
+        return;
+    };
+    let Some(text) = e.borrow_text().map(str::trim) else {
+        return;
+    };
+    if text == quiz.start {
+        start(&e, group, quiz).await;
+    } else if text == quiz.stop {
+        stop(e.group_id).await;
+    } else {
+        answer(&e, text).await;
+    }
+}
+
+async fn start(e: &GroupMsg, group: &GroupSetting, setting: &QuizSetting) {
+    let group_id = e.group_id;
+    if quizzes().contains_key(&group_id) {
+        e.reply(tr!(group_id, "quiz.running")).await;
+        return;
+    }
+    let mut questions = match setting.question_file {
+        Some(ref file) => load_questions(file).await,
+        None => vec![],
+    };
+    if questions.is_empty() {
+        questions = generate_questions(group, setting.rounds).await;
+    }
+    questions.shuffle(&mut thread_rng());
+    questions.truncate(setting.rounds);
+    if questions.is_empty() {
+        e.reply(tr!(group_id, "quiz.no_question")).await;
+        return;
+    }
+
+    let round_timeout = Duration::from_secs(setting.round_timeout_sec);
+    let quiz = Quiz {
+        questions,
+        round: 0,
+        deadline: Instant::now() + round_timeout,
+        round_timeout,
+        scoreboard: Scoreboard::default(),
+    };
+    let msg = tr!(
+        group_id,
+        "quiz.started",
+        count = quiz.questions.len(),
+        seconds = setting.round_timeout_sec,
+        question = quiz.ask(group_id),
+    );
+    {
+        let mut quizzes = quizzes();
+        // started by someone else while loading questions
+        if quizzes.contains_key(&group_id) {
+            return;
+        }
+        quizzes.insert(group_id, quiz);
+    }
+    util::send_group_and_log(group_id, msg).await;
+}
+
+async fn stop(group_id: i64) {
+    let mut outcome = Outcome::default();
+    if let Some(quiz) = quizzes().remove(&group_id) {
+        outcome.finished.push((group_id, quiz.scoreboard));
+    }
+    outcome.send().await;
+}
+
+async fn answer(e: &GroupMsg, text: &str) {
+    let group_id = e.group_id;
+    let mut outcome = Outcome::default();
+    {
+        let mut quizzes = quizzes();
+        let Some(quiz) = quizzes.get_mut(&group_id) else {
+            return;
+        };
+        let is_correct = quiz
+            .current()
+            .answers
+            .iter()
+            .any(|x| x.trim().eq_ignore_ascii_case(text));
+        if !is_correct {
+            return;
+        }
+        quiz.scoreboard.add(e.sender_id, 1);
+        let msg = Message::new()
+            .add_at(&e.sender_id.to_string())
+            .add_text(tr!(
+                group_id,
+                "quiz.correct",
+                answer = quiz.current().answers[0]
+            ));
+        outcome.messages.push((group_id, msg));
+        advance(&mut quizzes, group_id, &mut outcome);
+    }
+    outcome.send().await;
+}
+
+pub(super) async fn tick(now: Instant) {
+    let mut outcome = Outcome::default();
+    {
+        let mut quizzes = quizzes();
+        let overdue: Vec<i64> = quizzes
+            .iter()
+            .filter(|(_, quiz)| quiz.deadline <= now)
+            .map(|(&group_id, _)| group_id)
+            .collect();
+        for group_id in overdue {
+            let quiz = &quizzes[&group_id];
+            let answer = quiz.current().answers.first().cloned().unwrap_or_default();
+            let msg = Message::from(tr!(group_id, "quiz.timeout", answer = answer));
+            outcome.messages.push((group_id, msg));
+            advance(&mut quizzes, group_id, &mut outcome);
+        }
+    }
+    outcome.send().await;
+}
+
+/// Ask next question or finish the game.
+fn advance(quizzes: &mut HashMap<i64, Quiz>, group_id: i64, outcome: &mut Outcome) {
+    let Some(quiz) = quizzes.get_mut(&group_id) else {
+        return;
+    };
+    if quiz.next_round() {
+        outcome
+            .messages
+            .push((group_id, Message::from(quiz.ask(group_id))));
+    } else if let Some(quiz) = quizzes.remove(&group_id) {
+        outcome.finished.push((group_id, quiz.scoreboard));
+    }
+}
+
+/// Load questions from a toml file under data path, empty on failure.
+async fn load_questions(file: &str) -> Vec<Question> {
+    let path = DATA_PATH.get().unwrap().join(file);
+    let content = match kovi::tokio::fs::read_to_string(&path).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_info!("Read question file failed, fallback to agent: {err}");
+            return vec![];
+        }
+    };
+    match toml::from_str::<QuestionFile>(&content) {
+        Ok(file) => file.questions,
+        Err(err) => {
+            std_error!("Parse question file failed: {err}");
+            vec![]
+        }
+    }
+}
+
+/// Ask agent of the group for questions, empty if agent is not configured or fails.
+async fn generate_questions(group: &GroupSetting, n: usize) -> Vec<Question> {
+    let Some(ref agent) = group.agent else {
+        return vec![];
+    };
+    let dev_prompt = "You write trivia questions in Chinese. Output one question per line in \
+                      the form of question|answer, with no numbering or anything else.";
+    let user_prompt = format!("出{n}道题");
+    let model = agent.get_model().await;
+    let Some(answer) = agent::complete(
+        &agent.api_url,
+        &agent.api_key,
+        &model,
+        dev_prompt,
+        &user_prompt,
+    )
+    .await
+    else {
+        return vec![];
+    };
+    answer
+        .lines()
+        .filter_map(|line| {
+            let (question, answer) = line.split_once('|')?;
+            let (question, answer) = (question.trim(), answer.trim());
+            if question.is_empty() || answer.is_empty() {
+                return None;
+            }
+            Some(Question {
+                question: question.to_string(),
+                answers: vec![answer.to_string()],
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,227 @@
+//! GitHub repository event notifications by polling REST API.
+
+use std::time::Duration;
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    exception::PluginResult,
+    global_state::{GithubEvent, GithubRepoSetting},
+    http::Request,
+    std_error, tr,
+    util::{self, schedule_task_blocking},
+    CONFIG,
+};
+
+/// Event to announce, rendered in the locale of each subscribed group.
+enum Notice {
+    Release {
+        tag: String,
+        title: String,
+        url: String,
+    },
+    Issue {
+        number: u64,
+        title: String,
+        author: String,
+        url: String,
+    },
+    Star {
+        added: u64,
+        total: u64,
+    },
+}
+
+impl Notice {
+    fn render(&self, group_id: i64, name: &str) -> String {
+        match self {
+            Notice::Release { tag, title, url } => tr!(
+                group_id,
+                "github.release",
+                repo = name,
+                tag = tag,
+                title = title,
+                url = url,
+            ),
+            Notice::Issue {
+                number,
+                title,
+                author,
+                url,
+            } => tr!(
+                group_id,
+                "github.issue",
+                repo = name,
+                number = number,
+                title = title,
+                author = author,
+                url = url,
+            ),
+            Notice::Star { added, total } => tr!(
+                group_id,
+                "github.star",
+                repo = name,
+                added = added,
+                total = total,
+            ),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct Release {
+    id: u64,
+    tag_name: String,
+    name: Option<String>,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    user: User,
+    // issues api lists pull requests as well
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct User {
+    login: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Repository {
+    stargazers_count: u64,
+}
+
+async fn github_get<T: DeserializeOwned>(
+    path: &str,
+    query: &[(&str, &str)],
+    token: Option<&str>,
+) -> PluginResult<T> {
+    let mut req = Request::get(format!("https://api.github.com/{path}"))
+        .timeout(Duration::from_secs(20))
+        .user_agent("momo-bot")
+        .query(query)
+        .header("Accept", "application/vnd.github+json");
+    if let Some(token) = token {
+        req = req.bearer_auth(token);
+    }
+    req.send().await?.json()
+}
+
+/// Poll all configured repositories on a shared interval.
+pub async fn subscribe_github() {
+    let config = CONFIG.get().unwrap();
+    // no-op if no github config
+    let Some(ref github) = config.github else {
+        return;
+    };
+    if github.repos.is_empty() {
+        return;
+    }
+    kovi::spawn(async move {
+        let duration = Duration::from_secs(github.poll_interval_sec);
+        let token = github.token.as_deref();
+        schedule_task_blocking(duration, || async move {
+            for repo in &github.repos {
+                poll_repo(token, repo).await;
+            }
+        })
+        .await;
+    });
+}
+
+/// Announce events newer than last observation, the first poll only records the state.
+pub async fn poll_repo(token: Option<&str>, repo: &GithubRepoSetting) {
+    let last = repo.state.lock().unwrap().clone();
+    let init = last.is_none();
+    let mut state = last.unwrap_or_default();
+    let name = &repo.repo;
+    let mut notices = Vec::new();
+
+    if repo.events.contains(&GithubEvent::Release) {
+        let path = format!("repos/{name}/releases");
+        let releases: Vec<Release> = match github_get(&path, &[("per_page", "10")], token).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Query releases of {name} failed: {err}");
+                return;
+            }
+        };
+        // listed newest first
+        for release in releases.iter().rev().filter(|r| r.id > state.release_id) {
+            notices.push(Notice::Release {
+                tag: release.tag_name.clone(),
+                title: release.name.clone().unwrap_or_default(),
+                url: release.html_url.clone(),
+            });
+        }
+        let latest = releases.iter().map(|r| r.id).max().unwrap_or_default();
+        state.release_id = state.release_id.max(latest);
+    }
+
+    if repo.events.contains(&GithubEvent::Issue) {
+        let path = format!("repos/{name}/issues");
+        let query = [
+            ("state", "all"),
+            ("sort", "created"),
+            ("direction", "desc"),
+            ("per_page", "20"),
+        ];
+        let issues: Vec<Issue> = match github_get(&path, &query, token).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Query issues of {name} failed: {err}");
+                return;
+            }
+        };
+        let issues = issues.iter().filter(|i| i.pull_request.is_none());
+        for issue in issues
+            .clone()
+            .rev()
+            .filter(|i| i.number > state.issue_number)
+        {
+            notices.push(Notice::Issue {
+                number: issue.number,
+                title: issue.title.clone(),
+                author: issue.user.login.clone(),
+                url: issue.html_url.clone(),
+            });
+        }
+        let latest = issues.map(|i| i.number).max().unwrap_or_default();
+        state.issue_number = state.issue_number.max(latest);
+    }
+
+    if repo.events.contains(&GithubEvent::Star) {
+        let path = format!("repos/{name}");
+        let repository: Repository = match github_get(&path, &[], token).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Query repository {name} failed: {err}");
+                return;
+            }
+        };
+        let stars = repository.stargazers_count;
+        // unstarring is not announced
+        if stars > state.stars {
+            notices.push(Notice::Star {
+                added: stars - state.stars,
+                total: stars,
+            });
+        }
+        state.stars = stars;
+    }
+
+    *repo.state.lock().unwrap() = Some(state);
+    if init {
+        return;
+    }
+    for notice in notices {
+        for &group_id in &repo.group_ids {
+            util::send_group_msg(group_id, notice.render(group_id, name));
+        }
+    }
+}
@@ -1,12 +1,12 @@
 //! Global states that are readonly and available throughout lifetime of plugin.
 
 use indoc::formatdoc;
-use kovi::{tokio::sync::RwLock, PluginBuilder as plugin, RuntimeBot};
+use kovi::{tokio::sync::RwLock, PluginBuilder as plugin};
 use regex::{Regex, RegexSet};
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     fs::{create_dir_all, File, OpenOptions},
     io::{Read, Write},
@@ -14,17 +14,22 @@ use std::{
     process::exit,
     sync::{
         atomic::{AtomicBool, AtomicU8},
-        Arc, OnceLock,
+        Arc, Mutex, OnceLock, RwLock as StdRwLock,
     },
+    time::{Duration, Instant},
 };
 
 use crate::{
-    exception::{PluginError::*, PluginResult}, std_db_info, std_error, std_info, store
+    ab_test,
+    exception::{PluginError::*, PluginResult},
+    locale,
+    onebot::OneBotApi,
+    persona, std_db_info, std_error, std_info, store, util,
 };
 
 // metadata, not from config
-pub static BOT: OnceLock<Arc<RuntimeBot>> = OnceLock::new();
-pub fn get_bot() -> Arc<RuntimeBot> {
+pub static BOT: OnceLock<Arc<dyn OneBotApi>> = OnceLock::new();
+pub fn get_bot() -> Arc<dyn OneBotApi> {
     Arc::clone(BOT.get().unwrap())
 }
 pub static ADMIN_QQ: OnceLock<i64> = OnceLock::new();
@@ -34,8 +39,41 @@ pub static DATA_PATH: OnceLock<PathBuf> = OnceLock::new();
 // database connection pool
 pub static DB_POOL: OnceLock<SqlitePool> = OnceLock::new();
 
-// configuration
-pub static CONFIG: OnceLock<Config> = OnceLock::new();
+// configuration, replaced as a whole on reload
+pub static CONFIG: ConfigCell = ConfigCell::new();
+
+/// Holds current [Config], which can be replaced at runtime.
+///
+/// A replaced config is leaked on purpose, so tasks spawned with the old one keep a valid
+/// reference.
+pub struct ConfigCell(StdRwLock<Option<&'static Config>>);
+
+impl ConfigCell {
+    const fn new() -> Self {
+        Self(StdRwLock::new(None))
+    }
+
+    pub fn get(&self) -> Option<&'static Config> {
+        *self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Fails if config has been set.
+    pub fn set(&self, config: Config) -> PluginResult<()> {
+        let mut cur = self.0.write().unwrap_or_else(|e| e.into_inner());
+        if cur.is_some() {
+            return Err(InitGlobalState(
+                "CONFIG set before init_global_state()".into(),
+            ));
+        }
+        *cur = Some(Box::leak(Box::new(config)));
+        Ok(())
+    }
+
+    pub fn replace(&self, config: Config) {
+        let mut cur = self.0.write().unwrap_or_else(|e| e.into_inner());
+        *cur = Some(Box::leak(Box::new(config)));
+    }
+}
 
 fn set_with_err<T>(state: &'static OnceLock<T>, value: T) -> PluginResult<()> {
     let cause = format!("{} set before init_global_state()", stringify!(state));
@@ -66,6 +104,9 @@ pub async fn init_global_state() -> PluginResult<()> {
     set_with_err(&ADMIN_QQ, admin_qq)?;
     set_with_err(&BOT_QQ, bot_qq)?;
 
+    // restore archive of another server before anything is loaded
+    crate::migration::import_state(&data_path)?;
+
     // load config
     std_info!("Loading configuration...");
     let (mut config, has_config) = init_config()?;
@@ -80,46 +121,107 @@ pub async fn init_global_state() -> PluginResult<()> {
     }
 
     // save bot
-    set_with_err(&BOT, bot)?;
-
-    // init groups
-    if let Some(groups) = config.groups.as_mut() {
-        // init agent
-        let agents = groups.iter_mut().filter_map(|g| g.agent.as_mut());
-        for agent in agents {
-            agent.load_members();
-            agent.set_model(agent.model.clone()).await;
-        }
-
-        // init command regex
-        let commands = groups.iter_mut().filter_map(|g| g.command.as_mut());
-        for command in commands {
-            if let Err(err) = command.init_regex() {
-                std_error!(
-                    "
-                    Initialize command regex failed.
-                    {err}
-                    ");
-            }
-        }
-    }
+    set_with_err(&BOT, bot as Arc<dyn OneBotApi>)?;
+
+    prepare_config(&mut config).await;
     std_info!("{:?}", config);
     let max_conn = config.database.max_connections;
     // save config
-    set_with_err(&CONFIG, config)?;
+    CONFIG.set(config)?;
 
     // init database
     std_info!("Initializing database connection pool...");
     let pool = store::init_sqlite_pool(max_conn).await?;
     set_with_err(&DB_POOL, pool)?;
-    std_info!("Initializing log table...");
-    store::init_log_table().await?;
-
+    std_info!("Initializing pre-defined tables...");
+    store::init_predefined_tables().await?;
+    std_info!("Initializing message tables of configured groups...");
+    store::init_group_tables().await?;
 
     std_db_info!("Global state initialization has completed.");
     Ok(())
 }
 
+/// Fill runtime states of groups that are derived from config.
+pub async fn prepare_config(config: &mut Config) {
+    // CONFIG is not replaced yet, resolve time zones from the new config
+    let global_timezone = config.global.timezone.clone();
+    let Some(groups) = config.groups.as_mut() else {
+        return;
+    };
+
+    // init agent
+    for group in groups.iter_mut() {
+        let offset = util::resolve_offset(group.timezone.as_deref(), global_timezone.as_deref());
+        let Some(ref mut agent) = group.agent else {
+            continue;
+        };
+        agent.load_members();
+        agent.set_model(agent.model.clone()).await;
+        agent.update_persona(persona::minute_of_day(offset));
+    }
+
+    // init moderation regex
+    let moderations = groups.iter_mut().filter_map(|g| g.moderation.as_mut());
+    for moderation in moderations {
+        if let Err(err) = moderation.init_regex() {
+            std_error!(
+                "
+                Initialize moderation regex failed.
+                {err}
+                "
+            );
+        }
+    }
+
+    // init command regex
+    let commands = groups.iter_mut().filter_map(|g| g.command.as_mut());
+    for command in commands {
+        if let Err(err) = command.init_regex() {
+            std_error!(
+                "
+                Initialize command regex failed.
+                {err}
+                "
+            );
+        }
+    }
+}
+
+/// Re-read config file and replace current config.
+///
+/// Mute state and model switched by command are carried over. Live subscriptions keep polling
+/// with the config loaded at startup until restart.
+pub async fn reload_config() -> PluginResult<()> {
+    let config_path = DATA_PATH.get().unwrap().join("config.toml");
+    let toml_str = kovi::tokio::fs::read_to_string(&config_path).await?;
+    let mut config = parse_config(&toml_str)?;
+    prepare_config(&mut config).await;
+
+    let old_config = CONFIG.get().unwrap();
+    if let (Some(old_groups), Some(new_groups)) = (&old_config.groups, &config.groups) {
+        for new_group in new_groups {
+            let Some(old_group) = old_groups.iter().find(|g| g.id == new_group.id) else {
+                continue;
+            };
+            let (Some(old_agent), Some(new_agent)) = (&old_group.agent, &new_group.agent) else {
+                continue;
+            };
+            if old_agent.is_mute() {
+                new_agent.mute();
+            }
+            let old_model = old_agent.get_model().await;
+            if old_model != old_agent.model {
+                new_agent.set_model(old_model).await;
+            }
+        }
+    }
+    CONFIG.replace(config);
+    locale::clear_cache();
+    std_db_info!("Configuration reloaded.");
+    Ok(())
+}
+
 /// Initialize config, either read or create.
 ///
 /// If no error occurs, returns ([ChatConfig], true) if read from existing config, ([ChatConfig],
@@ -139,8 +241,7 @@ fn init_config() -> PluginResult<(Config, bool)> {
         // config does not exist, create and return false
         Ok(mut config_file) => {
             let empty_config = Config::default();
-            let toml_str =
-                toml::to_string_pretty(&empty_config).map_err(|e| SerializeToml(e.to_string()))?;
+            let toml_str = crate::config_template::generate()?;
             config_file.write_all(toml_str.as_bytes())?;
             Ok((empty_config, false))
         }
@@ -149,23 +250,144 @@ fn init_config() -> PluginResult<(Config, bool)> {
             let mut config_file = File::open(&config_path)?;
             let mut toml_str = String::new();
             config_file.read_to_string(&mut toml_str)?;
-            let config = toml::from_str(&toml_str).map_err(|e| DeserializeToml(e.to_string()))?;
+            let config = parse_config(&toml_str)?;
             Ok((config, true))
         }
     }
 }
 
+/// Deserialize config, upgrading the file first if it is of an older schema.
+fn parse_config(toml_str: &str) -> PluginResult<Config> {
+    let Some((from, upgraded)) = upgrade_config(toml_str)? else {
+        return toml::from_str(toml_str).map_err(|e| DeserializeToml(e.to_string()));
+    };
+    let config = toml::from_str(&upgraded).map_err(|e| DeserializeToml(e.to_string()))?;
+    let data_path = DATA_PATH.get().unwrap();
+    let backup_path = data_path.join(format!("config.v{from}.toml.bak"));
+    std::fs::write(&backup_path, toml_str)?;
+    std::fs::write(data_path.join("config.toml"), &upgraded)?;
+    std_info!(
+        "Config upgraded from version {from} to {CONFIG_VERSION}, the original is kept at {}",
+        backup_path.display()
+    );
+    Ok(config)
+}
+
+/// Returns (old version, upgraded toml) if the config is older than [CONFIG_VERSION].
+///
+/// Fields added since are filled by the template, except optional sections absent from the
+/// config, which stay disabled.
+pub fn upgrade_config(toml_str: &str) -> PluginResult<Option<(u32, String)>> {
+    let mut table: toml::Table =
+        toml::from_str(toml_str).map_err(|e| DeserializeToml(e.to_string()))?;
+    // configs before versioning have no version
+    let from = match table.get("version") {
+        Some(v) => v
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or(DeserializeToml(format!("invalid config version: {v}")))?,
+        None => 0,
+    };
+    if from >= CONFIG_VERSION {
+        return Ok(None);
+    }
+    let template =
+        toml::Table::try_from(Config::default()).map_err(|e| SerializeToml(e.to_string()))?;
+    fill_defaults(&mut table, &template);
+    table.insert(String::from("version"), toml::Value::from(CONFIG_VERSION));
+    let upgraded = toml::to_string_pretty(&table).map_err(|e| SerializeToml(e.to_string()))?;
+    Ok(Some((from, upgraded)))
+}
+
+/// Add keys missing from `table` with their template value, recursively into present tables.
+/// Missing tables are skipped as they are optional features.
+fn fill_defaults(table: &mut toml::Table, template: &toml::Table) {
+    for (key, default) in template {
+        let Some(value) = table.get_mut(key) else {
+            let is_section = match default {
+                toml::Value::Table(_) => true,
+                toml::Value::Array(items) => items.iter().any(toml::Value::is_table),
+                _ => false,
+            };
+            if !is_section {
+                table.insert(key.clone(), default.clone());
+            }
+            continue;
+        };
+        match (value, default) {
+            (toml::Value::Table(t), toml::Value::Table(d)) => fill_defaults(t, d),
+            // e.g. every group is filled by the template group
+            (toml::Value::Array(items), toml::Value::Array(defaults)) => {
+                let Some(toml::Value::Table(d)) = defaults.first() else {
+                    continue;
+                };
+                for item in items {
+                    if let toml::Value::Table(t) = item {
+                        fill_defaults(t, d);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Schema version of [Config], bumped when existing configs no longer deserialize, e.g. a required
+/// field is added.
+pub const CONFIG_VERSION: u32 = 1;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
+    /// Schema version, absent in configs older than versioning.
+    pub version: Option<u32>,
     pub global: GlobalSetting,
     pub database: DatabaseSetting,
     pub object_storage: Option<ObjectStorageSetting>,
+    pub error_digest: Option<ErrorDigestSetting>,
+    pub db_check: Option<DbCheckSetting>,
+    pub disk: Option<DiskSetting>,
+    pub media: Option<MediaSetting>,
+    pub maintenance: Option<MaintenanceSetting>,
+    pub private_agent: Option<PrivateAgentSetting>,
+    pub temp_session: Option<TempSessionSetting>,
+    pub private_live: Option<PrivateLiveSetting>,
+    pub news: Option<NewsSetting>,
+    pub github: Option<GithubSetting>,
+    pub ocr: Option<OcrSetting>,
+    pub caption: Option<CaptionSetting>,
+    pub unfurl: Option<UnfurlSetting>,
+    pub image_safety: Option<ImageSafetySetting>,
+    pub stt: Option<SttSetting>,
+    pub bilibili: Option<BilibiliSetting>,
+    pub spend: Option<SpendSetting>,
+    pub stats_api: Option<StatsApiSetting>,
     pub groups: Option<Vec<GroupSetting>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GlobalSetting {
     pub max_sleep_sec: usize,
+    pub unknown_group: Option<UnknownGroupPolicy>,
+    /// Locale of replies, see [crate::locale].
+    pub locale: Option<String>,
+    /// Log outgoing messages and group actions instead of performing them.
+    pub dry_run: Option<bool>,
+    /// Fixed UTC offset like "+08:00" for timestamps and schedules, Beijing time if absent.
+    pub timezone: Option<String>,
+    /// Count feature usage daily, see [crate::analytics].
+    pub analytics: Option<bool>,
+}
+
+/// Handling of messages from groups absent from config.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownGroupPolicy {
+    Ignore,
+    /// Record chat history only.
+    Log,
+    /// Record chat history, and reply a hint when the bot is at.
+    #[default]
+    Reply,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -173,12 +395,311 @@ pub struct ObjectStorageSetting {
     pub script_path: String,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorDigestSetting {
+    pub hour: u8,
+}
+
+/// Daily integrity check and maintenance of the database, see [crate::db_check].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DbCheckSetting {
+    /// Better be a quiet hour, the database is busy during the check.
+    pub hour: u8,
+}
+
+/// Private commands of the main admin, see [crate::maintenance].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MaintenanceSetting {
+    pub restart: String,
+    pub check_update: String,
+    /// Longest wait for answers in the outbox and buffered history before exiting.
+    pub drain_timeout_sec: u64,
+}
+
+/// Free space of the disk holding the data path, see [crate::disk].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiskSetting {
+    pub interval_sec: u64,
+    pub min_free_mb: u64,
+    /// Delete local copies of uploaded media when space is low.
+    pub prune: bool,
+}
+
+/// Downloads of images and records in group history, see [crate::media].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MediaSetting {
+    /// Longest wait for a fetch, including the wait for a free slot.
+    pub timeout_sec: u64,
+    /// Larger files are not kept in history.
+    pub max_file_mb: u64,
+    pub max_concurrent: usize,
+}
+
+/// Morning digest of feed headlines posted to opted-in groups.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewsSetting {
+    pub hour: u8,
+    /// RSS or Atom urls.
+    pub sources: Vec<String>,
+    pub headlines_per_source: usize,
+    pub group_ids: Vec<i64>,
+    /// Headlines are summarized by the agent of each group if present.
+    pub summary_prompt: Option<String>,
+}
+
+/// Poll GitHub repositories and announce events to mapped groups.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GithubSetting {
+    /// Optional personal access token, raises the rate limit of GitHub API.
+    pub token: Option<String>,
+    pub poll_interval_sec: u64,
+    pub repos: Vec<GithubRepoSetting>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GithubRepoSetting {
+    #[serde(skip)]
+    pub state: Mutex<Option<GithubRepoState>>,
+
+    /// In the form of `owner/name`.
+    pub repo: String,
+    pub group_ids: Vec<i64>,
+    pub events: Vec<GithubEvent>,
+}
+
+/// Latest observation of a repository, events up to it are not announced.
+#[derive(Debug, Default, Clone)]
+pub struct GithubRepoState {
+    pub release_id: u64,
+    pub issue_number: u64,
+    pub stars: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GithubEvent {
+    Release,
+    Issue,
+    Star,
+}
+
+/// Recognize text of group images, stored as `ocr` segments of history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OcrSetting {
+    pub engine: OcrEngine,
+    /// Receives image bytes by POST and responds `{"text": "..."}`, required by endpoint engine.
+    pub endpoint: Option<String>,
+    pub api_key: Option<String>,
+    /// Passed to tesseract by `-l`.
+    pub language: String,
+    pub timeout_sec: u64,
+    /// Shorter results are dropped as noise.
+    pub min_chars: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OcrEngine {
+    Tesseract,
+    Endpoint,
+}
+
+/// Credentials of a logged in account, some Bilibili endpoints throttle anonymous requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BilibiliSetting {
+    /// Cookie header copied from browser, e.g. `SESSDATA=...; bili_jct=...`.
+    pub cookie: String,
+    pub user_agent: Option<String>,
+}
+
+/// Monthly ceiling of OpenAI spending summed over all groups, see [crate::spend].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SpendSetting {
+    /// In the same currency as prices.
+    pub monthly_limit: f64,
+    /// Price per million tokens of models absent from `prices`.
+    pub default_price: f64,
+    /// Model -> price per million tokens.
+    pub prices: HashMap<String, f64>,
+    /// Model used after the limit is reached, agents stay silent if absent.
+    pub fallback_model: Option<String>,
+}
+
+/// JSON statistics for external dashboards over HTTP, see [crate::stats_api].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StatsApiSetting {
+    /// Address to listen on, e.g. `127.0.0.1:8787`.
+    pub bind: String,
+    /// Bearer token of every request, the API is not served if it is shorter than 16 characters.
+    pub token: String,
+}
+
+/// Describe group images, stored as `caption` segments of history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CaptionSetting {
+    /// Receives image bytes by POST and responds `{"text": "..."}`.
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub timeout_sec: u64,
+    /// Longer descriptions are truncated.
+    pub max_chars: usize,
+}
+
+/// Speech to text of voice messages, see [crate::stt].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SttSetting {
+    /// Receives audio bytes by POST and responds `{"text": "..."}`.
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub timeout_sec: u64,
+}
+
+/// Check images by endpoint before the bot sends them, e.g. live covers and memes.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImageSafetySetting {
+    /// Receives image bytes by POST and responds `{"score": 0.97}`, higher is less safe.
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub timeout_sec: u64,
+    /// Images scored no lower are flagged.
+    pub threshold: f64,
+    pub action: SafetyAction,
+}
+
+/// Handling of flagged images, images that fail to be checked are skipped as well.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SafetyAction {
+    Skip,
+    Blur,
+}
+
+/// Fetch title and description of links, stored as `link` segments of history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UnfurlSetting {
+    pub timeout_sec: u64,
+    /// Only the head of page is read.
+    pub max_bytes: usize,
+    pub max_urls_per_message: usize,
+    /// Domains never fetched, subdomains included.
+    pub blocklist: Vec<String>,
+    /// Domains whose preview is replied in group, subdomains included.
+    pub preview_domains: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GroupSetting {
     pub id: i64,
+    /// Stages of message handling to run in the listed order, all if absent.
+    pub pipeline: Option<Vec<PipelineStage>>,
+    /// Overrides the global locale.
+    pub locale: Option<String>,
+    /// Overrides the global time zone.
+    pub timezone: Option<String>,
     pub live: Option<LiveSetting>,
     pub agent: Option<AgentSetting>,
     pub command: Option<CommandSetting>,
+    pub report: Option<ReportSetting>,
+    pub verify: Option<VerifySetting>,
+    pub moderation: Option<ModerationSetting>,
+    pub repeat: Option<RepeatSetting>,
+    pub points: Option<PointsSetting>,
+    pub level: Option<LevelSetting>,
+    pub quiz: Option<QuizSetting>,
+    pub idiom: Option<IdiomSetting>,
+    pub feed: Option<FeedSetting>,
+    pub meme: Option<MemeSetting>,
+    pub search: Option<SearchSetting>,
+    pub annual_report: Option<AnnualReportSetting>,
+    pub summary: Option<SummarySetting>,
+    pub mention: Option<MentionSetting>,
+    pub card: Option<CardSetting>,
+    pub join_request: Option<JoinRequestSetting>,
+    pub word_filter: Option<WordFilterSetting>,
+    pub reminder: Option<ReminderSetting>,
+    pub blacklist: Option<BlacklistSetting>,
+    pub rate_limit: Option<RateLimitSetting>,
+    pub keyword: Option<KeywordSetting>,
+    pub feedback: Option<FeedbackSetting>,
+    pub heat: Option<HeatSetting>,
+    pub takeover: Option<TakeoverSetting>,
+    pub sticker: Option<StickerSetting>,
+    pub outbox: Option<OutboxSetting>,
+    pub ignore: Option<IgnoreSetting>,
+    pub repost: Option<RepostSetting>,
+    pub activity: Option<ActivitySetting>,
+    pub quota: Option<QuotaSetting>,
+    pub guild: Option<GuildSetting>,
+}
+
+/// Handlers of group messages, see [crate::pipeline].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    Ignore,
+    Logger,
+    Heat,
+    Blacklist,
+    Moderation,
+    RateLimit,
+    Keyword,
+    Command,
+    Preview,
+    Repeat,
+    Repost,
+    Points,
+    Quota,
+    Level,
+    Games,
+    Verify,
+    Report,
+    Feed,
+    Meme,
+    Search,
+    AnnualReport,
+    Activity,
+    Summary,
+    Mention,
+    Reminder,
+    Feedback,
+    Sticker,
+    Live,
+    Agent,
+}
+
+impl PipelineStage {
+    /// Default order.
+    pub const ALL: [PipelineStage; 29] = [
+        Self::Ignore,
+        Self::Logger,
+        Self::Heat,
+        Self::Blacklist,
+        Self::Moderation,
+        Self::RateLimit,
+        Self::Keyword,
+        Self::Command,
+        Self::Preview,
+        Self::Repeat,
+        Self::Repost,
+        Self::Points,
+        Self::Quota,
+        Self::Level,
+        Self::Games,
+        Self::Verify,
+        Self::Report,
+        Self::Feed,
+        Self::Meme,
+        Self::Search,
+        Self::AnnualReport,
+        Self::Activity,
+        Self::Summary,
+        Self::Mention,
+        Self::Reminder,
+        Self::Feedback,
+        Self::Sticker,
+        Self::Live,
+        Self::Agent,
+    ];
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -186,241 +707,2043 @@ pub struct DatabaseSetting {
     pub max_connections: u32,
     pub log_table_name: String,
     pub group_table_prefix: String,
+    /// Group message segments kept in memory while the database is unavailable.
+    pub write_buffer_cap: Option<usize>,
+    /// Interval of retrying buffered writes.
+    pub write_retry_sec: Option<u64>,
+    /// Content of group message segments this long or longer is stored compressed by zstd.
+    pub compress_min_bytes: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct LiveSetting {
     #[serde(skip, default = "default_switch")]
     pub switch: AtomicU8,
+    #[serde(skip)]
+    pub last_notified: Mutex<Option<Instant>>,
 
     pub room_id: String,
     pub online_msg: String,
     pub offline_msg: String,
     pub query_message: String,
+    /// Reply the current key frame while streaming.
+    pub screenshot_message: Option<String>,
     pub poll_interval_sec: u64,
+    /// Other groups notified by the same poller, which need no live setting of their own.
+    pub notify_group_ids: Option<Vec<i64>>,
+    /// Notify private subscribers of the room as well, the private poller skips the room then.
+    pub notify_subscribers: Option<bool>,
+    /// Status changes within this many seconds after a notification are held back.
+    pub notify_window_sec: Option<u64>,
 }
 fn default_switch() -> AtomicU8 {
     AtomicU8::from(2)
 }
 
+/// Endpoint of an OpenAI compatible API, or several of them, e.g. relay proxies, tried in turn
+/// by [crate::endpoint].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ApiUrl {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ApiUrl {
+    pub fn urls(&self) -> &[String] {
+        match self {
+            Self::One(url) => std::slice::from_ref(url),
+            Self::Many(urls) => urls,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AgentSetting {
     #[serde(skip, default = "default_atomic_bool")]
     pub mute: AtomicBool,
     #[serde(skip)]
     pub cur_model: RwLock<String>,
+    /// Index into `persona_schedule`, updated by [crate::persona].
+    #[serde(skip)]
+    pub active_persona: Mutex<Option<usize>>,
 
-    pub api_url: String,
+    pub api_url: ApiUrl,
     pub api_key: String,
     pub model: String,
     pub dev_prompt: String,
     pub user_prompt: String,
     pub aware_history_segments: i64,
+    /// Fit history into this many estimated tokens instead of a fixed number of segments.
+    pub aware_history_tokens: Option<usize>,
+    /// Oldest history is dropped if the whole prompt would take more estimated tokens, rather
+    /// than the request failing for exceeding context of the model.
+    pub max_prompt_tokens: Option<usize>,
     // id -> (name, description)
     pub known_members: HashMap<String, (String, String)>,
+    /// Alternative prompts assigned to half of the replies, see [crate::ab_test].
+    pub ab_test: Option<AbTestSetting>,
+    /// Source of `<!weather!>` in prompts, see [crate::calendar].
+    pub weather: Option<WeatherSetting>,
+    /// Prompts replacing the base ones within a time of day, the first matching window wins.
+    pub persona_schedule: Option<Vec<PersonaWindow>>,
+    /// Reply to at-me messages while muted, at most once a minute per group, so that members
+    /// know the bot is quiet on purpose.
+    pub muted_reply: Option<String>,
+    /// Face id reacting to at-me messages while muted, for implementations supporting
+    /// `set_msg_emoji_like`.
+    pub muted_reaction: Option<String>,
+    /// Language answers must be in, see [crate::language].
+    pub language: Option<LanguageSetting>,
+    /// Summarize long texts before they enter prompts, see [crate::long_message].
+    pub long_message: Option<LongMessageSetting>,
+    /// Role of the sender, "owner" or "admin", -> instruction appended to the developer prompt.
+    pub role_prompts: Option<HashMap<String, String>>,
 }
 fn default_atomic_bool() -> AtomicBool {
     AtomicBool::from(false)
 }
 
+/// `start` and `end` are "HH:MM" of Beijing time, the window wraps past midnight if `end` is
+/// earlier than `start`. Prompts absent here are taken from the agent.
 #[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct CommandSetting {
-    #[serde(skip)]
-    regex_set: RegexSet,
-    #[serde(skip, default = "default_regex")]
-    regex_mute: Regex,
-    #[serde(skip, default = "default_regex")]
-    regex_unmute: Regex,
-    #[serde(skip, default = "default_regex")]
-    regex_switch_model: Regex,
-    #[serde(skip, default = "default_regex")]
-    regex_dump_history: Regex,
-    #[serde(skip, default = "default_regex")]
-    regex_dump_log: Regex,
+pub struct PersonaWindow {
+    pub name: String,
+    pub start: String,
+    pub end: String,
+    pub dev_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+}
 
-    pub mute: String,
-    pub unmute: String,
-    pub switch_model: String,
-    pub dump_history: String,
-    pub dump_log: String,
-    pub admin_ids: Vec<i64>,
+impl PersonaWindow {
+    /// None if `start` or `end` is not "HH:MM".
+    pub fn contains(&self, minute_of_day: u32) -> Option<bool> {
+        let start = parse_hhmm(&self.start)?;
+        let end = parse_hhmm(&self.end)?;
+        let contains = if start <= end {
+            (start..end).contains(&minute_of_day)
+        } else {
+            minute_of_day >= start || minute_of_day < end
+        };
+        Some(contains)
+    }
 }
-fn default_regex() -> Regex {
-    Regex::new("empty").unwrap()
+
+/// Minutes since midnight of "HH:MM".
+fn parse_hhmm(time: &str) -> Option<u32> {
+    let (hour, minute) = time.split_once(':')?;
+    let (hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    (hour < 24 && minute < 60).then_some(hour * 60 + minute)
 }
 
-pub enum GroupCommand {
-    Mute,
-    Unmute,
-    SwitchModel(String),
-    DumpHistory(i64),
-    DumpLog(i64),
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeatherSetting {
+    pub city: String,
+    /// `<!city!>` is replaced by city, the response text is the weather. wttr.in if absent.
+    pub api_url: Option<String>,
 }
 
-impl CommandSetting {
-    pub fn init_regex(&mut self) -> PluginResult<()> {
-        let mute_pat = self.mute.as_str();
-        let unmute_pat = self.unmute.as_str();
-        let switch_model_pat = format!(
-            r"{}\s+(?<model>gpt4o|chatgpt-4o-latest|gpt-4o-mini|o1-mini|o1-preview)",
-            self.switch_model
-        );
-        let dump_history_pat = format!(r"{}\s+(?<count>\d+)", self.dump_history);
-        let dump_log_pat = format!(r"{}\s+(?<count>\d+)", self.dump_log);
-        self.regex_mute = Regex::new(mute_pat)?;
-        self.regex_unmute = Regex::new(unmute_pat)?;
-        self.regex_switch_model = Regex::new(&switch_model_pat)?;
-        self.regex_dump_history = Regex::new(&dump_history_pat)?;
-        self.regex_dump_log = Regex::new(&dump_log_pat)?;
-        self.regex_set = RegexSet::new([
-            mute_pat,
-            unmute_pat,
-            &switch_model_pat,
-            &dump_history_pat,
-            &dump_log_pat,
-        ])?;
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LongMessageSetting {
+    /// Texts with more characters are summarized.
+    pub min_chars: usize,
+    /// System prompt of summarizing, the text is the user prompt.
+    pub instruction: String,
+}
 
-        std_info!(
-            "
-            Initialize regex complete.
-            mute: {mute_pat}
-            unmute: {unmute_pat}
-            switch_model: {switch_model_pat}
-            dump_history: {dump_history_pat}
-            dump_log: {dump_log_pat}
-            "
-        );
-        Ok(())
-    }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LanguageSetting {
+    pub expect: Language,
+    /// Share of words in the expected language, below which an answer mismatches.
+    pub min_ratio: f64,
+    pub action: LanguageAction,
+    /// Appended to the developer prompt when retrying, or the developer prompt of translating.
+    pub instruction: String,
+}
 
-    pub fn parse_command(&self, input: &str) -> Option<GroupCommand> {
-        for idx in self.regex_set.matches(input).iter() {
-            match idx {
-            0 => {
-                return Some(GroupCommand::Mute);
-            }
-            1 => {
-                return Some(GroupCommand::Unmute);
-            }
-            2 => {
-                if let Some(caps) = self.regex_switch_model.captures(input) {
-                    if let Some(model_match) = caps.name("model") {
-                        return Some(GroupCommand::SwitchModel(model_match.as_str().to_string()));
-                    }
-                }
-            }
-            3 => {
-                if let Some(caps) = self.regex_dump_history.captures(input) {
-                    if let Some(count_match) = caps.name("count") {
-                        if let Ok(count) = count_match.as_str().parse::<i64>() {
-                            return Some(GroupCommand::DumpHistory(count));
-                        }
-                    }
-                }
-            }
-            4 => {
-                if let Some(caps) = self.regex_dump_log.captures(input) {
-                    if let Some(count_match) = caps.name("count") {
-                        if let Ok(count) = count_match.as_str().parse::<i64>() {
-                            return Some(GroupCommand::DumpLog(count));
-                        }
-                    }
-                }
-            }
-            _ => return None
-            }
-        }
-        None
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    Zh,
+    En,
 }
 
-pub enum LiveSwitch {
-    On,
-    Off,
-    Init,
-    Trap,
+/// Handling of answers in another language, the fixed answer is sent even if still mismatched.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LanguageAction {
+    /// Ask the same question again with the corrective instruction.
+    Retry,
+    /// Have the model translate the answer.
+    Translate,
 }
 
-impl LiveSetting {
-    pub fn get_switch(&self) -> LiveSwitch {
-        match self.switch.load(std::sync::atomic::Ordering::Acquire) {
-            0 => LiveSwitch::Off,
-            1 => LiveSwitch::On,
-            2 => LiveSwitch::Init,
-            _ => LiveSwitch::Trap,
-        }
-    }
+/// Prompts absent here are taken from the agent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AbTestSetting {
+    pub name: String,
+    pub dev_prompt: Option<String>,
+    pub user_prompt: Option<String>,
+}
 
-    pub fn set_switch(&self, switch: LiveSwitch) {
-        let value = match switch {
-            LiveSwitch::Off => 0,
-            LiveSwitch::On => 1,
-            LiveSwitch::Init => 2,
-            LiveSwitch::Trap => 3,
-        };
-        self.switch
-            .store(value, std::sync::atomic::Ordering::Release);
-    }
+/// Members report a message by replying to it, admins of [CommandSetting] receive it privately.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportSetting {
+    pub trigger: String,
+    pub context_segments: i64,
 }
 
-impl AgentSetting {
-    pub fn mute(&self) {
-        self.mute.store(true, std::sync::atomic::Ordering::Release);
-    }
+/// New members must answer a question before timeout.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VerifySetting {
+    pub question: String,
+    /// Accepted answers, ignored if `ai_criteria` is set.
+    pub answers: Vec<String>,
+    /// Let agent of the group judge free-form answers by this criteria.
+    pub ai_criteria: Option<String>,
+    pub timeout_min: i64,
+    pub on_fail: VerifyFailAction,
+}
 
-    pub fn unmute(&self) {
-        self.mute.store(false, std::sync::atomic::Ordering::Release);
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyFailAction {
+    /// Notify command admins of the group in private chat.
+    Notify,
+    Kick,
+}
 
-    pub fn is_mute(&self) -> bool {
-        self.mute.load(std::sync::atomic::Ordering::Acquire)
-    }
+/// `<trigger> <time> <content>`, see [crate::reminder].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReminderSetting {
+    pub trigger: String,
+    /// Pending reminders per member.
+    pub max_pending: i64,
+}
 
-    pub async fn set_model(&self, model: String) {
-        let mut cur_model = self.cur_model.write().await;
-        *cur_model = model;
-    }
+/// Answers of the agent containing at least `threshold` of the sensitive words are posted only after
+/// a command admin approves them in private chat, see [crate::takeover].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TakeoverSetting {
+    pub sensitive_words: Vec<String>,
+    /// 0 to review every answer.
+    pub threshold: usize,
+    pub approve: String,
+    pub reject: String,
+    /// Post `<edit> <id> <content>` instead.
+    pub edit: String,
+}
 
-    pub async fn get_model(&self) -> String {
-        let cur_model = self.cur_model.read().await;
-        cur_model.to_string()
-    }
+/// Answers of the agent are sent after a delay, see [crate::outbox].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OutboxSetting {
+    pub delay_sec: u64,
+}
 
-    pub fn load_members(&mut self) {
-        let mut buf = String::new();
-        for (name, desc) in self.known_members.values() {
-            buf.push_str("- ");
-            buf.push_str(name);
-            buf.push_str(": ");
-            buf.push_str(desc);
-            buf.push('\n');
-        }
-        self.dev_prompt = self.dev_prompt.replace("<!members!>", &buf);
-        self.user_prompt = self.user_prompt.replace("<!members!>", &buf);
-    }
+/// Stickers of the group picked from images in history by command admins, see [crate::sticker].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StickerSetting {
+    /// Reply to an image by `<add> <tag>`.
+    pub add: String,
+    /// `<remove> <tag>`.
+    pub remove: String,
+    pub list: String,
 }
 
-impl Default for Config {
-    fn default() -> Self {
+/// Detect heated arguments by message rate and negative words, see [crate::heat].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HeatSetting {
+    #[serde(skip)]
+    pub state: Mutex<HeatState>,
+
+    pub window_sec: u64,
+    /// Messages within window for the group to be heated.
+    pub min_messages: usize,
+    /// Fraction of messages within window that contain negative words.
+    pub negative_ratio: f64,
+    pub negative_words: Vec<String>,
+    /// Agent and playful notices resume after the group stays calm for this long.
+    pub cooldown_sec: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct HeatState {
+    // (time, negative) of messages within window
+    recent: VecDeque<(Instant, bool)>,
+    heated_until: Option<Instant>,
+}
+
+/// `<trigger> <good|bad>` in reply to an answer of the agent rates it, see [crate::feedback].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedbackSetting {
+    pub trigger: String,
+    pub good: String,
+    pub bad: String,
+}
+
+/// Messages neither logged nor handled, e.g. those of other bots in the group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IgnoreSetting {
+    pub bot_ids: Vec<i64>,
+    /// Commands of other bots.
+    pub prefixes: Vec<String>,
+    /// Messages of images only with more images than this.
+    pub max_images: Option<usize>,
+}
+
+/// Images posted again within a window, see [crate::repost].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RepostSetting {
+    pub window_days: i64,
+    /// Smaller images, mostly stickers, are not tracked.
+    pub min_kb: u64,
+    /// Reply to reposts, `<!sender!>` and `<!date!>` are of the original posting. Reposts are
+    /// only logged if absent.
+    pub tease: Option<String>,
+}
+
+/// Daily uses of costly features per member, see [crate::quota].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuotaSetting {
+    /// Memes rendered, unlimited if absent, so are the others.
+    pub image: Option<i64>,
+    /// History searches and questions.
+    pub search: Option<i64>,
+    /// History and log dumps by commands.
+    pub dump: Option<i64>,
+    /// `<query>` replies quota left today of the sender.
+    pub query: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum QuotaKind {
+    Image,
+    Search,
+    Dump,
+}
+
+impl QuotaKind {
+    pub const ALL: [QuotaKind; 3] = [Self::Image, Self::Search, Self::Dump];
+
+    /// Name in config, commands and the database.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Search => "search",
+            Self::Dump => "dump",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.as_str() == s)
+    }
+
+    pub fn limit(self, setting: &QuotaSetting) -> Option<i64> {
+        match self {
+            Self::Image => setting.image,
+            Self::Search => setting.search,
+            Self::Dump => setting.dump,
+        }
+    }
+}
+
+/// Guild (频道) channels handled as this group, see [crate::guild].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GuildSetting {
+    pub channels: Vec<GuildChannel>,
+}
+
+/// Channel of a guild, ids are strings in OneBot guild events.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GuildChannel {
+    pub guild_id: String,
+    pub channel_id: String,
+}
+
+/// Messages of these members are logged only.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlacklistSetting {
+    pub user_ids: Vec<i64>,
+}
+
+/// Messages of a member beyond `max_messages` within `window_sec` are logged only, admins of
+/// [CommandSetting] are exempted.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RateLimitSetting {
+    // user id -> times of messages within window
+    #[serde(skip)]
+    pub recent: Mutex<HashMap<i64, VecDeque<Instant>>>,
+
+    pub window_sec: u64,
+    pub max_messages: usize,
+}
+
+/// Fixed replies to messages containing keywords, the first matching rule applies.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeywordSetting {
+    pub rules: Vec<KeywordRule>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeywordRule {
+    pub keyword: String,
+    pub reply: String,
+    /// Skip later stages once replied.
+    #[serde(default)]
+    pub stop: bool,
+}
+
+/// Words masked in bot output, see [crate::word_filter].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WordFilterSetting {
+    pub words: Vec<String>,
+    pub mask: char,
+    /// Warn members who send the words, strikes are counted in database.
+    pub warn: bool,
+}
+
+/// Review join requests when the bot is admin, see [crate::join_request].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JoinRequestSetting {
+    /// Approve users of at least this QQ level.
+    pub min_level: Option<i64>,
+    /// Approve if the request comment contains any of them.
+    pub keywords: Vec<String>,
+    /// Let agent of the group judge the request comment by this criteria.
+    pub ai_criteria: Option<String>,
+    /// Requests not approved by the above.
+    pub otherwise: JoinRequestAction,
+    pub reject_reason: Option<String>,
+    /// Announce approvals in the group.
+    pub announce: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinRequestAction {
+    /// Notify command admins of the group in private chat.
+    Queue,
+    Reject,
+}
+
+/// Detect floods, repeated content and suspicious links per member, admins of [CommandSetting] are
+/// exempted.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ModerationSetting {
+    #[serde(skip)]
+    link_regex: RegexSet,
+    // user id -> (time, fingerprint) of messages within window
+    #[serde(skip)]
+    pub recent: Mutex<HashMap<i64, VecDeque<(Instant, u64)>>>,
+
+    pub window_sec: u64,
+    pub flood_messages: usize,
+    pub repeat_messages: usize,
+    pub link_patterns: Vec<String>,
+    pub action: ModerationAction,
+    pub mute_sec: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationAction {
+    Warn,
+    /// Notify command admins of the group in private chat.
+    Report,
+    /// Falls back to report if bot is not admin of the group.
+    Mute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    Flood,
+    Repeat,
+    Link,
+}
+
+impl Violation {
+    /// Description in the locale of the group, e.g. 刷屏.
+    pub fn describe(&self, group_id: i64) -> String {
+        let key = match self {
+            Violation::Flood => "moderation.flood",
+            Violation::Repeat => "moderation.repeat",
+            Violation::Link => "moderation.link",
+        };
+        crate::tr!(group_id, key)
+    }
+}
+
+/// Join or break a repeat (复读) chain of members.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RepeatSetting {
+    #[serde(skip)]
+    pub chain: Mutex<RepeatChain>,
+
+    /// Number of distinct members repeating before the bot acts.
+    pub threshold: usize,
+    pub join_probability: f64,
+    pub break_probability: f64,
+    pub cooldown_sec: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct RepeatChain {
+    fingerprint: u64,
+    sender_ids: Vec<i64>,
+    // the bot acts at most once per chain
+    acted: bool,
+    last_act: Option<Instant>,
+}
+
+/// Daily sign-in and points earned by activity.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PointsSetting {
+    pub sign_in: String,
+    pub my_points: String,
+    pub leaderboard: String,
+    pub sign_in_points: i64,
+    /// Extra points per consecutive day, capped by `max_streak_bonus`.
+    pub streak_bonus: i64,
+    pub max_streak_bonus: i64,
+    pub activity_points: i64,
+    pub activity_daily_cap: i64,
+    pub leaderboard_size: i64,
+}
+
+/// Levels by experience earned from chatting, see [crate::level].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LevelSetting {
+    pub my_level: String,
+    pub ranking: String,
+    pub ranking_size: i64,
+    pub xp_per_message: i64,
+    /// Messages with fewer non-blank chars earn nothing.
+    pub min_chars: usize,
+    /// Minimum interval between two awards of a member.
+    pub cooldown_sec: i64,
+    pub daily_cap: i64,
+    /// Experience from level L to L + 1 is `level_xp * L`.
+    pub level_xp: i64,
+    /// Announce level-ups in the group.
+    pub announce: bool,
+}
+
+/// Multi-round quiz game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuizSetting {
+    pub start: String,
+    pub stop: String,
+    pub rounds: usize,
+    pub round_timeout_sec: u64,
+    /// Toml file under data path, questions are generated by agent of the group if absent.
+    pub question_file: Option<String>,
+}
+
+/// Idiom solitaire (成语接龙) game.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdiomSetting {
+    pub start: String,
+    pub stop: String,
+    pub hint: String,
+    /// Idioms one per line under data path, the bundled dictionary is used if absent.
+    pub dictionary_file: Option<String>,
+}
+
+/// RSS/Atom feeds subscribed by command admins, new entries are posted to the group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FeedSetting {
+    pub subscribe: String,
+    pub unsubscribe: String,
+    pub list: String,
+    /// Used if the subscribe command does not specify an interval.
+    pub poll_interval_sec: i64,
+    pub max_feeds: i64,
+    /// Placeholders: `<!feed!>`, `<!title!>`, `<!link!>`.
+    pub template: String,
+}
+
+/// Render text onto meme templates, built-in templates are drawn without image files.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MemeSetting {
+    pub trigger: String,
+    /// Font under data path, must cover CJK characters.
+    pub font_file: String,
+    /// Directory under data path, each image is a template named by its file stem.
+    pub template_dir: Option<String>,
+    /// Points spent per meme if points are enabled in the group.
+    pub cost: i64,
+}
+
+/// Search chat history of the group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchSetting {
+    /// Reply the earliest and latest message containing the keyword.
+    pub who_said: String,
+    /// Answer `<ask> <question>` by agent of the group from related history, citing who said it.
+    pub ask: Option<String>,
+    pub ask_prompt: Option<String>,
+    /// Most related segments given to the agent.
+    pub ask_segments: Option<i64>,
+}
+
+/// Yearly statistics of a member compiled from chat history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnnualReportSetting {
+    pub trigger: String,
+    pub favorite_words: usize,
+}
+
+/// Heatmap image of messages by hour and weekday.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActivitySetting {
+    pub trigger: String,
+    /// Messages of the last days are counted.
+    pub days: i64,
+    /// Font under data path for axis labels, labels are omitted if not found.
+    pub font_file: Option<String>,
+}
+
+/// Recap of recent chat history by agent of the group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SummarySetting {
+    pub trigger: String,
+    /// History of the last hours is summarized, `<trigger> N` for the last N hours.
+    pub hours: i64,
+    pub max_hours: i64,
+    pub prompt: String,
+    /// Longer history is summarized chunk by chunk, then the partial summaries are merged.
+    pub chunk_chars: usize,
+}
+
+/// Statistics of at segments, `<trigger> [days]` filters the last days, `今天` for today.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MentionSetting {
+    /// Who at the sender the most.
+    pub who_cares: String,
+    /// Who are at the most.
+    pub ranking: String,
+    pub top_n: i64,
+}
+
+/// Group card of the bot, see [crate::card].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CardSetting {
+    pub names: Vec<String>,
+    /// Rotate through `names` at this interval, never if absent.
+    pub rotate_interval_sec: Option<u64>,
+}
+
+/// Live notifications subscribed by users in private chat.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PrivateLiveSetting {
+    // room id -> last time notified
+    #[serde(skip)]
+    pub last_notified: Mutex<HashMap<String, Instant>>,
+
+    pub subscribe: String,
+    pub unsubscribe: String,
+    pub max_rooms_per_user: i64,
+    pub poll_interval_sec: u64,
+    /// Rooms turning online again within this many seconds after a notification are not notified.
+    pub notify_window_sec: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PrivateAgentSetting {
+    // user id -> last time replied
+    #[serde(skip)]
+    pub last_reply: Mutex<HashMap<i64, Instant>>,
+
+    pub api_url: ApiUrl,
+    pub api_key: String,
+    pub model: String,
+    pub dev_prompt: String,
+    pub user_prompt: String,
+    pub aware_history_messages: i64,
+    pub rate_limit_sec: u64,
+    pub allow_ids: Vec<i64>,
+    // id -> persona
+    pub personas: HashMap<String, String>,
+}
+
+/// Private messages from group members who are not friends, see [crate::private].
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TempSessionSetting {
+    // user id -> last time handled
+    #[serde(skip)]
+    pub last_handled: Mutex<HashMap<i64, Instant>>,
+
+    pub policy: TempSessionPolicy,
+    /// Later messages of a user within this many seconds are dropped, as temporary sessions are
+    /// a common way of spamming.
+    pub rate_limit_sec: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TempSessionPolicy {
+    Ignore,
+    /// Reply with the private agent, regardless of its allowlist.
+    Agent,
+    /// Forward to the main admin.
+    Forward,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CommandSetting {
+    #[serde(skip)]
+    regex_set: RegexSet,
+    // parallel to regex_set, only configured commands are present
+    #[serde(skip)]
+    regex_list: Vec<(CommandKind, Regex)>,
+
+    pub mute: String,
+    pub unmute: String,
+    pub switch_model: String,
+    pub dump_history: String,
+    pub dump_log: String,
+    pub error_stat: Option<String>,
+    pub reload_config: Option<String>,
+    /// Send dump results to the requester in private chat instead of the group.
+    pub private_dump: Option<bool>,
+    /// Post `<announce> <content>` as announcement.
+    pub announce: Option<String>,
+    /// At all members in announcements if the bot is admin of the group.
+    pub announce_at_all: Option<bool>,
+    /// Resend latest messages by `<recap> N` as a forward message.
+    pub recap: Option<String>,
+    /// Backfill history by `<import_history> <file>`, see [crate::history_import].
+    pub import_history: Option<String>,
+    /// Archive config and database for migration, main admin only, see [crate::migration].
+    pub export_state: Option<String>,
+    /// Export fine-tuning dataset of the group, sent to the requester privately.
+    pub export_dataset: Option<String>,
+    /// Compare engagement of agent prompt variants, see [crate::ab_test].
+    pub ab_report: Option<String>,
+    /// Ratings of agent replies per persona and model, see [crate::feedback].
+    pub feedback_report: Option<String>,
+    /// Size of the database and result of the last check, see [crate::db_check].
+    pub db_health: Option<String>,
+    /// Cancel the latest answer of the agent waiting in outbox, see [crate::outbox].
+    pub cancel_send: Option<String>,
+    /// Resend saved images by `<recent_images> N [page]` as a forward message.
+    pub recent_images: Option<String>,
+    /// List group files as a forward message.
+    pub group_files: Option<String>,
+    /// Set group card of the bot by `<set_card> <card>`, see [crate::card].
+    pub set_card: Option<String>,
+    /// Grant extra uses for today by `<grant_quota> <user_id> <kind> N`, see [crate::quota].
+    pub grant_quota: Option<String>,
+    /// Uses of each feature in the last 30 days, see [crate::analytics].
+    pub usage_stat: Option<String>,
+    /// Effective config and runtime state of the group with secrets redacted, see
+    /// [crate::config_view].
+    pub show_config: Option<String>,
+    /// Uptime and restarts of the plugin, see [crate::uptime].
+    pub run_status: Option<String>,
+    /// Accept commands spoken in voice messages of admins, requires `stt`.
+    pub voice: Option<bool>,
+    pub admin_ids: Vec<i64>,
+    /// Where command admins come from, `admin_ids` only if absent.
+    pub admin_source: Option<AdminSource>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminSource {
+    /// `admin_ids` in config.
+    #[default]
+    Config,
+    /// Owner and admins of the group.
+    Platform,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CommandKind {
+    Mute,
+    Unmute,
+    SwitchModel,
+    DumpHistory,
+    DumpLog,
+    ErrorStat,
+    ReloadConfig,
+    Announce,
+    Recap,
+    ImportHistory,
+    ExportState,
+    ExportDataset,
+    AbReport,
+    FeedbackReport,
+    DbHealth,
+    CancelSend,
+    RecentImages,
+    GroupFiles,
+    SetCard,
+    GrantQuota,
+    UsageStat,
+    ShowConfig,
+    RunStatus,
+}
+
+#[derive(Debug)]
+pub enum GroupCommand {
+    Mute,
+    Unmute,
+    SwitchModel(String),
+    DumpHistory(i64),
+    DumpLog(i64),
+    ErrorStat,
+    ReloadConfig,
+    Announce(String),
+    Recap(i64),
+    ImportHistory(String),
+    ExportState,
+    ExportDataset,
+    AbReport,
+    FeedbackReport,
+    DbHealth,
+    CancelSend,
+    /// (count, page)
+    RecentImages(i64, i64),
+    GroupFiles,
+    SetCard(String),
+    /// (user_id, kind, count)
+    GrantQuota(i64, QuotaKind, i64),
+    UsageStat,
+    ShowConfig,
+    RunStatus,
+}
+
+impl GroupCommand {
+    /// Key of the command in config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupCommand::Mute => "mute",
+            GroupCommand::Unmute => "unmute",
+            GroupCommand::SwitchModel(_) => "switch_model",
+            GroupCommand::DumpHistory(_) => "dump_history",
+            GroupCommand::DumpLog(_) => "dump_log",
+            GroupCommand::ErrorStat => "error_stat",
+            GroupCommand::ReloadConfig => "reload_config",
+            GroupCommand::Announce(_) => "announce",
+            GroupCommand::Recap(_) => "recap",
+            GroupCommand::ImportHistory(_) => "import_history",
+            GroupCommand::ExportState => "export_state",
+            GroupCommand::ExportDataset => "export_dataset",
+            GroupCommand::AbReport => "ab_report",
+            GroupCommand::FeedbackReport => "feedback_report",
+            GroupCommand::DbHealth => "db_health",
+            GroupCommand::CancelSend => "cancel_send",
+            GroupCommand::RecentImages(..) => "recent_images",
+            GroupCommand::GroupFiles => "group_files",
+            GroupCommand::SetCard(_) => "set_card",
+            GroupCommand::GrantQuota(..) => "grant_quota",
+            GroupCommand::UsageStat => "usage_stat",
+            GroupCommand::ShowConfig => "show_config",
+            GroupCommand::RunStatus => "run_status",
+        }
+    }
+
+    /// Commands acting beyond the group, e.g. on the shared config or logs of all groups.
+    pub fn is_global(&self) -> bool {
+        matches!(
+            self,
+            GroupCommand::DumpLog(_)
+                | GroupCommand::ErrorStat
+                | GroupCommand::ReloadConfig
+                | GroupCommand::DbHealth
+                | GroupCommand::UsageStat
+                | GroupCommand::RunStatus
+        )
+    }
+}
+
+impl CommandSetting {
+    pub fn init_regex(&mut self) -> PluginResult<()> {
+        use CommandKind::*;
+        let mut patterns = vec![
+            (Mute, self.mute.clone()),
+            (Unmute, self.unmute.clone()),
+            (
+                SwitchModel,
+                format!(
+                    r"{}\s+(?<model>gpt4o|chatgpt-4o-latest|gpt-4o-mini|o1-mini|o1-preview)",
+                    self.switch_model
+                ),
+            ),
+            (
+                DumpHistory,
+                format!(r"{}\s+(?<count>\d+)", self.dump_history),
+            ),
+            (DumpLog, format!(r"{}\s+(?<count>\d+)", self.dump_log)),
+        ];
+        if let Some(ref error_stat) = self.error_stat {
+            patterns.push((ErrorStat, error_stat.clone()));
+        }
+        if let Some(ref reload_config) = self.reload_config {
+            patterns.push((ReloadConfig, reload_config.clone()));
+        }
+        if let Some(ref announce) = self.announce {
+            patterns.push((Announce, format!(r"(?s)^{announce}\s+(?<content>.+)")));
+        }
+        if let Some(ref recap) = self.recap {
+            patterns.push((Recap, format!(r"^{recap}\s+(?<count>\d+)")));
+        }
+        if let Some(ref import) = self.import_history {
+            patterns.push((ImportHistory, format!(r"^{import}\s+(?<file>\S+)$")));
+        }
+        if let Some(ref export) = self.export_state {
+            patterns.push((ExportState, format!(r"^{export}$")));
+        }
+        if let Some(ref export) = self.export_dataset {
+            patterns.push((ExportDataset, format!(r"^{export}$")));
+        }
+        if let Some(ref ab_report) = self.ab_report {
+            patterns.push((AbReport, format!(r"^{ab_report}$")));
+        }
+        if let Some(ref feedback_report) = self.feedback_report {
+            patterns.push((FeedbackReport, format!(r"^{feedback_report}$")));
+        }
+        if let Some(ref db_health) = self.db_health {
+            patterns.push((DbHealth, format!(r"^{db_health}$")));
+        }
+        if let Some(ref cancel_send) = self.cancel_send {
+            patterns.push((CancelSend, format!(r"^{cancel_send}$")));
+        }
+        if let Some(ref images) = self.recent_images {
+            patterns.push((
+                RecentImages,
+                format!(r"^{images}\s+(?<count>\d+)(\s+(?<page>\d+))?$"),
+            ));
+        }
+        if let Some(ref files) = self.group_files {
+            patterns.push((GroupFiles, format!(r"^{files}$")));
+        }
+        if let Some(ref set_card) = self.set_card {
+            patterns.push((SetCard, format!(r"^{set_card}\s+(?<card>.+)$")));
+        }
+        if let Some(ref grant) = self.grant_quota {
+            patterns.push((
+                GrantQuota,
+                format!(r"^{grant}\s+(?<user>\d+)\s+(?<kind>image|search|dump)\s+(?<count>\d+)$"),
+            ));
+        }
+        if let Some(ref usage_stat) = self.usage_stat {
+            patterns.push((UsageStat, format!(r"^{usage_stat}$")));
+        }
+        if let Some(ref show_config) = self.show_config {
+            patterns.push((ShowConfig, format!(r"^{show_config}$")));
+        }
+        if let Some(ref run_status) = self.run_status {
+            patterns.push((RunStatus, format!(r"^{run_status}$")));
+        }
+
+        let mut regex_list = Vec::with_capacity(patterns.len());
+        for (kind, pat) in patterns.iter() {
+            regex_list.push((*kind, Regex::new(pat)?));
+        }
+        self.regex_set = RegexSet::new(patterns.iter().map(|(_, pat)| pat))?;
+        self.regex_list = regex_list;
+
+        let mut buf = String::new();
+        for (kind, pat) in patterns.iter() {
+            buf.push_str(&format!("{kind:?}: {pat}\n"));
+        }
+        std_info!(
+            "
+            Initialize regex complete.
+            {buf}
+            "
+        );
+        Ok(())
+    }
+
+    /// False if [CommandSetting::init_regex] failed, no command is recognized then.
+    pub fn is_ready(&self) -> bool {
+        !self.regex_list.is_empty()
+    }
+
+    pub fn parse_command(&self, input: &str) -> Option<GroupCommand> {
+        for idx in self.regex_set.matches(input).iter() {
+            let (kind, regex) = self.regex_list.get(idx)?;
+            match kind {
+                CommandKind::Mute => {
+                    return Some(GroupCommand::Mute);
+                }
+                CommandKind::Unmute => {
+                    return Some(GroupCommand::Unmute);
+                }
+                CommandKind::SwitchModel => {
+                    if let Some(caps) = regex.captures(input) {
+                        if let Some(model_match) = caps.name("model") {
+                            return Some(GroupCommand::SwitchModel(
+                                model_match.as_str().to_string(),
+                            ));
+                        }
+                    }
+                }
+                CommandKind::DumpHistory => {
+                    if let Some(count) = capture_count(regex, input) {
+                        return Some(GroupCommand::DumpHistory(count));
+                    }
+                }
+                CommandKind::DumpLog => {
+                    if let Some(count) = capture_count(regex, input) {
+                        return Some(GroupCommand::DumpLog(count));
+                    }
+                }
+                CommandKind::ErrorStat => {
+                    return Some(GroupCommand::ErrorStat);
+                }
+                CommandKind::ReloadConfig => {
+                    return Some(GroupCommand::ReloadConfig);
+                }
+                CommandKind::Announce => {
+                    if let Some(content) = regex.captures(input).and_then(|c| c.name("content")) {
+                        return Some(GroupCommand::Announce(content.as_str().trim().to_string()));
+                    }
+                }
+                CommandKind::Recap => {
+                    if let Some(count) = capture_count(regex, input) {
+                        return Some(GroupCommand::Recap(count));
+                    }
+                }
+                CommandKind::ImportHistory => {
+                    if let Some(file) = regex.captures(input).and_then(|c| c.name("file")) {
+                        return Some(GroupCommand::ImportHistory(file.as_str().to_string()));
+                    }
+                }
+                CommandKind::ExportState => {
+                    return Some(GroupCommand::ExportState);
+                }
+                CommandKind::ExportDataset => {
+                    return Some(GroupCommand::ExportDataset);
+                }
+                CommandKind::AbReport => {
+                    return Some(GroupCommand::AbReport);
+                }
+                CommandKind::FeedbackReport => {
+                    return Some(GroupCommand::FeedbackReport);
+                }
+                CommandKind::DbHealth => {
+                    return Some(GroupCommand::DbHealth);
+                }
+                CommandKind::CancelSend => {
+                    return Some(GroupCommand::CancelSend);
+                }
+                CommandKind::RecentImages => {
+                    let Some(caps) = regex.captures(input) else {
+                        continue;
+                    };
+                    let Ok(count) = caps["count"].parse() else {
+                        continue;
+                    };
+                    let page = caps
+                        .name("page")
+                        .and_then(|p| p.as_str().parse().ok())
+                        .unwrap_or(1);
+                    return Some(GroupCommand::RecentImages(count, page));
+                }
+                CommandKind::GroupFiles => {
+                    return Some(GroupCommand::GroupFiles);
+                }
+                CommandKind::SetCard => {
+                    if let Some(card) = regex.captures(input).and_then(|c| c.name("card")) {
+                        return Some(GroupCommand::SetCard(card.as_str().trim().to_string()));
+                    }
+                }
+                CommandKind::GrantQuota => {
+                    let Some(caps) = regex.captures(input) else {
+                        continue;
+                    };
+                    let (Ok(user_id), Some(kind), Ok(count)) = (
+                        caps["user"].parse(),
+                        QuotaKind::parse(&caps["kind"]),
+                        caps["count"].parse(),
+                    ) else {
+                        continue;
+                    };
+                    return Some(GroupCommand::GrantQuota(user_id, kind, count));
+                }
+                CommandKind::UsageStat => {
+                    return Some(GroupCommand::UsageStat);
+                }
+                CommandKind::ShowConfig => {
+                    return Some(GroupCommand::ShowConfig);
+                }
+                CommandKind::RunStatus => {
+                    return Some(GroupCommand::RunStatus);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl CommandSetting {
+    /// Command admins of the group according to [AdminSource].
+    pub async fn admins(&self, group_id: i64) -> Vec<i64> {
+        let source = self.admin_source.unwrap_or_default();
+        let mut admins = Vec::new();
+        if source != AdminSource::Platform {
+            admins.extend(&self.admin_ids);
+        }
+        if source != AdminSource::Config {
+            for id in util::get_group_admins(group_id).await {
+                if !admins.contains(&id) {
+                    admins.push(id);
+                }
+            }
+        }
+        admins
+    }
+
+    pub async fn is_admin(&self, group_id: i64, user_id: i64) -> bool {
+        self.admins(group_id).await.contains(&user_id)
+    }
+
+    /// Whether the member may run [GroupCommand::is_global] commands. Platform roles only grant
+    /// commands of the group, these are limited to `admin_ids` and the main admin.
+    pub fn is_global_admin(&self, user_id: i64) -> bool {
+        self.admin_ids.contains(&user_id) || user_id == *ADMIN_QQ.get().unwrap()
+    }
+}
+
+fn capture_count(regex: &Regex, input: &str) -> Option<i64> {
+    let caps = regex.captures(input)?;
+    caps.name("count")?.as_str().parse::<i64>().ok()
+}
+
+pub enum LiveSwitch {
+    On,
+    Off,
+    Init,
+    Trap,
+}
+
+impl LiveSetting {
+    pub fn get_switch(&self) -> LiveSwitch {
+        match self.switch.load(std::sync::atomic::Ordering::Acquire) {
+            0 => LiveSwitch::Off,
+            1 => LiveSwitch::On,
+            2 => LiveSwitch::Init,
+            _ => LiveSwitch::Trap,
+        }
+    }
+
+    pub fn set_switch(&self, switch: LiveSwitch) {
+        let value = match switch {
+            LiveSwitch::Off => 0,
+            LiveSwitch::On => 1,
+            LiveSwitch::Init => 2,
+            LiveSwitch::Trap => 3,
+        };
+        self.switch
+            .store(value, std::sync::atomic::Ordering::Release);
+    }
+
+    /// Returns true and records the time if not notified within the window.
+    pub fn try_notify(&self) -> bool {
+        let mut last_notified = self.last_notified.lock().unwrap_or_else(|e| e.into_inner());
+        let window = Duration::from_secs(self.notify_window_sec.unwrap_or(0));
+        let now = Instant::now();
+        match *last_notified {
+            Some(last) if now.duration_since(last) < window => false,
+            _ => {
+                *last_notified = Some(now);
+                true
+            }
+        }
+    }
+
+    /// Groups notified of status changes, the owner group first.
+    pub fn target_groups(&self, group_id: i64) -> Vec<i64> {
+        let mut groups = vec![group_id];
+        for &id in self.notify_group_ids.iter().flatten() {
+            if !groups.contains(&id) {
+                groups.push(id);
+            }
+        }
+        groups
+    }
+}
+
+impl AgentSetting {
+    pub fn mute(&self) {
+        self.mute.store(true, std::sync::atomic::Ordering::Release);
+    }
+
+    pub fn unmute(&self) {
+        self.mute.store(false, std::sync::atomic::Ordering::Release);
+    }
+
+    pub fn is_mute(&self) -> bool {
+        self.mute.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    pub async fn set_model(&self, model: String) {
+        let mut cur_model = self.cur_model.write().await;
+        *cur_model = model;
+    }
+
+    pub async fn get_model(&self) -> String {
+        let cur_model = self.cur_model.read().await;
+        cur_model.to_string()
+    }
+
+    pub fn load_members(&mut self) {
+        let mut buf = String::new();
+        for (name, desc) in self.known_members.values() {
+            buf.push_str("- ");
+            buf.push_str(name);
+            buf.push_str(": ");
+            buf.push_str(desc);
+            buf.push('\n');
+        }
+        self.dev_prompt = self.dev_prompt.replace("<!members!>", &buf);
+        self.user_prompt = self.user_prompt.replace("<!members!>", &buf);
+        if let Some(ref mut ab_test) = self.ab_test {
+            let prompts = [&mut ab_test.dev_prompt, &mut ab_test.user_prompt];
+            for prompt in prompts.into_iter().flatten() {
+                *prompt = prompt.replace("<!members!>", &buf);
+            }
+        }
+        for window in self.persona_schedule.iter_mut().flatten() {
+            let prompts = [&mut window.dev_prompt, &mut window.user_prompt];
+            for prompt in prompts.into_iter().flatten() {
+                *prompt = prompt.replace("<!members!>", &buf);
+            }
+        }
+    }
+
+    /// Switch to the persona window containing `minute_of_day`, or back to base prompts if there
+    /// is none. Returns the name of the new persona if it has changed.
+    pub fn update_persona(&self, minute_of_day: u32) -> Option<&str> {
+        let windows = self.persona_schedule.as_deref().unwrap_or_default();
+        let active = windows
+            .iter()
+            .position(|w| w.contains(minute_of_day) == Some(true));
+        let mut cur = self.active_persona.lock().unwrap();
+        if *cur == active {
+            return None;
+        }
+        *cur = active;
+        Some(active.map_or(ab_test::BASE_VARIANT, |idx| &windows[idx].name))
+    }
+
+    /// (variant, dev prompt, user prompt) of a reply. Prompts of the active persona window are used
+    /// if any, otherwise the variant is drawn with equal chance.
+    pub fn pick_prompts(&self) -> (&str, &str, &str) {
+        let active = *self.active_persona.lock().unwrap();
+        let window = active.and_then(|idx| self.persona_schedule.as_ref()?.get(idx));
+        if let Some(window) = window {
+            return (
+                &window.name,
+                window.dev_prompt.as_deref().unwrap_or(&self.dev_prompt),
+                window.user_prompt.as_deref().unwrap_or(&self.user_prompt),
+            );
+        }
+        let base = (
+            ab_test::BASE_VARIANT,
+            self.dev_prompt.as_str(),
+            self.user_prompt.as_str(),
+        );
+        let Some(ref ab_test) = self.ab_test else {
+            return base;
+        };
+        if rand::random::<bool>() {
+            return base;
+        }
+        (
+            &ab_test.name,
+            ab_test.dev_prompt.as_deref().unwrap_or(&self.dev_prompt),
+            ab_test.user_prompt.as_deref().unwrap_or(&self.user_prompt),
+        )
+    }
+}
+
+impl PrivateLiveSetting {
+    /// Returns true and records the time if `room_id` has not been notified within the window.
+    pub fn try_notify(&self, room_id: &str) -> bool {
+        let mut last_notified = self.last_notified.lock().unwrap_or_else(|e| e.into_inner());
+        let window = Duration::from_secs(self.notify_window_sec.unwrap_or(0));
+        let now = Instant::now();
+        match last_notified.get(room_id) {
+            Some(last) if now.duration_since(*last) < window => false,
+            _ => {
+                last_notified.insert(room_id.to_string(), now);
+                true
+            }
+        }
+    }
+}
+
+impl PrivateAgentSetting {
+    /// Returns true and records the time if user has not been replied within rate limit.
+    pub fn try_acquire(&self, user_id: i64) -> bool {
+        let mut last_reply = self.last_reply.lock().unwrap_or_else(|e| e.into_inner());
+        let limit = Duration::from_secs(self.rate_limit_sec);
+        let now = Instant::now();
+        match last_reply.get(&user_id) {
+            Some(last) if now.duration_since(*last) < limit => false,
+            _ => {
+                last_reply.insert(user_id, now);
+                true
+            }
+        }
+    }
+
+    pub fn get_persona(&self, user_id: i64) -> &str {
+        self.personas
+            .get(&user_id.to_string())
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+}
+
+impl ModerationSetting {
+    pub fn init_regex(&mut self) -> Result<(), regex::Error> {
+        self.link_regex = RegexSet::new(&self.link_patterns)?;
+        Ok(())
+    }
+
+    /// False if [ModerationSetting::init_regex] failed, links are not checked then.
+    pub fn is_ready(&self) -> bool {
+        self.link_regex.len() == self.link_patterns.len()
+    }
+
+    /// Record a message and check it, history of the user is cleared once a violation is found.
+    pub fn record(&self, user_id: i64, text: &str, fingerprint: u64) -> Option<Violation> {
+        if self.link_regex.is_match(text) {
+            return Some(Violation::Link);
+        }
+        let now = Instant::now();
+        let window = Duration::from_secs(self.window_sec);
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        // members who went quiet are dropped, so the map only holds recent senders
+        recent.retain(|_, history| {
+            history.retain(|(t, _)| now.duration_since(*t) < window);
+            !history.is_empty()
+        });
+        let history = recent.entry(user_id).or_default();
+        history.push_back((now, fingerprint));
+
+        let repeated = history.iter().filter(|(_, x)| *x == fingerprint).count();
+        let violation = if history.len() >= self.flood_messages {
+            Some(Violation::Flood)
+        } else if repeated >= self.repeat_messages {
+            Some(Violation::Repeat)
+        } else {
+            None
+        };
+        if violation.is_some() {
+            recent.remove(&user_id);
+        }
+        violation
+    }
+}
+
+impl GroupSetting {
+    pub fn runs(&self, stage: PipelineStage) -> bool {
+        self.pipeline.as_ref().is_none_or(|p| p.contains(&stage))
+    }
+
+    /// Stages to run in order.
+    pub fn stages(&self) -> &[PipelineStage] {
+        self.pipeline.as_deref().unwrap_or(&PipelineStage::ALL)
+    }
+}
+
+impl HeatSetting {
+    /// Record a message, returns true if the group just becomes heated.
+    pub fn record(&self, negative: bool, now: Instant) -> bool {
+        let window = Duration::from_secs(self.window_sec);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.recent.retain(|(t, _)| now.duration_since(*t) < window);
+        state.recent.push_back((now, negative));
+
+        let total = state.recent.len();
+        let negatives = state.recent.iter().filter(|(_, n)| *n).count();
+        if total < self.min_messages || (negatives as f64) < self.negative_ratio * total as f64 {
+            return false;
+        }
+        let was_heated = state.heated_until.is_some_and(|t| now < t);
+        // cooldown restarts while the argument goes on
+        state.heated_until = Some(now + Duration::from_secs(self.cooldown_sec));
+        !was_heated
+    }
+
+    pub fn is_heated(&self, now: Instant) -> bool {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.heated_until.is_some_and(|t| now < t)
+    }
+}
+
+impl TempSessionSetting {
+    /// Returns true and records the time if user has not been handled within rate limit.
+    pub fn try_acquire(&self, user_id: i64) -> bool {
+        let mut last_handled = self.last_handled.lock().unwrap_or_else(|e| e.into_inner());
+        let limit = Duration::from_secs(self.rate_limit_sec);
+        let now = Instant::now();
+        match last_handled.get(&user_id) {
+            Some(last) if now.duration_since(*last) < limit => false,
+            _ => {
+                last_handled.insert(user_id, now);
+                true
+            }
+        }
+    }
+}
+
+impl RateLimitSetting {
+    /// Record a message, false if the member exceeds the limit.
+    pub fn record(&self, user_id: i64, now: Instant) -> bool {
+        let window = Duration::from_secs(self.window_sec);
+        let mut recent = self.recent.lock().unwrap_or_else(|e| e.into_inner());
+        let history = recent.entry(user_id).or_default();
+        while history
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= window)
+        {
+            history.pop_front();
+        }
+        if history.len() >= self.max_messages {
+            return false;
+        }
+        history.push_back(now);
+        true
+    }
+}
+
+pub fn is_configured_group(group_id: i64) -> bool {
+    let config = CONFIG.get().unwrap();
+    config.groups.iter().flatten().any(|g| g.id == group_id)
+}
+
+/// Unconfigured groups run every stage, each handler decides on its own.
+pub fn pipeline_runs(group_id: i64, stage: PipelineStage) -> bool {
+    let config = CONFIG.get().unwrap();
+    config
+        .groups
+        .iter()
+        .flatten()
+        .find(|g| g.id == group_id)
+        .is_none_or(|g| g.runs(stage))
+}
+
+impl RepeatSetting {
+    /// Record a message, returns true if the chain just reaches threshold out of cooldown.
+    pub fn record(&self, sender_id: i64, fingerprint: u64) -> bool {
+        let mut chain = self.chain.lock().unwrap_or_else(|e| e.into_inner());
+        if chain.fingerprint != fingerprint {
+            chain.fingerprint = fingerprint;
+            chain.sender_ids = vec![sender_id];
+            chain.acted = false;
+            return false;
+        }
+        if !chain.sender_ids.contains(&sender_id) {
+            chain.sender_ids.push(sender_id);
+        }
+        if chain.acted || chain.sender_ids.len() < self.threshold {
+            return false;
+        }
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(self.cooldown_sec);
+        if chain
+            .last_act
+            .is_some_and(|t| now.duration_since(t) < cooldown)
+        {
+            return false;
+        }
+        chain.acted = true;
+        chain.last_act = Some(now);
+        true
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: Some(CONFIG_VERSION),
+            global: GlobalSetting::default(),
+            database: DatabaseSetting::default(),
+            object_storage: Some(ObjectStorageSetting::default()),
+            error_digest: Some(ErrorDigestSetting::default()),
+            db_check: Some(DbCheckSetting::default()),
+            disk: Some(DiskSetting::default()),
+            media: Some(MediaSetting::default()),
+            maintenance: Some(MaintenanceSetting::default()),
+            private_agent: Some(PrivateAgentSetting::default()),
+            temp_session: Some(TempSessionSetting::default()),
+            private_live: Some(PrivateLiveSetting::default()),
+            news: Some(NewsSetting::default()),
+            github: Some(GithubSetting::default()),
+            ocr: Some(OcrSetting::default()),
+            caption: Some(CaptionSetting::default()),
+            unfurl: Some(UnfurlSetting::default()),
+            image_safety: Some(ImageSafetySetting::default()),
+            stt: Some(SttSetting::default()),
+            bilibili: Some(BilibiliSetting::default()),
+            spend: Some(SpendSetting::default()),
+            stats_api: Some(StatsApiSetting::default()),
+            groups: Some(vec![GroupSetting::default(), GroupSetting::default()]),
+        }
+    }
+}
+
+impl Default for GlobalSetting {
+    fn default() -> Self {
+        Self {
+            max_sleep_sec: 8,
+            unknown_group: Some(UnknownGroupPolicy::Reply),
+            locale: Some(String::from(locale::DEFAULT_LOCALE)),
+            dry_run: Some(false),
+            timezone: Some(String::from("+08:00")),
+            analytics: Some(false),
+        }
+    }
+}
+
+impl Default for ObjectStorageSetting {
+    fn default() -> Self {
+        Self {
+            script_path: String::from("/a/b/c"),
+        }
+    }
+}
+
+impl Default for ErrorDigestSetting {
+    fn default() -> Self {
+        Self { hour: 9 }
+    }
+}
+
+impl Default for MaintenanceSetting {
+    fn default() -> Self {
+        Self {
+            restart: String::from("重启"),
+            check_update: String::from("检查更新"),
+            drain_timeout_sec: 30,
+        }
+    }
+}
+
+impl Default for DbCheckSetting {
+    fn default() -> Self {
+        Self { hour: 4 }
+    }
+}
+
+impl Default for DiskSetting {
+    fn default() -> Self {
+        Self {
+            interval_sec: 600,
+            min_free_mb: 1024,
+            prune: false,
+        }
+    }
+}
+
+impl Default for MediaSetting {
+    fn default() -> Self {
+        Self {
+            timeout_sec: 30,
+            max_file_mb: 20,
+            max_concurrent: 4,
+        }
+    }
+}
+
+impl Default for UnfurlSetting {
+    fn default() -> Self {
+        Self {
+            timeout_sec: 5,
+            max_bytes: 256 * 1024,
+            max_urls_per_message: 3,
+            blocklist: vec![],
+            preview_domains: vec![String::from("github.com")],
+        }
+    }
+}
+
+impl Default for BilibiliSetting {
+    fn default() -> Self {
+        Self {
+            cookie: String::from("SESSDATA=xxx; bili_jct=xxx"),
+            user_agent: None,
+        }
+    }
+}
+
+impl Default for StatsApiSetting {
+    fn default() -> Self {
+        Self {
+            bind: String::from("127.0.0.1:8787"),
+            token: String::from("xxx"),
+        }
+    }
+}
+
+impl Default for SpendSetting {
+    fn default() -> Self {
+        Self {
+            monthly_limit: 20.0,
+            default_price: 10.0,
+            prices: HashMap::from([
+                (String::from("gpt-4o"), 10.0),
+                (String::from("gpt-4o-mini"), 0.6),
+            ]),
+            fallback_model: Some(String::from("gpt-4o-mini")),
+        }
+    }
+}
+
+impl Default for CaptionSetting {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://127.0.0.1:8000/caption"),
+            api_key: None,
+            timeout_sec: 20,
+            max_chars: 30,
+        }
+    }
+}
+
+impl Default for SttSetting {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://127.0.0.1:8000/stt"),
+            api_key: None,
+            timeout_sec: 20,
+        }
+    }
+}
+
+impl Default for ImageSafetySetting {
+    fn default() -> Self {
+        Self {
+            endpoint: String::from("http://127.0.0.1:8000/nsfw"),
+            api_key: None,
+            timeout_sec: 10,
+            threshold: 0.8,
+            action: SafetyAction::Skip,
+        }
+    }
+}
+
+impl Default for OcrSetting {
+    fn default() -> Self {
+        Self {
+            engine: OcrEngine::Tesseract,
+            endpoint: None,
+            api_key: None,
+            language: String::from("chi_sim+eng"),
+            timeout_sec: 10,
+            min_chars: 2,
+        }
+    }
+}
+
+impl Default for GithubSetting {
+    fn default() -> Self {
+        Self {
+            token: None,
+            poll_interval_sec: 300,
+            repos: vec![GithubRepoSetting::default()],
+        }
+    }
+}
+
+impl Default for GithubRepoSetting {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(None),
+            repo: String::from("realzhujunhao/momo-bot"),
+            group_ids: vec![12345678],
+            events: vec![GithubEvent::Release, GithubEvent::Issue, GithubEvent::Star],
+        }
+    }
+}
+
+impl Default for NewsSetting {
+    fn default() -> Self {
         Self {
-            global: GlobalSetting::default(),
-            database: DatabaseSetting::default(),
-            object_storage: Some(ObjectStorageSetting::default()),
-            groups: Some(vec![GroupSetting::default(), GroupSetting::default()]),
+            hour: 8,
+            sources: vec![String::from("https://www.solidot.org/index.rss")],
+            headlines_per_source: 5,
+            group_ids: vec![12345678],
+            summary_prompt: Some(String::from(
+                "把以下新闻标题整理成一份简短的中文早报，按主题归类，不要编造标题以外的内容。",
+            )),
         }
     }
 }
 
-impl Default for GlobalSetting {
+impl Default for VerifySetting {
     fn default() -> Self {
-        Self { max_sleep_sec: 8 }
+        Self {
+            question: String::from("本群主播的直播间号是多少?"),
+            answers: vec![String::from("12345678")],
+            ai_criteria: None,
+            timeout_min: 10,
+            on_fail: VerifyFailAction::Notify,
+        }
     }
 }
 
-impl Default for ObjectStorageSetting {
+impl Default for ReminderSetting {
     fn default() -> Self {
         Self {
-            script_path: String::from("/a/b/c"),
+            trigger: String::from("提醒我"),
+            max_pending: 5,
+        }
+    }
+}
+
+impl Default for TakeoverSetting {
+    fn default() -> Self {
+        Self {
+            sensitive_words: ["政治", "宗教", "自杀", "赌博"].map(String::from).to_vec(),
+            threshold: 1,
+            approve: String::from("通过"),
+            reject: String::from("否决"),
+            edit: String::from("修改"),
+        }
+    }
+}
+
+impl Default for OutboxSetting {
+    fn default() -> Self {
+        Self { delay_sec: 5 }
+    }
+}
+
+impl Default for StickerSetting {
+    fn default() -> Self {
+        Self {
+            add: String::from("添加表情"),
+            remove: String::from("删除表情"),
+            list: String::from("表情列表"),
+        }
+    }
+}
+
+impl Default for HeatSetting {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(HeatState::default()),
+            window_sec: 120,
+            min_messages: 30,
+            negative_ratio: 0.3,
+            negative_words: ["傻", "滚", "闭嘴", "有病", "脑残", "恶心", "垃圾", "放屁"]
+                .map(String::from)
+                .to_vec(),
+            cooldown_sec: 600,
+        }
+    }
+}
+
+impl Default for FeedbackSetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("反馈"),
+            good: String::from("好"),
+            bad: String::from("差"),
+        }
+    }
+}
+
+impl Default for IgnoreSetting {
+    fn default() -> Self {
+        Self {
+            bot_ids: vec![34567890],
+            prefixes: vec![String::from("/")],
+            max_images: Some(3),
+        }
+    }
+}
+
+impl Default for RepostSetting {
+    fn default() -> Self {
+        Self {
+            window_days: 30,
+            min_kb: 20,
+            tease: Some(String::from("火星了, <!sender!>在<!date!>就发过这张图")),
+        }
+    }
+}
+
+impl Default for QuotaSetting {
+    fn default() -> Self {
+        Self {
+            image: Some(10),
+            search: Some(20),
+            dump: Some(3),
+            query: Some(String::from("我的额度")),
+        }
+    }
+}
+
+impl Default for GuildSetting {
+    fn default() -> Self {
+        Self {
+            channels: vec![GuildChannel {
+                guild_id: String::from("12345678901234567"),
+                channel_id: String::from("1234567"),
+            }],
+        }
+    }
+}
+
+impl Default for BlacklistSetting {
+    fn default() -> Self {
+        Self {
+            user_ids: vec![12345678],
+        }
+    }
+}
+
+impl Default for RateLimitSetting {
+    fn default() -> Self {
+        Self {
+            recent: Mutex::new(HashMap::new()),
+            window_sec: 60,
+            max_messages: 20,
+        }
+    }
+}
+
+impl Default for KeywordSetting {
+    fn default() -> Self {
+        Self {
+            rules: vec![KeywordRule {
+                keyword: String::from("早上好"),
+                reply: String::from("早上好呀"),
+                stop: false,
+            }],
+        }
+    }
+}
+
+impl Default for WordFilterSetting {
+    fn default() -> Self {
+        Self {
+            words: vec![String::from("傻逼"), String::from("废物")],
+            mask: '*',
+            warn: true,
+        }
+    }
+}
+
+impl Default for JoinRequestSetting {
+    fn default() -> Self {
+        Self {
+            min_level: Some(16),
+            keywords: vec![String::from("12345678")],
+            ai_criteria: Some(String::from("说明了自己是从哪里知道本群的")),
+            otherwise: JoinRequestAction::Queue,
+            reject_reason: None,
+            announce: true,
+        }
+    }
+}
+
+impl Default for ModerationSetting {
+    fn default() -> Self {
+        Self {
+            link_regex: RegexSet::empty(),
+            recent: Mutex::new(HashMap::new()),
+            window_sec: 10,
+            flood_messages: 8,
+            repeat_messages: 3,
+            link_patterns: vec![String::from(r"(?i)https?://(t\.cn|bit\.ly|tinyurl\.com)/")],
+            action: ModerationAction::Warn,
+            mute_sec: 600,
+        }
+    }
+}
+
+impl Default for RepeatSetting {
+    fn default() -> Self {
+        Self {
+            chain: Mutex::new(RepeatChain::default()),
+            threshold: 3,
+            join_probability: 0.5,
+            break_probability: 0.2,
+            cooldown_sec: 300,
+        }
+    }
+}
+
+impl Default for PointsSetting {
+    fn default() -> Self {
+        Self {
+            sign_in: String::from("签到"),
+            my_points: String::from("我的积分"),
+            leaderboard: String::from("积分排行"),
+            sign_in_points: 10,
+            streak_bonus: 2,
+            max_streak_bonus: 20,
+            activity_points: 1,
+            activity_daily_cap: 20,
+            leaderboard_size: 10,
+        }
+    }
+}
+
+impl Default for LevelSetting {
+    fn default() -> Self {
+        Self {
+            my_level: String::from("我的等级"),
+            ranking: String::from("等级排行"),
+            ranking_size: 10,
+            xp_per_message: 10,
+            min_chars: 2,
+            cooldown_sec: 60,
+            daily_cap: 300,
+            level_xp: 100,
+            announce: true,
+        }
+    }
+}
+
+impl Default for QuizSetting {
+    fn default() -> Self {
+        Self {
+            start: String::from("开始答题"),
+            stop: String::from("结束答题"),
+            rounds: 5,
+            round_timeout_sec: 30,
+            question_file: Some(String::from("quiz.toml")),
+        }
+    }
+}
+
+impl Default for IdiomSetting {
+    fn default() -> Self {
+        Self {
+            start: String::from("开始接龙"),
+            stop: String::from("结束接龙"),
+            hint: String::from("接龙提示"),
+            dictionary_file: None,
+        }
+    }
+}
+
+impl Default for FeedSetting {
+    fn default() -> Self {
+        Self {
+            subscribe: String::from("订阅RSS"),
+            unsubscribe: String::from("取消订阅RSS"),
+            list: String::from("RSS列表"),
+            poll_interval_sec: 600,
+            max_feeds: 10,
+            template: String::from("<!feed!>更新了: <!title!>\n<!link!>"),
+        }
+    }
+}
+
+impl Default for MemeSetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("做表情"),
+            font_file: String::from("font.ttf"),
+            template_dir: Some(String::from("memes")),
+            cost: 0,
+        }
+    }
+}
+
+impl Default for SearchSetting {
+    fn default() -> Self {
+        Self {
+            who_said: String::from("谁说过"),
+            ask: Some(String::from("群史问答")),
+            ask_prompt: Some(formatdoc! {
+                "
+                You answer questions about the chat history of a group.
+                Answer only from the given history, and say so if it does not contain the answer.
+                Cite the date and speaker of each fact you use, like \"2024-03-02 张三说过...\".
+                Speak only in Mandarin Chinese, and keep the answer within 5 sentences.
+                "
+            }),
+            ask_segments: Some(30),
+        }
+    }
+}
+
+impl Default for AnnualReportSetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("年度报告"),
+            favorite_words: 3,
+        }
+    }
+}
+
+impl Default for ActivitySetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("活跃热力图"),
+            days: 28,
+            font_file: Some(String::from("font.ttf")),
+        }
+    }
+}
+
+impl Default for SummarySetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("总结一下"),
+            hours: 6,
+            max_hours: 24,
+            prompt: String::from(
+                "以下是群聊记录，每行格式为\"时间 发送者: 内容\"。请用简洁的中文分条总结主要话题和结论，不超过200字。",
+            ),
+            chunk_chars: 6000,
+        }
+    }
+}
+
+impl Default for MentionSetting {
+    fn default() -> Self {
+        Self {
+            who_cares: String::from("谁最关心我"),
+            ranking: String::from("艾特排行"),
+            top_n: 5,
+        }
+    }
+}
+
+impl Default for CardSetting {
+    fn default() -> Self {
+        Self {
+            names: vec![String::from("桃桃"), String::from("摸鱼的桃桃")],
+            rotate_interval_sec: Some(86400),
+        }
+    }
+}
+
+impl Default for ReportSetting {
+    fn default() -> Self {
+        Self {
+            trigger: String::from("举报"),
+            context_segments: 10,
+        }
+    }
+}
+
+impl Default for PrivateLiveSetting {
+    fn default() -> Self {
+        Self {
+            subscribe: String::from("订阅直播间"),
+            unsubscribe: String::from("取消订阅直播间"),
+            last_notified: Mutex::new(HashMap::new()),
+            max_rooms_per_user: 5,
+            poll_interval_sec: 60,
+            notify_window_sec: Some(300),
         }
     }
 }
@@ -431,6 +2754,9 @@ impl Default for DatabaseSetting {
             max_connections: 5,
             log_table_name: String::from("bot_log"),
             group_table_prefix: String::from("message"),
+            write_buffer_cap: Some(10000),
+            write_retry_sec: Some(30),
+            compress_min_bytes: Some(4096),
         }
     }
 }
@@ -439,9 +2765,43 @@ impl Default for GroupSetting {
     fn default() -> Self {
         Self {
             id: 12345678,
+            pipeline: Some(PipelineStage::ALL.to_vec()),
+            locale: None,
+            timezone: None,
             live: Some(LiveSetting::default()),
             agent: Some(AgentSetting::default()),
             command: Some(CommandSetting::default()),
+            report: Some(ReportSetting::default()),
+            verify: Some(VerifySetting::default()),
+            moderation: Some(ModerationSetting::default()),
+            repeat: Some(RepeatSetting::default()),
+            points: Some(PointsSetting::default()),
+            level: Some(LevelSetting::default()),
+            quiz: Some(QuizSetting::default()),
+            idiom: Some(IdiomSetting::default()),
+            feed: Some(FeedSetting::default()),
+            meme: Some(MemeSetting::default()),
+            search: Some(SearchSetting::default()),
+            annual_report: Some(AnnualReportSetting::default()),
+            summary: Some(SummarySetting::default()),
+            mention: Some(MentionSetting::default()),
+            card: Some(CardSetting::default()),
+            join_request: Some(JoinRequestSetting::default()),
+            word_filter: Some(WordFilterSetting::default()),
+            reminder: Some(ReminderSetting::default()),
+            blacklist: Some(BlacklistSetting::default()),
+            rate_limit: Some(RateLimitSetting::default()),
+            keyword: Some(KeywordSetting::default()),
+            feedback: Some(FeedbackSetting::default()),
+            heat: Some(HeatSetting::default()),
+            takeover: Some(TakeoverSetting::default()),
+            sticker: Some(StickerSetting::default()),
+            outbox: Some(OutboxSetting::default()),
+            ignore: Some(IgnoreSetting::default()),
+            repost: Some(RepostSetting::default()),
+            activity: Some(ActivitySetting::default()),
+            quota: Some(QuotaSetting::default()),
+            guild: Some(GuildSetting::default()),
         }
     }
 }
@@ -450,11 +2810,16 @@ impl Default for LiveSetting {
     fn default() -> Self {
         Self {
             switch: default_switch(),
+            last_notified: Mutex::new(None),
             room_id: String::from("12345678"),
             online_msg: String::from("XX开播了"),
             offline_msg: String::from("XX下播了"),
             query_message: String::from("查询直播间"),
+            screenshot_message: Some(String::from("直播截图")),
             poll_interval_sec: 60,
+            notify_group_ids: Some(vec![]),
+            notify_subscribers: Some(false),
+            notify_window_sec: Some(300),
         }
     }
 }
@@ -469,8 +2834,9 @@ impl Default for AgentSetting {
         Self {
             mute: default_atomic_bool(),
             cur_model: RwLock::default(),
+            active_persona: Mutex::default(),
 
-            api_url: String::from("https://api.openai.com/v1/chat/completions"),
+            api_url: ApiUrl::One(String::from("https://api.openai.com/v1/chat/completions")),
             api_key: String::from("API KEY"),
             model: String::from("chatgpt-4o-latest"),
             dev_prompt: formatdoc!{
@@ -488,6 +2854,9 @@ impl Default for AgentSetting {
                 Recent Chat History:
                 <!history!>
                 
+                Live Status:
+                <!live_status!>
+                
                 New message from someone you <!know!>:
                 <!message!>
                 
@@ -496,7 +2865,98 @@ impl Default for AgentSetting {
                 "
             ),
             aware_history_segments: 30,
+            aware_history_tokens: Some(2000),
+            max_prompt_tokens: Some(100000),
             known_members,
+            ab_test: Some(AbTestSetting {
+                name: String::from("butler"),
+                dev_prompt: Some(formatdoc! {
+                    "
+                    You are a calm and witty butler serving 你的昵称, who participates in group chats with dry humor.
+                    Speak only in Mandarin Chinese, and ensure your responses are concise, limited to 4 sentences.
+                    "
+                }),
+                user_prompt: None,
+            }),
+            weather: Some(WeatherSetting {
+                city: String::from("上海"),
+                api_url: None,
+            }),
+            persona_schedule: Some(vec![PersonaWindow {
+                name: String::from("sleepy"),
+                start: String::from("01:00"),
+                end: String::from("07:00"),
+                dev_prompt: Some(formatdoc! {
+                    "
+                    You are a sleepy catgirl who was woken up late at night by 你的昵称's group chat.
+                    Speak only in Mandarin Chinese, yawn often, and reply in no more than 2 short sentences.
+                    "
+                }),
+                user_prompt: None,
+            }]),
+            muted_reply: Some(String::from("主人不让我说话, 等我被放出来再找我吧")),
+            muted_reaction: None,
+            language: Some(LanguageSetting {
+                expect: Language::Zh,
+                min_ratio: 0.6,
+                action: LanguageAction::Retry,
+                instruction: String::from(
+                    "Answer in Mandarin Chinese only, even if asked in another language.",
+                ),
+            }),
+            long_message: Some(LongMessageSetting {
+                min_chars: 1000,
+                instruction: String::from(
+                    "用不超过200字概括这条群聊消息的要点，保留其中的提问、关键事实、数字和链接。",
+                ),
+            }),
+            role_prompts: Some(HashMap::from([(
+                String::from("owner"),
+                String::from("提问的人是群主，语气要格外恭敬。"),
+            )])),
+        }
+    }
+}
+
+impl Default for TempSessionSetting {
+    fn default() -> Self {
+        Self {
+            last_handled: Mutex::default(),
+            policy: TempSessionPolicy::Forward,
+            rate_limit_sec: 600,
+        }
+    }
+}
+
+impl Default for PrivateAgentSetting {
+    fn default() -> Self {
+        let personas = [("12345678".into(), "TA是你的主人,你非常依赖TA".into())];
+        Self {
+            last_reply: Mutex::default(),
+
+            api_url: ApiUrl::One(String::from("https://api.openai.com/v1/chat/completions")),
+            api_key: String::from("API KEY"),
+            model: String::from("gpt-4o-mini"),
+            dev_prompt: formatdoc! {
+                "
+                You are a cute and smart catgirl with a strong anime-style personality.
+                You are chatting privately with <!sender!>. <!persona!>
+                Speak only in Mandarin Chinese, and ensure your responses are concise, limited to 4 sentences.
+                "
+            },
+            user_prompt: formatdoc!(
+                "
+                Recent Chat History:
+                <!history!>
+
+                New message:
+                <!message!>
+                "
+            ),
+            aware_history_messages: 20,
+            rate_limit_sec: 10,
+            allow_ids: vec![12345678],
+            personas: HashMap::from_iter(personas),
         }
     }
 }
@@ -505,17 +2965,183 @@ impl Default for CommandSetting {
     fn default() -> Self {
         Self {
             regex_set: RegexSet::default(),
-            regex_mute: default_regex(),
-            regex_unmute: default_regex(),
-            regex_switch_model: default_regex(),
-            regex_dump_history: default_regex(),
-            regex_dump_log: default_regex(),
+            regex_list: Vec::new(),
             mute: String::from("禁用聊天回复"),
             unmute: String::from("启用聊天回复"),
             switch_model: String::from("更换模型"),
             dump_history: String::from("最近聊天记录"),
             dump_log: String::from("最近日志"),
+            error_stat: Some(String::from("错误统计")),
+            reload_config: Some(String::from("重载配置")),
+            private_dump: Some(true),
+            announce: Some(String::from("公告")),
+            announce_at_all: Some(false),
+            recap: Some(String::from("回顾")),
+            import_history: Some(String::from("导入聊天记录")),
+            export_state: Some(String::from("导出状态")),
+            export_dataset: Some(String::from("导出训练集")),
+            ab_report: Some(String::from("人设对比")),
+            feedback_report: Some(String::from("反馈统计")),
+            db_health: Some(String::from("数据库状态")),
+            cancel_send: Some(String::from("撤掉")),
+            recent_images: Some(String::from("最近图片")),
+            group_files: Some(String::from("群文件")),
+            set_card: Some(String::from("设置群名片")),
+            grant_quota: Some(String::from("加额度")),
+            usage_stat: Some(String::from("功能使用统计")),
+            show_config: Some(String::from("查看配置")),
+            run_status: Some(String::from("运行状态")),
+            voice: Some(true),
             admin_ids: vec![1234, 5678],
+            admin_source: Some(AdminSource::Config),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_upgrade() {
+        let old = indoc::indoc! {r#"
+            [global]
+            max_sleep_sec = 3
+
+            [database]
+            max_connections = 2
+            log_table_name = "bot_log"
+            group_table_prefix = "message"
+
+            [[groups]]
+            id = 1
+
+            [groups.live]
+            room_id = "42"
+            online_msg = "开播了"
+            offline_msg = "下播了"
+            query_message = "查询直播间"
+        "#};
+        let (from, upgraded) = upgrade_config(old).unwrap().unwrap();
+        assert_eq!(from, 0);
+        let config: Config = toml::from_str(&upgraded).unwrap();
+        assert_eq!(config.version, Some(CONFIG_VERSION));
+        assert_eq!(config.global.max_sleep_sec, 3);
+        let group = &config.groups.as_ref().unwrap()[0];
+        let live = group.live.as_ref().unwrap();
+        assert_eq!((live.room_id.as_str(), live.poll_interval_sec), ("42", 60));
+        // absent sections stay disabled
+        assert!(group.agent.is_none() && config.ocr.is_none());
+
+        // current configs are left alone
+        assert!(upgrade_config(&upgraded).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_moderation_history() {
+        let setting = ModerationSetting {
+            repeat_messages: 2,
+            ..Default::default()
+        };
+        assert_eq!(setting.record(1, "你好", 1), None);
+        assert_eq!(setting.record(2, "你好", 1), None);
+        assert_eq!(users_of(&setting), [1, 2]);
+        // history of the offender is dropped with its entry
+        assert_eq!(setting.record(1, "你好", 1), Some(Violation::Repeat));
+        assert_eq!(users_of(&setting), [2]);
+
+        // entries of members past the window are dropped
+        let setting = ModerationSetting {
+            window_sec: 0,
+            ..Default::default()
+        };
+        setting.record(1, "你好", 1);
+        setting.record(2, "你好", 1);
+        assert_eq!(users_of(&setting), [2]);
+    }
+
+    fn users_of(setting: &ModerationSetting) -> Vec<i64> {
+        let mut users: Vec<i64> = setting.recent.lock().unwrap().keys().copied().collect();
+        users.sort();
+        users
+    }
+
+    #[test]
+    fn test_heat_setting() {
+        let setting = HeatSetting {
+            min_messages: 4,
+            negative_ratio: 0.5,
+            cooldown_sec: 60,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let at = |sec| now + Duration::from_secs(sec);
+        assert!(!setting.record(true, at(0)));
+        assert!(!setting.record(false, at(1)));
+        assert!(!setting.record(false, at(2)));
+        // 1 of 4 is negative
+        assert!(!setting.record(false, at(3)));
+        assert!(!setting.record(true, at(4)));
+        assert!(setting.record(true, at(5)));
+        // cooldown restarts while heated
+        assert!(!setting.record(true, at(6)));
+        assert!(setting.is_heated(at(65)));
+        assert!(!setting.is_heated(at(66)));
+    }
+
+    #[test]
+    fn test_persona_schedule() {
+        let window = |name: &str, start: &str, end: &str| PersonaWindow {
+            name: name.to_string(),
+            start: start.to_string(),
+            end: end.to_string(),
+            dev_prompt: Some(format!("{name} <!members!>")),
+            user_prompt: None,
+        };
+        let night = window("sleepy", "23:30", "06:00");
+        assert_eq!(night.contains(23 * 60 + 30), Some(true));
+        assert_eq!(night.contains(5 * 60 + 59), Some(true));
+        assert_eq!(night.contains(6 * 60), Some(false));
+        assert_eq!(window("bad", "25:00", "06:00").contains(0), None);
+
+        let mut agent = AgentSetting {
+            persona_schedule: Some(vec![night, window("work", "09:00", "18:00")]),
+            ab_test: None,
+            ..Default::default()
+        };
+        agent.load_members();
+        assert_eq!(agent.update_persona(10 * 60), Some("work"));
+        assert_eq!(agent.update_persona(11 * 60), None);
+        let (variant, dev_prompt, user_prompt) = agent.pick_prompts();
+        assert_eq!(variant, "work");
+        assert!(dev_prompt.starts_with("work") && !dev_prompt.contains("<!members!>"));
+        assert_eq!(user_prompt, agent.user_prompt);
+        assert_eq!(agent.update_persona(20 * 60), Some(crate::ab_test::BASE_VARIANT));
+        assert_eq!(agent.pick_prompts().1, agent.dev_prompt);
+    }
+
+    #[test]
+    fn test_pipeline_stages() {
+        let live_only = GroupSetting {
+            pipeline: Some(vec![PipelineStage::Live]),
+            ..Default::default()
+        };
+        assert!(live_only.runs(PipelineStage::Live));
+        assert!(!live_only.runs(PipelineStage::Logger));
+        assert!(!live_only.runs(PipelineStage::Agent));
+        let unrestricted = GroupSetting {
+            pipeline: None,
+            ..Default::default()
+        };
+        assert!(PipelineStage::ALL.iter().all(|&s| unrestricted.runs(s)));
+        assert_eq!(unrestricted.stages(), PipelineStage::ALL);
+        let reordered = GroupSetting {
+            pipeline: Some(vec![PipelineStage::Agent, PipelineStage::Logger]),
+            ..Default::default()
+        };
+        assert_eq!(
+            reordered.stages(),
+            [PipelineStage::Agent, PipelineStage::Logger]
+        );
+    }
+}
@@ -1,15 +1,19 @@
 //! Strong typed implementation of group notice handler.
 
 use crate::{
-    agent, db_error, db_warn, std_db_info, std_error,
+    agent, db_error, db_warn, event, heat, std_db_info, std_error, std_info,
     store::{self, GroupChatSegment},
-    util, BOT_QQ,
+    tr, util, verify, BOT_QQ,
 };
 use kovi::{log::error, Message, NoticeEvent};
 use serde::Deserialize;
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 pub async fn act(e: Arc<NoticeEvent>) {
+    if !event::first_seen(event::notice_key(&e.original_json)) {
+        std_info!("Duplicate notice dropped: {}", e.original_json);
+        return;
+    }
     // deserialize notice
     let notice = match serde_json::from_value::<NoticeResponse>(e.original_json.clone()) {
         Ok(v) => v,
@@ -21,9 +25,12 @@ pub async fn act(e: Arc<NoticeEvent>) {
             return;
         }
     };
+    dispatch(notice).await;
+}
 
+/// Dispatch a deserialized notice to its handler.
+pub async fn dispatch(notice: NoticeResponse) {
     use Notify::*;
-    // dispatch event
     match notice {
         NoticeResponse::GroupUpload(_notice) => (),
         NoticeResponse::GroupAdmin(notice) => handle_admin(notice).await,
@@ -32,6 +39,8 @@ pub async fn act(e: Arc<NoticeEvent>) {
         NoticeResponse::GroupBan(notice) => handle_ban(notice).await,
         NoticeResponse::FriendAdd(_notice) => (),
         NoticeResponse::GroupRecall(notice) => handle_recall(notice).await,
+        NoticeResponse::GroupMsgEmojiLike(notice) => handle_emoji_like(notice).await,
+        NoticeResponse::Reaction(notice) => handle_reaction(notice).await,
         NoticeResponse::Notify(notice) => match notice {
             Poke(notice) => handle_poke(notice).await,
             Honor(notice) => handle_honor(notice).await,
@@ -40,11 +49,15 @@ pub async fn act(e: Arc<NoticeEvent>) {
 }
 
 async fn handle_admin(notice: GroupAdmin) {
+    util::forget_role(notice.group_id, notice.user_id);
+    if heat::is_heated(notice.group_id) {
+        return;
+    }
     let user_name = util::get_name_in_group(notice.group_id, notice.user_id).await;
     use GroupAdminSubType::*;
     let msg_str = match notice.sub_type {
-        Set => format!("{user_name}被群主赐予了管理员之力!"),
-        Unset => format!("{user_name}被群主剥夺了管理员之力!"),
+        Set => tr!(notice.group_id, "notice.admin_set", user = user_name),
+        Unset => tr!(notice.group_id, "notice.admin_unset", user = user_name),
     };
     let message = Message::from(msg_str);
     util::send_group_and_log(notice.group_id, message).await;
@@ -52,14 +65,20 @@ async fn handle_admin(notice: GroupAdmin) {
 
 async fn handle_decrease(notice: GroupDecrease) {
     let group_id = notice.group_id;
+    if heat::is_heated(group_id) {
+        return;
+    }
     use GroupDecreaseSubType::*;
     let user_name = util::get_name_in_group(notice.group_id, notice.user_id).await;
     let op_name = util::get_name_in_group(notice.group_id, notice.operator_id).await;
     let msg_str = match notice.sub_type {
-        Leave => {
-            format!("{user_name}忍一时越想越气,退一步越想越亏,怒发冲冠下将所有人踢出了群聊!")
-        }
-        Kick => format!("{user_name}由于讨厌{op_name}选择将所有人踢出群聊!"),
+        Leave => tr!(group_id, "notice.leave", user = user_name),
+        Kick => tr!(
+            group_id,
+            "notice.kick",
+            user = user_name,
+            operator = op_name
+        ),
         KickMe => return,
     };
     let message = Message::from(msg_str);
@@ -72,27 +91,85 @@ async fn handle_increase(notice: GroupIncrease) {
     let user_name = util::get_name_in_group(notice.group_id, notice.user_id).await;
     let op_name = util::get_name_in_group(notice.group_id, notice.operator_id).await;
     let msg_str = match notice.sub_type {
-        Approve => format!("{user_name}大发慈悲、勉为其难地允许了{op_name}通过ta的入群申请~"),
-        Invite => format!("{user_name}在{op_name}的苦苦哀求下加入了我们~"),
+        Approve => tr!(
+            group_id,
+            "notice.approve",
+            user = user_name,
+            operator = op_name
+        ),
+        Invite => tr!(
+            group_id,
+            "notice.invite",
+            user = user_name,
+            operator = op_name
+        ),
     };
-    let message = Message::from(msg_str);
-    util::send_group_and_log(group_id, message).await;
+    // new members are still verified while heated
+    if !heat::is_heated(group_id) {
+        util::send_group_and_log(group_id, Message::from(msg_str)).await;
+    }
+    verify::start(group_id, notice.user_id).await;
 }
 
 async fn handle_ban(notice: GroupBan) {
     let group_id = notice.group_id;
     use GroupBanSubType::*;
+    // user_id is 0 for whole group ban
+    let bot_qq = *BOT_QQ.get().unwrap();
+    if notice.user_id == bot_qq || notice.user_id == 0 {
+        track_bot_ban(&notice).await;
+        return;
+    }
+    if heat::is_heated(group_id) {
+        return;
+    }
     let user_name = util::get_name_in_group(notice.group_id, notice.user_id).await;
     let op_name = util::get_name_in_group(notice.group_id, notice.operator_id).await;
     let duration = notice.duration;
     let msg_str = match notice.sub_type {
-        Ban => format!("{user_name}因为讨厌{op_name}决定在{duration}秒内冷暴力大家!"),
-        LiftBan => format!("{op_name}哄好了{user_name},TA现在愿意和我们说话了!"),
+        Ban => tr!(
+            group_id,
+            "notice.ban",
+            user = user_name,
+            operator = op_name,
+            duration = duration,
+        ),
+        LiftBan => tr!(
+            group_id,
+            "notice.lift_ban",
+            user = user_name,
+            operator = op_name
+        ),
     };
     let message = Message::from(msg_str);
     util::send_group_and_log(group_id, message).await;
 }
 
+/// Pause outgoing messages while the bot is banned, so that nothing is sent in vain.
+async fn track_bot_ban(notice: &GroupBan) {
+    let group_id = notice.group_id;
+    match notice.sub_type {
+        GroupBanSubType::Ban if notice.user_id == 0 => {
+            // owner and admins can speak during whole group ban
+            let bot_qq = *BOT_QQ.get().unwrap();
+            let role = util::get_role_in_group(group_id, bot_qq).await;
+            if !matches!(role.as_deref(), Some("owner" | "admin")) {
+                std_db_info!("Whole group ban in {group_id}, pause sending.");
+                util::set_bot_banned(group_id, None);
+            }
+        }
+        GroupBanSubType::Ban => {
+            std_db_info!("Bot banned in {group_id} for {}s.", notice.duration);
+            let duration = Duration::from_secs(notice.duration.max(0) as u64);
+            util::set_bot_banned(group_id, Some(duration));
+        }
+        GroupBanSubType::LiftBan => {
+            std_db_info!("Ban in {group_id} lifted, resume sending.");
+            util::lift_bot_ban(group_id);
+        }
+    }
+}
+
 async fn handle_recall(notice: GroupRecall) {
     let group_id = notice.group_id;
     let timestamp = notice.time;
@@ -111,7 +188,7 @@ async fn handle_recall(notice: GroupRecall) {
         db_warn!("Recalled message not found.\ngroup_id={group_id}, msg_id={message_id}");
         return;
     }
-    let Ok(time) = util::iso8601_from_timestamp(timestamp) else {
+    let Ok(time) = util::iso8601_from_timestamp(Some(group_id), timestamp) else {
         db_error!("Recall notice timestamp error, value = {timestamp}");
         return;
     };
@@ -138,6 +215,47 @@ async fn handle_recall(notice: GroupRecall) {
     }
 }
 
+/// NapCat reports one member's reaction, with the reacted emoji in `likes`.
+async fn handle_emoji_like(notice: GroupMsgEmojiLike) {
+    let added = notice.is_add.unwrap_or(true);
+    for like in notice.likes {
+        let reaction = (notice.group_id, notice.message_id, notice.user_id);
+        track_reaction(reaction, &like.emoji_id, added, notice.time).await;
+    }
+}
+
+/// Lagrange and LLOneBot report a reaction with its emoji code.
+async fn handle_reaction(notice: Reaction) {
+    let reaction = (notice.group_id, notice.message_id, notice.operator_id);
+    let added = notice.sub_type == ReactionSubType::Add;
+    track_reaction(reaction, &notice.code, added, notice.time).await;
+}
+
+/// Add or remove the reaction of (group_id, message_id, user_id), ignoring that of the bot.
+async fn track_reaction(
+    (group_id, message_id, user_id): (i64, i64, i64),
+    emoji: &str,
+    added: bool,
+    timestamp: i64,
+) {
+    if user_id == *BOT_QQ.get().unwrap() {
+        return;
+    }
+    let message_id = message_id as i32;
+    let result = if added {
+        let Ok(time) = util::iso8601_from_timestamp(Some(group_id), timestamp) else {
+            db_error!("Reaction notice timestamp error, value = {timestamp}");
+            return;
+        };
+        store::db_add_reaction(group_id, message_id, user_id, emoji, &time).await
+    } else {
+        store::db_remove_reaction(group_id, message_id, user_id, emoji).await
+    };
+    if let Err(e) = result {
+        db_error!("Track reaction failed: {e}");
+    }
+}
+
 async fn handle_poke(notice: Poke) {
     let bot_qq = *BOT_QQ.get().unwrap();
 
@@ -145,7 +263,7 @@ async fn handle_poke(notice: Poke) {
         let user_id = notice.user_id;
         let group_id = notice.group_id;
 
-        match agent::query_with_id_msg(group_id, user_id, String::from("戳了戳你")).await {
+        match agent::query_with_id_msg(group_id, user_id, tr!(group_id, "notice.poke")).await {
             Ok(ans) => {
                 util::send_group_and_log(group_id, ans).await;
             }
@@ -158,11 +276,14 @@ async fn handle_poke(notice: Poke) {
 
 async fn handle_honor(notice: Honor) {
     std_db_info!("Trigger handle honor.");
+    if heat::is_heated(notice.group_id) {
+        return;
+    }
     use HonorType::*;
     let user_name = util::get_name_in_group(notice.group_id, notice.user_id).await;
     match notice.honor_type {
         Talkative => {
-            let message = Message::from(format!("恭喜龙王{user_name}登基!"));
+            let message = Message::from(tr!(notice.group_id, "notice.talkative", user = user_name));
             util::send_group_and_log(notice.group_id, message).await;
         }
         Performer => (),
@@ -180,6 +301,8 @@ pub enum NoticeResponse {
     GroupBan(GroupBan),
     FriendAdd(FriendAdd),
     GroupRecall(GroupRecall),
+    GroupMsgEmojiLike(GroupMsgEmojiLike),
+    Reaction(Reaction),
     Notify(Notify),
 }
 
@@ -248,6 +371,36 @@ pub struct GroupRecall {
     message_id: i64,
 }
 
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct GroupMsgEmojiLike {
+    time: i64,
+    self_id: i64,
+    group_id: i64,
+    user_id: i64,
+    message_id: i64,
+    likes: Vec<EmojiLike>,
+    /// Absent in older versions, which report additions only.
+    is_add: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct EmojiLike {
+    emoji_id: String,
+    count: i64,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+pub struct Reaction {
+    time: i64,
+    self_id: i64,
+    sub_type: ReactionSubType,
+    group_id: i64,
+    message_id: i64,
+    operator_id: i64,
+    code: String,
+    count: i64,
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 #[serde(tag = "sub_type", rename_all = "snake_case")]
 pub enum Notify {
@@ -302,6 +455,13 @@ pub enum GroupBanSubType {
     LiftBan,
 }
 
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionSubType {
+    Add,
+    Remove,
+}
+
 #[derive(Deserialize, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum HonorType {
@@ -367,4 +527,37 @@ mod tests {
             serde_json::from_str(json_data).expect("Deserialization failed");
         assert_eq!(result, expected);
     }
+
+    #[test]
+    fn test_deserialize_emoji_like() {
+        let json_data = r#"
+        {
+            "notice_type": "group_msg_emoji_like",
+            "time": 1234,
+            "self_id": 5678,
+            "group_id": 91011,
+            "post_type": "notice",
+            "user_id": 1122334455,
+            "message_id": 42,
+            "likes": [{"emoji_id": "76", "count": 1}]
+        }
+        "#;
+
+        let expected = NoticeResponse::GroupMsgEmojiLike(GroupMsgEmojiLike {
+            time: 1234,
+            self_id: 5678,
+            group_id: 91011,
+            user_id: 1122334455,
+            message_id: 42,
+            likes: vec![EmojiLike {
+                emoji_id: String::from("76"),
+                count: 1,
+            }],
+            is_add: None,
+        });
+
+        let result: NoticeResponse =
+            serde_json::from_str(json_data).expect("Deserialization failed");
+        assert_eq!(result, expected);
+    }
 }
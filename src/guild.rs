@@ -0,0 +1,82 @@
+//! Guild (频道) channels handled as groups.
+//!
+//! Channels listed in [GuildSetting] of a group share its config and history. Their messages are
+//! written to the group history, with the channel kept in the `guild_message` table, and at
+//! messages to the bot are answered by the group agent in the channel. Other features keyed by QQ
+//! numbers, e.g. commands and points, do not apply to guild members.
+//!
+//! [GuildSetting]: crate::global_state::GuildSetting
+
+use std::sync::Arc;
+
+use kovi::{Message, Segment};
+use serde_json::json;
+
+use crate::{
+    analytics,
+    event::GuildMsg,
+    global_state::{GroupSetting, GuildChannel},
+    heat, std_db_error, std_info, store,
+    util::{self, TimeRepr},
+    CONFIG,
+};
+
+/// Group that the channel is handled as, the first listing it.
+pub fn group_of(
+    guild_id: &str,
+    channel_id: &str,
+) -> Option<(&'static GroupSetting, &'static GuildChannel)> {
+    let config = CONFIG.get().unwrap();
+    config.groups.iter().flatten().find_map(|group| {
+        let guild = group.guild.as_ref()?;
+        let channel = guild
+            .channels
+            .iter()
+            .find(|c| c.guild_id == guild_id && c.channel_id == channel_id)?;
+        Some((group, channel))
+    })
+}
+
+/// Log messages of mapped channels, and answer those at the bot with the group agent.
+pub async fn handler(e: Arc<GuildMsg>) {
+    // no-op if the channel is not mapped
+    let Some((group, channel)) = group_of(&e.guild_id, &e.channel_id) else {
+        return;
+    };
+    let group_id = group.id;
+    if let Err(err) = store::write_guild_msg(group_id, &e).await {
+        std_db_error!("Write guild message failed: {err}");
+    }
+
+    // no-op if not at me, or no agent config
+    if !e.is_at_me() {
+        return;
+    }
+    let Some(ref agent) = group.agent else {
+        return;
+    };
+    if agent.is_mute() || heat::is_heated(group_id) {
+        return;
+    }
+    let content = e.borrow_text().unwrap_or_default().trim();
+    if content.is_empty() {
+        return;
+    }
+
+    analytics::count(group_id, "guild_agent").await;
+    let time = TimeRepr::UnixTimeStamp(e.time);
+    let Some(answer) = agent
+        .group_answer(group_id, Some(time), e.sender_id, content, None)
+        .await
+    else {
+        return;
+    };
+    std_info!(
+        "Answer guild {} channel {} as group {group_id}",
+        e.guild_id,
+        e.channel_id
+    );
+    let quote = Segment::new("reply", json!({ "id": e.message_id }));
+    let message = Message::from(vec![quote]).add_text(answer.text);
+    util::send_guild_channel_msg(group_id, channel, message);
+}
@@ -0,0 +1,38 @@
+//! Pause the agent and playful notices while members argue heatedly.
+//!
+//! A group is heated once enough messages arrive within the window and a large share of them
+//! contain negative words. It calms down once the cooldown passes without that happening again.
+
+use std::{sync::Arc, time::Instant};
+
+use crate::{event::GroupMsg, global_state::HeatSetting, std_db_info, CONFIG};
+
+fn setting_of(group_id: i64) -> Option<&'static HeatSetting> {
+    let config = CONFIG.get().unwrap();
+    let groups = config.groups.as_ref()?;
+    let group = groups.iter().find(|g| g.id == group_id)?;
+    group.heat.as_ref()
+}
+
+pub async fn heat_handler(e: Arc<GroupMsg>) {
+    let Some(setting) = setting_of(e.group_id) else {
+        return;
+    };
+    let text = e.borrow_text().unwrap_or_default().to_ascii_lowercase();
+    let negative = setting
+        .negative_words
+        .iter()
+        .any(|w| !w.is_empty() && text.contains(&w.to_ascii_lowercase()));
+    if setting.record(negative, Instant::now()) {
+        std_db_info!(
+            "Group {} is heated, pause agent and notices for {}s.",
+            e.group_id,
+            setting.cooldown_sec
+        );
+    }
+}
+
+/// Whether the agent and playful notices of the group are paused.
+pub fn is_heated(group_id: i64) -> bool {
+    setting_of(group_id).is_some_and(|s| s.is_heated(Instant::now()))
+}
@@ -0,0 +1,160 @@
+//! Backfill group history from exported chat record files, read from `<DATA_PATH>/imports/`.
+//!
+//! Two formats are recognized by extension:
+//! - `.txt`: text export of QQ, where each message starts with a line of
+//!   `2024-01-01 8:00:00 name(12345678)` followed by its content lines.
+//! - `.json`: an array of messages, or an object with such array under `messages`. Each message
+//!   has `time` (unix timestamp or `YYYY-MM-DD HH:MM:SS`), `sender_id`, optional `sender_name`,
+//!   and `content`.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    store::{self, PendingRow},
+    util, DATA_PATH,
+};
+
+/// Returns (parsed, inserted), messages already in history are skipped.
+pub async fn import_file(group_id: i64, file_name: &str) -> PluginResult<(usize, usize)> {
+    // reject path traversal
+    if file_name.contains(['/', '\\']) || file_name.starts_with('.') {
+        return Err(PluginError::PathNotAvailable(file_name.to_string()));
+    }
+    let path = DATA_PATH.get().unwrap().join("imports").join(file_name);
+    let content = kovi::tokio::fs::read_to_string(&path).await?;
+    let content = content.trim_start_matches('\u{feff}');
+    let rows = if file_name.ends_with(".json") {
+        parse_json(group_id, content)?
+    } else {
+        parse_txt(group_id, content)
+    };
+    let inserted = store::db_import_group_rows(group_id, &rows).await?;
+    Ok((rows.len(), inserted))
+}
+
+/// Messages of QQ text export, lines before the first message header are ignored.
+pub fn parse_txt(group_id: i64, content: &str) -> Vec<PendingRow> {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    let header = HEADER.get_or_init(|| {
+        Regex::new(
+            r"^(?<date>\d{4}-\d{1,2}-\d{1,2}) (?<clock>\d{1,2}:\d{2}:\d{2}) (?<name>.*?)[(<](?<id>[^()<>]+)[)>]$",
+        )
+        .unwrap()
+    });
+    let mut rows = Vec::new();
+    let mut cur: Option<(PendingRow, Vec<&str>)> = None;
+    for line in content.lines() {
+        let line = line.trim_end_matches('\r');
+        let Some(caps) = header.captures(line) else {
+            if let Some((_, ref mut lines)) = cur {
+                lines.push(line);
+            }
+            continue;
+        };
+        rows.extend(cur.take().and_then(finish));
+        let Some(time) = normalize_time(&caps["date"], &caps["clock"]) else {
+            continue;
+        };
+        // the id is an email address for some accounts, which is not a QQ number
+        let sender_id = caps["id"].parse().unwrap_or(0);
+        let row = PendingRow {
+            group_id,
+            message_id: 0,
+            time,
+            sender_id,
+            sender_name: caps["name"].to_string(),
+            seg_type: String::from("text"),
+            content: String::new(),
+            interpret: String::from("text"),
+        };
+        cur = Some((row, Vec::new()));
+    }
+    rows.extend(cur.and_then(finish));
+    rows
+}
+
+/// Join content lines, None if empty.
+fn finish((mut row, lines): (PendingRow, Vec<&str>)) -> Option<PendingRow> {
+    let content = lines.join("\n").trim().to_string();
+    if content.is_empty() {
+        return None;
+    }
+    row.content = content;
+    Some(row)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonExport {
+    Messages(Vec<JsonMsg>),
+    Wrapped { messages: Vec<JsonMsg> },
+}
+
+#[derive(Deserialize)]
+struct JsonMsg {
+    time: Value,
+    sender_id: Value,
+    sender_name: Option<String>,
+    content: String,
+}
+
+pub fn parse_json(group_id: i64, content: &str) -> PluginResult<Vec<PendingRow>> {
+    let msgs = match serde_json::from_str(content)? {
+        JsonExport::Messages(msgs) | JsonExport::Wrapped { messages: msgs } => msgs,
+    };
+    let mut rows = Vec::with_capacity(msgs.len());
+    for msg in msgs {
+        let time = match msg.time {
+            Value::Number(n) => n
+                .as_i64()
+                .and_then(|t| util::iso8601_from_timestamp(Some(group_id), t).ok()),
+            Value::String(s) => s
+                .trim()
+                .split_once(' ')
+                .and_then(|(date, clock)| normalize_time(date, clock)),
+            _ => None,
+        };
+        let sender_id = match msg.sender_id {
+            Value::Number(n) => n.as_i64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        let (Some(time), Some(sender_id)) = (time, sender_id) else {
+            continue;
+        };
+        let content = msg.content.trim();
+        if content.is_empty() {
+            continue;
+        }
+        rows.push(PendingRow {
+            group_id,
+            message_id: 0,
+            time,
+            sender_id,
+            sender_name: msg.sender_name.unwrap_or_else(|| sender_id.to_string()),
+            seg_type: String::from("text"),
+            content: content.to_string(),
+            interpret: String::from("text"),
+        });
+    }
+    Ok(rows)
+}
+
+/// `YYYY-MM-DD HH:MM:SS` from date and clock of unpadded fields, None if malformed.
+fn normalize_time(date: &str, clock: &str) -> Option<String> {
+    let parse =
+        |s: &str| -> Option<Vec<u32>> { s.split(['-', ':']).map(|p| p.parse().ok()).collect() };
+    let (date, clock) = (parse(date)?, parse(clock)?);
+    let (&[y, mo, d], &[h, mi, s]) = (&date[..], &clock[..]) else {
+        return None;
+    };
+    if !(1..=12).contains(&mo) || !(1..=31).contains(&d) || h > 23 || mi > 59 || s > 59 {
+        return None;
+    }
+    Some(format!("{y:04}-{mo:02}-{d:02} {h:02}:{mi:02}:{s:02}"))
+}
@@ -0,0 +1,204 @@
+//! Outgoing HTTP requests of plugin features, behind [HttpClient] so that the real client is
+//! compiled in every build while tests serve them with [crate::testing::MockHttp].
+//!
+//! A [Request] carries everything the client is built with, e.g. timeout and redirect policy,
+//! since features differ in those.
+
+use std::{future::Future, pin::Pin, sync::OnceLock, time::Duration};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::exception::PluginResult;
+
+pub type HttpFuture<'a> = Pin<Box<dyn Future<Output = PluginResult<Response>> + Send + 'a>>;
+
+/// Client sending [Request]s, [Reqwest] in production.
+pub trait HttpClient: Send + Sync {
+    /// Response of a successful status, Err otherwise.
+    fn send(&self, request: Request) -> HttpFuture<'_>;
+}
+
+static CLIENT: OnceLock<Box<dyn HttpClient>> = OnceLock::new();
+
+/// Send requests through `client` instead of network, e.g. a mock in tests. Only the first call
+/// before any request takes effect.
+pub fn set_client(client: impl HttpClient + 'static) {
+    let _ = CLIENT.set(Box::new(client));
+}
+
+fn client() -> &'static dyn HttpClient {
+    CLIENT.get_or_init(|| Box::new(Reqwest)).as_ref()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Debug, Clone)]
+pub enum Body {
+    Bytes(Vec<u8>),
+    /// Content of a local file, read when sent.
+    File(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Body>,
+    pub timeout: Option<Duration>,
+    /// Reading stops once the body reaches this many bytes.
+    pub max_bytes: Option<usize>,
+    /// Redirects are followed only to hosts it accepts, at most 3 of them.
+    pub redirect_guard: Option<fn(&str) -> bool>,
+}
+
+impl Request {
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::Get, url.into())
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::Post, url.into())
+    }
+
+    fn new(method: Method, url: String) -> Self {
+        Self {
+            method,
+            url,
+            query: Vec::new(),
+            headers: Vec::new(),
+            body: None,
+            timeout: None,
+            max_bytes: None,
+            redirect_guard: None,
+        }
+    }
+
+    pub fn query(mut self, query: &[(&str, &str)]) -> Self {
+        let query = query.iter().map(|&(k, v)| (k.to_string(), v.to_string()));
+        self.query.extend(query);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn bearer_auth(self, token: &str) -> Self {
+        self.header("Authorization", format!("Bearer {token}"))
+    }
+
+    pub fn user_agent(self, user_agent: impl Into<String>) -> Self {
+        self.header("User-Agent", user_agent)
+    }
+
+    pub fn json(mut self, value: &Value) -> Self {
+        self.body = Some(Body::Bytes(value.to_string().into_bytes()));
+        self.header("Content-Type", "application/json")
+    }
+
+    /// Raw bytes, or a local file with [Body::File].
+    pub fn body(mut self, body: Body) -> Self {
+        self.body = Some(body);
+        self.header("Content-Type", "application/octet-stream")
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn redirect_guard(mut self, guard: fn(&str) -> bool) -> Self {
+        self.redirect_guard = Some(guard);
+        self
+    }
+
+    pub async fn send(self) -> PluginResult<Response> {
+        client().send(self).await
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Response {
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).to_string()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> PluginResult<T> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+pub struct Reqwest;
+
+impl HttpClient for Reqwest {
+    fn send(&self, request: Request) -> HttpFuture<'_> {
+        Box::pin(send_reqwest(request))
+    }
+}
+
+async fn send_reqwest(request: Request) -> PluginResult<Response> {
+    use reqwest::{header::CONTENT_TYPE, redirect::Policy};
+
+    let mut builder = reqwest::Client::builder();
+    if let Some(timeout) = request.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(guard) = request.redirect_guard {
+        builder = builder.redirect(Policy::custom(move |attempt| {
+            let accepted = attempt.url().host_str().is_some_and(guard);
+            if attempt.previous().len() > 3 || !accepted {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        }));
+    }
+    let client = builder.build()?;
+    let mut req = match request.method {
+        Method::Get => client.get(&request.url),
+        Method::Post => client.post(&request.url),
+    };
+    if !request.query.is_empty() {
+        req = req.query(&request.query);
+    }
+    for (name, value) in &request.headers {
+        req = req.header(name, value);
+    }
+    match request.body {
+        Some(Body::Bytes(bytes)) => req = req.body(bytes),
+        Some(Body::File(path)) => req = req.body(kovi::tokio::fs::read(path).await?),
+        None => {}
+    }
+    let mut resp = req.send().await?.error_for_status()?;
+    let content_type = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if request.max_bytes.is_some_and(|max| body.len() >= max) {
+            break;
+        }
+    }
+    Ok(Response { content_type, body })
+}
@@ -0,0 +1,110 @@
+//! Optional safety check of images before the bot sends them.
+
+use std::time::Duration;
+
+use kovi::tokio::time::timeout;
+use time::OffsetDateTime;
+
+use crate::{
+    exception::PluginResult,
+    global_state::{ImageSafetySetting, SafetyAction},
+    http::{Body, Request},
+    std_error, std_info, util, CONFIG, DATA_PATH,
+};
+
+const BLUR_SIGMA: f32 = 24.0;
+
+#[derive(serde::Deserialize, Debug)]
+struct SafetyResponse {
+    score: f64,
+}
+
+/// Image to send in place of `src`, an url or `file://` path.
+///
+/// Returns `src` itself if safe or the check is disabled, a blurred copy if flagged and the action
+/// is blur, otherwise None.
+pub async fn screen(src: &str) -> Option<String> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no image safety config
+    let Some(ref safety) = config.image_safety else {
+        return Some(src.to_string());
+    };
+    let task = async {
+        let bytes = load(src, safety).await?;
+        let score = score(&bytes, safety).await?;
+        PluginResult::Ok((bytes, score))
+    };
+    let (bytes, score) = match timeout(Duration::from_secs(safety.timeout_sec), task).await {
+        Ok(Ok(v)) => v,
+        Ok(Err(err)) => {
+            std_error!("Check image {src} failed: {err}");
+            return None;
+        }
+        Err(_) => {
+            std_error!("Check image {src} timed out");
+            return None;
+        }
+    };
+    if score < safety.threshold {
+        return Some(src.to_string());
+    }
+    std_info!("Image flagged with score {score:.2}: {src}");
+    match safety.action {
+        SafetyAction::Skip => None,
+        SafetyAction::Blur => blur(src, bytes).await,
+    }
+}
+
+async fn load(src: &str, safety: &ImageSafetySetting) -> PluginResult<Vec<u8>> {
+    if let Some(path) = src.strip_prefix("file://") {
+        return Ok(kovi::tokio::fs::read(path).await?);
+    }
+    let resp = Request::get(src)
+        .timeout(Duration::from_secs(safety.timeout_sec))
+        .send()
+        .await?;
+    Ok(resp.body)
+}
+
+async fn score(bytes: &[u8], safety: &ImageSafetySetting) -> PluginResult<f64> {
+    let mut req = Request::post(&safety.endpoint).body(Body::Bytes(bytes.to_vec()));
+    if let Some(ref key) = safety.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp: SafetyResponse = req.send().await?.json()?;
+    Ok(resp.score)
+}
+
+/// Blurred copy uploaded through object storage, fallback to local file.
+async fn blur(src: &str, bytes: Vec<u8>) -> Option<String> {
+    let millis = OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000;
+    let path = DATA_PATH
+        .get()
+        .unwrap()
+        .join(format!("blurred_{millis}.png"));
+    let path_owned = path.clone();
+    let blurred = kovi::tokio::task::spawn_blocking(move || -> PluginResult<()> {
+        let image = image::load_from_memory(&bytes)?.blur(BLUR_SIGMA);
+        image.save(path_owned)?;
+        Ok(())
+    })
+    .await;
+    match blurred {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            std_error!("Blur image {src} failed: {err}");
+            return None;
+        }
+        Err(err) => {
+            std_error!("Blur image task failed: {err}");
+            return None;
+        }
+    }
+    let path_str = path.to_string_lossy().to_string();
+    let uploaded = util::call_upload(&path_str).await;
+    if uploaded.starts_with("http") {
+        Some(uploaded)
+    } else {
+        Some(format!("file://{path_str}"))
+    }
+}
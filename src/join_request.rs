@@ -0,0 +1,156 @@
+//! Review group join requests by [JoinRequestSetting] when the bot is admin of the group.
+//!
+//! Requests are approved by QQ level, keywords or agent judgement in turn. Others are handled by
+//! [JoinRequestAction], human admins still review queued requests in their clients.
+
+use std::sync::Arc;
+
+use kovi::RequestEvent;
+use serde::Deserialize;
+
+use crate::{
+    agent, event,
+    global_state::{GroupSetting, JoinRequestAction, JoinRequestSetting},
+    std_db_info, std_info, tr, util, CONFIG,
+};
+
+/// Why a request is approved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Approval {
+    Level,
+    Keyword,
+    Agent,
+}
+
+/// Request of a user to join, invitations of the bot are not handled.
+#[derive(Deserialize, Debug)]
+pub struct JoinRequest {
+    pub group_id: i64,
+    pub user_id: i64,
+    #[serde(default)]
+    pub comment: String,
+    pub flag: String,
+}
+
+pub async fn act(e: Arc<RequestEvent>) {
+    let json = &e.original_json;
+    if json["request_type"] != "group" || json["sub_type"] != "add" {
+        return;
+    }
+    let Ok(request) = serde_json::from_value::<JoinRequest>(json.clone()) else {
+        std_info!("JoinRequest deserialize failed, raw: {json}");
+        return;
+    };
+    if !event::first_seen(format!("request:{}", request.flag)) {
+        std_info!("Duplicate join request dropped: {json}");
+        return;
+    }
+    handle(request).await;
+}
+
+pub async fn handle(request: JoinRequest) {
+    let config = CONFIG.get().unwrap();
+    let group = config
+        .groups
+        .as_ref()
+        .and_then(|groups| groups.iter().find(|g| g.id == request.group_id));
+    let Some(group) = group else {
+        return;
+    };
+    let Some(ref setting) = group.join_request else {
+        return;
+    };
+    let group_id = request.group_id;
+    // requests are only visible to and answered by admins
+    let bot_role = util::get_role_in_group(group_id, *crate::BOT_QQ.get().unwrap()).await;
+    if !matches!(bot_role.as_deref(), Some("owner" | "admin")) {
+        return;
+    }
+
+    let user_id = request.user_id;
+    if let Some(approval) = review(group, setting, &request).await {
+        std_db_info!("Join request of {user_id} to {group_id} approved by {approval:?}.");
+        util::set_group_add_request(&request.flag, true, "");
+        if setting.announce {
+            let key = match approval {
+                Approval::Level => "join_request.by_level",
+                Approval::Keyword => "join_request.by_keyword",
+                Approval::Agent => "join_request.by_agent",
+            };
+            let reason = tr!(group_id, key);
+            let msg = tr!(
+                group_id,
+                "join_request.approved",
+                user = user_id,
+                reason = reason
+            );
+            util::send_group_and_log(group_id, msg).await;
+        }
+        return;
+    }
+    match setting.otherwise {
+        JoinRequestAction::Queue => {
+            let Some(ref command) = group.command else {
+                return;
+            };
+            let msg = tr!(
+                group_id,
+                "join_request.queued",
+                group = group_id,
+                user = user_id,
+                comment = request.comment
+            );
+            for admin_id in command.admins(group_id).await {
+                util::send_private_msg(admin_id, msg.as_str());
+            }
+        }
+        JoinRequestAction::Reject => {
+            std_db_info!("Join request of {user_id} to {group_id} rejected.");
+            let reason = setting.reject_reason.as_deref().unwrap_or_default();
+            util::set_group_add_request(&request.flag, false, reason);
+        }
+    }
+}
+
+/// First policy that approves the request, None if all decline.
+async fn review(
+    group: &GroupSetting,
+    setting: &JoinRequestSetting,
+    request: &JoinRequest,
+) -> Option<Approval> {
+    if let Some(min_level) = setting.min_level {
+        let level = util::get_user_level(request.user_id).await;
+        if level.is_some_and(|level| level >= min_level) {
+            return Some(Approval::Level);
+        }
+    }
+    let comment = request.comment.trim();
+    if setting
+        .keywords
+        .iter()
+        .any(|k| comment.contains(k.as_str()))
+    {
+        return Some(Approval::Keyword);
+    }
+    let (Some(criteria), Some(agent)) = (&setting.ai_criteria, &group.agent) else {
+        return None;
+    };
+    if comment.is_empty() {
+        return None;
+    }
+    let dev_prompt = "You judge whether the join request of a group meets the criteria. \
+                      Reply with yes or no only.";
+    let user_prompt = format!("标准: {criteria}\n申请: {comment}");
+    let model = agent.get_model().await;
+    let judgement = agent::complete(
+        &agent.api_url,
+        &agent.api_key,
+        &model,
+        dev_prompt,
+        &user_prompt,
+    )
+    .await;
+    judgement
+        .is_some_and(|x| x.trim().to_lowercase().starts_with("yes"))
+        .then_some(Approval::Agent)
+}
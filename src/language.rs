@@ -0,0 +1,126 @@
+//! Check that answers of the agent are in the configured language, and retry or translate those
+//! that are not.
+//!
+//! Languages are told apart by script, Han characters for Chinese and words of Latin letters for
+//! English. Markup in square brackets like `[at:张三]` is not counted.
+
+use crate::{
+    agent,
+    global_state::{AgentSetting, Language, LanguageAction},
+    std_info,
+};
+
+/// Answers with fewer words, e.g. "ok" or "哈哈", always match.
+const MIN_WORDS: usize = 4;
+
+/// Whether at least `min_ratio` of words in `text` are in `expect`.
+pub fn matches(text: &str, expect: Language, min_ratio: f64) -> bool {
+    let (mut han, mut latin) = (0, 0);
+    let mut depth = 0usize;
+    let mut in_word = false;
+    for c in text.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth = depth.saturating_sub(1),
+            _ if depth > 0 => {}
+            c if is_han(c) => han += 1,
+            c if c.is_ascii_alphabetic() => {
+                if !in_word {
+                    latin += 1;
+                }
+                in_word = true;
+                continue;
+            }
+            _ => {}
+        }
+        in_word = false;
+    }
+    let total = han + latin;
+    if total < MIN_WORDS {
+        return true;
+    }
+    let hits = match expect {
+        Language::Zh => han,
+        Language::En => latin,
+    };
+    hits as f64 / total as f64 >= min_ratio
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}')
+}
+
+/// The answer itself if in the configured language, otherwise the retried or translated one.
+pub async fn enforce(
+    agent: &AgentSetting,
+    model: &str,
+    (dev_prompt, user_prompt): (&str, &str),
+    answer: String,
+) -> String {
+    let Some(ref setting) = agent.language else {
+        return answer;
+    };
+    if matches(&answer, setting.expect, setting.min_ratio) {
+        return answer;
+    }
+    std_info!(
+        "Answer not in {:?}, {:?}: {answer}",
+        setting.expect,
+        setting.action
+    );
+    let fixed = match setting.action {
+        LanguageAction::Retry => {
+            let dev_prompt = format!("{dev_prompt}\n{}", setting.instruction);
+            agent::complete(
+                &agent.api_url,
+                &agent.api_key,
+                model,
+                &dev_prompt,
+                user_prompt,
+            )
+            .await
+        }
+        LanguageAction::Translate => {
+            agent::complete(
+                &agent.api_url,
+                &agent.api_key,
+                model,
+                &setting.instruction,
+                &answer,
+            )
+            .await
+        }
+    };
+    let Some(fixed) = fixed else {
+        return answer;
+    };
+    if !matches(&fixed, setting.expect, setting.min_ratio) {
+        std_info!("Fixed answer still not in {:?}: {fixed}", setting.expect);
+    }
+    fixed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("你好呀今天天气不错", Language::Zh, 0.6));
+        assert!(!matches(
+            "Hello there, how are you today",
+            Language::Zh,
+            0.6
+        ));
+        assert!(matches("Hello there, how are you today", Language::En, 0.6));
+        // markup and short answers are not judged
+        assert!(matches(
+            "[at:Alice Bob Carol] 好的没问题",
+            Language::Zh,
+            0.6
+        ));
+        assert!(matches("ok", Language::Zh, 0.6));
+        // words rather than letters of English count
+        assert!(matches("我们用GitHub Actions跑CI吧", Language::Zh, 0.6));
+    }
+}
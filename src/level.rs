@@ -0,0 +1,170 @@
+//! Levels of group members by experience earned from chatting.
+//!
+//! Reaching level `L + 1` from `L` takes `level_xp * L` experience. Messages shorter than
+//! `min_chars`, within `cooldown_sec` of the last award, or over `daily_cap` earn nothing.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    event::GroupMsg,
+    exception::PluginResult,
+    global_state::LevelSetting,
+    std_db_error,
+    store::{self, MemberLevel},
+    tr, util, CONFIG,
+};
+
+pub async fn level_handler(e: Arc<GroupMsg>) {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == e.group_id) else {
+        return;
+    };
+    let Some(ref setting) = group.level else {
+        return;
+    };
+    let Some(text) = e.borrow_text().map(str::trim) else {
+        return;
+    };
+    let res = if text == setting.my_level {
+        my_level(&e, setting).await
+    } else if text == setting.ranking {
+        ranking(e.group_id, setting).await
+    } else {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        match award(e.group_id, e.sender_id, text, setting, now).await {
+            Ok(Some(level)) if setting.announce => {
+                let name = util::get_name_in_group(e.group_id, e.sender_id).await;
+                e.reply(tr!(e.group_id, "level.up", name = name, level = level))
+                    .await;
+                Ok(())
+            }
+            res => res.map(|_| ()),
+        }
+    };
+    if let Err(err) = res {
+        std_db_error!("Level handler failed: {err}");
+    }
+}
+
+/// Level reached with total experience `xp`, starting from 1.
+pub fn level_of(xp: i64, level_xp: i64) -> i64 {
+    let mut level = 1;
+    if level_xp <= 0 {
+        return level;
+    }
+    while xp >= xp_to_reach(level + 1, level_xp) {
+        level += 1;
+    }
+    level
+}
+
+/// Total experience needed to reach `level`.
+pub fn xp_to_reach(level: i64, level_xp: i64) -> i64 {
+    level_xp * level * (level - 1) / 2
+}
+
+/// Award experience for a message sent at unix time `now`, returns the new level on level-up.
+pub async fn award(
+    group_id: i64,
+    user_id: i64,
+    text: &str,
+    setting: &LevelSetting,
+    now: i64,
+) -> PluginResult<Option<i64>> {
+    if text.chars().filter(|c| !c.is_whitespace()).count() < setting.min_chars {
+        return Ok(None);
+    }
+    let today = util::cur_date_iso8601(Some(group_id));
+    let mut member = store::db_load_level(group_id, user_id)
+        .await?
+        .unwrap_or(MemberLevel {
+            user_id,
+            xp: 0,
+            level: 1,
+            day: today.clone(),
+            day_xp: 0,
+            last_award: 0,
+        });
+    if member.day != today {
+        member.day = today;
+        member.day_xp = 0;
+    }
+    let gained = setting
+        .xp_per_message
+        .min(setting.daily_cap - member.day_xp);
+    if gained <= 0 || now - member.last_award < setting.cooldown_sec {
+        return Ok(None);
+    }
+    member.xp += gained;
+    member.day_xp += gained;
+    member.last_award = now;
+    let level = level_of(member.xp, setting.level_xp);
+    let level_up = (level > member.level).then_some(level);
+    member.level = level;
+    store::db_save_level(group_id, &member).await?;
+    Ok(level_up)
+}
+
+async fn my_level(e: &GroupMsg, setting: &LevelSetting) -> PluginResult<()> {
+    let (group_id, user_id) = (e.group_id, e.sender_id);
+    let Some(member) = store::db_load_level(group_id, user_id).await? else {
+        e.reply_and_quote(tr!(group_id, "level.none")).await;
+        return Ok(());
+    };
+    let rank = store::db_level_rank(group_id, member.xp).await?;
+    let next = xp_to_reach(member.level + 1, setting.level_xp);
+    e.reply_and_quote(tr!(
+        group_id,
+        "level.mine",
+        level = member.level,
+        xp = member.xp,
+        next = next,
+        rank = rank
+    ))
+    .await;
+    Ok(())
+}
+
+async fn ranking(group_id: i64, setting: &LevelSetting) -> PluginResult<()> {
+    let members = store::db_level_ranking(group_id, setting.ranking_size).await?;
+    if members.is_empty() {
+        util::send_group_msg(group_id, tr!(group_id, "level.ranking_empty"));
+        return Ok(());
+    }
+    let mut lines = vec![tr!(group_id, "level.ranking_title")];
+    for (idx, member) in members.iter().enumerate() {
+        let name = util::get_name_in_group(group_id, member.user_id).await;
+        lines.push(tr!(
+            group_id,
+            "level.ranking_entry",
+            rank = idx + 1,
+            name = name,
+            level = member.level,
+            xp = member.xp
+        ));
+    }
+    util::send_group_and_log(group_id, lines.join("\n")).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_of() {
+        assert_eq!(level_of(0, 100), 1);
+        assert_eq!(level_of(99, 100), 1);
+        assert_eq!(level_of(100, 100), 2);
+        assert_eq!(level_of(300, 100), 3);
+    }
+}
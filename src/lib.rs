@@ -1,35 +1,177 @@
 #![allow(clippy::too_many_arguments)]
 //! Momo QQ chat bot
-use std::{env, process::exit, sync::Arc};
+use std::{
+    process::exit,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
 use exception::PluginError;
 use global_state::*;
-use kovi::PluginBuilder as plugin;
+use kovi::{tokio::time::timeout, PluginBuilder as plugin};
+pub mod ab_test;
+pub mod activity;
 pub mod agent;
+pub mod album;
+pub mod analytics;
+pub mod annual_report;
+pub mod calendar;
+pub mod caption;
+pub mod card;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod command;
+pub mod config_template;
+pub mod config_view;
+pub mod dataset;
+pub mod db_check;
+pub mod disk;
+pub mod endpoint;
+pub mod error_stat;
+pub mod event;
 pub mod exception;
+pub mod feed;
+pub mod feedback;
+pub mod games;
+pub mod github;
 pub mod global_state;
 pub mod group_notice;
+pub mod guild;
+pub mod heat;
+pub mod history_import;
+pub mod http;
+pub mod image_safety;
+pub mod join_request;
+pub mod language;
+pub mod level;
 pub mod live;
+pub mod locale;
 pub mod log;
+pub mod long_message;
+pub mod maintenance;
+pub mod media;
+pub mod meme;
+pub mod mention;
+pub mod migration;
+pub mod moderation;
+pub mod news;
+pub mod ocr;
+pub mod onebot;
+pub mod outbox;
+pub mod persona;
+pub mod pipeline;
+pub mod points;
+pub mod private;
+pub mod quota;
+pub mod reminder;
+pub mod repeat;
+pub mod repost;
+pub mod report;
+pub mod search;
+pub mod self_check;
+pub mod spend;
+pub mod stats_api;
 pub mod store;
+pub mod sticker;
+pub mod stt;
+pub mod summary;
+pub mod takeover;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod time_parse;
+pub mod unfurl;
+pub mod uptime;
 pub mod util;
+pub mod verify;
+pub mod word_filter;
+
+const ABORT_NOTIFY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Extension points for sibling plugins or downstream crates, registered by [register_hook].
+///
+/// Hooks observe events after momo-bot acts on them and run on its tasks, so spawn anything slow.
+pub trait Hook: Send + Sync {
+    /// The group agent sent `answer` as message `reply_id` for `prompt`.
+    fn on_agent_reply(
+        &self,
+        _group_id: i64,
+        _reply_id: i32,
+        _prompt: &str,
+        _answer: &agent::GroupAnswer,
+    ) {
+    }
+
+    /// A group command of `requester` is about to run.
+    fn on_command(&self, _group_id: i64, _requester: i64, _command: &GroupCommand) {}
+
+    /// Live room of `group_id` turned online or offline, see [live::LiveData::is_streaming].
+    fn on_live_status_change(&self, _group_id: i64, _room_id: &str, _room: &live::LiveRoom) {}
+}
+
+static HOOKS: RwLock<Vec<Arc<dyn Hook>>> = RwLock::new(Vec::new());
+
+/// Register a hook for the lifetime of the process.
+pub fn register_hook(hook: impl Hook + 'static) {
+    HOOKS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Arc::new(hook));
+}
+
+/// Call `f` on each registered hook.
+pub(crate) fn run_hooks(f: impl Fn(&dyn Hook)) {
+    // snapshot so that hooks may register others
+    let hooks = HOOKS.read().unwrap_or_else(|e| e.into_inner()).clone();
+    for hook in hooks {
+        f(hook.as_ref());
+    }
+}
 
 #[kovi::plugin]
 async fn main() {
     if let Err(e) = global_state::init_global_state().await {
-        log_and_abort(e);
+        log_and_abort(e).await;
     }
+    uptime::record_start().await;
 
+    store::subscribe_write_retry().await;
     live::subscribe_live().await;
+    error_stat::subscribe_digest().await;
+    db_check::subscribe_check().await;
+    disk::subscribe_disk().await;
+    verify::subscribe_expiry().await;
+    games::subscribe_ticker().await;
+    feed::subscribe_feeds().await;
+    news::subscribe_news().await;
+    github::subscribe_github().await;
+    persona::subscribe_persona().await;
+    card::subscribe_rotation().await;
+    reminder::subscribe_reminders().await;
+    stats_api::serve().await;
+
+    kovi::spawn(self_check::report());
 
     plugin::on_group_msg(move |e| async move {
-        agent::logger(Arc::clone(&e)).await;
-        util::sleep_rand_time().await;
-        command::act(Arc::clone(&e)).await;
-        live::local_query_handler(Arc::clone(&e)).await;
-        live::general_query_handler(Arc::clone(&e)).await;
-        agent::at_me_handler(Arc::clone(&e)).await;
+        let Some(e) = event::GroupMsg::from_event(&e) else {
+            return;
+        };
+        if !event::first_seen(e.dedup_key()) {
+            return;
+        }
+        let e = Arc::new(e);
+        if !global_state::is_configured_group(e.group_id) {
+            agent::unknown_group_handler(e).await;
+            return;
+        }
+        pipeline::run(e).await;
+    });
+
+    // guild messages are raw events, see [guild]
+    plugin::on(move |e: Arc<event::GuildMsg>| async move {
+        if !event::first_seen(e.dedup_key()) {
+            return;
+        }
+        guild::handler(e).await;
     });
 
     plugin::on_all_notice(move |e| async move {
@@ -37,17 +179,68 @@ async fn main() {
         group_notice::act(e).await;
     });
 
+    plugin::on_all_request(move |e| async move {
+        util::sleep_rand_time().await;
+        join_request::act(e).await;
+    });
+
     plugin::on_admin_msg(|_e| async move {});
 
-    plugin::on_private_msg(move |_e| async move {
-        // util::sleep_rand_time().await;
-        // no-op
+    plugin::drop(|| async {
+        uptime::record_stop().await;
+    });
+
+    plugin::on_private_msg(move |e| async move {
+        let Some(e) = event::PrivateMsg::from_event(&e) else {
+            return;
+        };
+        if !event::first_seen(e.dedup_key()) {
+            return;
+        }
+        let e = Arc::new(e);
+        util::sleep_rand_time().await;
+        if maintenance::private_handler(Arc::clone(&e)).await {
+            return;
+        }
+        if command::private_console(Arc::clone(&e)).await {
+            return;
+        }
+        if takeover::review_handler(Arc::clone(&e)).await {
+            return;
+        }
+        if private::temp_session_handler(Arc::clone(&e)).await {
+            return;
+        }
+        if live::private_subscribe_handler(Arc::clone(&e)).await {
+            return;
+        }
+        private::agent_handler(Arc::clone(&e)).await;
     });
 }
 
-fn log_and_abort(e: PluginError) {
+/// Log and tell main admin the cause before exiting, so that the bot does not die silently.
+async fn log_and_abort(e: PluginError) {
     std_error!("{}", e);
+    uptime::save_init_error(&e);
     let bot = plugin::get_runtime_bot();
+    // ADMIN_QQ is unset if init failed before metadata was saved
+    let admin_qq = ADMIN_QQ
+        .get()
+        .copied()
+        .or_else(|| bot.get_main_admin().ok());
+    if let Some(admin_qq) = admin_qq {
+        // config may be absent, so the locale is not read from it
+        let msg = locale::format_in(None, "startup.aborted", &[("error", &e)]);
+        // wait for delivery, as exit drops pending requests
+        let sent = timeout(
+            ABORT_NOTIFY_TIMEOUT,
+            bot.send_private_msg_return(admin_qq, msg),
+        )
+        .await;
+        if !matches!(sent, Ok(Ok(_))) {
+            std_error!("Notify admin of abort failed");
+        }
+    }
     bot.disable_plugin("chat").unwrap();
     exit(1);
 }
@@ -1,33 +1,235 @@
 //! Bilibili live module
 
-use std::{fmt::Display, sync::Arc, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
-use indoc::{formatdoc, writedoc};
-use kovi::{Message, MsgEvent};
+use kovi::{tokio::sync::Mutex, Message};
 use serde::{Deserialize, Deserializer};
+use serde_json::Value;
 
 use crate::{
-    exception::PluginResult,
-    global_state::{self, LiveSwitch},
-    std_error, std_info,
-    util::schedule_task_blocking,
+    analytics,
+    event::{GroupMsg, PrivateMsg},
+    exception::{PluginError, PluginResult},
+    global_state::{LiveSetting, LiveSwitch, PrivateLiveSetting},
+    image_safety, std_db_error, std_error, std_info, store, tr,
+    util::{self, schedule_task_blocking},
     CONFIG,
 };
 
+/// Owner of live states persisted by the private poller, not a valid group id.
+const PRIVATE_POLLER: i64 = 0;
+
+pub type LiveFuture<'a> = Pin<Box<dyn Future<Output = PluginResult<Value>> + Send + 'a>>;
+
+/// Source of raw room info responses of Bilibili, mocked in tests.
+pub trait LiveApi: Send + Sync {
+    /// Response of `room/v1/Room/get_info` as is, Err only if Bilibili is unreachable.
+    fn get_info<'a>(&'a self, room_id: &'a str) -> LiveFuture<'a>;
+}
+
+static LIVE_API: OnceLock<Box<dyn LiveApi>> = OnceLock::new();
+
+/// Query rooms through `api` instead of Bilibili, e.g. a mock in tests. Only the first call before
+/// any query takes effect.
+pub fn set_api(api: impl LiveApi + 'static) {
+    let _ = LIVE_API.set(Box::new(api));
+}
+
+fn api() -> &'static dyn LiveApi {
+    LIVE_API.get_or_init(|| Box::new(client::Bilibili)).as_ref()
+}
+
 async fn query_liveroom(room_id: &str) -> PluginResult<LiveRoom> {
-    let url = "https://api.live.bilibili.com/room/v1/Room/get_info";
-    let params = [("room_id", room_id)];
-    let client = reqwest::Client::new();
-    let room = client.get(url).query(&params).send().await?.json().await?;
-    Ok(room)
+    #[cfg(feature = "chaos")]
+    crate::chaos::inject(crate::chaos::Point::Live).await?;
+    fetch_room(api(), room_id).await
 }
 
-async fn query_handler(e: Arc<MsgEvent>, room_id: &str, online_msg: &str, offline_msg: &str) {
-    // no-op if not group message
-    if e.group_id.is_none() {
-        return;
+/// Room info from `api`.
+pub async fn fetch_room(api: &dyn LiveApi, room_id: &str) -> PluginResult<LiveRoom> {
+    parse_room(api.get_info(room_id).await?)
+}
+
+/// Room info in the response, Err if Bilibili rejected the request.
+pub fn parse_room(value: Value) -> PluginResult<LiveRoom> {
+    let value = check_rejected(value)?;
+    // data of a missing room is `[]` or absent
+    if value["code"].as_i64() != Some(0) {
+        return Ok(LiveRoom {
+            exist: false,
+            data: LiveData::default(),
+        });
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+fn check_rejected(value: Value) -> PluginResult<Value> {
+    // -101 for expired login, -352 and -412 for risk control
+    if let Some(code @ (-101 | -352 | -412)) = value["code"].as_i64() {
+        let message = value["message"].as_str().unwrap_or_default();
+        return Err(PluginError::BilibiliRejected(format!("{code} {message}")));
+    }
+    // risk control may also answer code 0 with a captcha voucher in place of room info
+    if let Some(voucher) = value["data"]["v_voucher"].as_str() {
+        return Err(PluginError::BilibiliRejected(format!(
+            "v_voucher {voucher}"
+        )));
+    }
+    Ok(value)
+}
+
+/// Whether the room exists, Err if Bilibili is unreachable.
+pub async fn room_exists(room_id: &str) -> PluginResult<bool> {
+    Ok(query_liveroom(room_id).await?.exist)
+}
+
+/// Shared clients of Bilibili api.
+mod client {
+    use std::{
+        sync::{Mutex, OnceLock},
+        time::{Duration, Instant},
+    };
+
+    use reqwest::{
+        header::{HeaderMap, HeaderValue, COOKIE, REFERER},
+        Client,
     };
 
+    use serde_json::Value;
+
+    use super::{LiveApi, LiveFuture};
+    use crate::{exception::PluginResult, global_state::BilibiliSetting, std_error, CONFIG};
+
+    const USER_AGENT: &str = "Mozilla/5.0 (compatible; momo-bot)";
+    /// Auth is retried after a while, the cookie may be renewed by reloading config.
+    const AUTH_RETRY: Duration = Duration::from_secs(3600);
+
+    /// Client built from the cookie, and until when auth is suspended.
+    #[derive(Default)]
+    struct AuthState {
+        cookie: String,
+        client: Option<Client>,
+        suspended_until: Option<Instant>,
+    }
+
+    fn auth_state() -> std::sync::MutexGuard<'static, AuthState> {
+        static STATE: OnceLock<Mutex<AuthState>> = OnceLock::new();
+        STATE
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn anonymous() -> Client {
+        static CLIENT: OnceLock<Client> = OnceLock::new();
+        CLIENT
+            .get_or_init(|| {
+                Client::builder()
+                    .user_agent(USER_AGENT)
+                    .build()
+                    .unwrap_or_default()
+            })
+            .clone()
+    }
+
+    /// Client with cookie of the account, None if auth is suspended or the cookie is invalid.
+    fn authenticated(setting: &BilibiliSetting) -> Option<Client> {
+        let mut state = auth_state();
+        // a new cookie from reloaded config is tried at once
+        if state.cookie != setting.cookie {
+            *state = AuthState {
+                cookie: setting.cookie.clone(),
+                ..Default::default()
+            };
+        }
+        if state.suspended_until.is_some_and(|t| Instant::now() < t) {
+            return None;
+        }
+        if let Some(ref client) = state.client {
+            return Some(client.clone());
+        }
+        let mut headers = HeaderMap::new();
+        let cookie = match HeaderValue::from_str(&setting.cookie) {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Invalid bilibili cookie: {err}");
+                state.suspended_until = Some(Instant::now() + AUTH_RETRY);
+                return None;
+            }
+        };
+        headers.insert(COOKIE, cookie);
+        headers.insert(
+            REFERER,
+            HeaderValue::from_static("https://live.bilibili.com/"),
+        );
+        let user_agent = setting.user_agent.as_deref().unwrap_or(USER_AGENT);
+        let client = match Client::builder()
+            .user_agent(user_agent)
+            .default_headers(headers)
+            .build()
+        {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Build bilibili client failed: {err}");
+                state.suspended_until = Some(Instant::now() + AUTH_RETRY);
+                return None;
+            }
+        };
+        state.client = Some(client.clone());
+        Some(client)
+    }
+
+    fn suspend_auth() {
+        auth_state().suspended_until = Some(Instant::now() + AUTH_RETRY);
+    }
+
+    /// Live api of the account if configured and not suspended, otherwise anonymous.
+    pub struct Bilibili;
+
+    impl LiveApi for Bilibili {
+        fn get_info<'a>(&'a self, room_id: &'a str) -> LiveFuture<'a> {
+            Box::pin(get_info_of_account(room_id))
+        }
+    }
+
+    async fn get_info_of_account(room_id: &str) -> PluginResult<Value> {
+        let config = CONFIG.get().unwrap();
+        if let Some(ref bilibili) = config.bilibili {
+            if let Some(authed) = authenticated(bilibili) {
+                match get_info(&authed, room_id)
+                    .await
+                    .and_then(super::check_rejected)
+                {
+                    Ok(value) => return Ok(value),
+                    Err(err) => {
+                        std_error!(
+                            "Authenticated bilibili request failed, fallback to anonymous: {err}"
+                        );
+                        suspend_auth();
+                    }
+                }
+            }
+        }
+        get_info(&anonymous(), room_id).await
+    }
+
+    async fn get_info(client: &Client, room_id: &str) -> PluginResult<Value> {
+        let url = "https://api.live.bilibili.com/room/v1/Room/get_info";
+        let params = [("room_id", room_id)];
+        let resp = client.get(url).query(&params).send().await?;
+        Ok(resp.error_for_status()?.json().await?)
+    }
+}
+
+async fn query_handler(e: Arc<GroupMsg>, room_id: &str, online_msg: &str, offline_msg: &str) {
+    let group_id = e.group_id;
+    analytics::count(group_id, "live_query").await;
     let room = match query_liveroom(room_id).await {
         Ok(room) => room,
         Err(err) => {
@@ -36,8 +238,8 @@ async fn query_handler(e: Arc<MsgEvent>, room_id: &str, online_msg: &str, offlin
         }
     };
     if !room.exist {
-        let message = Message::from(format!("直播间{}不存在", room_id));
-        e.reply(message);
+        e.reply(tr!(group_id, "live.room_not_found", room = room_id))
+            .await;
         return;
     }
     let status_str = if room.data.is_streaming {
@@ -45,48 +247,33 @@ async fn query_handler(e: Arc<MsgEvent>, room_id: &str, online_msg: &str, offlin
     } else {
         offline_msg
     };
-    let resp = formatdoc!(
-        "
-        {status_str}
-        链接:{}
-        {}
-        ",
-        LiveRoom::url_from_id(room_id),
-        room
-    );
-    let mut message = Message::new().add_text(resp);
-    // add key_frame if exists, otherwise fallback to user_cover
-    let fallback_list = [room.data.keyframe, room.data.user_cover];
-    let image = fallback_list.iter().find(|&x| !x.is_empty());
-    if let Some(img) = image {
-        message = message.add_image(img);
-    }
-    e.reply(message);
+    let message = online_message(Some(group_id), status_str, room_id, &room).await;
+    e.reply(message).await;
 }
 
-pub async fn general_query_handler(e: Arc<MsgEvent>) {
+pub async fn general_query_handler(e: Arc<GroupMsg>) {
     // no-op if no text
     let Some(msg) = e.borrow_text() else {
         return;
     };
-    let query_message = "查询直播间";
-    if !msg.contains(query_message) {
+    let group_id = e.group_id;
+    let query_message = tr!(group_id, "live.query");
+    if !msg.contains(&query_message) {
         return;
     }
-    let msg = msg.replace(query_message, "");
+    let msg = msg.replace(&query_message, "");
     let room_id = msg.trim();
     if room_id.parse::<usize>().is_err() {
-        e.reply("直播间不存在");
+        e.reply(tr!(group_id, "live.invalid_room")).await;
         return;
     }
-    query_handler(e, room_id, "直播中", "不在直播").await;
+    let online_msg = tr!(group_id, "live.streaming");
+    let offline_msg = tr!(group_id, "live.not_streaming");
+    query_handler(e, room_id, &online_msg, &offline_msg).await;
 }
 
-pub async fn local_query_handler(e: Arc<MsgEvent>) {
-    // no-op if not group message
-    let Some(group_id) = e.group_id else {
-        return;
-    };
+pub async fn local_query_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
     // no-op if no text
     let Some(msg) = e.borrow_text() else {
         return;
@@ -105,12 +292,102 @@ pub async fn local_query_handler(e: Arc<MsgEvent>) {
     };
 
     // now pre-configured group found, and it has live setting
+    if live.screenshot_message.as_deref() == Some(msg.trim()) {
+        let message = screenshot(group_id, &live.room_id, &live.offline_msg).await;
+        e.reply(message).await;
+        return;
+    }
     // check query_msg
     if msg.contains(&live.query_message) {
         query_handler(e, &live.room_id, &live.online_msg, &live.offline_msg).await;
     }
 }
 
+/// Current key frame of the room with live info, or `offline_msg` if not streaming.
+pub async fn screenshot(group_id: i64, room_id: &str, offline_msg: &str) -> Message {
+    let room = match query_liveroom(room_id).await {
+        Ok(room) => room,
+        Err(err) => {
+            std_error!("Query liveroom failed: {err}");
+            return Message::from(tr!(group_id, "live.screenshot_failed"));
+        }
+    };
+    if !room.exist {
+        return Message::from(tr!(group_id, "live.room_not_found", room = room_id));
+    }
+    if !room.data.is_streaming {
+        return Message::from(offline_msg);
+    }
+    let info = tr!(
+        group_id,
+        "live.screenshot",
+        title = room.data.title,
+        area = room.data.area_name,
+        online = room.data.online,
+        since = room.data.live_time,
+    );
+    let keyframe = match room.data.keyframe.as_str() {
+        "" => None,
+        keyframe => image_safety::screen(keyframe).await,
+    };
+    match keyframe {
+        Some(img) => Message::new().add_image(&img).add_text(info),
+        None => Message::new().add_text(format!(
+            "{info}\n{}",
+            tr!(group_id, "live.screenshot_unavailable")
+        )),
+    }
+}
+
+/// Rooms whose status changes are notified to `group_id`.
+pub fn followed_rooms(group_id: i64) -> Vec<&'static str> {
+    let config = CONFIG.get().unwrap();
+    let mut rooms = Vec::new();
+    for g in config.groups.iter().flatten() {
+        let Some(ref live) = g.live else {
+            continue;
+        };
+        let notified = g.id == group_id
+            || live
+                .notify_group_ids
+                .iter()
+                .flatten()
+                .any(|&id| id == group_id);
+        if notified && !rooms.contains(&live.room_id.as_str()) {
+            rooms.push(live.room_id.as_str());
+        }
+    }
+    rooms
+}
+
+/// One line per room followed by `group_id` for the agent, queried on demand.
+pub async fn live_status(group_id: i64) -> String {
+    let mut lines = Vec::new();
+    for room_id in followed_rooms(group_id) {
+        let line = match query_liveroom(room_id).await {
+            Ok(room) if !room.exist => tr!(group_id, "live.status_missing", room = room_id),
+            Ok(room) if room.data.is_streaming => tr!(
+                group_id,
+                "live.status_streaming",
+                room = room_id,
+                area = room.data.area_name,
+                title = room.data.title,
+            ),
+            Ok(_) => tr!(group_id, "live.status_offline", room = room_id),
+            Err(err) => {
+                std_error!("Query live room failed: {err}");
+                tr!(group_id, "live.status_failed", room = room_id)
+            }
+        };
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        return tr!(group_id, "live.status_none");
+    }
+
+    lines.join("\n")
+}
+
 pub async fn subscribe_live() {
     let config = CONFIG.get().unwrap();
 
@@ -125,81 +402,314 @@ pub async fn subscribe_live() {
 
     // spawn a task for each live room
     for (group_id, live) in id_lives {
+        restore_switch(group_id, live).await;
         kovi::spawn(async move {
             let duration = Duration::from_secs(live.poll_interval_sec);
-            schedule_task_blocking(duration, move || {
-                async move {
-                    let room = match query_liveroom(&live.room_id).await {
-                        Ok(v) => v,
-                        Err(err) => {
-                            std_error!("Query live room failed: {err}");
-                            return;
-                        }
-                    };
-                    if !room.exist {
-                        std_error!("直播间{}不存在", live.room_id);
-                        return;
-                    }
-                    let bot = global_state::get_bot();
-                    match live.get_switch() {
-                        LiveSwitch::On => {
-                            // used to be online, send msg only if offline
-                            if !room.data.is_streaming {
-                                std_info!("not streaming, offline notification");
-                                let msg = Message::new().add_text(&live.offline_msg);
-                                bot.send_group_msg(group_id, msg);
-                                live.set_switch(LiveSwitch::Off);
-                            }
-                        }
-                        LiveSwitch::Off => {
-                            // used to be offline, send msg only if online
-                            if room.data.is_streaming {
-                                std_info!("streaming, online notification");
-                                let resp = formatdoc!(
-                                    "
-                                    {}
-                                    链接:{}
-                                    {}
-                                    ",
-                                    &live.online_msg,
-                                    LiveRoom::url_from_id(&live.room_id),
-                                    room
-                                );
-                                let mut message = Message::new().add_text(resp);
-
-                                // add key_frame if exists, otherwise fallback to user_cover
-                                let fallback_list = [room.data.keyframe, room.data.user_cover];
-                                let image = fallback_list.iter().find(|&x| !x.is_empty());
-                                if let Some(img) = image {
-                                    message = message.add_image(img);
-                                }
-                                bot.send_group_msg(group_id, message);
-                                live.set_switch(LiveSwitch::On);
-                            }
-                        }
-                        LiveSwitch::Init => {
-                            // avoid online notification on launching
-                            std_info!("Live switch: Init");
-                            match room.data.is_streaming {
-                                true => live.set_switch(LiveSwitch::On),
-                                false => live.set_switch(LiveSwitch::Off),
-                            }
-                        }
-                        LiveSwitch::Trap => {
-                            // if I were myself 2 years ago I would use unreachable!()
-                            std_error!(
-                                "Subscribe live in trap state: group id = {}",
-                                &live.room_id
-                            );
-                        }
-                    }
+            schedule_task_blocking(duration, move || poll_room(group_id, live)).await;
+        });
+    }
+
+    // one poller serves all rooms subscribed in private chat
+    if let Some(ref private_live) = config.private_live {
+        kovi::spawn(async move {
+            let duration = Duration::from_secs(private_live.poll_interval_sec);
+            let streaming = match store::db_load_live_states(PRIVATE_POLLER).await {
+                Ok(v) => v.into_iter().collect(),
+                Err(err) => {
+                    std_db_error!("Load private live states failed: {err}");
+                    HashMap::new()
                 }
+            };
+            let streaming = Arc::new(Mutex::new(streaming));
+            schedule_task_blocking(duration, move || {
+                poll_private_subscriptions(Arc::clone(&streaming))
             })
             .await;
         });
     }
 }
 
+/// Resume the switch from the status persisted before restart, stay Init if never observed.
+pub async fn restore_switch(group_id: i64, live: &LiveSetting) {
+    let states = match store::db_load_live_states(group_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load live states of group {group_id} failed: {err}");
+            return;
+        }
+    };
+    if let Some((_, streaming)) = states.into_iter().find(|(room, _)| *room == live.room_id) {
+        std_info!("Live switch of room {} restored: {streaming}", live.room_id);
+        live.set_switch(if streaming {
+            LiveSwitch::On
+        } else {
+            LiveSwitch::Off
+        });
+    }
+}
+
+async fn record_switch(group_id: i64, live: &LiveSetting, room: &LiveRoom) {
+    let streaming = room.data.is_streaming;
+    live.set_switch(if streaming {
+        LiveSwitch::On
+    } else {
+        LiveSwitch::Off
+    });
+    if let Err(err) = store::db_save_live_state(group_id, &live.room_id, streaming).await {
+        std_db_error!("Save live state of room {} failed: {err}", live.room_id);
+    }
+    let title = &room.data.title;
+    if let Err(err) = store::db_record_live_session(group_id, &live.room_id, streaming, title).await
+    {
+        std_db_error!("Record live session of room {} failed: {err}", live.room_id);
+    }
+}
+
+/// Notify target groups of the live setting in `group_id` if the room status changes.
+pub async fn poll_room(group_id: i64, live: &LiveSetting) {
+    let room = match query_liveroom(&live.room_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Query live room failed: {err}");
+            return;
+        }
+    };
+    if !room.exist {
+        std_error!("直播间{}不存在", live.room_id);
+        return;
+    }
+    match live.get_switch() {
+        LiveSwitch::On => {
+            // used to be online, send msg only if offline
+            if !room.data.is_streaming {
+                // keep switch so that the change is notified once the window passes
+                if !live.try_notify() {
+                    std_info!(
+                        "Offline of room {} held back by notify window",
+                        live.room_id
+                    );
+                    return;
+                }
+                std_info!("not streaming, offline notification");
+                for target in live.target_groups(group_id) {
+                    let msg = Message::new().add_text(&live.offline_msg);
+                    util::send_group_msg(target, msg);
+                }
+                record_switch(group_id, live, &room).await;
+                crate::run_hooks(|h| h.on_live_status_change(group_id, &live.room_id, &room));
+            }
+        }
+        LiveSwitch::Off => {
+            // used to be offline, send msg only if online
+            if room.data.is_streaming {
+                if !live.try_notify() {
+                    std_info!("Online of room {} held back by notify window", live.room_id);
+                    return;
+                }
+                std_info!("streaming, online notification");
+                for target in live.target_groups(group_id) {
+                    let message =
+                        online_message(Some(target), &live.online_msg, &live.room_id, &room).await;
+                    util::send_group_msg(target, message);
+                }
+                if live.notify_subscribers.unwrap_or(false) {
+                    notify_subscribers(&live.room_id, &room).await;
+                }
+                record_switch(group_id, live, &room).await;
+                crate::run_hooks(|h| h.on_live_status_change(group_id, &live.room_id, &room));
+            }
+        }
+        LiveSwitch::Init => {
+            // avoid online notification on first observation
+            std_info!("Live switch: Init");
+            record_switch(group_id, live, &room).await;
+        }
+        LiveSwitch::Trap => {
+            // if I were myself 2 years ago I would use unreachable!()
+            std_error!("Subscribe live in trap state: group id = {}", &live.room_id);
+        }
+    }
+}
+
+async fn notify_subscribers(room_id: &str, room: &LiveRoom) {
+    let user_ids = match store::db_load_room_subscribers(room_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load subscribers of room {room_id} failed: {err}");
+            return;
+        }
+    };
+    if user_ids.is_empty() {
+        return;
+    }
+    let online_msg = tr!(None, "live.private_online");
+    let message = online_message(None, &online_msg, room_id, room).await;
+    for user_id in user_ids {
+        util::send_private_msg::<Message>(user_id, message.clone());
+    }
+}
+
+/// Notify subscribers of rooms that turn online, grouping them so that each room is queried once
+/// per round.
+async fn poll_private_subscriptions(streaming: Arc<Mutex<HashMap<String, bool>>>) {
+    let subs = match store::db_load_live_subscriptions().await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load live subscriptions failed: {err}");
+            return;
+        }
+    };
+    let config = CONFIG.get().unwrap();
+    let Some(ref private_live) = config.private_live else {
+        return;
+    };
+    // rooms whose group poller notifies subscribers
+    let fanned_out: Vec<&str> = config
+        .groups
+        .iter()
+        .flatten()
+        .filter_map(|g| g.live.as_ref())
+        .filter(|live| live.notify_subscribers.unwrap_or(false))
+        .map(|live| live.room_id.as_str())
+        .collect();
+    let mut rooms: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+    for sub in subs
+        .iter()
+        .filter(|s| !fanned_out.contains(&s.room_id.as_str()))
+    {
+        rooms.entry(&sub.room_id).or_default().push(sub.user_id);
+    }
+
+    let mut streaming = streaming.lock().await;
+    streaming.retain(|room_id, _| rooms.contains_key(room_id.as_str()));
+    for (room_id, user_ids) in rooms {
+        let room = match query_liveroom(room_id).await {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Query live room failed: {err}");
+                continue;
+            }
+        };
+        if !room.exist {
+            continue;
+        }
+        // first observation only records status, avoid notification on launching or subscribing
+        let was_streaming = streaming.insert(room_id.to_string(), room.data.is_streaming);
+        if was_streaming != Some(room.data.is_streaming) {
+            let res = store::db_save_live_state(PRIVATE_POLLER, room_id, room.data.is_streaming);
+            if let Err(err) = res.await {
+                std_db_error!("Save live state of room {room_id} failed: {err}");
+            }
+        }
+        if was_streaming == Some(false) && room.data.is_streaming {
+            if !private_live.try_notify(room_id) {
+                std_info!("Private notification of room {room_id} held back by notify window");
+                continue;
+            }
+            std_info!("streaming, private notification: {room_id}");
+            let online_msg = tr!(None, "live.private_online");
+            let message = online_message(None, &online_msg, room_id, &room).await;
+            for user_id in user_ids {
+                util::send_private_msg::<Message>(user_id, message.clone());
+            }
+        }
+    }
+}
+
+/// Subscribe or unsubscribe a live room in private chat of friends.
+///
+/// Returns true if the message is taken as a subscription command.
+pub async fn private_subscribe_handler(e: Arc<PrivateMsg>) -> bool {
+    if e.sub_type != "friend" {
+        return false;
+    }
+    let config = CONFIG.get().unwrap();
+    let Some(ref private_live) = config.private_live else {
+        return false;
+    };
+    let Some(text) = e.borrow_text() else {
+        return false;
+    };
+    let text = text.trim();
+    // check unsubscribe first in case it contains subscribe
+    if let Some(room_id) = text.strip_prefix(&private_live.unsubscribe) {
+        unsubscribe(&e, room_id.trim()).await;
+        return true;
+    }
+    if let Some(room_id) = text.strip_prefix(&private_live.subscribe) {
+        subscribe(&e, private_live, room_id.trim()).await;
+        return true;
+    }
+    false
+}
+
+async fn subscribe(e: &PrivateMsg, private_live: &PrivateLiveSetting, room_id: &str) {
+    if room_id.parse::<usize>().is_err() {
+        e.reply(tr!(None, "live.invalid_room"));
+        return;
+    }
+    match store::db_count_live_subscription(e.user_id).await {
+        Ok(count) if count >= private_live.max_rooms_per_user => {
+            let msg = tr!(
+                None,
+                "live.max_rooms",
+                max = private_live.max_rooms_per_user
+            );
+            e.reply(msg);
+            return;
+        }
+        Ok(_) => {}
+        Err(err) => {
+            std_db_error!("Count live subscription failed: {err}");
+            return;
+        }
+    }
+    match query_liveroom(room_id).await {
+        Ok(room) if room.exist => {}
+        Ok(_) => {
+            e.reply(tr!(None, "live.room_not_found", room = room_id));
+            return;
+        }
+        Err(err) => {
+            std_error!("Query liveroom failed: {err}");
+            return;
+        }
+    }
+    let time = util::cur_time_iso8601(None);
+    match store::db_add_live_subscription(e.user_id, room_id, &time).await {
+        Ok(true) => e.reply(tr!(None, "live.subscribed", room = room_id)),
+        Ok(false) => e.reply(tr!(None, "live.already_subscribed", room = room_id)),
+        Err(err) => std_db_error!("Add live subscription failed: {err}"),
+    }
+}
+
+async fn unsubscribe(e: &PrivateMsg, room_id: &str) {
+    match store::db_remove_live_subscription(e.user_id, room_id).await {
+        Ok(true) => e.reply(tr!(None, "live.unsubscribed", room = room_id)),
+        Ok(false) => e.reply(tr!(None, "live.not_subscribed", room = room_id)),
+        Err(err) => std_db_error!("Remove live subscription failed: {err}"),
+    }
+}
+
+/// Room status with room info and key frame, fallback to user cover. The image is dropped if it
+/// fails the safety check.
+async fn online_message(
+    group_id: Option<i64>,
+    online_msg: &str,
+    room_id: &str,
+    room: &LiveRoom,
+) -> Message {
+    let resp = format!("{online_msg}\n{}\n", room.describe(group_id, room_id));
+    let mut message = Message::new().add_text(resp);
+    let fallback_list = [&room.data.keyframe, &room.data.user_cover];
+    if let Some(img) = fallback_list.iter().find(|x| !x.is_empty()) {
+        if let Some(img) = image_safety::screen(img).await {
+            message = message.add_image(&img);
+        }
+    }
+    message
+}
+
 #[derive(Deserialize, Debug)]
 pub struct LiveRoom {
     #[serde(rename = "code", deserialize_with = "parse_code")]
@@ -213,33 +723,36 @@ impl LiveRoom {
     }
 }
 
-impl Display for LiveRoom {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writedoc!(
-            f,
-            "
-            分区:{}
-            标题:{}
-            简介:{}
-            热度:{}, 关注:{}
-            ",
-            self.data.area_name,
-            self.data.title,
-            self.data.description,
-            self.data.online,
-            self.data.attention
+impl LiveRoom {
+    /// Link and room info in the locale of group.
+    pub fn describe(&self, group_id: Option<i64>, room_id: &str) -> String {
+        tr!(
+            group_id,
+            "live.room_info",
+            url = Self::url_from_id(room_id),
+            area = self.data.area_name,
+            title = self.data.title,
+            description = self.data.description,
+            online = self.data.online,
+            attention = self.data.attention,
         )
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub struct LiveData {
     #[serde(rename = "live_status", deserialize_with = "parse_status")]
     pub is_streaming: bool,
     pub online: usize,
     pub attention: usize,
+    /// Absent for some rooms, e.g. those never streamed.
+    #[serde(default)]
     pub keyframe: String,
+    #[serde(default)]
     pub user_cover: String,
+    /// Start time of streaming, `0000-00-00 00:00:00` if not streaming.
+    #[serde(default)]
+    pub live_time: String,
     pub area_name: String,
     pub description: String,
     pub title: String,
@@ -0,0 +1,557 @@
+//! Built-in reply strings, zh-CN by default.
+//!
+//! A locale named `en` is read from `<DATA_PATH>/locales/en.toml`, which maps keys below to
+//! templates, e.g. `"command.mute" = "Muted"`, or `mute = "Muted"` under `[command]`. `<!name!>`
+//! in a template is replaced by the argument of the same name. Keys missing from a locale file
+//! fall back to zh-CN.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Mutex, MutexGuard, OnceLock},
+};
+
+use crate::{std_error, CONFIG, DATA_PATH};
+
+/// Name of the built-in locale, never read from file.
+pub const DEFAULT_LOCALE: &str = "zh-CN";
+
+const ZH_CN: &[(&str, &str)] = &[
+    ("agent.unknown_group", "该群聊未配置"),
+    ("command.private_dump", "导出结果将私聊发送"),
+    ("command.unknown_group", "群<!group!>未配置"),
+    ("command.no_command", "群<!group!>未配置指令"),
+    ("command.unrecognized", "无法识别的指令"),
+    ("command.no_agent", "未配置agent"),
+    ("command.unchanged", "..."),
+    ("command.mute", "接下来我将冷暴力你们所有人,直到主人哀求我"),
+    ("command.unmute", "我勉为其难地同意和你们聊天"),
+    ("command.switch_model", "我的脑子被换成了<!model!>"),
+    ("command.dump_history", "导出了<!count!>条聊天记录: <!url!>"),
+    ("command.dump_log", "导出了<!count!>条日志: <!url!>"),
+    ("command.reload", "配置已重载"),
+    ("command.reload_failed", "配置重载失败: <!error!>"),
+    ("command.announcement", "【公告】\n<!content!>"),
+    ("command.announced", "公告已发送"),
+    ("command.recap_empty", "没有聊天记录"),
+    ("command.images_empty", "没有保存的图片"),
+    ("command.set_card", "群名片已改为<!card!>"),
+    ("command.no_quota", "未配置quota"),
+    ("command.grant_quota", "已为<!user!>增加今日<!kind!>额度<!count!>次"),
+    ("command.cancel_send", "已撤掉待发送的回复"),
+    ("command.cancel_send_empty", "没有待发送的回复"),
+    ("command.files_empty", "群文件是空的"),
+    ("command.files_failed", "获取群文件失败: <!error!>"),
+    (
+        "command.import_history",
+        "导入了<!inserted!>条聊天记录, 跳过<!skipped!>条已有记录",
+    ),
+    ("command.import_failed", "导入聊天记录失败: <!error!>"),
+    ("command.main_admin_only", "仅主人可以使用该指令"),
+    (
+        "command.export_state",
+        "状态已导出到本机<!path!>, 清单中有<!media!>个媒体文件",
+    ),
+    ("command.export_failed", "导出失败: <!error!>"),
+    ("command.export_dataset", "导出了<!count!>条训练样本: <!url!>"),
+    ("command.recap_image", "[图片]"),
+    ("command.recap_record", "[语音]"),
+    ("calendar.mon", "星期一"),
+    ("calendar.tue", "星期二"),
+    ("calendar.wed", "星期三"),
+    ("calendar.thu", "星期四"),
+    ("calendar.fri", "星期五"),
+    ("calendar.sat", "星期六"),
+    ("calendar.sun", "星期日"),
+    ("calendar.festival_today", "今天是<!name!>"),
+    ("calendar.festival_upcoming", "<!days!>天后是<!name!>"),
+    ("calendar.no_festival", "最近没有节日"),
+    ("join_request.approved", "已通过<!user!>的入群申请, <!reason!>"),
+    ("join_request.by_level", "QQ等级达到要求"),
+    ("join_request.by_keyword", "申请信息包含关键词"),
+    ("join_request.by_agent", "申请信息符合要求"),
+    (
+        "join_request.queued",
+        "群<!group!>收到<!user!>的入群申请, 需要人工审核: <!comment!>",
+    ),
+    ("album.files_sender", "群文件"),
+    ("album.folder", "[文件夹] <!name!>, <!count!>个文件"),
+    (
+        "album.file",
+        "<!name!> (<!size!>), <!uploader!>上传于<!time!>",
+    ),
+    (
+        "database.unavailable",
+        "数据库写入失败, 聊天记录暂存在内存中等待重试: <!error!>",
+    ),
+    (
+        "database.recovered",
+        "数据库已恢复, 补写<!written!>条聊天记录, 丢弃<!dropped!>条",
+    ),
+    (
+        "spend.exceeded",
+        "本月OpenAI花费<!cost!>已达上限<!limit!>, 助理改用模型<!fallback!>(-表示停止回复)",
+    ),
+    ("startup.aborted", "插件初始化失败, 已停止运行: <!error!>"),
+    ("self_check.title", "启动自检完成, <!total!>项中<!failed!>项失败:"),
+    ("self_check.passed", "[正常] <!name!>"),
+    ("self_check.failed", "[失败] <!name!>: <!error!>"),
+    ("self_check.group", "[群<!group!>] <!problems!>"),
+    ("self_check.invalid_command_regex", "指令正则无效, 指令不可用"),
+    ("self_check.voice_needs_stt", "语音指令需要全局stt配置"),
+    ("self_check.invalid_link_regex", "可疑链接正则无效, 不检测链接"),
+    ("self_check.moderation_needs_command", "反刷屏通知管理员需要command配置"),
+    ("self_check.verify_needs_agent", "入群验证的AI判断需要agent配置"),
+    ("self_check.verify_needs_command", "入群验证通知管理员需要command配置"),
+    ("self_check.report_needs_command", "举报需要command配置"),
+    ("self_check.invalid_persona_window", "人设时段的时间格式无效, 应为HH:MM"),
+    ("self_check.summary_needs_agent", "群聊总结需要agent配置"),
+    ("self_check.invalid_timezone", "时区格式无效, 应为+08:00"),
+    ("ab_test.empty", "还没有人设对比的记录"),
+    (
+        "ab_test.title",
+        "人设对比, 后续互动统计回复后<!minutes!>分钟内的消息:",
+    ),
+    (
+        "ab_test.entry",
+        "<!variant!>: 回复<!replies!>次, 平均<!follow_ups!>条后续消息, <!rate!>%的提问者继续发言",
+    ),
+    ("feedback.usage", "请回复助理的消息并发送<!trigger!> <!good!>或<!trigger!> <!bad!>"),
+    ("feedback.not_agent_reply", "只能评价助理的回复"),
+    ("feedback.thanks", "感谢反馈"),
+    ("feedback.empty", "还没有助理回复的记录"),
+    ("feedback.title", "助理回复反馈, 差评率高的在前:"),
+    (
+        "feedback.entry",
+        "<!persona!> / <!model!>: 回复<!replies!>次, 好评<!good!>, 差评<!bad!>",
+    ),
+    (
+        "analytics.disabled",
+        "未开启功能使用统计, 请在global中设置analytics = true",
+    ),
+    ("analytics.empty", "最近<!days!>天没有功能使用记录"),
+    ("analytics.title", "最近<!days!>天功能使用次数:"),
+    (
+        "uptime.running",
+        "已运行<!days!>天<!hours!>小时<!minutes!>分钟, 启动于<!start!>",
+    ),
+    ("uptime.unknown", "本次启动未能记录"),
+    (
+        "uptime.restarts",
+        "最近7天启动<!starts!>次, 其中此前有<!unclean!>次非正常退出或初始化失败",
+    ),
+    ("uptime.init_error", "上次初始化失败: <!time!> <!error!>"),
+    ("uptime.no_init_error", "没有初始化失败的记录"),
+    ("maintenance.restart", "待发送的回复和暂存的聊天记录已处理, 正在重启"),
+    (
+        "maintenance.restart_unclean",
+        "等待<!timeout!>秒后仍有<!answers!>条回复未发送, <!rows!>条聊天记录未写入, 照常重启",
+    ),
+    ("maintenance.check_failed", "检查更新失败: <!error!>"),
+    (
+        "maintenance.update_available",
+        "发现新版本<!latest!>, 当前为<!current!>, 更新依赖并重新编译后发送重启命令生效",
+    ),
+    ("maintenance.up_to_date", "当前版本<!current!>已是最新"),
+    (
+        "takeover.pending",
+        "群<!group!>的助理回复待审核 #<!id!>\n提问: <!prompt!>\n回复: <!response!>\n发送<!approve!> <!id!>、<!reject!> <!id!>或<!edit!> <!id!> <新回复>",
+    ),
+    ("takeover.reviewed", "#<!id!>已经审核过了"),
+    ("takeover.done", "#<!id!>已<!status!>"),
+    ("sticker.usage", "请回复一张图片并发送<!add!> <标签>"),
+    ("sticker.added", "已添加表情[<!tag!>]"),
+    ("sticker.removed", "已删除表情[<!tag!>]"),
+    ("sticker.not_found", "没有表情[<!tag!>]"),
+    ("sticker.empty", "还没有表情"),
+    ("sticker.list", "表情: <!tags!>"),
+    ("db_check.failed", "数据库完整性检查发现问题:\n<!detail!>"),
+    ("db_check.size", "数据库大小: <!size!>MB"),
+    ("db_check.never", "还没有检查过数据库"),
+    ("db_check.ok", "上次检查: <!time!> 正常"),
+    ("db_check.problem", "上次检查: <!time!> 发现问题:\n<!detail!>"),
+    ("disk.low", "数据目录所在磁盘剩余<!free!>MB, 低于<!min!>MB, 已暂停下载聊天记录中的图片和语音"),
+    ("disk.recovered", "数据目录所在磁盘剩余<!free!>MB, 已恢复下载图片和语音"),
+    ("temp_session.forward", "<!name!>(<!id!>)通过群<!group!>发来临时会话:\n<!content!>"),
+    ("temp_session.unknown_group", "未知"),
+    ("error_stat.none", "<!day!> 没有错误"),
+    ("error_stat.title", "<!day!> 错误统计, 共<!total!>次:"),
+    ("error_stat.entry", "[<!count!>次] <!template!>\n  最近一次: <!last!>"),
+    ("notice.admin_set", "<!user!>被群主赐予了管理员之力!"),
+    ("notice.admin_unset", "<!user!>被群主剥夺了管理员之力!"),
+    (
+        "notice.leave",
+        "<!user!>忍一时越想越气,退一步越想越亏,怒发冲冠下将所有人踢出了群聊!",
+    ),
+    ("notice.kick", "<!user!>由于讨厌<!operator!>选择将所有人踢出群聊!"),
+    (
+        "notice.approve",
+        "<!user!>大发慈悲、勉为其难地允许了<!operator!>通过ta的入群申请~",
+    ),
+    ("notice.invite", "<!user!>在<!operator!>的苦苦哀求下加入了我们~"),
+    (
+        "notice.ban",
+        "<!user!>因为讨厌<!operator!>决定在<!duration!>秒内冷暴力大家!",
+    ),
+    ("notice.lift_ban", "<!operator!>哄好了<!user!>,TA现在愿意和我们说话了!"),
+    ("notice.poke", "戳了戳你"),
+    ("notice.talkative", "恭喜龙王<!user!>登基!"),
+    ("level.up", "恭喜<!name!>升到了<!level!>级!"),
+    ("level.none", "你还没有经验, 多聊聊天吧"),
+    (
+        "level.mine",
+        "你当前<!level!>级, 经验<!xp!>/<!next!>, 群内排名第<!rank!>",
+    ),
+    ("level.ranking_empty", "还没有人获得经验"),
+    ("level.ranking_title", "等级排行"),
+    (
+        "level.ranking_entry",
+        "<!rank!>. <!name!>: <!level!>级, <!xp!>经验",
+    ),
+    (
+        "word_filter.warning",
+        "<!user!>请注意文明用语, 这是第<!strikes!>次警告",
+    ),
+    (
+        "reminder.usage",
+        "用法: <!trigger!> <时间> <内容>, 例如<!trigger!> 明天下午三点 开会",
+    ),
+    ("reminder.past", "这个时间已经过去了"),
+    ("reminder.too_many", "最多同时设置<!max!>个提醒"),
+    ("reminder.failed", "提醒设置失败"),
+    ("reminder.set", "好的, 将在<!time!>提醒你: <!content!>"),
+    ("reminder.fire", "提醒: <!content!>"),
+    ("live.query", "查询直播间"),
+    ("live.streaming", "直播中"),
+    ("live.not_streaming", "不在直播"),
+    ("live.invalid_room", "直播间不存在"),
+    ("live.room_not_found", "直播间<!room!>不存在"),
+    (
+        "live.room_info",
+        "链接:<!url!>\n分区:<!area!>\n标题:<!title!>\n简介:<!description!>\n热度:<!online!>, 关注:<!attention!>",
+    ),
+    (
+        "live.screenshot",
+        "<!title!>\n分区:<!area!>, 热度:<!online!>, 开播于<!since!>",
+    ),
+    ("live.screenshot_unavailable", "暂时没有直播画面"),
+    ("live.screenshot_failed", "直播间查询失败"),
+    ("live.private_online", "你订阅的直播间开播了"),
+    ("live.max_rooms", "最多订阅<!max!>个直播间"),
+    ("live.subscribed", "已订阅直播间<!room!>，开播时会私聊通知你"),
+    ("live.already_subscribed", "已经订阅过直播间<!room!>"),
+    ("live.unsubscribed", "已取消订阅直播间<!room!>"),
+    ("live.not_subscribed", "未订阅直播间<!room!>"),
+    ("live.status_missing", "直播间<!room!>: 不存在"),
+    (
+        "live.status_streaming",
+        "直播间<!room!>: 直播中, 分区: <!area!>, 标题: <!title!>",
+    ),
+    ("live.status_offline", "直播间<!room!>: 未开播"),
+    ("live.status_failed", "直播间<!room!>: 查询失败"),
+    ("live.status_none", "没有关注的直播间"),
+    ("feed.usage", "用法: <!command!> <链接> [间隔分钟]"),
+    ("feed.invalid_interval", "间隔需为正整数分钟"),
+    ("feed.invalid_url", "请提供http(s)链接"),
+    ("feed.max_feeds", "本群最多订阅<!max!>个RSS"),
+    ("feed.fetch_failed", "无法读取RSS: <!url!>"),
+    ("feed.already_subscribed", "已订阅过<!title!>"),
+    ("feed.subscribed", "已订阅<!title!>, 每<!minutes!>分钟检查一次"),
+    ("feed.unsubscribed", "已取消订阅<!url!>"),
+    ("feed.not_subscribed", "未订阅<!url!>"),
+    ("feed.empty", "本群未订阅RSS"),
+    ("feed.entry", "<!title!> (<!minutes!>分钟)\n<!url!>"),
+    ("quiz.ask", "第<!round!>题: <!question!>"),
+    ("quiz.running", "答题正在进行中"),
+    ("quiz.no_question", "没有可用的题目"),
+    (
+        "quiz.started",
+        "答题开始，共<!count!>题，每题<!seconds!>秒\n<!question!>",
+    ),
+    ("quiz.correct", " 答对了，答案是<!answer!>"),
+    ("quiz.timeout", "时间到，答案是<!answer!>"),
+    ("quiz.finished", "答题结束，排行榜:\n<!board!>"),
+    ("quiz.finished_empty", "答题结束，没有人答对"),
+    ("idiom.running", "接龙正在进行中，当前成语: <!current!>"),
+    ("idiom.empty_dictionary", "成语词典为空"),
+    ("idiom.started", "成语接龙开始: <!current!>\n请接「<!next!>」开头的成语"),
+    ("idiom.stopped", "接龙结束"),
+    ("idiom.no_candidate", "没有成语可以接了"),
+    ("idiom.finished", "<!reason!>，排行榜:\n<!board!>"),
+    ("idiom.finished_empty", "<!reason!>，没有人接上"),
+    ("idiom.wrong_start", "要接「<!next!>」开头的成语"),
+    ("idiom.used", "<!idiom!>已经用过了"),
+    ("idiom.same_player", "不能连续接龙，等等其他人吧"),
+    ("idiom.accepted", " 接龙成功，请接「<!next!>」开头的成语"),
+    ("idiom.hint", "提示: <!tip!>"),
+    ("idiom.hint_count", "提示: 还有<!count!>个「<!next!>」开头的成语可以接"),
+    ("points.signed_in", "今天已经签到过了"),
+    (
+        "points.sign_in",
+        "签到成功，连续签到<!streak!>天，获得<!earned!>积分，当前共<!balance!>积分",
+    ),
+    ("points.balance", "你当前共有<!balance!>积分"),
+    ("points.leaderboard_empty", "还没有人获得积分"),
+    ("points.leaderboard_title", "积分排行"),
+    ("verify.question", " 请在<!minutes!>分钟内回答: <!question!>"),
+    ("verify.wrong", "回答错误，请重新回答"),
+    ("verify.passed", "验证通过，欢迎加入"),
+    ("verify.kicked", "<!user!>未在时限内完成验证，已被移出群聊"),
+    (
+        "verify.expired",
+        "群<!group!>的新成员<!user!>(<!user_id!>)未在时限内完成验证",
+    ),
+    ("report.no_reply", "请回复要举报的消息"),
+    ("report.no_admin", "本群未配置管理员"),
+    ("report.not_found", "找不到被举报的消息"),
+    (
+        "report.forward",
+        "群<!group!>收到举报\n举报人: <!reporter!>(<!reporter_id!>)\n被举报: <!sender!>(<!sender_id!>) <!time!>\n内容: <!content!>\n最近消息:\n<!context!>",
+    ),
+    ("report.forwarded", "已转发给管理员"),
+    ("report.image", "[图片]<!content!>"),
+    ("report.record", "[语音]<!content!>"),
+    ("report.ocr", "[图片文字]<!content!>"),
+    ("report.caption", "[图片描述]<!content!>"),
+    ("report.link", "[链接]<!content!>"),
+    ("search.usage", "用法: <!command!> <关键词>"),
+    ("search.not_found", "没有人说过「<!keyword!>」"),
+    ("search.found", "「<!keyword!>」共出现<!count!>次\n最早: <!earliest!>"),
+    ("search.latest", "最近: <!latest!>"),
+    ("search.ask_usage", "用法: <!command!> <问题>"),
+    ("search.ask_not_found", "聊天记录里没有找到相关内容"),
+    ("meme.usage", "用法: <!command!> <模板> <文字>\n可用模板: <!templates!>"),
+    ("meme.unknown_template", "没有模板<!name!>\n<!usage!>"),
+    ("meme.no_font", "未配置表情字体"),
+    ("meme.insufficient_points", "积分不足, 做表情需要<!cost!>积分"),
+    ("meme.unsafe", "图片未通过安全检查"),
+    ("github.release", "<!repo!> 发布了 <!tag!> <!title!>\n<!url!>"),
+    (
+        "github.issue",
+        "<!repo!> 新issue #<!number!> <!title!>\n来自 <!author!>\n<!url!>",
+    ),
+    ("github.star", "<!repo!> 收获了<!added!>个star, 共<!total!>个"),
+    ("summary.usage", "用法: <!command!> [1到<!max!>之间的小时数]"),
+    ("summary.empty", "最近<!hours!>小时没有聊天记录"),
+    ("summary.result", "最近<!hours!>小时总结:\n<!summary!>"),
+    ("mention.usage", "用法: <!command!> [1到<!max!>之间的天数|今天]"),
+    ("mention.all_time", "有史以来"),
+    ("mention.today", "今天"),
+    ("mention.recent_days", "最近<!days!>天"),
+    ("mention.who_cares_empty", "<!range!>还没有人艾特过<!user!>"),
+    ("mention.who_cares_title", "<!range!>最关心<!user!>的人:"),
+    ("mention.who_cares_entry", "<!rank!>. <!user!> 艾特了<!count!>次"),
+    ("mention.ranking_empty", "<!range!>还没有人被艾特过"),
+    ("mention.ranking_title", "<!range!>艾特排行:"),
+    ("mention.ranking_entry", "<!rank!>. <!user!> 被艾特<!count!>次"),
+    ("activity.mon", "一"),
+    ("activity.tue", "二"),
+    ("activity.wed", "三"),
+    ("activity.thu", "四"),
+    ("activity.fri", "五"),
+    ("activity.sat", "六"),
+    ("activity.sun", "日"),
+    ("activity.empty", "最近<!days!>天没有聊天记录"),
+    (
+        "activity.caption",
+        "最近<!days!>天共<!total!>条消息, 最活跃: 周<!weekday!><!hour!>点",
+    ),
+    ("quota.image", "做表情"),
+    ("quota.search", "搜索聊天记录"),
+    ("quota.dump", "导出"),
+    ("quota.exceeded", "今日<!feature!>次数已用完, 每天<!limit!>次"),
+    ("quota.left_title", "今日剩余次数"),
+    ("quota.left_entry", "<!feature!>: <!left!>/<!total!>"),
+    ("config_view.sender", "查看配置"),
+    ("config_view.title", "群<!group!>运行时状态"),
+    ("config_view.locale", "语言: <!locale!>, 时区: <!timezone!>"),
+    ("config_view.stages", "处理环节: <!stages!>"),
+    (
+        "config_view.agent",
+        "助理: <!state!>, 当前模型: <!model!> (配置为<!configured!>), 时段人设: <!persona!>",
+    ),
+    ("config_view.agent_enabled", "启用"),
+    ("config_view.agent_muted", "已禁用"),
+    ("config_view.no_persona", "无"),
+    ("config_view.no_agent", "助理: 未配置"),
+    ("config_view.live", "本群直播间: <!room!> <!status!>"),
+    ("config_view.live_on", "直播中"),
+    ("config_view.live_off", "未直播"),
+    ("config_view.live_init", "尚未查询"),
+    ("config_view.live_trap", "异常"),
+    ("config_view.followed_rooms", "通知本群的直播间: <!rooms!>"),
+    ("config_view.admins", "命令管理员: <!admins!>"),
+    ("moderation.warn", "请勿<!violation!>"),
+    ("moderation.mute", "<!user!>因<!violation!>被禁言<!sec!>秒"),
+    ("moderation.report", "群<!group!>的<!user!>(<!user_id!>)<!violation!>: <!content!>"),
+    ("moderation.flood", "刷屏"),
+    ("moderation.repeat", "重复发送相同内容"),
+    ("moderation.link", "发送可疑链接"),
+    ("annual_report.usage", "用法: <!trigger!> [年份]"),
+    ("annual_report.silent", "<!name!>在<!year!>年还没有在本群发言"),
+    ("annual_report.title", "<!name!>的<!year!>年度报告"),
+    ("annual_report.messages", "发言<!count!>条, 本群第<!rank!>/<!total!>"),
+    ("annual_report.active_hour", "最活跃的时段: <!hour!>点, 发言<!count!>条"),
+    ("annual_report.most_at", "最常@的人: <!name!>, <!count!>次"),
+    ("annual_report.word", "「<!word!>」<!count!>次"),
+    ("annual_report.words", "口头禅: <!words!>"),
+    ("news.title", "早报 <!date!>"),
+];
+
+/// Reply string of `key` in the locale of a group, or the global locale if group is None.
+///
+/// ```ignore
+/// tr!(group_id, "command.switch_model", model = "gpt-4o");
+/// tr!(None, "live.private_online");
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($group:expr, $key:expr) => {
+        $crate::locale::format($group, $key, &[])
+    };
+    // bound to a local so that arguments are not held across await
+    ($group:expr, $key:expr, $($name:ident = $value:expr),+ $(,)?) => {{
+        let text = $crate::locale::format(
+            $group,
+            $key,
+            &[$((stringify!($name), &$value as &dyn std::fmt::Display)),+],
+        );
+        text
+    }};
+}
+
+/// locale name -> key -> template, None if the file failed to load
+type Locales = HashMap<String, Option<HashMap<String, String>>>;
+
+/// Files are read on first use, and dropped by [clear_cache] on config reload.
+fn locales() -> MutexGuard<'static, Locales> {
+    static LOCALES: OnceLock<Mutex<Locales>> = OnceLock::new();
+    LOCALES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn clear_cache() {
+    locales().clear();
+}
+
+pub fn format(
+    group_id: impl Into<Option<i64>>,
+    key: &str,
+    args: &[(&str, &dyn Display)],
+) -> String {
+    let config = CONFIG.get().unwrap();
+    let group_locale = group_id.into().and_then(|id| {
+        let group = config.groups.iter().flatten().find(|g| g.id == id)?;
+        group.locale.as_deref()
+    });
+    let locale = group_locale.or(config.global.locale.as_deref());
+    format_in(locale, key, args)
+}
+
+/// Template of `key` in the given locale with arguments filled.
+pub fn format_in(locale: Option<&str>, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut text = match lookup(locale, key) {
+        Some(template) => template,
+        None => {
+            std_error!("Unknown locale key: {key}");
+            return key.to_string();
+        }
+    };
+    for (name, value) in args {
+        text = text.replace(&format!("<!{name}!>"), &value.to_string());
+    }
+    text
+}
+
+fn lookup(locale: Option<&str>, key: &str) -> Option<String> {
+    if let Some(name) = locale.filter(|&n| n != DEFAULT_LOCALE) {
+        let mut locales = locales();
+        let table = locales
+            .entry(name.to_string())
+            .or_insert_with(|| load(name));
+        if let Some(template) = table.as_ref().and_then(|t| t.get(key)) {
+            return Some(template.clone());
+        }
+    }
+    ZH_CN
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v.to_string())
+}
+
+fn load(name: &str) -> Option<HashMap<String, String>> {
+    // reject path traversal
+    if name.contains(['/', '\\', '.']) {
+        std_error!("Invalid locale name: {name}");
+        return None;
+    }
+    let path = DATA_PATH
+        .get()
+        .unwrap()
+        .join("locales")
+        .join(format!("{name}.toml"));
+    let content = match std::fs::read_to_string(&path) {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Read locale file {} failed: {err}", path.display());
+            return None;
+        }
+    };
+    let root: toml::Table = match toml::from_str(&content) {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Parse locale file {} failed: {err}", path.display());
+            return None;
+        }
+    };
+    let mut table = HashMap::new();
+    flatten("", root, &mut table);
+    // likely a typo
+    for key in table.keys().filter(|&k| ZH_CN.iter().all(|(z, _)| z != k)) {
+        std_error!("Unknown key {key} in locale file {}", path.display());
+    }
+    Some(table)
+}
+
+/// Tables are joined into dotted keys, non-string values are ignored.
+fn flatten(prefix: &str, table: toml::Table, out: &mut HashMap<String, String>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key
+        } else {
+            format!("{prefix}.{key}")
+        };
+        match value {
+            toml::Value::String(v) => {
+                out.insert(key, v);
+            }
+            toml::Value::Table(t) => flatten(&key, t, out),
+            _ => std_error!("Locale value of {key} is not a string"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_keys_grouped_by_namespace() {
+        let mut keys = HashSet::new();
+        let mut closed = HashSet::new();
+        let mut current = "";
+        for (key, _) in ZH_CN {
+            assert!(keys.insert(*key), "duplicate key {key}");
+            let namespace = key.split_once('.').map_or(*key, |(ns, _)| ns);
+            if namespace != current {
+                assert!(closed.insert(current), "{current} is split");
+                assert!(!closed.contains(namespace), "{namespace} is split");
+                current = namespace;
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 //! Log methods default to [indoc] format.
 //!
 //! # Examples  
-//! ```
+//! ```ignore
 //! std_error!(
 //!     "
 //!     Write bot log to database failed: {e}
@@ -11,7 +11,7 @@
 //! ```
 //!
 //! which is equivalent to  
-//! ```
+//! ```ignore
 //! kovi::log::error!("Write bot log to database failed: {e}\nLog: {content}")
 //! ```
 //!
@@ -20,9 +20,13 @@
 //! 2. database: use db_debug, db_info, db_warn, db_error  
 //! 3. both: use std_db_debug, std_db_info, std_db_warn, std_db_error  
 //!
+//! Error entries written to database are also counted by template, see
+//! [crate::store::db_count_error].
+//!
 //! Pitfalls  
 //! 1. db_* and std_db_* must be in async context  
-//! 2. [indoc] does not trim trailing spaces
+//! 2. [indoc] does not trim trailing spaces  
+//! 3. db_error and std_db_error expect a string literal as the first token
 
 /// Append debug log entry to stdout
 #[macro_export]
@@ -65,7 +69,7 @@ macro_rules! std_error {
 macro_rules! db_debug {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         $crate::store::db_write_bot_log(time, "DEBUG".to_string(), content).await;
     }};
 }
@@ -75,7 +79,7 @@ macro_rules! db_debug {
 macro_rules! db_info {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         $crate::store::db_write_bot_log(time, "INFO".to_string(), content).await;
     }};
 }
@@ -85,7 +89,7 @@ macro_rules! db_info {
 macro_rules! db_warn {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         $crate::store::db_write_bot_log(time, "WARN".to_string(), content).await;
     }};
 }
@@ -93,9 +97,10 @@ macro_rules! db_warn {
 /// Append error log entry to database.
 #[macro_export]
 macro_rules! db_error {
-    ($($t:tt)*) => {{
-        let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+    ($fmt:tt $($t:tt)*) => {{
+        let content = indoc::formatdoc!($fmt $($t)*);
+        let time = $crate::util::cur_time_iso8601(None);
+        $crate::store::db_count_error(indoc::indoc!($fmt), &content).await;
         $crate::store::db_write_bot_log(time, "ERROR".to_string(), content).await;
     }};
 }
@@ -105,7 +110,7 @@ macro_rules! db_error {
 macro_rules! std_db_debug {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         kovi::log::debug!("{}", content);
         $crate::store::db_write_bot_log(time, "DEBUG".to_string(), content).await;
     }};
@@ -116,7 +121,7 @@ macro_rules! std_db_debug {
 macro_rules! std_db_info {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         kovi::log::info!("{}", content);
         $crate::store::db_write_bot_log(time, "INFO".to_string(), content).await;
     }};
@@ -127,7 +132,7 @@ macro_rules! std_db_info {
 macro_rules! std_db_warn {
     ($($t:tt)*) => {{
         let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+        let time = $crate::util::cur_time_iso8601(None);
         kovi::log::warn!("{}", content);
         $crate::store::db_write_bot_log(time, "WARN".to_string(), content).await;
     }};
@@ -136,10 +141,11 @@ macro_rules! std_db_warn {
 /// Append error log entry to stdout and database.
 #[macro_export]
 macro_rules! std_db_error {
-    ($($t:tt)*) => {{
-        let content = indoc::formatdoc!($($t)*);
-        let time = $crate::util::cur_time_iso8601();
+    ($fmt:tt $($t:tt)*) => {{
+        let content = indoc::formatdoc!($fmt $($t)*);
+        let time = $crate::util::cur_time_iso8601(None);
         kovi::log::error!("{}", content);
+        $crate::store::db_count_error(indoc::indoc!($fmt), &content).await;
         $crate::store::db_write_bot_log(time, "ERROR".to_string(), content).await;
     }};
 }
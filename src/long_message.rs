@@ -0,0 +1,79 @@
+//! Summarize very long texts, e.g. pasted articles, so that a single message cannot take the
+//! whole context of the agent.
+//!
+//! History keeps the raw text as content and the summary as interpret of the text segment, and
+//! prompts show the summary. The text is cut instead if the agent fails to summarize it.
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{agent, global_state::GroupSetting, std_info, util, BOT_QQ, CONFIG};
+
+/// Summaries of the latest texts, so that the agent stage reuses the one made by the logger.
+const CACHE_CAPACITY: usize = 32;
+/// Shown before the summary in prompts.
+const LABEL: &str = "[长消息摘要]";
+
+fn group_of(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+fn cache() -> &'static Mutex<VecDeque<(u64, String)>> {
+    static CACHE: OnceLock<Mutex<VecDeque<(u64, String)>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+/// Summary of `text` by the agent of the group, None if the text is not long or the group has no
+/// `agent.long_message`. Texts of the bot itself are never summarized.
+pub async fn digest(group_id: i64, sender_id: i64, text: &str) -> Option<String> {
+    let agent = group_of(group_id)?.agent.as_ref()?;
+    let setting = agent.long_message.as_ref()?;
+    let text = text.trim();
+    if sender_id == *BOT_QQ.get().unwrap() || text.chars().count() <= setting.min_chars {
+        return None;
+    }
+    let hash = util::fnv1a_hash(text);
+    let cached = cache()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(h, _)| *h == hash)
+        .map(|(_, summary)| summary.clone());
+    if cached.is_some() {
+        return cached;
+    }
+    let model = agent.get_model().await;
+    let summary = agent::complete(
+        &agent.api_url,
+        &agent.api_key,
+        &model,
+        &setting.instruction,
+        text,
+    )
+    .await;
+    let summary = match summary {
+        Some(summary) => summary.trim().to_string(),
+        None => {
+            std_info!(
+                "Summarize long message failed, cut to {} chars",
+                setting.min_chars
+            );
+            let head: String = text.chars().take(setting.min_chars).collect();
+            format!("{head}…")
+        }
+    };
+    let mut cache = cache().lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= CACHE_CAPACITY {
+        cache.pop_front();
+    }
+    cache.push_back((hash, summary.clone()));
+    Some(summary)
+}
+
+/// How a summarized text appears in prompts.
+pub fn render(summary: &str) -> String {
+    format!("{LABEL}{summary}")
+}
@@ -0,0 +1,185 @@
+//! Restart and update check from private chat of the main admin, so that the bot can be managed
+//! without shell access.
+//!
+//! Global states are set once per process, so the plugin cannot be started again in place.
+//! Restart drains pending work and exits instead, a supervisor such as systemd or docker is
+//! expected to start the process again.
+
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use kovi::{
+    tokio::time::{sleep, timeout, Instant},
+    Message,
+};
+use serde::Deserialize;
+
+use crate::{
+    event::PrivateMsg, exception::PluginResult, global_state::MaintenanceSetting, http::Request,
+    outbox, std_info, store, tr, uptime, util, ADMIN_QQ, CONFIG,
+};
+
+const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Interval of checking whether pending work is done.
+const DRAIN_POLL: Duration = Duration::from_millis(500);
+
+static EXIT: OnceLock<fn()> = OnceLock::new();
+
+/// Exit for restart through `exit` instead, e.g. to count restarts in tests. Only the first call
+/// takes effect.
+pub fn set_exit(exit: fn()) {
+    let _ = EXIT.set(exit);
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Deserialize, Debug)]
+struct CrateInfo {
+    max_version: String,
+    /// Absent if only pre-releases are published.
+    max_stable_version: Option<String>,
+}
+
+/// Run a maintenance command of the main admin.
+///
+/// Returns true if the message is taken as one.
+pub async fn private_handler(e: Arc<PrivateMsg>) -> bool {
+    let config = CONFIG.get().unwrap();
+    let Some(ref setting) = config.maintenance else {
+        return false;
+    };
+    if e.user_id != *ADMIN_QQ.get().unwrap() {
+        return false;
+    }
+    let Some(text) = e.borrow_text() else {
+        return false;
+    };
+    let text = text.trim();
+    if text == setting.restart {
+        restart(e.user_id, setting).await;
+    } else if text == setting.check_update {
+        util::send_private_msg(e.user_id, check_update().await);
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Wait for answers in the outbox and buffered history, then exit.
+async fn restart(admin_qq: i64, setting: &MaintenanceSetting) {
+    let (answers, rows) = drain(Duration::from_secs(setting.drain_timeout_sec)).await;
+    let message = if answers == 0 && rows == 0 {
+        tr!(None, "maintenance.restart")
+    } else {
+        tr!(
+            None,
+            "maintenance.restart_unclean",
+            timeout = setting.drain_timeout_sec,
+            answers = answers,
+            rows = rows,
+        )
+    };
+    uptime::record_stop().await;
+    exit_for_restart(admin_qq, message).await;
+}
+
+/// Returns (answers still in the outbox, rows still buffered) once both are empty or `timeout`
+/// passes.
+pub async fn drain(timeout: Duration) -> (usize, usize) {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if store::write_buffer().is_open() {
+            store::flush_write_buffer().await;
+        }
+        let answers = outbox::pending();
+        let rows = store::write_buffer().rows.len();
+        if (answers == 0 && rows == 0) || Instant::now() >= deadline {
+            return (answers, rows);
+        }
+        sleep(DRAIN_POLL).await;
+    }
+}
+
+/// Tell the admin and exit.
+async fn exit_for_restart(admin_qq: i64, message: String) {
+    // wait for delivery, as exit drops pending requests
+    let sent = timeout(
+        Duration::from_secs(5),
+        util::send_private_msg_return(admin_qq, Message::from(message)),
+    )
+    .await;
+    if !matches!(sent, Ok(Some(_))) {
+        std_info!("Restart notice to admin not delivered");
+    }
+    (EXIT.get().copied().unwrap_or(exit_process))();
+}
+
+fn exit_process() {
+    std_info!("Exit for restart.");
+    std::process::exit(0);
+}
+
+/// Compare the running version with the latest one on crates.io.
+pub async fn check_update() -> String {
+    let latest = match latest_version().await {
+        Ok(v) => v,
+        Err(err) => return tr!(None, "maintenance.check_failed", error = err),
+    };
+    if is_newer(&latest, CURRENT_VERSION) {
+        tr!(
+            None,
+            "maintenance.update_available",
+            latest = latest,
+            current = CURRENT_VERSION,
+        )
+    } else {
+        tr!(None, "maintenance.up_to_date", current = CURRENT_VERSION)
+    }
+}
+
+async fn latest_version() -> PluginResult<String> {
+    let name = env!("CARGO_PKG_NAME");
+    // crates.io rejects requests without user agent
+    let resp: CrateResponse = Request::get(format!("https://crates.io/api/v1/crates/{name}"))
+        .timeout(Duration::from_secs(20))
+        .user_agent(format!("{name}/{CURRENT_VERSION}"))
+        .send()
+        .await?
+        .json()?;
+    Ok(resp
+        .krate
+        .max_stable_version
+        .unwrap_or(resp.krate.max_version))
+}
+
+/// Whether `latest` is a higher "major.minor.patch" than `current`, pre-release suffixes ignored.
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Vec<u64> {
+        v.split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|n| n.parse().unwrap_or(0))
+            .collect()
+    };
+    parse(latest) > parse(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.3"));
+        assert!(is_newer("0.1.10", "0.1.9"));
+        assert!(!is_newer("0.1.3", "0.1.3"));
+        assert!(!is_newer("0.1.3-beta", "0.1.3"));
+    }
+}
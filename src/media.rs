@@ -0,0 +1,101 @@
+//! Images and records of group history are downloaded by the OneBot implementation on request.
+//!
+//! Fetches are bounded in time, file size and number at once, so that a burst of large files
+//! cannot stall message logging. Segments whose file is not fetched keep only the file id, as
+//! while disk space is low. Files are skipped before download if the segment reports a size over
+//! the limit, and deleted if found over the limit after download.
+
+use std::{sync::OnceLock, time::Duration};
+
+use kovi::tokio::{fs, sync::Semaphore, time::timeout};
+use serde_json::Value;
+
+use crate::{
+    exception::{PluginError::OneBotApi, PluginResult},
+    global_state::MediaSetting,
+    std_error, std_info, util, CONFIG,
+};
+
+const MB: u64 = 1024 * 1024;
+
+fn setting() -> MediaSetting {
+    let config = CONFIG.get().unwrap();
+    config.media.clone().unwrap_or_default()
+}
+
+/// Sized on first use, so a new `max_concurrent` takes effect on restart.
+fn pool() -> &'static Semaphore {
+    static POOL: OnceLock<Semaphore> = OnceLock::new();
+    POOL.get_or_init(|| Semaphore::new(setting().max_concurrent.max(1)))
+}
+
+/// Size in bytes reported in the segment, a number or a string by implementation.
+fn file_size(data: &Value) -> Option<u64> {
+    match &data["file_size"] {
+        Value::Number(n) => n.as_u64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Local path of the image or record of the segment `data`, None if the fetch fails, times out,
+/// or the file is larger than `max_file_mb`.
+pub async fn fetch(seg_type: &str, data: &Value) -> Option<String> {
+    let file = data["file"].as_str()?;
+    let setting = setting();
+    let max_bytes = setting.max_file_mb * MB;
+    // skip before downloading if the size is known
+    if let Some(size) = file_size(data).filter(|&size| size > max_bytes) {
+        std_info!("Skip {seg_type} {file} of {}MB", size / MB);
+        return None;
+    }
+    // waiting for a free slot counts towards the timeout
+    let task = async {
+        let _permit = pool().acquire().await.ok()?;
+        Some(get_media(seg_type, file).await)
+    };
+    let path = match timeout(Duration::from_secs(setting.timeout_sec), task).await {
+        Ok(Some(Ok(path))) if path.starts_with('/') => path,
+        Ok(Some(Ok(path))) => {
+            std_error!("Fetch {seg_type} {file} returned no local path: {path}");
+            return None;
+        }
+        Ok(Some(Err(err))) => {
+            std_error!("Fetch {seg_type} {file} failed: {err}");
+            return None;
+        }
+        Ok(None) => return None,
+        Err(_) => {
+            std_error!("Fetch {seg_type} {file} timed out");
+            return None;
+        }
+    };
+    match fs::metadata(&path).await {
+        Ok(meta) if meta.len() > max_bytes => {
+            std_info!("Skip {seg_type} {path} of {}MB", meta.len() / MB);
+            // not referenced by history, would only take disk space
+            if let Err(err) = fs::remove_file(&path).await {
+                std_error!("Remove skipped {seg_type} {path} failed: {err}");
+            }
+            None
+        }
+        Ok(_) => Some(path),
+        Err(err) => {
+            std_error!("Read metadata of {path} failed: {err}");
+            None
+        }
+    }
+}
+
+/// Download through bot.
+async fn get_media(seg_type: &str, file: &str) -> PluginResult<String> {
+    let res = match seg_type {
+        "record" => util::get_record(file, "mp3").await,
+        _ => util::get_image(file).await,
+    };
+    let api = res.map_err(|e| OneBotApi(e.to_string()))?;
+    api.data["file"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| OneBotApi(format!("no file in {}", api.data)))
+}
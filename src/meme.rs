@@ -0,0 +1,292 @@
+//! Render text onto meme templates and reply with the image.
+
+use std::{path::PathBuf, sync::Arc};
+
+use ab_glyph::{FontVec, PxScale};
+use image::{Rgba, RgbaImage};
+use imageproc::{
+    drawing::{draw_hollow_rect_mut, draw_text_mut, text_size},
+    rect::Rect,
+};
+use kovi::Message;
+use time::OffsetDateTime;
+
+use crate::{
+    event::GroupMsg,
+    exception::PluginResult,
+    global_state::{MemeSetting, QuotaKind},
+    image_safety, points, quota, std_db_error, std_error, tr, util, CONFIG, DATA_PATH,
+};
+
+/// Solid background with framed text, e.g. 喜报.
+struct Builtin {
+    name: &'static str,
+    background: Rgba<u8>,
+    foreground: Rgba<u8>,
+}
+
+const BUILTINS: [Builtin; 3] = [
+    Builtin {
+        name: "喜报",
+        background: Rgba([200, 22, 30, 255]),
+        foreground: Rgba([255, 215, 0, 255]),
+    },
+    Builtin {
+        name: "悲报",
+        background: Rgba([225, 225, 225, 255]),
+        foreground: Rgba([20, 20, 20, 255]),
+    },
+    Builtin {
+        name: "警告",
+        background: Rgba([255, 204, 0, 255]),
+        foreground: Rgba([0, 0, 0, 255]),
+    },
+];
+const BUILTIN_SIZE: (u32, u32) = (800, 600);
+const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+enum Template {
+    Builtin(&'static Builtin),
+    /// Text is drawn at the bottom with outline.
+    Image(PathBuf),
+}
+
+/// Triggered by `<trigger> <template> <text>`, lists templates if arguments are missing.
+pub async fn meme_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no meme config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref meme) = group.meme else {
+        return;
+    };
+    let Some(args) = text.trim().strip_prefix(&meme.trigger) else {
+        return;
+    };
+
+    let usage = tr!(
+        group_id,
+        "meme.usage",
+        command = meme.trigger,
+        templates = template_names(meme).join(", "),
+    );
+    let Some((name, caption)) = args.trim().split_once(char::is_whitespace) else {
+        e.reply(usage).await;
+        return;
+    };
+    let caption = caption.trim();
+    let Some(template) = find_template(meme, name) else {
+        e.reply(tr!(
+            group_id,
+            "meme.unknown_template",
+            name = name,
+            usage = usage
+        ))
+        .await;
+        return;
+    };
+    let font_path = DATA_PATH.get().unwrap().join(&meme.font_file);
+    if !font_path.is_file() {
+        std_error!("Meme font not found: {}", font_path.display());
+        e.reply(tr!(group_id, "meme.no_font")).await;
+        return;
+    }
+    if let Err(reply) = quota::take(group_id, e.sender_id, QuotaKind::Image).await {
+        e.reply_and_quote(reply).await;
+        return;
+    }
+
+    let caption_owned = caption.to_string();
+    let rendered = kovi::tokio::task::spawn_blocking(move || -> PluginResult<RgbaImage> {
+        let font = FontVec::try_from_vec(std::fs::read(font_path)?)?;
+        render(&template, &caption_owned, &font)
+    })
+    .await;
+    let image = match rendered {
+        Ok(Ok(image)) => image,
+        Ok(Err(err)) => {
+            std_error!("Render meme failed: {err}");
+            return;
+        }
+        Err(err) => {
+            std_error!("Render meme task failed: {err}");
+            return;
+        }
+    };
+
+    if meme.cost > 0 && group.points.is_some() {
+        match points::try_spend(group_id, e.sender_id, meme.cost, "meme").await {
+            Ok(true) => {}
+            Ok(false) => {
+                e.reply_and_quote(tr!(group_id, "meme.insufficient_points", cost = meme.cost))
+                    .await;
+                return;
+            }
+            Err(err) => {
+                std_db_error!("Spend points for meme failed: {err}");
+                return;
+            }
+        }
+    }
+
+    let millis = OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000;
+    let path = DATA_PATH
+        .get()
+        .unwrap()
+        .join(format!("meme_{group_id}_{millis}.png"));
+    if let Err(err) = image.save(&path) {
+        std_error!("Save meme failed: {err}");
+        return;
+    }
+    // upload through object storage, fallback to local file
+    let path_str = path.to_string_lossy().to_string();
+    let uploaded = util::call_upload(&path_str).await;
+    let file = if uploaded.starts_with("http") {
+        uploaded
+    } else {
+        format!("file://{path_str}")
+    };
+    let Some(file) = image_safety::screen(&file).await else {
+        e.reply(tr!(group_id, "meme.unsafe")).await;
+
+        return;
+    };
+    e.reply(Message::new().add_image(&file)).await;
+}
+
+fn template_names(setting: &MemeSetting) -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|b| b.name.to_string()).collect();
+    let Some(dir) = template_dir(setting) else {
+        return names;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return names;
+    };
+    let mut custom: Vec<String> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let ext = path.extension()?.to_str()?.to_lowercase();
+            if !IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().to_string())
+        })
+        .collect();
+    custom.sort();
+    names.extend(custom);
+    names
+}
+
+fn template_dir(setting: &MemeSetting) -> Option<PathBuf> {
+    let dir = setting.template_dir.as_ref()?;
+    Some(DATA_PATH.get().unwrap().join(dir))
+}
+
+fn find_template(setting: &MemeSetting, name: &str) -> Option<Template> {
+    if let Some(builtin) = BUILTINS.iter().find(|b| b.name == name) {
+        return Some(Template::Builtin(builtin));
+    }
+    // reject path traversal
+    if name.contains(['/', '\\', '.']) {
+        return None;
+    }
+    let dir = template_dir(setting)?;
+    IMAGE_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{name}.{ext}")))
+        .find(|path| path.is_file())
+        .map(Template::Image)
+}
+
+fn render(template: &Template, text: &str, font: &FontVec) -> PluginResult<RgbaImage> {
+    match template {
+        Template::Builtin(builtin) => {
+            let (width, height) = BUILTIN_SIZE;
+            let mut canvas = RgbaImage::from_pixel(width, height, builtin.background);
+            for inset in [20, 32] {
+                let rect = Rect::at(inset, inset)
+                    .of_size(width - 2 * inset as u32, height - 2 * inset as u32);
+                draw_hollow_rect_mut(&mut canvas, rect, builtin.foreground);
+            }
+            let (scale, lines) = fit(font, text, width - 120, height - 120);
+            let line_height = scale.y as u32;
+            let total = line_height * lines.len() as u32;
+            let mut y = height.saturating_sub(total) / 2;
+            for line in lines {
+                let (w, _) = text_size(scale, font, &line);
+                let x = width.saturating_sub(w) / 2;
+                draw_text_mut(
+                    &mut canvas,
+                    builtin.foreground,
+                    x as i32,
+                    y as i32,
+                    scale,
+                    font,
+                    &line,
+                );
+                y += line_height;
+            }
+            Ok(canvas)
+        }
+        Template::Image(path) => {
+            let mut canvas = image::open(path)?.to_rgba8();
+            let (width, height) = canvas.dimensions();
+            let (scale, lines) = fit(font, text, width * 9 / 10, height / 3);
+            let line_height = scale.y as u32;
+            let total = line_height * lines.len() as u32;
+            let mut y = height.saturating_sub(total + height / 20);
+            let black = Rgba([0, 0, 0, 255]);
+            let white = Rgba([255, 255, 255, 255]);
+            for line in lines {
+                let (w, _) = text_size(scale, font, &line);
+                let x = (width.saturating_sub(w) / 2) as i32;
+                let y_i = y as i32;
+                for (dx, dy) in [(-2, 0), (2, 0), (0, -2), (0, 2)] {
+                    draw_text_mut(&mut canvas, black, x + dx, y_i + dy, scale, font, &line);
+                }
+                draw_text_mut(&mut canvas, white, x, y_i, scale, font, &line);
+                y += line_height;
+            }
+            Ok(canvas)
+        }
+    }
+}
+
+/// Largest scale whose wrapped lines fit in the box, no smaller than 16px.
+fn fit(font: &FontVec, text: &str, max_width: u32, max_height: u32) -> (PxScale, Vec<String>) {
+    let mut size = (max_height as f32).min(120.0);
+    loop {
+        let scale = PxScale::from(size);
+        let lines = wrap(font, text, scale, max_width);
+        if size <= 16.0 || size as u32 * lines.len() as u32 <= max_height {
+            return (scale, lines);
+        }
+        size *= 0.9;
+    }
+}
+
+/// Break text by character so that CJK text without spaces wraps as well.
+fn wrap(font: &FontVec, text: &str, scale: PxScale, max_width: u32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for c in text.chars() {
+        line.push(c);
+        if text_size(scale, font, &line).0 > max_width && line.chars().count() > 1 {
+            line.pop();
+            lines.push(line);
+            line = c.to_string();
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,131 @@
+//! Rankings of who at whom, recorded by [store::write_group_msg].
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{event::GroupMsg, exception::PluginResult, std_db_error, store, tr, util, CONFIG};
+
+/// Longest range in days, beyond which the lower bound is meaningless.
+const MAX_DAYS: i64 = 3650;
+
+/// `<who_cares> [range]` and `<ranking> [range]`, where range is days or `今天`, all time if absent.
+pub async fn mention_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no mention config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref setting) = group.mention else {
+        return;
+    };
+    let text = text.trim();
+    let (trigger, arg, who_cares) = if let Some(arg) = text.strip_prefix(&setting.who_cares) {
+        (&setting.who_cares, arg, true)
+    } else if let Some(arg) = text.strip_prefix(&setting.ranking) {
+        (&setting.ranking, arg, false)
+    } else {
+        return;
+    };
+    let Some((since, range)) = parse_range(group_id, arg.trim()) else {
+        e.reply(tr!(
+            group_id,
+            "mention.usage",
+            command = trigger,
+            max = MAX_DAYS
+        ))
+        .await;
+        return;
+    };
+
+    let res = if who_cares {
+        who_cares_reply(&e, &since, &range, setting.top_n).await
+    } else {
+        ranking_reply(group_id, &since, &range, setting.top_n).await
+    };
+    match res {
+        Ok(reply) => e.reply(reply).await,
+        Err(err) => std_db_error!("Load mention statistics failed: {err}"),
+    }
+}
+
+async fn who_cares_reply(e: &GroupMsg, since: &str, range: &str, n: i64) -> PluginResult<String> {
+    let (group_id, user_id) = (e.group_id, e.sender_id);
+    let name = util::get_name_in_group(group_id, user_id).await;
+    let rows = store::db_top_mentioners(group_id, user_id, since, n).await?;
+    if rows.is_empty() {
+        return Ok(tr!(
+            group_id,
+            "mention.who_cares_empty",
+            range = range,
+            user = name
+        ));
+    }
+    let mut lines = vec![tr!(
+        group_id,
+        "mention.who_cares_title",
+        range = range,
+        user = name
+    )];
+    for (i, (sender_id, count)) in rows.into_iter().enumerate() {
+        let sender = util::get_name_in_group(group_id, sender_id).await;
+        lines.push(tr!(
+            group_id,
+            "mention.who_cares_entry",
+            rank = i + 1,
+            user = sender,
+            count = count,
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+async fn ranking_reply(group_id: i64, since: &str, range: &str, n: i64) -> PluginResult<String> {
+    let rows = store::db_top_mentioned(group_id, since, n).await?;
+    if rows.is_empty() {
+        return Ok(tr!(group_id, "mention.ranking_empty", range = range));
+    }
+    let mut lines = vec![tr!(group_id, "mention.ranking_title", range = range)];
+    for (i, (target_id, count)) in rows.into_iter().enumerate() {
+        let target = util::get_name_in_group(group_id, target_id).await;
+        lines.push(tr!(
+            group_id,
+            "mention.ranking_entry",
+            rank = i + 1,
+            user = target,
+            count = count,
+        ));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Lower bound of time and its description, None if malformed or longer than [MAX_DAYS]. Empty
+/// lower bound for all time.
+fn parse_range(group_id: i64, arg: &str) -> Option<(String, String)> {
+    if arg.is_empty() {
+        return Some((String::new(), tr!(group_id, "mention.all_time")));
+    }
+    let today = tr!(group_id, "mention.today");
+    if arg == today {
+        return Some((util::cur_date_iso8601(Some(group_id)), today));
+    }
+    let days = arg
+        .parse::<i64>()
+        .ok()
+        .filter(|d| (1..=MAX_DAYS).contains(d))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let since = days.checked_mul(86400).and_then(|s| now.checked_sub(s))?;
+    let since = util::iso8601_from_timestamp(Some(group_id), since).ok()?;
+    Some((since, tr!(group_id, "mention.recent_days", days = days)))
+}
@@ -0,0 +1,139 @@
+//! Move the bot to another server by a single archive of its state.
+//!
+//! `state-<timestamp>.tar.gz` exported under `<DATA_PATH>` contains `config.toml`, a consistent
+//! snapshot `store.db`, and `manifest.json` listing media files referenced by group history,
+//! which are not archived since they are usually uploaded already. Placing the archive as
+//! `<DATA_PATH>/state-import.tar.gz` on the new server restores it on next startup.
+
+use std::{
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    global_state::CONFIG_VERSION,
+    std_info,
+    store::{self, MediaEntry},
+    util, BOT_QQ, DATA_PATH,
+};
+
+/// Archive restored at startup, renamed after import so it is not restored twice.
+pub const IMPORT_ARCHIVE: &str = "state-import.tar.gz";
+/// Directory inside the archive.
+const STATE_DIR: &str = "state";
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Manifest {
+    pub created_at: String,
+    pub bot_qq: i64,
+    pub config_version: u32,
+    pub media: Vec<MediaEntry>,
+}
+
+/// Returns path of the archive and number of media files in manifest.
+pub async fn export_state() -> PluginResult<(String, usize)> {
+    let data_path = DATA_PATH.get().unwrap();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let work_dir = data_path.join(format!("state-{timestamp}"));
+    let state_dir = work_dir.join(STATE_DIR);
+    kovi::tokio::fs::create_dir_all(&state_dir).await?;
+
+    let res = async {
+        store::db_snapshot(&state_dir.join("store.db").to_string_lossy()).await?;
+        let config_path = data_path.join("config.toml");
+        if kovi::tokio::fs::try_exists(&config_path).await? {
+            kovi::tokio::fs::copy(&config_path, state_dir.join("config.toml")).await?;
+        }
+        let mut media = Vec::new();
+        for group_id in store::db_group_table_ids().await? {
+            media.extend(store::db_load_media(group_id).await?);
+        }
+        let count = media.len();
+        let manifest = Manifest {
+            created_at: util::cur_time_iso8601(None),
+            bot_qq: *BOT_QQ.get().unwrap(),
+            config_version: CONFIG_VERSION,
+            media,
+        };
+        let json = serde_json::to_string_pretty(&manifest)?;
+        kovi::tokio::fs::write(state_dir.join("manifest.json"), json).await?;
+
+        let archive = data_path.join(format!("state-{timestamp}.tar.gz"));
+        let output = kovi::tokio::process::Command::new("tar")
+            .arg("-czf")
+            .arg(&archive)
+            .arg("-C")
+            .arg(&work_dir)
+            .arg(STATE_DIR)
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+            return Err(PluginError::ChildProcess("tar".into(), stderr));
+        }
+        Ok((archive.to_string_lossy().to_string(), count))
+    }
+    .await;
+    kovi::tokio::fs::remove_dir_all(&work_dir).await?;
+    res
+}
+
+/// Restore [IMPORT_ARCHIVE] under `data_path` if present, before config and database are loaded.
+///
+/// Replaced files are kept with suffix `.before-import`. Returns whether an archive is imported.
+pub fn import_state(data_path: &Path) -> PluginResult<bool> {
+    let archive = data_path.join(IMPORT_ARCHIVE);
+    if !archive.exists() {
+        return Ok(false);
+    }
+    std_info!("Importing state from {}...", archive.display());
+    let work_dir = data_path.join("state-import");
+    if work_dir.exists() {
+        std::fs::remove_dir_all(&work_dir)?;
+    }
+    std::fs::create_dir_all(&work_dir)?;
+    let output = Command::new("tar")
+        .arg("-xzf")
+        .arg(&archive)
+        .arg("-C")
+        .arg(&work_dir)
+        .output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(PluginError::ChildProcess("tar".into(), stderr));
+    }
+    let state_dir = work_dir.join(STATE_DIR);
+    if !state_dir.join("store.db").exists() {
+        return Err(PluginError::PathNotAvailable(format!(
+            "store.db in {}",
+            archive.display()
+        )));
+    }
+    // journal files belong to the replaced database
+    for name in ["config.toml", "store.db", "store.db-wal", "store.db-shm"] {
+        let path = data_path.join(name);
+        if path.exists() {
+            std::fs::rename(&path, data_path.join(format!("{name}.before-import")))?;
+        }
+    }
+    for name in ["config.toml", "store.db"] {
+        let path = state_dir.join(name);
+        if path.exists() {
+            std::fs::rename(path, data_path.join(name))?;
+        }
+    }
+    std::fs::remove_dir_all(&work_dir)?;
+    std::fs::rename(
+        &archive,
+        data_path.join(format!("{IMPORT_ARCHIVE}.imported")),
+    )?;
+    std_info!("State imported, replaced files are kept with suffix .before-import");
+    Ok(true)
+}
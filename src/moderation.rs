@@ -0,0 +1,87 @@
+//! Anti-spam for group messages.
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg,
+    global_state::{GroupSetting, ModerationAction, Violation},
+    std_info, tr, util, BOT_QQ, CONFIG,
+};
+
+pub async fn spam_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref moderation) = group.moderation else {
+        return;
+    };
+    // admins are exempted
+    if let Some(ref command) = group.command {
+        if command.is_admin(group_id, e.sender_id).await {
+            return;
+        }
+    }
+    let text = e.borrow_text().unwrap_or_default();
+    // media-only messages are told apart by their segments
+    let fingerprint = util::fnv1a_hash(&serde_json::to_string(&e.message).unwrap_or_default());
+    let Some(violation) = moderation.record(e.sender_id, text, fingerprint) else {
+        return;
+    };
+    std_info!(
+        "Moderation: {violation:?}, group = {group_id}, user = {}",
+        e.sender_id
+    );
+
+    match moderation.action {
+        ModerationAction::Warn => {
+            let violation = violation.describe(group_id);
+            e.reply_and_quote(tr!(group_id, "moderation.warn", violation = violation))
+                .await
+        }
+        ModerationAction::Report => report(group, &e, violation).await,
+        ModerationAction::Mute => {
+            let bot_qq = *BOT_QQ.get().unwrap();
+            let role = util::get_role_in_group(group_id, bot_qq).await;
+            if !matches!(role.as_deref(), Some("admin" | "owner")) {
+                report(group, &e, violation).await;
+                return;
+            }
+            util::ban_group_member(group_id, e.sender_id, moderation.mute_sec);
+            let user_name = util::get_name_in_group(group_id, e.sender_id).await;
+            let msg = tr!(
+                group_id,
+                "moderation.mute",
+                user = user_name,
+                violation = violation.describe(group_id),
+                sec = moderation.mute_sec,
+            );
+            util::send_group_and_log(group_id, msg).await;
+        }
+    }
+}
+
+/// Notify command admins of the group in private chat.
+async fn report(group: &GroupSetting, e: &GroupMsg, violation: Violation) {
+    let Some(ref command) = group.command else {
+        return;
+    };
+    let user_name = util::get_name_in_group(group.id, e.sender_id).await;
+    let text = e.borrow_text().unwrap_or_default();
+    let msg = tr!(
+        group.id,
+        "moderation.report",
+        group = group.id,
+        user = user_name,
+        user_id = e.sender_id,
+        violation = violation.describe(group.id),
+        content = text,
+    );
+    for admin_id in command.admins(group.id).await {
+        util::send_private_msg(admin_id, msg.as_str());
+    }
+}
@@ -0,0 +1,77 @@
+//! Daily news digest built from feed headlines.
+
+use crate::{
+    agent,
+    feed::{self, Feed},
+    global_state::NewsSetting,
+    std_error, std_info, tr,
+    util::{self, schedule_daily_blocking},
+    CONFIG,
+};
+
+/// Post the digest to opted-in groups every day at the configured hour.
+pub async fn subscribe_news() {
+    let config = CONFIG.get().unwrap();
+    // no-op if no news config
+    let Some(ref news) = config.news else {
+        return;
+    };
+    if news.group_ids.is_empty() || news.sources.is_empty() {
+        return;
+    }
+    kovi::spawn(async move {
+        schedule_daily_blocking(news.hour, || post_digest(news)).await;
+    });
+}
+
+/// Fetch headlines once and post to each opted-in group, summarized by its agent if possible.
+pub async fn post_digest(news: &NewsSetting) {
+    let mut feeds = Vec::new();
+    for url in &news.sources {
+        match feed::fetch_feed(url).await {
+            Ok(v) => feeds.push(v),
+            Err(err) => std_error!("Fetch news source {url} failed: {err}"),
+        }
+    }
+    let headlines = format_headlines(&feeds, news.headlines_per_source);
+    if headlines.is_empty() {
+        std_info!("No headline today, skip news digest.");
+        return;
+    }
+    let date = util::cur_date_iso8601(None);
+
+    let config = CONFIG.get().unwrap();
+    for &group_id in &news.group_ids {
+        let agent = config
+            .groups
+            .iter()
+            .flatten()
+            .find(|g| g.id == group_id)
+            .and_then(|g| g.agent.as_ref());
+        let summary = match (agent, &news.summary_prompt) {
+            (Some(agent), Some(prompt)) => {
+                let model = agent.get_model().await;
+                agent::complete(&agent.api_url, &agent.api_key, &model, prompt, &headlines).await
+            }
+            _ => None,
+        };
+        // fallback to plain headlines if not summarized
+        let body = summary.as_deref().unwrap_or(&headlines);
+        let title = tr!(group_id, "news.title", date = date);
+        util::send_group_msg(group_id, format!("{title}\n{body}"));
+    }
+}
+
+fn format_headlines(feeds: &[Feed], per_source: usize) -> String {
+    let mut buf = String::new();
+    for feed in feeds {
+        if feed.entries.is_empty() {
+            continue;
+        }
+        buf.push_str(&format!("【{}】\n", feed.title));
+        for (i, entry) in feed.entries.iter().take(per_source).enumerate() {
+            buf.push_str(&format!("{}. {}\n", i + 1, entry.title));
+        }
+    }
+    buf.trim_end().to_string()
+}
@@ -0,0 +1,74 @@
+//! Optional OCR of group images, so that screenshot text is searchable and visible to agent.
+
+use std::time::Duration;
+
+use kovi::tokio::{process::Command, time::timeout};
+use serde::Deserialize;
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    global_state::{OcrEngine, OcrSetting},
+    http::{Body, Request},
+    std_error, CONFIG,
+};
+
+#[derive(Deserialize, Debug)]
+struct OcrResponse {
+    text: String,
+}
+
+/// Text of a local image, None if OCR is disabled, failed, or the result is too short.
+pub async fn recognize(path: &str) -> Option<String> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no ocr config
+    let ocr = config.ocr.as_ref()?;
+    let task = async {
+        match ocr.engine {
+            OcrEngine::Tesseract => by_tesseract(ocr, path).await,
+            OcrEngine::Endpoint => by_endpoint(ocr, path).await,
+        }
+    };
+    let text = match timeout(Duration::from_secs(ocr.timeout_sec), task).await {
+        Ok(Ok(text)) => text,
+        Ok(Err(err)) => {
+            std_error!("OCR {path} failed: {err}");
+            return None;
+        }
+        Err(_) => {
+            std_error!("OCR {path} timed out");
+            return None;
+        }
+    };
+    let text = normalize(&text);
+    (text.chars().count() >= ocr.min_chars).then_some(text)
+}
+
+async fn by_tesseract(ocr: &OcrSetting, path: &str) -> PluginResult<String> {
+    let output = Command::new("tesseract")
+        .args([path, "stdout", "-l", &ocr.language])
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(PluginError::ChildProcess("tesseract".into(), stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+async fn by_endpoint(ocr: &OcrSetting, path: &str) -> PluginResult<String> {
+    let Some(ref endpoint) = ocr.endpoint else {
+        return Err(PluginError::PathNotAvailable(String::from("ocr endpoint")));
+    };
+    let mut req = Request::post(endpoint).body(Body::File(path.to_string()));
+    if let Some(ref key) = ocr.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp: OcrResponse = req.send().await?.json()?;
+    Ok(resp.text)
+}
+
+/// Join lines and collapse whitespace, OCR output of chat screenshots is mostly short phrases.
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,31 @@
+//! OneBot actions taken by the plugin, behind [OneBotApi] so that the real calls are compiled in
+//! every build while tests serve them with [crate::testing::MockBot].
+//!
+//! Actions and params are those of OneBot v11 and its common extensions. Handlers go through the
+//! wrappers of [crate::util], which also apply dry run and bans.
+
+use std::{future::Future, pin::Pin};
+
+use kovi::{ApiReturn, RuntimeBot};
+use serde_json::Value;
+
+pub type ApiFuture<'a> = Pin<Box<dyn Future<Output = Result<ApiReturn, ApiReturn>> + Send + 'a>>;
+
+/// Connection to the OneBot implementation, [RuntimeBot] in production.
+pub trait OneBotApi: Send + Sync {
+    /// Send an action without waiting for the response.
+    fn send_api(&self, action: &str, params: Value);
+
+    /// Send an action and wait for the response.
+    fn send_api_return<'a>(&'a self, action: &'a str, params: Value) -> ApiFuture<'a>;
+}
+
+impl OneBotApi for RuntimeBot {
+    fn send_api(&self, action: &str, params: Value) {
+        RuntimeBot::send_api(self, action, params);
+    }
+
+    fn send_api_return<'a>(&'a self, action: &'a str, params: Value) -> ApiFuture<'a> {
+        Box::pin(RuntimeBot::send_api_return(self, action, params))
+    }
+}
@@ -0,0 +1,53 @@
+//! Answers of the agent wait in an outbox for a short delay before being sent, so that a command
+//! admin can cancel an obviously wrong one by the `cancel_send` command.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, OnceLock,
+    },
+    time::Duration,
+};
+
+use kovi::tokio::time::sleep;
+
+/// Cancel flags of waiting messages per group, the latest last.
+fn outbox() -> MutexGuard<'static, HashMap<i64, Vec<Arc<AtomicBool>>>> {
+    static OUTBOX: OnceLock<Mutex<HashMap<i64, Vec<Arc<AtomicBool>>>>> = OnceLock::new();
+    OUTBOX
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap()
+}
+
+/// Wait `delay_sec` before sending to the group. Returns false if cancelled meanwhile.
+pub async fn hold(group_id: i64, delay_sec: u64) -> bool {
+    if delay_sec == 0 {
+        return true;
+    }
+    let cancelled = Arc::new(AtomicBool::new(false));
+    outbox()
+        .entry(group_id)
+        .or_default()
+        .push(Arc::clone(&cancelled));
+    sleep(Duration::from_secs(delay_sec)).await;
+    if let Some(waiting) = outbox().get_mut(&group_id) {
+        waiting.retain(|c| !Arc::ptr_eq(c, &cancelled));
+    }
+    !cancelled.load(Ordering::Relaxed)
+}
+
+/// Messages waiting to be sent to any group.
+pub fn pending() -> usize {
+    outbox().values().map(Vec::len).sum()
+}
+
+/// Cancel the latest message waiting to be sent to the group, false if there is none.
+pub fn cancel_latest(group_id: i64) -> bool {
+    let Some(cancelled) = outbox().get_mut(&group_id).and_then(Vec::pop) else {
+        return false;
+    };
+    cancelled.store(true, Ordering::Relaxed);
+    true
+}
@@ -0,0 +1,45 @@
+//! Switch agents to the persona of the current time of day, see [PersonaWindow].
+//!
+//! [PersonaWindow]: crate::global_state::PersonaWindow
+
+use std::time::Duration;
+
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::{
+    std_db_info,
+    util::{self, schedule_task_blocking},
+    CONFIG,
+};
+
+const TICK_SEC: u64 = 60;
+
+/// Ticks regardless of config, so that schedules added by reloading config take effect.
+pub async fn subscribe_persona() {
+    kovi::spawn(async {
+        let duration = Duration::from_secs(TICK_SEC);
+        schedule_task_blocking(duration, tick).await;
+    });
+}
+
+/// Minutes since midnight at the offset.
+pub fn minute_of_day(offset: UtcOffset) -> u32 {
+    let now = OffsetDateTime::now_utc().to_offset(offset);
+    now.hour() as u32 * 60 + now.minute() as u32
+}
+
+/// Update active persona of every agent, logging the switches.
+pub async fn tick() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    for group in groups {
+        let Some(ref agent) = group.agent else {
+            continue;
+        };
+        if let Some(name) = agent.update_persona(minute_of_day(util::utc_offset(Some(group.id)))) {
+            std_db_info!("Persona of group {} switched to {name}.", group.id);
+        }
+    }
+}
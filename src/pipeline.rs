@@ -0,0 +1,169 @@
+//! Chain of handlers for group messages, run in the order of the group's `pipeline`.
+//!
+//! A stage may stop the chain, e.g. blacklisted members are logged but never answered. New
+//! features hook in by adding a [PipelineStage] and its arm in [run_stage].
+
+use std::{sync::Arc, time::Instant};
+
+use crate::{
+    activity, agent, annual_report, command,
+    event::GroupMsg,
+    feed, feedback, games,
+    global_state::{GroupSetting, PipelineStage},
+    heat, level, live, meme, mention, moderation, points, quota, reminder, repeat, report, repost,
+    search, std_info, sticker, summary, unfurl, util, verify, word_filter, CONFIG,
+};
+
+/// Whether later stages run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+fn group_of(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+/// Run stages configured for the group, all stages if the group is not configured.
+pub async fn run(e: Arc<GroupMsg>) {
+    let stages = group_of(e.group_id).map_or(&PipelineStage::ALL[..], |g| g.stages());
+    run_stages(e, stages).await;
+}
+
+pub async fn run_stages(e: Arc<GroupMsg>, stages: &[PipelineStage]) {
+    let mut slept = false;
+    for &stage in stages {
+        // ignoring and logging are not delayed
+        if !matches!(stage, PipelineStage::Ignore | PipelineStage::Logger) && !slept {
+            util::sleep_rand_time().await;
+            slept = true;
+        }
+        if run_stage(stage, Arc::clone(&e)).await == Flow::Stop {
+            std_info!(
+                "Pipeline stopped at {stage:?}, group = {}, user = {}",
+                e.group_id,
+                e.sender_id
+            );
+            return;
+        }
+    }
+}
+
+async fn run_stage(stage: PipelineStage, e: Arc<GroupMsg>) -> Flow {
+    match stage {
+        PipelineStage::Ignore => return ignore_handler(&e),
+        PipelineStage::Logger => agent::logger(e).await,
+        PipelineStage::Heat => heat::heat_handler(e).await,
+        PipelineStage::Blacklist => return blacklist_handler(&e),
+        PipelineStage::Moderation => {
+            moderation::spam_handler(Arc::clone(&e)).await;
+            word_filter::warn_handler(e).await;
+        }
+        PipelineStage::RateLimit => return rate_limit_handler(&e).await,
+        PipelineStage::Keyword => return keyword_handler(&e).await,
+        PipelineStage::Command => command::act(e).await,
+        PipelineStage::Preview => unfurl::preview_handler(e).await,
+        PipelineStage::Repeat => repeat::repeat_handler(e).await,
+        PipelineStage::Repost => repost::repost_handler(e).await,
+        PipelineStage::Points => points::points_handler(e).await,
+        PipelineStage::Quota => quota::query_handler(e).await,
+        PipelineStage::Level => level::level_handler(e).await,
+        PipelineStage::Games => games::game_handler(e).await,
+        PipelineStage::Verify => verify::answer_handler(e).await,
+        PipelineStage::Report => report::report_handler(e).await,
+        PipelineStage::Feed => feed::feed_handler(e).await,
+        PipelineStage::Meme => meme::meme_handler(e).await,
+        PipelineStage::Search => search::search_handler(e).await,
+        PipelineStage::AnnualReport => annual_report::report_handler(e).await,
+        PipelineStage::Activity => activity::activity_handler(e).await,
+        PipelineStage::Summary => summary::summary_handler(e).await,
+        PipelineStage::Mention => mention::mention_handler(e).await,
+        PipelineStage::Reminder => reminder::reminder_handler(e).await,
+        PipelineStage::Feedback => feedback::feedback_handler(e).await,
+        PipelineStage::Sticker => sticker::sticker_handler(e).await,
+        PipelineStage::Live => {
+            live::local_query_handler(Arc::clone(&e)).await;
+            live::general_query_handler(e).await;
+        }
+        PipelineStage::Agent => agent::at_me_handler(e).await,
+    }
+    Flow::Continue
+}
+
+fn ignore_handler(e: &GroupMsg) -> Flow {
+    let Some(ignore) = group_of(e.group_id).and_then(|g| g.ignore.as_ref()) else {
+        return Flow::Continue;
+    };
+    let text = e.borrow_text().unwrap_or_default().trim_start();
+    let images = e.message.get("image").len();
+    let only_images = e.message.iter().all(|seg| seg.type_ == "image");
+    let ignored = ignore.bot_ids.contains(&e.sender_id)
+        || ignore
+            .prefixes
+            .iter()
+            .any(|p| !p.is_empty() && text.starts_with(p.as_str()))
+        || ignore
+            .max_images
+            .is_some_and(|max| only_images && images > max);
+    if ignored {
+        Flow::Stop
+    } else {
+        Flow::Continue
+    }
+}
+
+fn blacklist_handler(e: &GroupMsg) -> Flow {
+    let blacklisted = group_of(e.group_id)
+        .and_then(|g| g.blacklist.as_ref())
+        .is_some_and(|b| b.user_ids.contains(&e.sender_id));
+    if blacklisted {
+        Flow::Stop
+    } else {
+        Flow::Continue
+    }
+}
+
+async fn rate_limit_handler(e: &GroupMsg) -> Flow {
+    let Some(group) = group_of(e.group_id) else {
+        return Flow::Continue;
+    };
+    let Some(ref rate_limit) = group.rate_limit else {
+        return Flow::Continue;
+    };
+    if let Some(ref command) = group.command {
+        if command.is_admin(e.group_id, e.sender_id).await {
+            return Flow::Continue;
+        }
+    }
+    if rate_limit.record(e.sender_id, Instant::now()) {
+        Flow::Continue
+    } else {
+        Flow::Stop
+    }
+}
+
+async fn keyword_handler(e: &GroupMsg) -> Flow {
+    let Some(keyword) = group_of(e.group_id).and_then(|g| g.keyword.as_ref()) else {
+        return Flow::Continue;
+    };
+    let Some(text) = e.borrow_text() else {
+        return Flow::Continue;
+    };
+    let Some(rule) = keyword
+        .rules
+        .iter()
+        .find(|r| !r.keyword.is_empty() && text.contains(&r.keyword))
+    else {
+        return Flow::Continue;
+    };
+    if e.claim_reply() {
+        e.reply(rule.reply.as_str()).await;
+    }
+    if rule.stop {
+        Flow::Stop
+    } else {
+        Flow::Continue
+    }
+}
@@ -0,0 +1,105 @@
+//! Sign-in and points economy of group members.
+//!
+//! Points are kept as a ledger, balance is the sum of all entries of a member in a group.
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg, exception::PluginResult, global_state::PointsSetting, std_db_error, store, tr,
+    util, CONFIG,
+};
+
+pub async fn points_handler(e: Arc<GroupMsg>) {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == e.group_id) else {
+        return;
+    };
+    let Some(ref points) = group.points else {
+        return;
+    };
+    let res = match e.borrow_text().map(str::trim) {
+        Some(text) if text == points.sign_in => sign_in(&e, points).await,
+        Some(text) if text == points.my_points => my_points(&e).await,
+        Some(text) if text == points.leaderboard => leaderboard(&e, points).await,
+        _ => award_activity(&e, points).await,
+    };
+    if let Err(err) = res {
+        std_db_error!("Points handler failed: {err}");
+    }
+}
+
+/// Spend points of a member, returns false without spending if balance is insufficient.
+pub async fn try_spend(group_id: i64, user_id: i64, cost: i64, reason: &str) -> PluginResult<bool> {
+    let balance = store::db_points_balance(group_id, user_id).await?;
+    if balance < cost {
+        return Ok(false);
+    }
+    store::db_add_points(group_id, user_id, -cost, reason).await?;
+    Ok(true)
+}
+
+async fn sign_in(e: &GroupMsg, points: &PointsSetting) -> PluginResult<()> {
+    let (group_id, user_id) = (e.group_id, e.sender_id);
+    let today = util::cur_date_iso8601(Some(group_id));
+    let yesterday = util::date_iso8601(Some(group_id), -1);
+    let streak = match store::db_load_sign_in(group_id, user_id).await? {
+        Some(last) if last.last_day == today => {
+            e.reply_and_quote(tr!(group_id, "points.signed_in")).await;
+            return Ok(());
+        }
+        Some(last) if last.last_day == yesterday => last.streak + 1,
+        _ => 1,
+    };
+    let bonus = ((streak - 1) * points.streak_bonus).min(points.max_streak_bonus);
+    let earned = points.sign_in_points + bonus;
+    store::db_save_sign_in(group_id, user_id, &today, streak).await?;
+    store::db_add_points(group_id, user_id, earned, "sign_in").await?;
+    let balance = store::db_points_balance(group_id, user_id).await?;
+    e.reply_and_quote(tr!(
+        group_id,
+        "points.sign_in",
+        streak = streak,
+        earned = earned,
+        balance = balance,
+    ))
+    .await;
+    Ok(())
+}
+
+async fn my_points(e: &GroupMsg) -> PluginResult<()> {
+    let balance = store::db_points_balance(e.group_id, e.sender_id).await?;
+    e.reply_and_quote(tr!(e.group_id, "points.balance", balance = balance))
+        .await;
+    Ok(())
+}
+
+async fn leaderboard(e: &GroupMsg, points: &PointsSetting) -> PluginResult<()> {
+    let ranks = store::db_points_leaderboard(e.group_id, points.leaderboard_size).await?;
+    if ranks.is_empty() {
+        e.reply(tr!(e.group_id, "points.leaderboard_empty")).await;
+        return Ok(());
+    }
+    let mut lines = vec![tr!(e.group_id, "points.leaderboard_title")];
+
+    for (idx, rank) in ranks.iter().enumerate() {
+        let name = util::get_name_in_group(e.group_id, rank.user_id).await;
+        lines.push(format!("{}. {name}: {}", idx + 1, rank.points));
+    }
+    util::send_group_and_log(e.group_id, lines.join("\n")).await;
+    Ok(())
+}
+
+/// Award points for chatting, up to a daily cap.
+async fn award_activity(e: &GroupMsg, points: &PointsSetting) -> PluginResult<()> {
+    if points.activity_points <= 0 {
+        return Ok(());
+    }
+    let earned = store::db_points_today(e.group_id, e.sender_id, "activity").await?;
+    if earned + points.activity_points > points.activity_daily_cap {
+        return Ok(());
+    }
+    store::db_add_points(e.group_id, e.sender_id, points.activity_points, "activity").await
+}
@@ -0,0 +1,159 @@
+//! Private chat module.
+
+use std::sync::Arc;
+
+use crate::{
+    agent,
+    event::PrivateMsg,
+    global_state::{PrivateAgentSetting, TempSessionPolicy},
+    std_db_error, std_info,
+    store::{self, PrivateChatMessage},
+    tr,
+    util::{self, TimeRepr},
+    CONFIG,
+};
+
+/// Reply friends in allowlist with agent.
+pub async fn agent_handler(e: Arc<PrivateMsg>) {
+    // no-op if not from friend
+    if e.sub_type != "friend" {
+        return;
+    }
+    // no-op if no private agent config
+    let config = CONFIG.get().unwrap();
+    let Some(ref agent) = config.private_agent else {
+        return;
+    };
+    // no-op if not opted in
+    if !agent.allow_ids.contains(&e.user_id) {
+        return;
+    }
+    reply_with_agent(&e, agent).await;
+}
+
+/// Handle temporary sessions started from groups by the configured policy.
+///
+/// Returns true if the message is from a temporary session, which is never passed to handlers of
+/// friends.
+pub async fn temp_session_handler(e: Arc<PrivateMsg>) -> bool {
+    if e.sub_type != "group" {
+        return false;
+    }
+    let config = CONFIG.get().unwrap();
+    let Some(ref setting) = config.temp_session else {
+        return true;
+    };
+    if setting.policy == TempSessionPolicy::Ignore {
+        return true;
+    }
+    if !setting.try_acquire(e.user_id) {
+        std_info!("Temporary session rate limited: {}", e.user_id);
+        return true;
+    }
+    match setting.policy {
+        TempSessionPolicy::Ignore => {}
+        TempSessionPolicy::Agent => {
+            if let Some(ref agent) = config.private_agent {
+                reply_with_agent(&e, agent).await;
+            }
+        }
+        TempSessionPolicy::Forward => {
+            let content = util::extract_text(&e.message).await;
+            if content.trim().is_empty() {
+                return true;
+            }
+            let group = match e.source_group {
+                Some(group_id) => group_id.to_string(),
+                None => tr!(None, "temp_session.unknown_group"),
+            };
+            util::notify_admin(tr!(
+                None,
+                "temp_session.forward",
+                name = e.sender_name,
+                id = e.user_id,
+                group = group,
+                content = content,
+            ));
+        }
+    }
+    true
+}
+
+/// Answer by the private agent, messages of both sides are persisted.
+async fn reply_with_agent(e: &PrivateMsg, agent: &PrivateAgentSetting) {
+    let content = util::extract_text(&e.message).await;
+    if content.trim().is_empty() {
+        return;
+    }
+    let Some(time) = TimeRepr::UnixTimeStamp(e.time).to_iso8601(None).await else {
+        return;
+    };
+
+    // history excludes the new message
+    let history = match store::db_load_n_private_msg(e.user_id, agent.aware_history_messages).await
+    {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load private chat history failed: {err}");
+            return;
+        }
+    };
+    if let Err(err) =
+        store::db_write_private_msg(e.user_id, e.message_id, &time, "user", &content).await
+    {
+        std_db_error!("Write private message failed: {err}");
+    }
+
+    if !agent.try_acquire(e.user_id) {
+        std_info!("Private agent rate limited: {}", e.user_id);
+        return;
+    }
+
+    let message = format!("{time} {}: {content}", e.sender_name);
+    let (dev_prompt, user_prompt) = agent.substitute_dev_user(e, &history, &message);
+    let Some(answer) = agent::complete(
+        &agent.api_url,
+        &agent.api_key,
+        &agent.model,
+        &dev_prompt,
+        &user_prompt,
+    )
+    .await
+    else {
+        return;
+    };
+    e.reply(answer.as_str());
+
+    let time = util::cur_time_iso8601(None);
+    if let Err(err) = store::db_write_private_msg(e.user_id, 0, &time, "assistant", &answer).await {
+        std_db_error!("Write private message failed: {err}");
+    }
+}
+
+impl PrivateAgentSetting {
+    /// Replace placeholders for sender, persona, message, and history by their runtime value.
+    fn substitute_dev_user(
+        &self,
+        e: &PrivateMsg,
+        history: &[PrivateChatMessage],
+        message: &str,
+    ) -> (String, String) {
+        let mut buf = String::new();
+        for msg in history {
+            let name = match msg.role.as_str() {
+                "assistant" => "你",
+                _ => e.sender_name.as_str(),
+            };
+            buf.push_str(&format!("{} {}: {}\n", msg.time, name, msg.content));
+        }
+        let persona = self.get_persona(e.user_id);
+        let substitute = |prompt: &str| {
+            prompt
+                .replace("<!sender!>", &e.sender_name)
+                .replace("<!persona!>", persona)
+                .replace("<!message!>", message)
+                .replace("<!history!>", &buf)
+        };
+        (substitute(&self.dev_prompt), substitute(&self.user_prompt))
+    }
+}
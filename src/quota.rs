@@ -0,0 +1,102 @@
+//! Daily quota of costly features per member, i.e. memes, history search and dumps.
+//!
+//! Uses are counted per day in the timezone of the group. Command admins may grant extra uses for
+//! the day, see [crate::global_state::CommandSetting::grant_quota].
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg,
+    exception::PluginResult,
+    global_state::{QuotaKind, QuotaSetting},
+    std_db_error, store, tr, util, CONFIG,
+};
+
+fn setting_of(group_id: i64) -> Option<&'static QuotaSetting> {
+    let config = CONFIG.get().unwrap();
+    let groups = config.groups.as_ref()?;
+    let group = groups.iter().find(|g| g.id == group_id)?;
+    group.quota.as_ref()
+}
+
+fn name(group_id: i64, kind: QuotaKind) -> String {
+    let key = match kind {
+        QuotaKind::Image => "quota.image",
+        QuotaKind::Search => "quota.search",
+        QuotaKind::Dump => "quota.dump",
+    };
+    tr!(group_id, key)
+}
+
+/// Take a use of `kind` for the member, Err with the reply to send if used up today.
+///
+/// Uses are not limited while the database is unavailable.
+pub async fn take(group_id: i64, user_id: i64, kind: QuotaKind) -> Result<(), String> {
+    let Some(limit) = setting_of(group_id).and_then(|s| kind.limit(s)) else {
+        return Ok(());
+    };
+    let day = util::cur_date_iso8601(Some(group_id));
+    let (used, extra) = match store::db_load_quota(group_id, user_id, kind.as_str(), &day).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load quota failed: {err}");
+            return Ok(());
+        }
+    };
+    if used >= limit + extra {
+        return Err(tr!(
+            group_id,
+            "quota.exceeded",
+            feature = name(group_id, kind),
+            limit = limit,
+        ));
+    }
+    if let Err(err) = store::db_add_quota(group_id, user_id, kind.as_str(), &day, (1, 0)).await {
+        std_db_error!("Record quota use failed: {err}");
+    }
+    Ok(())
+}
+
+/// Extra uses of `kind` for the member today.
+pub async fn grant(group_id: i64, user_id: i64, kind: QuotaKind, count: i64) -> PluginResult<()> {
+    let day = util::cur_date_iso8601(Some(group_id));
+    store::db_add_quota(group_id, user_id, kind.as_str(), &day, (0, count)).await
+}
+
+/// `<query>` replies uses left today of each limited feature.
+pub async fn query_handler(e: Arc<GroupMsg>) {
+    let (group_id, user_id) = (e.group_id, e.sender_id);
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    let Some(setting) = setting_of(group_id) else {
+        return;
+    };
+    if setting.query.as_deref() != Some(text.trim()) {
+        return;
+    }
+    let day = util::cur_date_iso8601(Some(group_id));
+    let mut lines = vec![tr!(group_id, "quota.left_title")];
+    for kind in QuotaKind::ALL {
+        let Some(limit) = kind.limit(setting) else {
+            continue;
+        };
+        let (used, extra) = match store::db_load_quota(group_id, user_id, kind.as_str(), &day).await
+        {
+            Ok(v) => v,
+            Err(err) => {
+                std_db_error!("Load quota failed: {err}");
+                return;
+            }
+        };
+        let total = limit + extra;
+        lines.push(tr!(
+            group_id,
+            "quota.left_entry",
+            feature = name(group_id, kind),
+            left = (total - used).max(0),
+            total = total,
+        ));
+    }
+    e.reply_and_quote(lines.join("\n")).await;
+}
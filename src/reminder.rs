@@ -0,0 +1,109 @@
+//! Reminders set by members, e.g. "提醒我 明天下午三点 开会", parsed by [time_parse].
+
+use std::{sync::Arc, time::Duration};
+
+use kovi::Message;
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::{
+    event::GroupMsg, global_state::ReminderSetting, std_db_error, store, time_parse, tr, util,
+    util::schedule_task_blocking, CONFIG,
+};
+
+const SWEEP_INTERVAL_SEC: u64 = 30;
+
+pub async fn reminder_handler(e: Arc<GroupMsg>) {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == e.group_id) else {
+        return;
+    };
+    let Some(ref setting) = group.reminder else {
+        return;
+    };
+    let Some(arg) = e
+        .borrow_text()
+        .and_then(|t| t.trim().strip_prefix(&setting.trigger))
+    else {
+        return;
+    };
+    let reply = set_reminder(&e, setting, arg, util::now_in(Some(e.group_id))).await;
+    e.reply_and_quote(reply).await;
+}
+
+/// Parse and save a reminder of the sender, returns the reply.
+pub async fn set_reminder(
+    e: &GroupMsg,
+    setting: &ReminderSetting,
+    arg: &str,
+    now: OffsetDateTime,
+) -> String {
+    let group_id = e.group_id;
+    let usage = || tr!(group_id, "reminder.usage", trigger = setting.trigger);
+    let Some((at, content)) = time_parse::parse(arg, now) else {
+        return usage();
+    };
+    if content.is_empty() {
+        return usage();
+    }
+    if at <= now {
+        return tr!(group_id, "reminder.past");
+    }
+    let pending = match store::db_count_reminders(group_id, e.sender_id).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Count reminders failed: {err}");
+            return tr!(group_id, "reminder.failed");
+        }
+    };
+    if pending >= setting.max_pending {
+        return tr!(group_id, "reminder.too_many", max = setting.max_pending);
+    }
+    let due = at.unix_timestamp();
+    if let Err(err) = store::db_add_reminder(group_id, e.sender_id, due, content).await {
+        std_db_error!("Add reminder failed: {err}");
+        return tr!(group_id, "reminder.failed");
+    }
+    let desc = format_description!("[year]-[month]-[day] [hour]:[minute]");
+    let time = at.format(desc).unwrap_or_default();
+    tr!(group_id, "reminder.set", time = time, content = content)
+}
+
+/// Sweep reminders in database, which survive restarts.
+pub async fn subscribe_reminders() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    if groups.iter().all(|g| g.reminder.is_none()) {
+        return;
+    }
+    kovi::spawn(async {
+        let duration = Duration::from_secs(SWEEP_INTERVAL_SEC);
+        schedule_task_blocking(duration, || {
+            fire_due(OffsetDateTime::now_utc().unix_timestamp())
+        })
+        .await;
+    });
+}
+
+/// Send and remove reminders due at `now`, in unix timestamp.
+pub async fn fire_due(now: i64) {
+    let due = match store::db_take_due_reminders(now).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load due reminders failed: {err}");
+            return;
+        }
+    };
+    for reminder in due {
+        let group_id = reminder.group_id;
+        let text = tr!(group_id, "reminder.fire", content = reminder.content);
+        let message = Message::new()
+            .add_at(&reminder.user_id.to_string())
+            .add_text(format!(" {text}"));
+        util::send_group_and_log(group_id, message).await;
+    }
+}
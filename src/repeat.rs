@@ -0,0 +1,38 @@
+//! Join or break repeat (复读) chains in group.
+
+use std::sync::Arc;
+
+use rand::{thread_rng, Rng};
+
+use crate::{agent, event::GroupMsg, std_info, util, CONFIG};
+
+pub async fn repeat_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref repeat) = group.repeat else {
+        return;
+    };
+    let fingerprint = util::fnv1a_hash(&serde_json::to_string(&e.message).unwrap_or_default());
+    if !repeat.record(e.sender_id, fingerprint) {
+        return;
+    }
+
+    let roll: f64 = thread_rng().gen();
+    if roll < repeat.join_probability {
+        std_info!("Join repeat in group {group_id}");
+        util::send_group_and_log(group_id, e.message.clone()).await;
+    } else if roll < repeat.join_probability + repeat.break_probability {
+        let text = e.borrow_text().unwrap_or_default();
+        let message = format!("大家都在复读「{text}」，请你用一句话打断复读");
+        match agent::query_with_id_msg(group_id, e.sender_id, message).await {
+            Ok(quip) => util::send_group_and_log(group_id, quip).await,
+            Err(err) => std_info!("Break repeat skipped: {err}"),
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Forward reported group messages to admins in private chat.
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg,
+    std_db_error,
+    store::{self, GroupChatSegment},
+    tr, util, CONFIG,
+};
+
+/// Triggered by replying to a message with the report keyword, the reported message and recent
+/// context are sent to command admins of the group.
+pub async fn report_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no report config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref report) = group.report else {
+        return;
+    };
+    if text.trim() != report.trigger {
+        return;
+    }
+
+    let Some(reported_id) = util::reply_id(&e.message) else {
+        e.reply_and_quote(tr!(group_id, "report.no_reply")).await;
+        return;
+    };
+    let admin_ids = match group.command {
+        Some(ref command) => command.admins(group_id).await,
+        None => vec![],
+    };
+    if admin_ids.is_empty() {
+        e.reply(tr!(group_id, "report.no_admin")).await;
+        return;
+    }
+    let reported = match store::db_find_segment_by_id(group_id, reported_id).await {
+        Ok(segs) => segs,
+        Err(err) => {
+            std_db_error!("Find reported segment failed: {err}");
+            return;
+        }
+    };
+    let Some(first) = reported.first() else {
+        e.reply_and_quote(tr!(group_id, "report.not_found")).await;
+        return;
+    };
+    let context = match store::db_load_n_group_segment(group_id, report.context_segments).await {
+        Ok(segs) => segs,
+        Err(err) => {
+            std_db_error!("Load report context failed: {err}");
+            return;
+        }
+    };
+
+    let reporter = util::get_name_in_group(group_id, e.sender_id).await;
+    let content: Vec<String> = reported
+        .iter()
+        .map(|seg| format_segment(group_id, seg))
+        .collect();
+    let context: Vec<String> = context
+        .iter()
+        .map(|seg| {
+            let content = format_segment(group_id, seg);
+            format!("{} {}: {content}", seg.time, seg.sender_name)
+        })
+        .collect();
+    let forward = tr!(
+        group_id,
+        "report.forward",
+        group = group_id,
+        reporter = reporter,
+        reporter_id = e.sender_id,
+        sender = first.sender_name,
+        sender_id = first.sender_id,
+        time = first.time,
+        content = content.join(" "),
+        context = context.join("\n"),
+    );
+    for admin_id in admin_ids {
+        util::send_private_msg(admin_id, forward.as_str());
+    }
+    e.reply_and_quote(tr!(group_id, "report.forwarded")).await;
+}
+
+fn format_segment(group_id: i64, seg: &GroupChatSegment) -> String {
+    let key = match seg.seg_type.as_str() {
+        "text" => return seg.content.clone(),
+        "at" => return format!("@{}", seg.interpret),
+        "image" => "report.image",
+        "record" => "report.record",
+        "ocr" => "report.ocr",
+        "caption" => "report.caption",
+        "link" => "report.link",
+        other => return format!("[{other}]"),
+    };
+    tr!(group_id, key, content = seg.interpret)
+}
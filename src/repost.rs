@@ -0,0 +1,115 @@
+//! Spot images posted again in a group (火星图), by hash of the image bytes.
+//!
+//! The first posting within the window is kept, so a tease names whoever posted it first.
+
+use std::sync::Arc;
+
+use kovi::tokio::fs;
+
+use crate::{
+    disk,
+    event::GroupMsg,
+    global_state::GroupSetting,
+    media, std_db_error, std_error, std_info,
+    store::{self, ImagePost},
+    util, CONFIG,
+};
+
+/// Images of a message beyond this are not hashed.
+const MAX_IMAGES: usize = 3;
+
+fn group_of(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+pub async fn repost_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(setting) = group_of(group_id).and_then(|g| g.repost.as_ref()) else {
+        return;
+    };
+    let since = match util::iso8601_from_timestamp(
+        Some(group_id),
+        e.time - setting.window_days * 24 * 3600,
+    ) {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Repost window error: {err}");
+            return;
+        }
+    };
+
+    // images are not fetched while disk space is low, as for the history
+    if disk::is_low() {
+        return;
+    }
+
+    let mut teased = false;
+    for seg in e.message.get("image").iter().take(MAX_IMAGES) {
+        // within the limits of the media fetch, shared with the history
+        let Some(path) = media::fetch("image", &seg.data).await else {
+            continue;
+        };
+        let bytes = match fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                std_error!("Read image {path} to hash failed: {err}");
+                continue;
+            }
+        };
+        if (bytes.len() as u64) < setting.min_kb * 1024 {
+            continue;
+        }
+        let hash = format!("{:016x}", util::fnv1a_hash_bytes(&bytes));
+        let original = match store::db_find_image_post(group_id, &hash).await {
+            Ok(v) => v.filter(|p| p.time >= since),
+            Err(err) => {
+                std_db_error!("Load image post failed: {err}");
+                continue;
+            }
+        };
+        if let Some(original) = original {
+            // reposting one's own image is not news
+            if original.sender_id == e.sender_id {
+                continue;
+            }
+            std_info!(
+                "Image {hash} in group {group_id} reposted by {}, first posted by {} at {}",
+                e.sender_id,
+                original.sender_id,
+                original.time
+            );
+            if let Some(ref tease) = setting.tease {
+                if !teased {
+                    let date = original.time.get(..10).unwrap_or(&original.time);
+                    e.reply_and_quote(
+                        tease
+                            .replace("<!sender!>", &original.sender_name)
+                            .replace("<!date!>", date),
+                    )
+                    .await;
+                    teased = true;
+                }
+            }
+            continue;
+        }
+        let time = match util::iso8601_from_timestamp(Some(group_id), e.time) {
+            Ok(v) => v,
+            Err(err) => {
+                std_error!("Image post time error: {err}");
+                continue;
+            }
+        };
+        let post = ImagePost {
+            group_id,
+            hash,
+            message_id: e.message_id,
+            sender_id: e.sender_id,
+            sender_name: util::get_name_in_group(group_id, e.sender_id).await,
+            time,
+        };
+        if let Err(err) = store::db_add_image_post(&post).await {
+            std_db_error!("Record image post failed: {err}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Search chat history of a group.
+
+use std::{collections::HashSet, sync::Arc};
+
+use crate::{
+    agent,
+    event::GroupMsg,
+    global_state::{GroupSetting, QuotaKind, SearchSetting},
+    heat, quota, std_db_error, store,
+    store::GroupChatSegment,
+    tr, CONFIG,
+};
+
+const MAX_QUOTE_CHARS: usize = 50;
+/// Terms of a question beyond this are not searched.
+const MAX_TERMS: usize = 32;
+
+/// `<who_said> <keyword>` replies the earliest and latest message containing the keyword.
+pub async fn search_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no search config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let Some(ref search) = group.search else {
+        return;
+    };
+    if let Some(question) = search
+        .ask
+        .as_deref()
+        .and_then(|ask| text.trim().strip_prefix(ask))
+    {
+        ask_handler(&e, group, search, question.trim()).await;
+        return;
+    }
+    let Some(keyword) = text.trim().strip_prefix(&search.who_said) else {
+        return;
+    };
+    let keyword = keyword.trim();
+    if keyword.is_empty() {
+        e.reply(tr!(group_id, "search.usage", command = search.who_said))
+            .await;
+        return;
+    }
+    if let Err(reply) = quota::take(group_id, e.sender_id, QuotaKind::Search).await {
+        e.reply_and_quote(reply).await;
+        return;
+    }
+
+    // the query itself has been logged, exclude it
+    let found = store::db_search_group_msg(group_id, keyword, &search.who_said).await;
+    let (count, earliest, latest) = match found {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Search group history failed: {err}");
+            return;
+        }
+    };
+    let (Some(earliest), Some(latest)) = (earliest, latest) else {
+        e.reply_and_quote(tr!(group_id, "search.not_found", keyword = keyword))
+            .await;
+        return;
+    };
+    let mut reply = tr!(
+        group_id,
+        "search.found",
+        keyword = keyword,
+        count = count,
+        earliest = quote(&earliest),
+    );
+    if count > 1 {
+        reply.push('\n');
+        reply.push_str(&tr!(group_id, "search.latest", latest = quote(&latest)));
+    }
+    e.reply(reply).await;
+}
+
+/// `<ask> <question>` answers by agent of the group from the most related history.
+async fn ask_handler(e: &GroupMsg, group: &GroupSetting, search: &SearchSetting, question: &str) {
+    let group_id = e.group_id;
+    let Some(ref agent) = group.agent else {
+        return;
+    };
+    let (Some(ask), Some(prompt)) = (&search.ask, &search.ask_prompt) else {
+        return;
+    };
+    if agent.is_mute() || heat::is_heated(group_id) {
+        return;
+    }
+    if question.is_empty() {
+        e.reply(tr!(group_id, "search.ask_usage", command = ask))
+            .await;
+        return;
+    }
+    if let Err(reply) = quota::take(group_id, e.sender_id, QuotaKind::Search).await {
+        e.reply_and_quote(reply).await;
+        return;
+    }
+
+    let limit = search.ask_segments.unwrap_or(30);
+    // the question itself has been logged, exclude it
+    let found = store::db_related_group_msg(group_id, &terms(question), ask, limit).await;
+    let mut related = match found {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Search related group history failed: {err}");
+            return;
+        }
+    };
+    if related.is_empty() {
+        e.reply_and_quote(tr!(group_id, "search.ask_not_found"))
+            .await;
+
+        return;
+    }
+    related.sort_by(|a, b| a.time.cmp(&b.time));
+    let history = agent::format_history(&related);
+    let user_prompt = format!("Related Chat History:\n{history}\nQuestion:\n{question}");
+    let model = agent.get_model().await;
+    let Some(answer) =
+        agent::complete(&agent.api_url, &agent.api_key, &model, prompt, &user_prompt).await
+    else {
+        return;
+    };
+    e.reply_and_quote(answer.trim()).await;
+}
+
+/// Terms for the trigram index, i.e. words of ASCII and every 3 consecutive characters of others.
+fn terms(question: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut terms = Vec::new();
+    for run in question.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<char> = run.chars().collect();
+        if chars.len() < 3 {
+            continue;
+        }
+        let candidates = if run.is_ascii() {
+            vec![run.to_lowercase()]
+        } else {
+            chars.windows(3).map(|w| w.iter().collect()).collect()
+        };
+        for term in candidates {
+            if terms.len() < MAX_TERMS && seen.insert(term.clone()) {
+                terms.push(term);
+            }
+        }
+    }
+    terms
+}
+
+fn quote(seg: &GroupChatSegment) -> String {
+    let content = seg.content.trim();
+    let content = match content.char_indices().nth(MAX_QUOTE_CHARS) {
+        Some((i, _)) => format!("{}...", &content[..i]),
+        None => content.to_string(),
+    };
+    format!("{} {}: {content}", seg.time, seg.sender_name)
+}
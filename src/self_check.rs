@@ -0,0 +1,212 @@
+//! Diagnostics run once after startup, reported to the main admin privately.
+
+use std::{fmt::Display, future::Future, path::Path, time::Duration};
+
+use kovi::tokio::time::timeout;
+
+use crate::{
+    agent,
+    global_state::{Config, GroupSetting, ModerationAction, VerifyFailAction},
+    live, std_info, store, tr, util, CONFIG,
+};
+
+/// Each check gives up after this, a slow endpoint is as good as broken at startup.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Outcome of one check, error is None if passed.
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub error: Option<String>,
+}
+
+/// Run all checks and send the report to the main admin.
+pub async fn report() {
+    let config = CONFIG.get().unwrap();
+    let checks = run_checks(config).await;
+    let report = format_report(config, &checks);
+    std_info!("{report}");
+    util::notify_admin(report);
+}
+
+pub async fn run_checks(config: &Config) -> Vec<Check> {
+    let mut checks = vec![check("database", store::db_check_writable()).await];
+
+    if let Some(ref obj) = config.object_storage {
+        let error = check_script(Path::new(&obj.script_path)).err();
+        checks.push(Check {
+            name: format!("upload script {}", obj.script_path),
+            error,
+        });
+    }
+
+    // the same key may be shared by many groups, key itself is not revealed
+    let mut apis = Vec::new();
+    let agents = config
+        .groups
+        .iter()
+        .flatten()
+        .filter_map(|g| g.agent.as_ref());
+    for agent in agents {
+        for url in agent.api_url.urls() {
+            apis.push((url.as_str(), agent.api_key.as_str(), agent.model.as_str()));
+        }
+    }
+    if let Some(ref private) = config.private_agent {
+        for url in private.api_url.urls() {
+            apis.push((
+                url.as_str(),
+                private.api_key.as_str(),
+                private.model.as_str(),
+            ));
+        }
+    }
+    for (i, &(url, key, model)) in apis.iter().enumerate() {
+        if apis[..i].contains(&(url, key, model)) {
+            continue;
+        }
+        let name = format!("OpenAI {model} @ {url}");
+        checks.push(check(name, agent::check_api(url, key, model)).await);
+    }
+
+    let mut rooms: Vec<String> = config
+        .groups
+        .iter()
+        .flatten()
+        .filter_map(|g| g.live.as_ref().map(|l| l.room_id.clone()))
+        .collect();
+    if config.private_live.is_some() {
+        match store::db_load_live_subscriptions().await {
+            Ok(subs) => rooms.extend(subs.into_iter().map(|s| s.room_id)),
+            Err(err) => checks.push(Check {
+                name: String::from("live subscriptions"),
+                error: Some(err.to_string()),
+            }),
+        }
+    }
+    rooms.sort();
+    rooms.dedup();
+    for room_id in rooms {
+        let name = format!("live room {room_id}");
+        let reachable = async {
+            match live::room_exists(&room_id).await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(String::from("room does not exist")),
+                Err(err) => Err(err.to_string()),
+            }
+        };
+        checks.push(check(name, reachable).await);
+    }
+    checks
+}
+
+async fn check<E: Display>(
+    name: impl Into<String>,
+    task: impl Future<Output = Result<(), E>>,
+) -> Check {
+    let error = match timeout(CHECK_TIMEOUT, task).await {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+    Check {
+        name: name.into(),
+        error,
+    }
+}
+
+fn check_script(path: &Path) -> Result<(), String> {
+    let meta = path.metadata().map_err(|e| e.to_string())?;
+    if !meta.is_file() {
+        return Err(String::from("not a file"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if meta.permissions().mode() & 0o111 == 0 {
+            return Err(String::from("not executable"));
+        }
+    }
+    Ok(())
+}
+
+/// Features of a group that cannot work as configured, empty if none.
+pub fn group_problems(group: &GroupSetting) -> Vec<String> {
+    let mut problems = Vec::new();
+    let config = CONFIG.get().unwrap();
+    let has_agent = group.agent.is_some();
+    let has_command = group.command.is_some();
+    if let Some(ref command) = group.command {
+        if !command.is_ready() {
+            problems.push(tr!(group.id, "self_check.invalid_command_regex"));
+        }
+        if command.voice == Some(true) && config.stt.is_none() {
+            problems.push(tr!(group.id, "self_check.voice_needs_stt"));
+        }
+    }
+    if let Some(ref moderation) = group.moderation {
+        if !moderation.is_ready() {
+            problems.push(tr!(group.id, "self_check.invalid_link_regex"));
+        }
+        if moderation.action != ModerationAction::Warn && !has_command {
+            problems.push(tr!(group.id, "self_check.moderation_needs_command"));
+        }
+    }
+    if let Some(ref verify) = group.verify {
+        if verify.ai_criteria.is_some() && !has_agent {
+            problems.push(tr!(group.id, "self_check.verify_needs_agent"));
+        }
+        if verify.on_fail == VerifyFailAction::Notify && !has_command {
+            problems.push(tr!(group.id, "self_check.verify_needs_command"));
+        }
+    }
+    if group.report.is_some() && !has_command {
+        problems.push(tr!(group.id, "self_check.report_needs_command"));
+    }
+    let mut windows = group
+        .agent
+        .iter()
+        .flat_map(|a| a.persona_schedule.iter().flatten());
+    if windows.any(|w| w.contains(0).is_none()) {
+        problems.push(tr!(group.id, "self_check.invalid_persona_window"));
+    }
+    if group.summary.is_some() && !has_agent {
+        problems.push(tr!(group.id, "self_check.summary_needs_agent"));
+    }
+    if let Some(ref timezone) = group.timezone {
+        if util::parse_utc_offset(timezone).is_none() {
+            problems.push(tr!(group.id, "self_check.invalid_timezone"));
+        }
+    }
+    problems
+}
+
+pub fn format_report(config: &Config, checks: &[Check]) -> String {
+    let failed = checks.iter().filter(|c| c.error.is_some()).count();
+    let mut lines = vec![tr!(
+        None,
+        "self_check.title",
+        total = checks.len(),
+        failed = failed
+    )];
+    for check in checks {
+        let line = match check.error {
+            None => tr!(None, "self_check.passed", name = check.name),
+            Some(ref error) => tr!(None, "self_check.failed", name = check.name, error = error),
+        };
+        lines.push(line);
+    }
+    for group in config.groups.iter().flatten() {
+        let problems = group_problems(group);
+        if !problems.is_empty() {
+            let problems = problems.join("; ");
+            lines.push(tr!(
+                None,
+                "self_check.group",
+                group = group.id,
+                problems = problems
+            ));
+        }
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,105 @@
+//! Global monthly ceiling of OpenAI spending across all groups.
+
+use std::sync::OnceLock;
+
+use kovi::tokio::sync::{Mutex, MutexGuard};
+
+use crate::{global_state::SpendSetting, std_db_error, std_db_info, store, tr, util, CONFIG};
+
+/// Spending of the current month, loaded from database on first use and on month change.
+#[derive(Debug)]
+struct Ledger {
+    month: String,
+    cost: f64,
+    notified: bool,
+}
+
+async fn ledger() -> MutexGuard<'static, Option<Ledger>> {
+    static LEDGER: OnceLock<Mutex<Option<Ledger>>> = OnceLock::new();
+    LEDGER.get_or_init(|| Mutex::new(None)).lock().await
+}
+
+/// `YYYY-MM` in UTC+8, consistent with other dates of the plugin.
+fn cur_month() -> String {
+    util::cur_date_iso8601(None)[..7].to_string()
+}
+
+/// Cost of this month, resetting the ledger if a new month has begun.
+async fn month_cost(ledger: &mut Option<Ledger>) -> f64 {
+    let month = cur_month();
+    if ledger.as_ref().is_some_and(|l| l.month == month) {
+        return ledger.as_ref().unwrap().cost;
+    }
+    let cost = match store::db_load_spend(&month).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load OpenAI spending failed: {err}");
+            0.0
+        }
+    };
+    *ledger = Some(Ledger {
+        month,
+        cost,
+        notified: false,
+    });
+    cost
+}
+
+/// Model to request in place of `model`, None if the agent should stay silent.
+pub async fn model_for(model: &str) -> Option<String> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no spend config
+    let Some(ref spend) = config.spend else {
+        return Some(model.to_string());
+    };
+    choose_model(spend, model).await
+}
+
+/// [model_for] under the given setting.
+pub async fn choose_model(spend: &SpendSetting, model: &str) -> Option<String> {
+    let mut ledger = ledger().await;
+    let cost = month_cost(&mut ledger).await;
+    if cost < spend.monthly_limit {
+        return Some(model.to_string());
+    }
+    let ledger = ledger.as_mut().unwrap();
+    if !ledger.notified {
+        ledger.notified = true;
+        let fallback = spend.fallback_model.as_deref().unwrap_or("-");
+        std_db_info!("OpenAI spending {cost:.2} exceeded limit, fallback model: {fallback}");
+        util::notify_admin(tr!(
+            None,
+            "spend.exceeded",
+            cost = format!("{cost:.2}"),
+            limit = spend.monthly_limit,
+            fallback = fallback,
+        ));
+    }
+    spend.fallback_model.clone()
+}
+
+/// Add cost of `tokens` consumed by `model` to this month.
+pub async fn record(model: &str, tokens: usize) {
+    let config = CONFIG.get().unwrap();
+    let Some(ref spend) = config.spend else {
+        return;
+    };
+    charge(spend, model, tokens).await;
+}
+
+/// [record] under the given setting.
+pub async fn charge(spend: &SpendSetting, model: &str, tokens: usize) {
+    let price = spend
+        .prices
+        .get(model)
+        .copied()
+        .unwrap_or(spend.default_price);
+    let cost = tokens as f64 * price / 1_000_000.0;
+    let mut ledger = ledger().await;
+    month_cost(&mut ledger).await;
+    let ledger = ledger.as_mut().unwrap();
+    if let Err(err) = store::db_add_spend(&ledger.month, tokens as i64, cost).await {
+        std_db_error!("Save OpenAI spending failed: {err}");
+    }
+    ledger.cost += cost;
+}
@@ -0,0 +1,234 @@
+//! Read-only JSON statistics over HTTP, so that external dashboards need no access to the database.
+//!
+//! Every request carries `Authorization: Bearer <token>` of [StatsApiSetting], and ranges are
+//! given by `from` and `to` query parameters:
+//!
+//! 1. `GET /groups/<group_id>/messages?from=2026-01-01&to=2026-02-01`: messages of each member in
+//!    `[from, to)`, see [store::db_message_stats].
+//! 2. `GET /spend?from=2026-01&to=2026-03`: OpenAI usage of months in `[from, to]`.
+//! 3. `GET /live/sessions?from=2026-01-01&to=2026-02-01`: live sessions overlapping `[from, to)`,
+//!    ongoing ones have a null `end_time`.
+//!
+//! Responses are `{"data": ...}` or `{"error": "..."}`. The server speaks just enough HTTP/1.1 for
+//! a dashboard behind a reverse proxy, one request per connection.
+//!
+//! [StatsApiSetting]: crate::global_state::StatsApiSetting
+
+use std::{collections::HashMap, time::Duration};
+
+use kovi::tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{
+    exception::PluginResult, global_state, std_db_error, std_error, std_info, store, CONFIG,
+};
+
+const MIN_TOKEN_CHARS: usize = 16;
+const MAX_HEAD_BYTES: usize = 8192;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Status and JSON body of a request.
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn data<T: Serialize>(data: T) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => Self {
+                status: 200,
+                body: json!({ "data": data }),
+            },
+            Err(err) => {
+                std_error!("Serialize stats failed: {err}");
+                Self::error(500, "internal error")
+            }
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+/// Listen on the configured address, no-op if not configured or the token is too short.
+pub async fn serve() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref setting) = config.stats_api else {
+        return;
+    };
+    if setting.token.chars().count() < MIN_TOKEN_CHARS {
+        std_error!("Stats API not served, token is shorter than {MIN_TOKEN_CHARS} characters");
+        return;
+    }
+    let listener = match TcpListener::bind(&setting.bind).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_error!("Stats API failed to listen on {}: {err}", setting.bind);
+            return;
+        }
+    };
+    std_info!("Stats API listening on {}", setting.bind);
+    kovi::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    std_error!("Stats API accept failed: {err}");
+                    continue;
+                }
+            };
+            kovi::spawn(async move {
+                match timeout(REQUEST_TIMEOUT, handle(stream)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(err)) => std_error!("Stats API request failed: {err}"),
+                    Err(_) => std_info!("Stats API request timed out"),
+                }
+            });
+        }
+    });
+}
+
+async fn handle(mut stream: TcpStream) -> PluginResult<()> {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_HEAD_BYTES {
+            return write_response(&mut stream, Response::error(431, "request too large")).await;
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = (request_line.next(), request_line.next());
+    let authorization = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("authorization")
+            .then(|| value.trim())
+    });
+    let response = match (method, target) {
+        (Some("GET"), Some(target)) => respond(target, authorization).await,
+        (Some(_), Some(_)) => Response::error(405, "method not allowed"),
+        _ => Response::error(400, "malformed request"),
+    };
+    write_response(&mut stream, response).await
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> PluginResult<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+    let body = response.body.to_string();
+    let head = format!(
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        response.status,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Response to a GET of `target`, the path with query, given the Authorization header.
+pub async fn respond(target: &str, authorization: Option<&str>) -> Response {
+    let config = CONFIG.get().unwrap();
+    let Some(ref setting) = config.stats_api else {
+        return Response::error(404, "not found");
+    };
+    if authorization.and_then(|a| a.strip_prefix("Bearer ")) != Some(setting.token.as_str()) {
+        return Response::error(401, "unauthorized");
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = parse_query(query);
+    let (Some(from), Some(to)) = (params.get("from"), params.get("to")) else {
+        return Response::error(400, "from and to are required");
+    };
+    if from > to {
+        return Response::error(400, "from is after to");
+    }
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let res = match segments.as_slice() {
+        ["groups", group_id, "messages"] => {
+            let Some(group_id) = group_id
+                .parse::<i64>()
+                .ok()
+                .filter(|&id| global_state::is_configured_group(id))
+            else {
+                return Response::error(404, "unknown group");
+            };
+            store::db_message_stats(group_id, from, to)
+                .await
+                .map(Response::data)
+        }
+        ["spend"] => store::db_spend_stats(from, to).await.map(Response::data),
+        ["live", "sessions"] => store::db_live_sessions(from, to).await.map(Response::data),
+        _ => return Response::error(404, "not found"),
+    };
+    match res {
+        Ok(response) => response,
+        Err(err) => {
+            std_db_error!("Stats API query {path} failed: {err}");
+            Response::error(500, "internal error")
+        }
+    }
+}
+
+/// Percent-decoded `key=value` pairs, later keys win.
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = bytes
+                    .get(i + 1..i + 3)
+                    .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+                    .and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+                match hex {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,143 @@
+//! Stickers of a group, i.e. images of its history saved under tags by command admins.
+//!
+//! The agent sends them by `[sticker:<tag>]` in answers, and learns the tags from `<!stickers!>` in
+//! prompts.
+
+use std::sync::Arc;
+
+use crate::{
+    event::GroupMsg,
+    global_state::GroupSetting,
+    std_db_error, std_db_info,
+    store::{self, Sticker},
+    tr, util, CONFIG,
+};
+
+pub const STICKERS: &str = "<!stickers!>";
+
+fn group_of(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+pub async fn sticker_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(group) = group_of(group_id) else {
+        return;
+    };
+    let Some(ref setting) = group.sticker else {
+        return;
+    };
+    let Some(text) = e.borrow_text().map(str::trim) else {
+        return;
+    };
+    if text == setting.list {
+        let tags = tags(group_id).await;
+        if tags.is_empty() {
+            e.reply(tr!(group_id, "sticker.empty")).await;
+        } else {
+            e.reply(tr!(group_id, "sticker.list", tags = tags)).await;
+        }
+        return;
+    }
+    let (add, tag) = if let Some(tag) = text.strip_prefix(setting.add.as_str()) {
+        (true, tag.trim())
+    } else if let Some(tag) = text.strip_prefix(setting.remove.as_str()) {
+        (false, tag.trim())
+    } else {
+        return;
+    };
+    if tag.is_empty() {
+        return;
+    }
+    let Some(ref command) = group.command else {
+        return;
+    };
+    if !command.is_admin(group_id, e.sender_id).await {
+        return;
+    }
+
+    if !add {
+        match store::db_remove_sticker(group_id, tag).await {
+            Ok(true) => {
+                e.reply_and_quote(tr!(group_id, "sticker.removed", tag = tag))
+                    .await
+            }
+            Ok(false) => {
+                e.reply_and_quote(tr!(group_id, "sticker.not_found", tag = tag))
+                    .await
+            }
+            Err(err) => std_db_error!("Remove sticker failed: {err}"),
+        }
+        return;
+    }
+    let usage = || tr!(group_id, "sticker.usage", add = setting.add);
+    let Some(reply_id) = util::reply_id(&e.message) else {
+        e.reply_and_quote(usage()).await;
+        return;
+    };
+    let segs = match store::db_find_segment_by_id(group_id, reply_id).await {
+        Ok(segs) => segs,
+        Err(err) => {
+            std_db_error!("Load image of sticker failed: {err}");
+            return;
+        }
+    };
+    let Some(image) = segs
+        .into_iter()
+        .find(|s| s.seg_type == "image" && !s.content.is_empty())
+    else {
+        e.reply_and_quote(usage()).await;
+        return;
+    };
+    let sticker = Sticker {
+        group_id,
+        tag: tag.to_string(),
+        path: image.content,
+        url: image.interpret,
+        adder_id: e.sender_id,
+        time: util::cur_time_iso8601(Some(group_id)),
+    };
+    match store::db_add_sticker(&sticker).await {
+        Ok(()) => {
+            std_db_info!(
+                "Sticker {tag} of group {group_id} added by {}.",
+                e.sender_id
+            );
+            e.reply_and_quote(tr!(group_id, "sticker.added", tag = tag))
+                .await;
+        }
+        Err(err) => std_db_error!("Add sticker failed: {err}"),
+    }
+}
+
+/// Tags of the group joined by "、", empty if there are none.
+pub async fn tags(group_id: i64) -> String {
+    match store::db_load_stickers(group_id).await {
+        Ok(stickers) => stickers
+            .into_iter()
+            .map(|s| s.tag)
+            .collect::<Vec<_>>()
+            .join("、"),
+        Err(err) => {
+            std_db_error!("Load stickers failed: {err}");
+            String::new()
+        }
+    }
+}
+
+/// Image source of the sticker, the uploaded url if any.
+pub async fn find(group_id: i64, tag: &str) -> Option<String> {
+    let sticker = match store::db_find_sticker(group_id, tag).await {
+        Ok(sticker) => sticker?,
+        Err(err) => {
+            std_db_error!("Load sticker failed: {err}");
+            return None;
+        }
+    };
+    if sticker.url.starts_with("http") {
+        Some(sticker.url)
+    } else {
+        Some(format!("file://{}", sticker.path))
+    }
+}
@@ -4,16 +4,35 @@
 //! [crate::global_state::init_global_state].  
 //! db_* functions interact with database.
 use crate::{
+    caption, disk,
+    event::GuildMsg,
     exception::{PluginError, PluginResult},
-    global_state, std_db_error, std_error, std_info,
+    long_message, media, ocr, std_db_error, std_error, std_info, tr, unfurl,
     util::{self, TimeRepr},
-    CONFIG, DATA_PATH, DB_POOL,
+    BOT_QQ, CONFIG, DATA_PATH, DB_POOL,
 };
 use kovi::{
     tokio::{fs::File, io::AsyncWriteExt},
-    ApiReturn, Message,
+    Message,
 };
-use sqlx::{migrate::MigrateDatabase, prelude::FromRow, Pool, Sqlite};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    migrate::MigrateDatabase,
+    prelude::FromRow,
+    sqlite::{SqliteConnection, SqliteRow},
+    Pool, Row, Sqlite,
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+/// Messages walked back by [db_load_thread] at most.
+pub const MAX_THREAD_DEPTH: usize = 10;
+
+/// Frame header of zstd, which is not valid UTF-8 and so never starts a content stored as text.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
 
 /// Write log to log_bot table, fallback to kovi log on failure.
 pub async fn db_write_bot_log(time: String, level: String, content: String) {
@@ -35,6 +54,40 @@ pub async fn db_write_bot_log(time: String, level: String, content: String) {
     }
 }
 
+/// Count an error entry by its format template, fallback to kovi log on failure.
+///
+/// Entries sharing a template are aggregated per day, so the admin receives a digest rather than
+/// every occurrence.
+pub async fn db_count_error(template: &str, content: &str) {
+    let pool = DB_POOL.get().unwrap();
+    let day = util::cur_date_iso8601(None);
+    let hash = format!("{:016x}", util::fnv1a_hash(template));
+    let query = upsert_error_stat();
+    let res = sqlx::query(&query)
+        .bind(&day)
+        .bind(&hash)
+        .bind(template.trim())
+        .bind(content)
+        .execute(pool)
+        .await;
+    if let Err(e) = res {
+        std_error!(
+            "
+            Count error to database failed: {e}
+            Template: {template}
+            "
+        );
+    }
+}
+
+/// Load aggregated errors of a day, most frequent first.
+pub async fn db_load_error_stat(day: &str) -> PluginResult<Vec<ErrorStat>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_error_stat();
+    let stats: Vec<ErrorStat> = sqlx::query_as(&query).bind(day).fetch_all(pool).await?;
+    Ok(stats)
+}
+
 /// Initialize sqlite_pool
 pub async fn init_sqlite_pool(max_conn: u32) -> PluginResult<Pool<Sqlite>> {
     let data_path = DATA_PATH.get().unwrap();
@@ -56,11 +109,92 @@ pub async fn init_sqlite_pool(max_conn: u32) -> PluginResult<Pool<Sqlite>> {
 }
 
 /// Pre-defined tables that are known to exist at compile time.
-pub async fn init_log_table() -> PluginResult<()> {
+pub async fn init_predefined_tables() -> PluginResult<()> {
     let pool = DB_POOL.get().unwrap();
     std_info!("Initializing bot log table...");
     let query = create_log_table();
     sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing error stat table...");
+    let query = create_error_stat_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing private message table...");
+    let query = create_private_msg_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing live subscription table...");
+    let query = create_live_subscription_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing live state table...");
+    let query = create_live_state_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing live session table...");
+    let query = create_live_session_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing guild message table...");
+    let query = create_guild_message_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing member verification table...");
+    let query = create_verification_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing points tables...");
+    let query = create_points_tables();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing level table...");
+    let query = create_level_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing word filter table...");
+    let query = create_word_strike_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing reminder table...");
+    let query = create_reminder_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing idiom game tables...");
+    let query = create_idiom_tables();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing feed tables...");
+    let query = create_feed_tables();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing announcement table...");
+    let query = create_announcement_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing mention table...");
+    let query = create_mention_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing OpenAI spend table...");
+    let query = create_spend_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing reaction table...");
+    let query = create_reaction_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing A/B test table...");
+    let query = create_ab_reply_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing agent reply table...");
+    let query = create_agent_reply_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing feedback table...");
+    let query = create_feedback_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing pending reply table...");
+    let query = create_pending_reply_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing sticker table...");
+    let query = create_sticker_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing image hash table...");
+    let query = create_image_hash_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing quota table...");
+    let query = create_quota_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing feature usage table...");
+    let query = create_feature_usage_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing run history table...");
+    let query = create_run_history_table();
+    sqlx::query(&query).execute(pool).await?;
+    std_info!("Initializing database check table...");
+    let query = create_db_check_table();
+    sqlx::query(&query).execute(pool).await?;
     Ok(())
 }
 
@@ -75,47 +209,78 @@ pub async fn write_group_msg<T>(
 ) where
     T: Into<Message>,
 {
-    let bot = global_state::get_bot();
-
-    let Some(time) = time.unwrap_or_default().to_iso8601().await else {
+    let Some(time) = time
+        .unwrap_or_else(|| TimeRepr::Iso8601(util::cur_time_iso8601(Some(group_id))))
+        .to_iso8601(Some(group_id))
+        .await
+    else {
         return;
     };
     let sender_name = util::get_name_in_group(group_id, sender_id).await;
     let segments = util::extract_segments(message).await;
-    for (seg_type, seg_content) in segments {
+    for (seg_type, seg_content, data) in segments {
+        // (type, content, interpret) derived from the segment, e.g. text recognized from image
+        let mut extra = Vec::new();
         let (content, interpret) = match seg_type.as_str() {
-            "share" => (seg_content, "url".to_string()),
+            "share" => {
+                let interpret = match unfurl::unfurl(&seg_content).await {
+                    Some(preview) => preview.to_string(),
+                    None => "url".to_string(),
+                };
+                (seg_content, interpret)
+            }
             "video" => (seg_content, "not supported".to_string()),
-            "record" => {
-                let res = bot.get_record(&seg_content, "mp3").await;
-                let path = extract_api(res, "file");
-                if path.starts_with('/') {
-                    (path.clone(), util::call_upload(&path).await)
-                } else {
-                    (path, String::new())
+            // only the file id is kept while disk space is low
+            "record" | "image" if disk::is_low() => (seg_content, String::new()),
+            "record" => match media::fetch("record", &data).await {
+                Some(path) => {
+                    let url = util::call_upload(&path).await;
+                    (path, url)
                 }
-            }
-            "image" => {
-                let res = bot.get_image(&seg_content).await;
-                let path = extract_api(res, "file");
-                if path.starts_with('/') {
-                    (path.clone(), util::call_upload(&path).await)
-                } else {
-                    (path.clone(), String::new())
+                None => (seg_content, String::new()),
+            },
+            "image" => match media::fetch("image", &data).await {
+                Some(path) => {
+                    if let Some(text) = ocr::recognize(&path).await {
+                        extra.push(("ocr", path.clone(), text));
+                    }
+                    if let Some(text) = caption::describe(&path).await {
+                        extra.push(("caption", path.clone(), text));
+                    }
+                    let url = util::call_upload(&path).await;
+                    (path, url)
                 }
-            }
+                None => (seg_content, String::new()),
+            },
             "at" => {
                 let Ok(receiver_id) = seg_content.parse::<i64>() else {
                     std_db_error!("At message has content not i64: {seg_content}");
                     continue;
                 };
+                // replies of the bot are not mentions by members
+                if sender_id != *BOT_QQ.get().unwrap() {
+                    if let Err(err) = db_add_mention(group_id, sender_id, receiver_id, &time).await
+                    {
+                        std_db_error!("Record mention failed: {err}");
+                    }
+                }
                 (
                     receiver_id.to_string(),
                     util::get_name_in_group(group_id, receiver_id).await,
                 )
             }
             "reply" => (seg_content, "message_id".to_string()),
-            "text" => (seg_content, "text".to_string()),
+            "text" => {
+                for url in unfurl::extract_urls(&seg_content) {
+                    if let Some(preview) = unfurl::unfurl(&url).await {
+                        extra.push(("link", url, preview.to_string()));
+                    }
+                }
+                let interpret = long_message::digest(group_id, sender_id, &seg_content)
+                    .await
+                    .unwrap_or_else(|| "text".to_string());
+                (seg_content, interpret)
+            }
             _ => (String::new(), String::new()),
         };
         let res = db_write_group_msg(
@@ -132,17 +297,56 @@ pub async fn write_group_msg<T>(
         if let Err(e) = res {
             std_db_error!("Write group message failed: {e}");
         }
+        for (seg_type, content, interpret) in extra {
+            let res = db_write_group_msg(
+                group_id,
+                message_id,
+                &time,
+                sender_id,
+                &sender_name,
+                seg_type,
+                &content,
+                &interpret,
+            )
+            .await;
+            if let Err(e) = res {
+                std_db_error!("Write {seg_type} segment failed: {e}");
+            }
+        }
     }
 }
 
-fn extract_api(res: Result<ApiReturn, ApiReturn>, field: &str) -> String {
-    match res {
-        Ok(api) => match serde_json::from_value(api.data[field].clone()) {
-            Ok(v) => v,
-            Err(e) => e.to_string(),
-        },
-        Err(e) => e.to_string(),
+/// Write a guild channel message into history of the group it is handled as, see [crate::guild].
+/// Returns the message id of its rows, the negated id of the `guild_message` row which keeps the
+/// channel and the original id.
+pub async fn write_guild_msg(group_id: i64, e: &GuildMsg) -> PluginResult<i32> {
+    let pool = DB_POOL.get().unwrap();
+    let time = util::iso8601_from_timestamp(Some(group_id), e.time)?;
+    let query = insert_guild_message();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(&e.guild_id)
+        .bind(&e.channel_id)
+        .bind(&e.message_id)
+        .execute(pool)
+        .await?;
+    let message_id = i32::try_from(-res.last_insert_rowid()).unwrap_or(i32::MIN);
+    for (seg_type, content, _) in util::extract_segments(e.message.clone()).await {
+        // guild members and media are not known by group apis, kept as ids
+        let interpret = if seg_type == "text" { "text" } else { "" };
+        db_write_group_msg(
+            group_id,
+            message_id,
+            &time,
+            e.sender_id,
+            &e.sender_name,
+            &seg_type,
+            &content,
+            interpret,
+        )
+        .await?;
     }
+    Ok(message_id)
 }
 
 async fn db_write_group_msg(
@@ -155,143 +359,3172 @@ async fn db_write_group_msg(
     content: &str,
     interpret: &str,
 ) -> PluginResult<()> {
+    let row = PendingRow {
+        group_id,
+        message_id,
+        time: time.to_string(),
+        sender_id,
+        sender_name: sender_name.to_string(),
+        seg_type: seg_type.to_string(),
+        content: content.to_string(),
+        interpret: interpret.to_string(),
+    };
+    // keep order of history, later rows wait behind the buffered ones
+    {
+        let mut buffer = write_buffer();
+        if buffer.is_open() {
+            buffer.push(row);
+            return Ok(());
+        }
+    }
+    if let Err(err) = insert_group_row(&row).await {
+        std_error!("Database unavailable, buffering group messages: {err}");
+        let mut buffer = write_buffer();
+        // concurrent writes may fail together, alert once
+        let was_open = std::mem::replace(&mut buffer.open, true);
+        buffer.push(row);
+        drop(buffer);
+        if !was_open {
+            util::notify_admin(tr!(None, "database.unavailable", error = err));
+        }
+    }
+    Ok(())
+}
+
+async fn insert_group_row(row: &PendingRow) -> PluginResult<()> {
+    #[cfg(feature = "chaos")]
+    crate::chaos::inject(crate::chaos::Point::Store).await?;
     let pool = DB_POOL.get().unwrap();
-    let table_name = get_group_msg_table_name(group_id);
-    let query = create_group_msg_table(&table_name);
-    sqlx::query(&query).execute(pool).await?;
+    let table_name = get_group_msg_table_name(row.group_id);
+    ensure_group_msg_table(&table_name).await?;
 
     let query = insert_group_msg(&table_name);
-    sqlx::query(&query)
-        .bind(message_id)
-        .bind(time)
-        .bind(sender_id)
-        .bind(sender_name)
-        .bind(seg_type)
-        .bind(content)
-        .bind(interpret)
-        .execute(pool)
-        .await?;
+    let mut tx = pool.begin().await?;
+    insert_row(&mut tx, &table_name, &query, row).await?;
+    tx.commit().await?;
     Ok(())
 }
 
-pub async fn db_load_n_group_segment(group_id: i64, n: i64) -> PluginResult<Vec<GroupChatSegment>> {
+/// Insert rows of imported history in one transaction, skipping those with the same time, sender
+/// and content as an existing row. Returns the number of inserted rows.
+pub async fn db_import_group_rows(group_id: i64, rows: &[PendingRow]) -> PluginResult<usize> {
     let pool = DB_POOL.get().unwrap();
     let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
 
-    let query = load_n_latest_msg(&table_name);
-    let segs: Vec<GroupChatSegment> = sqlx::query_as(&query).bind(n).fetch_all(pool).await?;
-    Ok(segs)
+    let query = import_group_msg(&table_name);
+    let mut tx = pool.begin().await?;
+    let mut inserted = 0;
+    for row in rows {
+        if insert_row(&mut tx, &table_name, &query, row).await? {
+            inserted += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(inserted)
 }
 
-async fn dump_csv(filename: &str, query: &str) -> PluginResult<String> {
-    let data_path = DATA_PATH.get().unwrap();
-    let file_path = data_path.join(filename);
-    let file_path_str = file_path.to_string_lossy().to_string();
-    let db_path = data_path.join("store.db");
-    let db_path_str = db_path.to_string_lossy().to_string();
+/// Run an insert query of group message with the row bound, compressing long content. Returns
+/// false if the query skipped the row.
+async fn insert_row(
+    conn: &mut SqliteConnection,
+    table_name: &str,
+    query: &str,
+    row: &PendingRow,
+) -> PluginResult<bool> {
+    let packed = pack(&row.content);
+    let query = sqlx::query(query)
+        .bind(row.message_id)
+        .bind(&row.time)
+        .bind(row.sender_id)
+        .bind(&row.sender_name)
+        .bind(&row.seg_type);
+    let query = match packed {
+        Some(ref packed) => query.bind(packed.as_slice()),
+        None => query.bind(&row.content),
+    };
+    let res = query.bind(&row.interpret).execute(&mut *conn).await?;
+    if res.rows_affected() == 0 {
+        return Ok(false);
+    }
+    // the trigger cannot read compressed text
+    if packed.is_some() && row.seg_type == "text" {
+        sqlx::query(&index_group_msg_fts(table_name))
+            .bind(res.last_insert_rowid())
+            .bind(&row.content)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(true)
+}
 
-    let mut cmd = kovi::tokio::process::Command::new("sqlite3");
-    let output = cmd
-        .args(["-header", "-csv", &db_path_str, query])
-        .output()
-        .await?;
-    if output.status.success() {
-        let mut csv_file = File::create(file_path).await?;
-        csv_file.write_all(&output.stdout).await?;
+/// Content compressed by zstd if `database.compress_min_bytes` is set and reached, None to store
+/// it as text.
+fn pack(content: &str) -> Option<Vec<u8>> {
+    let min_bytes = CONFIG.get().unwrap().database.compress_min_bytes?;
+    if content.len() < min_bytes {
+        return None;
+    }
+    match zstd::encode_all(content.as_bytes(), 0) {
+        Ok(packed) if packed.len() < content.len() => Some(packed),
+        Ok(_) => None,
+        Err(err) => {
+            std_error!("Compress content failed: {err}");
+            None
+        }
+    }
+}
+
+/// Content stored either as text or compressed by [pack].
+fn unpack(raw: Vec<u8>) -> String {
+    let raw = if raw.starts_with(&ZSTD_MAGIC) {
+        match zstd::decode_all(raw.as_slice()) {
+            Ok(text) => text,
+            Err(err) => {
+                std_error!("Decompress content failed: {err}");
+                raw
+            }
+        }
     } else {
-        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-        return Err(PluginError::ChildProcess("sqlite3".into(), stderr));
+        raw
+    };
+    String::from_utf8(raw).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+/// Segment of group message waiting to be written.
+#[derive(Debug, Clone)]
+pub struct PendingRow {
+    pub group_id: i64,
+    pub message_id: i32,
+    pub time: String,
+    pub sender_id: i64,
+    pub sender_name: String,
+    pub seg_type: String,
+    pub content: String,
+    pub interpret: String,
+}
+
+/// Circuit breaker of group message writes. Once a write fails, later writes are buffered in
+/// memory without touching the database until [flush_write_buffer] drains the buffer.
+#[derive(Debug, Default)]
+pub struct WriteBuffer {
+    pub open: bool,
+    pub rows: VecDeque<PendingRow>,
+    /// Rows dropped since the breaker opened, the oldest go first when the buffer is full.
+    pub dropped: usize,
+}
+
+impl WriteBuffer {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn push(&mut self, row: PendingRow) {
+        let config = CONFIG.get().unwrap();
+        let cap = config
+            .database
+            .write_buffer_cap
+            .unwrap_or(DEFAULT_WRITE_BUFFER_CAP);
+        self.rows.push_back(row);
+        while self.rows.len() > cap {
+            self.rows.pop_front();
+            self.dropped += 1;
+        }
     }
-    Ok(file_path_str)
 }
 
-pub async fn dump_log_csv(filename: &str, n: i64) -> PluginResult<String> {
-    let query = load_n_latest_log();
-    let query = query.replace("$1", &n.to_string());
-    dump_csv(filename, &query).await
+const DEFAULT_WRITE_BUFFER_CAP: usize = 10000;
+const DEFAULT_WRITE_RETRY_SEC: u64 = 30;
+
+pub fn write_buffer() -> MutexGuard<'static, WriteBuffer> {
+    static BUFFER: OnceLock<Mutex<WriteBuffer>> = OnceLock::new();
+    BUFFER
+        .get_or_init(|| Mutex::new(WriteBuffer::default()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
 }
 
-pub async fn dump_history_csv(group_id: i64, filename: &str, n: i64) -> PluginResult<String> {
+/// Retry buffered writes periodically, and tell admin once the database recovers.
+pub async fn subscribe_write_retry() {
+    let config = CONFIG.get().unwrap();
+    let interval = config
+        .database
+        .write_retry_sec
+        .unwrap_or(DEFAULT_WRITE_RETRY_SEC);
+    kovi::spawn(async move {
+        util::schedule_task_blocking(Duration::from_secs(interval), || async {
+            if !write_buffer().is_open() {
+                return;
+            }
+            if let Some((written, dropped)) = flush_write_buffer().await {
+                std_info!("Database recovered, {written} buffered rows written, {dropped} dropped");
+                util::notify_admin(tr!(
+                    None,
+                    "database.recovered",
+                    written = written,
+                    dropped = dropped
+                ));
+            }
+        })
+        .await;
+    });
+}
+
+/// Write buffered rows in order, and close the breaker if all are written.
+///
+/// Returns (written, dropped) on recovery, None if the database is still unavailable or another
+/// flush is running.
+pub async fn flush_write_buffer() -> Option<(usize, usize)> {
+    // one flush at a time, so that rows are written in order and recovery is reported once
+    static FLUSHING: kovi::tokio::sync::Mutex<()> = kovi::tokio::sync::Mutex::const_new(());
+    let _guard = FLUSHING.try_lock().ok()?;
+    let mut written = 0;
+    loop {
+        // not held across await, the breaker closes under the same lock that finds it empty
+        let row = {
+            let mut buffer = write_buffer();
+            match buffer.rows.pop_front() {
+                Some(row) => row,
+                None => {
+                    buffer.open = false;
+                    let dropped = std::mem::take(&mut buffer.dropped);
+                    return Some((written, dropped));
+                }
+            }
+        };
+        if let Err(err) = insert_group_row(&row).await {
+            let mut buffer = write_buffer();
+            std_error!(
+                "Database still unavailable, {} rows buffered: {err}",
+                buffer.rows.len() + 1
+            );
+            buffer.rows.push_front(row);
+            return None;
+        }
+        written += 1;
+    }
+}
+
+/// Create message tables of configured groups, so that groups without any message yet are listed
+/// by exports and stats.
+pub async fn init_group_tables() -> PluginResult<()> {
+    let config = CONFIG.get().unwrap();
+    for group in config.groups.iter().flatten() {
+        ensure_group_msg_table(&get_group_msg_table_name(group.id)).await?;
+    }
+    Ok(())
+}
+
+/// Create the message table of a group and its full-text index once per process, history written
+/// before the index existed is indexed on creation.
+async fn ensure_group_msg_table(table_name: &str) -> PluginResult<()> {
+    static READY: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    let ready = READY.get_or_init(|| Mutex::new(HashSet::new()));
+    if ready.lock().unwrap().contains(table_name) {
+        return Ok(());
+    }
+    let pool = DB_POOL.get().unwrap();
+    let query = create_group_msg_table(table_name);
+    sqlx::query(&query).execute(pool).await?;
+    let query = create_group_msg_fts(table_name);
+    sqlx::query(&query).execute(pool).await?;
+    ready.lock().unwrap().insert(table_name.to_string());
+    Ok(())
+}
+
+/// Text segments containing keyword, excluding those starting with `exclude_prefix` and those of
+/// the bot. Returns (count, earliest, latest).
+pub async fn db_search_group_msg(
+    group_id: i64,
+    keyword: &str,
+    exclude_prefix: &str,
+) -> PluginResult<(i64, Option<GroupChatSegment>, Option<GroupChatSegment>)> {
+    let pool = DB_POOL.get().unwrap();
     let table_name = get_group_msg_table_name(group_id);
-    let query = load_n_latest_msg(&table_name);
-    let query = query.replace("$1", &n.to_string());
-    dump_csv(filename, &query).await
+    ensure_group_msg_table(&table_name).await?;
+
+    // trigram index requires at least 3 characters
+    let (cond, pattern) = if keyword.chars().count() >= 3 {
+        let phrase = format!("\"{}\"", keyword.replace('"', "\"\""));
+        (search_fts_condition(&table_name), phrase)
+    } else {
+        (search_instr_condition(&table_name), keyword.to_string())
+    };
+    let exclude = format!("{exclude_prefix}%");
+    let query = count_search_group_msg(&table_name, &cond);
+    let bot_qq = *BOT_QQ.get().unwrap();
+    let (count,): (i64,) = sqlx::query_as(&query)
+        .bind(&pattern)
+        .bind(&exclude)
+        .bind(bot_qq)
+        .fetch_one(pool)
+        .await?;
+    let mut found = Vec::with_capacity(2);
+    for order in ["ASC", "DESC"] {
+        let query = search_group_msg(&table_name, &cond, order);
+        let seg: Option<GroupChatSegment> = sqlx::query_as(&query)
+            .bind(&pattern)
+            .bind(&exclude)
+            .bind(bot_qq)
+            .fetch_optional(pool)
+            .await?;
+        found.push(seg);
+    }
+    let latest = found.pop().flatten();
+    let earliest = found.pop().flatten();
+    Ok((count, earliest, latest))
 }
 
-pub async fn db_find_segment_by_id(
+/// Text and OCR segments matching any of the terms, most relevant first, excluding those starting
+/// with `exclude_prefix` and those of the bot.
+pub async fn db_related_group_msg(
     group_id: i64,
-    message_id: i32,
+    terms: &[String],
+    exclude_prefix: &str,
+    limit: i64,
 ) -> PluginResult<Vec<GroupChatSegment>> {
+    if terms.is_empty() {
+        return Ok(vec![]);
+    }
     let pool = DB_POOL.get().unwrap();
     let table_name = get_group_msg_table_name(group_id);
-
-    let query = find_segment_by_id(&table_name);
+    ensure_group_msg_table(&table_name).await?;
+    let pattern = terms
+        .iter()
+        .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    let query = related_group_msg(&table_name);
     let segs: Vec<GroupChatSegment> = sqlx::query_as(&query)
-        .bind(message_id)
+        .bind(pattern)
+        .bind(format!("{exclude_prefix}%"))
+        .bind(*BOT_QQ.get().unwrap())
+        .bind(limit)
         .fetch_all(pool)
         .await?;
     Ok(segs)
 }
 
-fn get_group_msg_table_name(group_id: i64) -> String {
-    let config = CONFIG.get().unwrap();
-    let prefix = &config.database.group_table_prefix;
-    format!("{}{}", prefix, group_id)
+/// (sender_id, message count) of the year, most active first.
+pub async fn db_count_messages_by_year(group_id: i64, year: i32) -> PluginResult<Vec<(i64, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = count_messages_by_year(&table_name);
+    let counts: Vec<(i64, i64)> = sqlx::query_as(&query)
+        .bind(format!("{year}-%"))
+        .bind(*BOT_QQ.get().unwrap())
+        .fetch_all(pool)
+        .await?;
+    Ok(counts)
 }
 
-use sql_query::*;
-mod sql_query {
-    use crate::CONFIG;
-    use indoc::{formatdoc, indoc};
+/// (hour, message count) of the member in the year, e.g. ("23", 42).
+pub async fn db_most_active_hour(
+    group_id: i64,
+    user_id: i64,
+    year: i32,
+) -> PluginResult<Option<(String, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    let query = most_active_hour(&table_name);
+    let hour: Option<(String, i64)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(format!("{year}-%"))
+        .fetch_optional(pool)
+        .await?;
+    Ok(hour)
+}
 
-    const CREATE_TABLE_IF_NOT_EXISTS: &str = "CREATE TABLE IF NOT EXISTS";
-    const CREATE_INDEX_IF_NOT_EXISTS: &str = "CREATE INDEX IF NOT EXISTS";
-    const INSERT_INTO: &str = "INSERT INTO";
-    const GROUP_MSG_SCHEMA: &str = indoc!(
-        "
-        (
-            auto_id INTEGER PRIMARY KEY,
-            message_id INTEGER,
-            time TEXT,
-            sender_id INTEGER,
-            sender_name TEXT,
-            type TEXT,
-            content TEXT,
-            interpret TEXT
-        )
-        "
-    );
-    pub const INSERT_GROUP_MSG_SCHEMA: &str = indoc!(
-        "
-        (message_id, time, sender_id, sender_name, type, content, interpret)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        "
-    );
+/// (name, times) of whom the member mentioned most in the year.
+pub async fn db_most_at(
+    group_id: i64,
+    user_id: i64,
+    year: i32,
+) -> PluginResult<Option<(String, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    let query = most_at(&table_name);
+    let at: Option<(String, i64)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(format!("{year}-%"))
+        .fetch_optional(pool)
+        .await?;
+    Ok(at)
+}
 
-    pub fn create_log_table() -> String {
-        let config = CONFIG.get().unwrap();
-        let table_name = &config.database.log_table_name;
-        formatdoc!(
-            "
-            {CREATE_TABLE_IF_NOT_EXISTS} {table_name}(
-                auto_id INTEGER PRIMARY KEY,
-                time TEXT,
-                level TEXT,
-                content TEXT
-            );
-            {CREATE_INDEX_IF_NOT_EXISTS} log_time
-            ON {table_name}(time);
-            "
-        )
-    }
+pub async fn db_load_texts_by_year(
+    group_id: i64,
+    user_id: i64,
+    year: i32,
+) -> PluginResult<Vec<String>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    let query = load_texts_by_year(&table_name);
+    let texts: Vec<(Vec<u8>,)> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(format!("{year}-%"))
+        .fetch_all(pool)
+        .await?;
+    Ok(texts.into_iter().map(|(t,)| unpack(t)).collect())
+}
 
-    pub fn insert_log() -> String {
+pub async fn db_load_n_group_segment(group_id: i64, n: i64) -> PluginResult<Vec<GroupChatSegment>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+
+    let query = load_n_latest_msg(&table_name);
+    let segs: Vec<GroupChatSegment> = sqlx::query_as(&query).bind(n).fetch_all(pool).await?;
+    Ok(segs)
+}
+
+/// Segments at or after `since`, in form of "[year-month-day hour:minute:second]".
+pub async fn db_load_group_segment_since(
+    group_id: i64,
+    since: &str,
+) -> PluginResult<Vec<GroupChatSegment>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = load_msg_since(&table_name);
+    let segs = sqlx::query_as(&query).bind(since).fetch_all(pool).await?;
+    Ok(segs)
+}
+
+/// Persist a private chat message, role is either "user" or "assistant".
+pub async fn db_write_private_msg(
+    user_id: i64,
+    message_id: i32,
+    time: &str,
+    role: &str,
+    content: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_private_msg();
+    sqlx::query(&query)
+        .bind(message_id)
+        .bind(time)
+        .bind(user_id)
+        .bind(role)
+        .bind(content)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_load_n_private_msg(user_id: i64, n: i64) -> PluginResult<Vec<PrivateChatMessage>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_n_latest_private_msg();
+    let msgs: Vec<PrivateChatMessage> = sqlx::query_as(&query)
+        .bind(user_id)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(msgs)
+}
+
+/// Returns false if the user has subscribed the room.
+pub async fn db_add_live_subscription(
+    user_id: i64,
+    room_id: &str,
+    time: &str,
+) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_live_subscription();
+    let res = sqlx::query(&query)
+        .bind(user_id)
+        .bind(room_id)
+        .bind(time)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Returns false if the user has not subscribed the room.
+pub async fn db_remove_live_subscription(user_id: i64, room_id: &str) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = delete_live_subscription();
+    let res = sqlx::query(&query)
+        .bind(user_id)
+        .bind(room_id)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn db_count_live_subscription(user_id: i64) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = count_live_subscription();
+    let (count,): (i64,) = sqlx::query_as(&query).bind(user_id).fetch_one(pool).await?;
+    Ok(count)
+}
+
+pub async fn db_load_live_subscriptions() -> PluginResult<Vec<LiveSubscription>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_live_subscriptions();
+    let subs: Vec<LiveSubscription> = sqlx::query_as(&query).fetch_all(pool).await?;
+    Ok(subs)
+}
+
+pub async fn db_load_room_subscribers(room_id: &str) -> PluginResult<Vec<i64>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_room_subscribers();
+    let users: Vec<(i64,)> = sqlx::query_as(&query).bind(room_id).fetch_all(pool).await?;
+    Ok(users.into_iter().map(|(u,)| u).collect())
+}
+
+/// Last known status of a room observed by the poller of `group_id`, 0 for the private poller.
+pub async fn db_save_live_state(group_id: i64, room_id: &str, streaming: bool) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_live_state();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(room_id)
+        .bind(streaming)
+        .bind(util::cur_time_iso8601(None))
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// (room_id, streaming) of rooms observed by the poller of `group_id`.
+pub async fn db_load_live_states(group_id: i64) -> PluginResult<Vec<(String, bool)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_live_states();
+    let states = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(states)
+}
+
+/// Open a session of the room watched by `group_id` when it starts streaming, close the open one
+/// when it stops. Repeated observations of the same status are no-ops.
+pub async fn db_record_live_session(
+    group_id: i64,
+    room_id: &str,
+    streaming: bool,
+    title: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let now = util::cur_time_iso8601(None);
+    if streaming {
+        let query = open_live_session();
+        sqlx::query(&query)
+            .bind(group_id)
+            .bind(room_id)
+            .bind(title)
+            .bind(now)
+            .execute(pool)
+            .await?;
+    } else {
+        let query = close_live_session();
+        sqlx::query(&query)
+            .bind(group_id)
+            .bind(room_id)
+            .bind(now)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Sessions overlapping `[from, to)`, both ISO8601, the ongoing ones have no end.
+pub async fn db_live_sessions(from: &str, to: &str) -> PluginResult<Vec<LiveSession>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_live_sessions();
+    let sessions: Vec<LiveSession> = sqlx::query_as(&query)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(sessions)
+}
+
+/// Start or restart verification of a member, previous status is overwritten.
+pub async fn db_add_pending_verification(
+    group_id: i64,
+    user_id: i64,
+    deadline: i64,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_pending_verification();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(deadline)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_is_verification_pending(group_id: i64, user_id: i64) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = find_pending_verification();
+    let row: Option<(i64,)> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.is_some())
+}
+
+/// Status is one of "pending", "passed" and "failed".
+pub async fn db_set_verification_status(
+    group_id: i64,
+    user_id: i64,
+    status: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = update_verification_status();
+    sqlx::query(&query)
+        .bind(status)
+        .bind(group_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Pending verifications whose deadline is before `now`, in unix timestamp.
+pub async fn db_load_overdue_verifications(now: i64) -> PluginResult<Vec<MemberVerification>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_overdue_verifications();
+    let rows: Vec<MemberVerification> = sqlx::query_as(&query).bind(now).fetch_all(pool).await?;
+    Ok(rows)
+}
+
+/// Append an entry to points ledger, negative delta for spending.
+pub async fn db_add_points(
+    group_id: i64,
+    user_id: i64,
+    delta: i64,
+    reason: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let time = util::cur_time_iso8601(Some(group_id));
+    let query = insert_points();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(&time)
+        .bind(delta)
+        .bind(reason)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_points_balance(group_id: i64, user_id: i64) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = points_balance();
+    let (balance,): (i64,) = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(balance)
+}
+
+/// Points earned today for the given reason.
+pub async fn db_points_today(group_id: i64, user_id: i64, reason: &str) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let day_prefix = format!("{}%", util::cur_date_iso8601(Some(group_id)));
+    let query = points_of_day();
+    let (points,): (i64,) = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(reason)
+        .bind(&day_prefix)
+        .fetch_one(pool)
+        .await?;
+    Ok(points)
+}
+
+pub async fn db_points_leaderboard(group_id: i64, n: i64) -> PluginResult<Vec<PointsRank>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = points_leaderboard();
+    let ranks: Vec<PointsRank> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(ranks)
+}
+
+pub async fn db_load_sign_in(group_id: i64, user_id: i64) -> PluginResult<Option<SignIn>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_sign_in();
+    let sign_in: Option<SignIn> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(sign_in)
+}
+
+pub async fn db_save_sign_in(
+    group_id: i64,
+    user_id: i64,
+    day: &str,
+    streak: i64,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_sign_in();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(day)
+        .bind(streak)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_load_level(group_id: i64, user_id: i64) -> PluginResult<Option<MemberLevel>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_level();
+    let member: Option<MemberLevel> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(member)
+}
+
+pub async fn db_save_level(group_id: i64, member: &MemberLevel) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_level();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(member.user_id)
+        .bind(member.xp)
+        .bind(member.level)
+        .bind(&member.day)
+        .bind(member.day_xp)
+        .bind(member.last_award)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Rank of a member with experience `xp`, 1 for the most.
+pub async fn db_level_rank(group_id: i64, xp: i64) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = count_level_above();
+    let (above,): (i64,) = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(xp)
+        .fetch_one(pool)
+        .await?;
+    Ok(above + 1)
+}
+
+pub async fn db_level_ranking(group_id: i64, n: i64) -> PluginResult<Vec<MemberLevel>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = level_ranking();
+    let members: Vec<MemberLevel> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(members)
+}
+
+/// Count a strike of a member, returns strikes so far.
+pub async fn db_add_word_strike(group_id: i64, user_id: i64) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let time = util::cur_time_iso8601(Some(group_id));
+    let query = upsert_word_strike();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(&time)
+        .execute(pool)
+        .await?;
+    let query = load_word_strikes();
+    let (strikes,): (i64,) = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(strikes)
+}
+
+pub async fn db_add_reminder(
+    group_id: i64,
+    user_id: i64,
+    due: i64,
+    content: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_reminder();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(due)
+        .bind(content)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_count_reminders(group_id: i64, user_id: i64) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = count_reminders();
+    let (count,): (i64,) = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+    Ok(count)
+}
+
+/// Remove and return reminders due before `now`, in unix timestamp.
+pub async fn db_take_due_reminders(now: i64) -> PluginResult<Vec<Reminder>> {
+    let pool = DB_POOL.get().unwrap();
+    let mut tx = pool.begin().await?;
+    let query = load_due_reminders();
+    let due: Vec<Reminder> = sqlx::query_as(&query).bind(now).fetch_all(&mut *tx).await?;
+    let query = delete_due_reminders();
+    sqlx::query(&query).bind(now).execute(&mut *tx).await?;
+    tx.commit().await?;
+    Ok(due)
+}
+
+pub async fn db_load_idiom_game(group_id: i64) -> PluginResult<Option<IdiomGame>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_idiom_game();
+    let game: Option<IdiomGame> = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(game)
+}
+
+pub async fn db_save_idiom_game(game: &IdiomGame) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_idiom_game();
+    sqlx::query(&query)
+        .bind(game.group_id)
+        .bind(&game.current)
+        .bind(&game.used)
+        .bind(game.last_player)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Delete game state and scores of a group.
+pub async fn db_delete_idiom_game(group_id: i64) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = delete_idiom_game();
+    sqlx::query(&query).bind(group_id).execute(pool).await?;
+    Ok(())
+}
+
+pub async fn db_add_idiom_score(group_id: i64, user_id: i64) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_idiom_score();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Returns (user_id, score), highest first.
+pub async fn db_load_idiom_scores(group_id: i64) -> PluginResult<Vec<(i64, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_idiom_scores();
+    let scores: Vec<(i64, i64)> = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(scores)
+}
+
+/// Returns false if the group has subscribed the feed.
+pub async fn db_add_feed_subscription(
+    group_id: i64,
+    url: &str,
+    title: &str,
+    interval_sec: i64,
+    next_poll: i64,
+) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_feed_subscription();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(url)
+        .bind(title)
+        .bind(interval_sec)
+        .bind(next_poll)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Returns false if the group has not subscribed the feed, seen entries are dropped as well.
+pub async fn db_remove_feed_subscription(group_id: i64, url: &str) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = delete_feed_subscription();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(url)
+        .execute(pool)
+        .await?;
+    let query = delete_feed_seen();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(url)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn db_load_feed_subscriptions(group_id: i64) -> PluginResult<Vec<FeedSubscription>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_feed_subscriptions();
+    let subs: Vec<FeedSubscription> = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(subs)
+}
+
+/// Subscriptions whose next poll is not later than `now`.
+pub async fn db_load_due_feeds(now: i64) -> PluginResult<Vec<FeedSubscription>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_due_feeds();
+    let subs: Vec<FeedSubscription> = sqlx::query_as(&query).bind(now).fetch_all(pool).await?;
+    Ok(subs)
+}
+
+pub async fn db_set_feed_next_poll(group_id: i64, url: &str, next_poll: i64) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = update_feed_next_poll();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(url)
+        .bind(next_poll)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Returns true if the entry has not been seen before.
+pub async fn db_mark_feed_entry(group_id: i64, url: &str, entry_id: &str) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_feed_seen();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(url)
+        .bind(entry_id)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn db_add_announcement(
+    group_id: i64,
+    sender_id: i64,
+    content: &str,
+    pinned: bool,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_announcement();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(sender_id)
+        .bind(content)
+        .bind(util::cur_time_iso8601(Some(group_id)))
+        .bind(pinned)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Latest first.
+pub async fn db_load_announcements(group_id: i64, n: i64) -> PluginResult<Vec<Announcement>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_announcements();
+    let rows: Vec<Announcement> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+async fn db_add_mention(
+    group_id: i64,
+    sender_id: i64,
+    target_id: i64,
+    time: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_mention();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(sender_id)
+        .bind(target_id)
+        .bind(time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Members who at `target_id` the most since `since`, "" for all time. Returns (sender_id, count).
+pub async fn db_top_mentioners(
+    group_id: i64,
+    target_id: i64,
+    since: &str,
+    n: i64,
+) -> PluginResult<Vec<(i64, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = top_mentioners();
+    let rows = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(target_id)
+        .bind(since)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// Members at by others the most since `since`, "" for all time. Returns (target_id, count).
+pub async fn db_top_mentioned(group_id: i64, since: &str, n: i64) -> PluginResult<Vec<(i64, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = top_mentioned();
+    let rows = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(since)
+        .bind(n)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows)
+}
+
+/// Write a log row in a transaction that is rolled back, fails if the database is read-only,
+/// locked, or otherwise unwritable.
+pub async fn db_check_writable() -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_log();
+    let mut tx = pool.begin().await?;
+    sqlx::query(&query)
+        .bind(util::cur_time_iso8601(None))
+        .bind("DEBUG")
+        .bind("self check")
+        .execute(&mut *tx)
+        .await?;
+    tx.rollback().await?;
+    Ok(())
+}
+
+/// Problems found by `PRAGMA integrity_check`, empty if the database is intact.
+pub async fn db_integrity_check() -> PluginResult<Vec<String>> {
+    let pool = DB_POOL.get().unwrap();
+    let rows: Vec<(String,)> = sqlx::query_as("PRAGMA integrity_check;")
+        .fetch_all(pool)
+        .await?;
+    let problems = rows
+        .into_iter()
+        .map(|(row,)| row)
+        .filter(|row| row != "ok")
+        .collect();
+    Ok(problems)
+}
+
+/// Rebuild indexes and refresh statistics of the query planner.
+pub async fn db_optimize() -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    for query in ["REINDEX;", "ANALYZE;", "PRAGMA optimize;"] {
+        sqlx::query(query).execute(pool).await?;
+    }
+    Ok(())
+}
+
+/// Size of the database in bytes.
+pub async fn db_size() -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let (pages,): (i64,) = sqlx::query_as("PRAGMA page_count;").fetch_one(pool).await?;
+    let (page_size,): (i64,) = sqlx::query_as("PRAGMA page_size;").fetch_one(pool).await?;
+    Ok(pages * page_size)
+}
+
+pub async fn db_add_check(check: &DbCheck) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_db_check();
+    sqlx::query(&query)
+        .bind(&check.time)
+        .bind(check.ok)
+        .bind(&check.detail)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_last_check() -> PluginResult<Option<DbCheck>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_last_db_check();
+    let check: Option<DbCheck> = sqlx::query_as(&query).fetch_optional(pool).await?;
+    Ok(check)
+}
+
+/// Record a start of the plugin, returns the id of the run.
+pub async fn db_start_run(time: &str) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_run();
+    let res = sqlx::query(&query)
+        .bind(time)
+        .bind(None::<&str>)
+        .execute(pool)
+        .await?;
+    Ok(res.last_insert_rowid())
+}
+
+/// Record a start that failed to initialize, as a run stopped at once.
+pub async fn db_add_failed_run(time: &str, error: &str) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_run();
+    sqlx::query(&query)
+        .bind(time)
+        .bind(error)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Record a clean shutdown of the run.
+pub async fn db_stop_run(id: i64, time: &str) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = stop_run();
+    sqlx::query(&query)
+        .bind(time)
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// (starts, unclean ones) since `since`, except the run `current` which is still going.
+pub async fn db_run_stats(since: &str, current: i64) -> PluginResult<(i64, i64)> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_run_stats();
+    let stats: (i64, i64) = sqlx::query_as(&query)
+        .bind(since)
+        .bind(current)
+        .fetch_one(pool)
+        .await?;
+    Ok(stats)
+}
+
+/// (time, error) of the latest start that failed to initialize.
+pub async fn db_last_init_error() -> PluginResult<Option<(String, String)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_last_init_error();
+    let error: Option<(String, String)> = sqlx::query_as(&query).fetch_optional(pool).await?;
+    Ok(error)
+}
+
+/// Add OpenAI usage to `month`, `YYYY-MM`.
+pub async fn db_add_spend(month: &str, tokens: i64, cost: f64) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_spend();
+    sqlx::query(&query)
+        .bind(month)
+        .bind(tokens)
+        .bind(cost)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// OpenAI cost of `month`, 0 if nothing is spent.
+pub async fn db_load_spend(month: &str) -> PluginResult<f64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_spend();
+    let cost: Option<(f64,)> = sqlx::query_as(&query)
+        .bind(month)
+        .fetch_optional(pool)
+        .await?;
+    Ok(cost.map_or(0.0, |(c,)| c))
+}
+
+/// OpenAI usage of months in `[from, to]`, both `YYYY-MM`.
+pub async fn db_spend_stats(from: &str, to: &str) -> PluginResult<Vec<SpendStat>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_spend_range();
+    let stats: Vec<SpendStat> = sqlx::query_as(&query)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(stats)
+}
+
+/// Messages of each member in `[from, to)`, both ISO8601, most active first.
+pub async fn db_message_stats(
+    group_id: i64,
+    from: &str,
+    to: &str,
+) -> PluginResult<Vec<MemberStat>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = count_messages_in_range(&table_name);
+    let stats: Vec<MemberStat> = sqlx::query_as(&query)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(stats)
+}
+
+/// (weekday, hour, message count) since `since` in ISO8601, weekday 0 for Sunday.
+pub async fn db_activity_by_hour(group_id: i64, since: &str) -> PluginResult<Vec<(i64, i64, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = activity_by_hour(&table_name);
+    let counts: Vec<(i64, i64, i64)> = sqlx::query_as(&query)
+        .bind(since)
+        .bind(*BOT_QQ.get().unwrap())
+        .fetch_all(pool)
+        .await?;
+    Ok(counts)
+}
+
+/// (used, extra) quota of the member on the day, zeros if none.
+pub async fn db_load_quota(
+    group_id: i64,
+    user_id: i64,
+    kind: &str,
+    day: &str,
+) -> PluginResult<(i64, i64)> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_quota();
+    let quota: Option<(i64, i64)> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(kind)
+        .bind(day)
+        .fetch_optional(pool)
+        .await?;
+    Ok(quota.unwrap_or_default())
+}
+
+/// Add to used and extra quota of the member on the day.
+pub async fn db_add_quota(
+    group_id: i64,
+    user_id: i64,
+    kind: &str,
+    day: &str,
+    (used, extra): (i64, i64),
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = add_quota();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(user_id)
+        .bind(kind)
+        .bind(day)
+        .bind(used)
+        .bind(extra)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Count a use of the feature in the group on the day.
+pub async fn db_add_feature_use(group_id: i64, day: &str, feature: &str) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = add_feature_use();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(day)
+        .bind(feature)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// (feature, count) of uses in the group since the day, most used first.
+pub async fn db_feature_usage_since(
+    group_id: i64,
+    since: &str,
+) -> PluginResult<Vec<(String, i64)>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = feature_usage_since();
+    let usage: Vec<(String, i64)> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(since)
+        .fetch_all(pool)
+        .await?;
+    Ok(usage)
+}
+
+/// Record the reaction of a member to a message, no-op if already recorded.
+pub async fn db_add_reaction(
+    group_id: i64,
+    message_id: i32,
+    user_id: i64,
+    emoji: &str,
+    time: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_reaction();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .bind(time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_remove_reaction(
+    group_id: i64,
+    message_id: i32,
+    user_id: i64,
+    emoji: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = delete_reaction();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(message_id)
+        .bind(user_id)
+        .bind(emoji)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// message_id -> number of reactions made since `time`, which are all reactions to messages sent
+/// since then.
+pub async fn db_reaction_counts(group_id: i64, time: &str) -> PluginResult<HashMap<i32, i64>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_reaction_counts();
+    let counts: Vec<(i32, i64)> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(time)
+        .fetch_all(pool)
+        .await?;
+    Ok(counts.into_iter().collect())
+}
+
+/// Record a reply of the agent made with prompt `variant`, triggered by `asker_id`.
+pub async fn db_add_ab_reply(
+    group_id: i64,
+    variant: &str,
+    asker_id: i64,
+    time: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_ab_reply();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(variant)
+        .bind(asker_id)
+        .bind(time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Record a reply of the group agent, `prompt` is the message of `asker_id`.
+pub async fn db_add_agent_reply(
+    group_id: i64,
+    message_id: i32,
+    time: &str,
+    asker_id: i64,
+    persona: &str,
+    model: &str,
+    prompt: &str,
+    response: &str,
+) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_agent_reply();
+    sqlx::query(&query)
+        .bind(group_id)
+        .bind(message_id)
+        .bind(time)
+        .bind(asker_id)
+        .bind(persona)
+        .bind(model)
+        .bind(prompt)
+        .bind(response)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Rate a reply of the agent, the latest rating of a member counts. False if the message is not a
+/// recorded reply.
+pub async fn db_add_feedback(
+    group_id: i64,
+    message_id: i32,
+    user_id: i64,
+    good: bool,
+    time: &str,
+) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = upsert_feedback();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(message_id)
+        .bind(user_id)
+        .bind(good)
+        .bind(time)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Hold an answer of the agent for review, returns its id.
+pub async fn db_add_pending_reply(reply: &PendingReply) -> PluginResult<i64> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_pending_reply();
+    let res = sqlx::query(&query)
+        .bind(reply.group_id)
+        .bind(reply.message_id)
+        .bind(reply.asker_id)
+        .bind(&reply.persona)
+        .bind(&reply.model)
+        .bind(&reply.prompt)
+        .bind(&reply.response)
+        .bind(&reply.time)
+        .execute(pool)
+        .await?;
+    Ok(res.last_insert_rowid())
+}
+
+/// A reply held for review, reviewed or not.
+pub async fn db_load_pending_reply(id: i64) -> PluginResult<Option<PendingReply>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_pending_reply();
+    let reply: Option<PendingReply> = sqlx::query_as(&query).bind(id).fetch_optional(pool).await?;
+    Ok(reply)
+}
+
+/// Mark a pending reply as `status` with the response actually posted, if any. False if it has
+/// been reviewed already.
+pub async fn db_resolve_pending_reply(
+    id: i64,
+    status: &str,
+    posted: Option<&str>,
+) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = resolve_pending_reply();
+    let res = sqlx::query(&query)
+        .bind(id)
+        .bind(status)
+        .bind(posted)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Add a sticker, replacing the one with the same tag.
+pub async fn db_add_sticker(sticker: &Sticker) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_sticker();
+    sqlx::query(&query)
+        .bind(sticker.group_id)
+        .bind(&sticker.tag)
+        .bind(&sticker.path)
+        .bind(&sticker.url)
+        .bind(sticker.adder_id)
+        .bind(&sticker.time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// False if the group has no sticker of the tag.
+pub async fn db_remove_sticker(group_id: i64, tag: &str) -> PluginResult<bool> {
+    let pool = DB_POOL.get().unwrap();
+    let query = delete_sticker();
+    let res = sqlx::query(&query)
+        .bind(group_id)
+        .bind(tag)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Stickers of a group ordered by tag.
+pub async fn db_load_stickers(group_id: i64) -> PluginResult<Vec<Sticker>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_stickers();
+    let stickers: Vec<Sticker> = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(stickers)
+}
+
+pub async fn db_find_sticker(group_id: i64, tag: &str) -> PluginResult<Option<Sticker>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = find_sticker();
+    let sticker: Option<Sticker> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(tag)
+        .fetch_optional(pool)
+        .await?;
+    Ok(sticker)
+}
+
+/// Record the latest posting of an image, replacing the earlier one of the same hash.
+pub async fn db_add_image_post(post: &ImagePost) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    let query = insert_image_post();
+    sqlx::query(&query)
+        .bind(post.group_id)
+        .bind(&post.hash)
+        .bind(post.message_id)
+        .bind(post.sender_id)
+        .bind(&post.sender_name)
+        .bind(&post.time)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn db_find_image_post(group_id: i64, hash: &str) -> PluginResult<Option<ImagePost>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = find_image_post();
+    let post: Option<ImagePost> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(hash)
+        .fetch_optional(pool)
+        .await?;
+    Ok(post)
+}
+
+/// Ratings per (persona, model) of replies in the group, worst first.
+pub async fn db_feedback_stats(group_id: i64) -> PluginResult<Vec<FeedbackStat>> {
+    let pool = DB_POOL.get().unwrap();
+    let query = load_feedback_stats();
+    let stats: Vec<FeedbackStat> = sqlx::query_as(&query)
+        .bind(group_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(stats)
+}
+
+/// Engagement per prompt variant in the group, counting messages within `window_sec` after each
+/// reply.
+pub async fn db_ab_stats(group_id: i64, window_sec: i64) -> PluginResult<Vec<AbStat>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = load_ab_stats(&table_name);
+    let stats: Vec<AbStat> = sqlx::query_as(&query)
+        .bind(group_id)
+        .bind(format!("+{window_sec} seconds"))
+        .bind(*BOT_QQ.get().unwrap())
+        .fetch_all(pool)
+        .await?;
+    Ok(stats)
+}
+
+/// Write a consistent copy of the whole database to `path`, which must not exist.
+pub async fn db_snapshot(path: &str) -> PluginResult<()> {
+    let pool = DB_POOL.get().unwrap();
+    sqlx::query("VACUUM INTO $1;")
+        .bind(path)
+        .execute(pool)
+        .await?;
+    // the copy of an in-memory database stays in memory
+    if !kovi::tokio::fs::try_exists(path).await? {
+        return Err(PluginError::PathNotAvailable(format!("snapshot {path}")));
+    }
+    Ok(())
+}
+
+/// Groups that have a message table.
+pub async fn db_group_table_ids() -> PluginResult<Vec<i64>> {
+    let pool = DB_POOL.get().unwrap();
+    let config = CONFIG.get().unwrap();
+    let prefix = &config.database.group_table_prefix;
+    let query = load_table_names();
+    let names: Vec<(String,)> = sqlx::query_as(&query).fetch_all(pool).await?;
+    let ids = names
+        .into_iter()
+        .filter_map(|(name,)| name.strip_prefix(prefix.as_str())?.parse().ok())
+        .collect();
+    Ok(ids)
+}
+
+/// Image segments of a group, the latest first.
+pub async fn db_load_recent_images(
+    group_id: i64,
+    n: i64,
+    offset: i64,
+) -> PluginResult<Vec<GroupChatSegment>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let query = load_recent_images(&table_name);
+    let segs: Vec<GroupChatSegment> = sqlx::query_as(&query)
+        .bind(n)
+        .bind(offset)
+        .fetch_all(pool)
+        .await?;
+    Ok(segs)
+}
+
+/// Image and record segments of a group, whose files are not in the database.
+pub async fn db_load_media(group_id: i64) -> PluginResult<Vec<MediaEntry>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+    let query = load_media(&table_name);
+    let rows: Vec<(String, String, Vec<u8>, String)> =
+        sqlx::query_as(&query).fetch_all(pool).await?;
+    let media = rows
+        .into_iter()
+        .map(|(time, media_type, path, url)| MediaEntry {
+            group_id,
+            time,
+            media_type,
+            path: unpack(path),
+            url,
+        })
+        .collect();
+    Ok(media)
+}
+
+async fn dump_csv(filename: &str, query: &str) -> PluginResult<String> {
+    let data_path = DATA_PATH.get().unwrap();
+    let file_path = data_path.join(filename);
+    let file_path_str = file_path.to_string_lossy().to_string();
+    let db_path = data_path.join("store.db");
+    let db_path_str = db_path.to_string_lossy().to_string();
+
+    let mut cmd = kovi::tokio::process::Command::new("sqlite3");
+    let output = cmd
+        .args(["-header", "-csv", &db_path_str, query])
+        .output()
+        .await?;
+    if output.status.success() {
+        let mut csv_file = File::create(file_path).await?;
+        csv_file.write_all(&output.stdout).await?;
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(PluginError::ChildProcess("sqlite3".into(), stderr));
+    }
+    Ok(file_path_str)
+}
+
+pub async fn dump_log_csv(filename: &str, n: i64) -> PluginResult<String> {
+    let query = load_n_latest_log();
+    let query = query.replace("$1", &n.to_string());
+    dump_csv(filename, &query).await
+}
+
+/// Written here rather than by sqlite3, which cannot decompress content.
+pub async fn dump_history_csv(group_id: i64, filename: &str, n: i64) -> PluginResult<String> {
+    let file_path = DATA_PATH.get().unwrap().join(filename);
+    let file_path_str = file_path.to_string_lossy().to_string();
+    let segs = db_load_n_group_segment(group_id, n).await?;
+    let mut csv = String::from("message_id,time,sender_id,sender_name,type,content,interpret\r\n");
+    for seg in segs {
+        let fields = [
+            seg.message_id.to_string(),
+            seg.time,
+            seg.sender_id.to_string(),
+            seg.sender_name,
+            seg.seg_type,
+            seg.content,
+            seg.interpret,
+        ];
+        let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    let mut csv_file = File::create(file_path).await?;
+    csv_file.write_all(csv.as_bytes()).await?;
+    csv_file.flush().await?;
+    Ok(file_path_str)
+}
+
+/// Quoted as sqlite3 does in csv mode.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+pub async fn db_find_segment_by_id(
+    group_id: i64,
+    message_id: i32,
+) -> PluginResult<Vec<GroupChatSegment>> {
+    let pool = DB_POOL.get().unwrap();
+    let table_name = get_group_msg_table_name(group_id);
+
+    let query = find_segment_by_id(&table_name);
+    let segs: Vec<GroupChatSegment> = sqlx::query_as(&query)
+        .bind(message_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(segs)
+}
+
+/// Segments of the reply chain ending at `message_id`, the earliest message first. The chain ends
+/// at a message missing from history or after [MAX_THREAD_DEPTH] messages.
+pub async fn db_load_thread(group_id: i64, message_id: i32) -> PluginResult<Vec<GroupChatSegment>> {
+    let table_name = get_group_msg_table_name(group_id);
+    ensure_group_msg_table(&table_name).await?;
+    let mut thread = Vec::new();
+    let mut visited = HashSet::new();
+    let mut next = Some(message_id);
+    while let Some(id) = next {
+        // replies never form a cycle unless message ids are reused
+        if thread.len() >= MAX_THREAD_DEPTH || !visited.insert(id) {
+            break;
+        }
+        let segs = db_find_segment_by_id(group_id, id).await?;
+        if segs.is_empty() {
+            break;
+        }
+        next = segs
+            .iter()
+            .find(|s| s.seg_type == "reply")
+            .and_then(|s| s.content.parse().ok());
+        thread.push(segs);
+    }
+    thread.reverse();
+    Ok(thread.into_iter().flatten().collect())
+}
+
+fn get_group_msg_table_name(group_id: i64) -> String {
+    let config = CONFIG.get().unwrap();
+    let prefix = &config.database.group_table_prefix;
+    format!("{}{}", prefix, group_id)
+}
+
+use sql_query::*;
+mod sql_query {
+    use crate::CONFIG;
+    use indoc::{formatdoc, indoc};
+
+    const CREATE_TABLE_IF_NOT_EXISTS: &str = "CREATE TABLE IF NOT EXISTS";
+    const CREATE_INDEX_IF_NOT_EXISTS: &str = "CREATE INDEX IF NOT EXISTS";
+    const INSERT_INTO: &str = "INSERT INTO";
+    const ERROR_STAT_TABLE: &str = "error_stat";
+    const PRIVATE_MSG_TABLE: &str = "private_message";
+    const LIVE_SUBSCRIPTION_TABLE: &str = "live_subscription";
+    const LIVE_STATE_TABLE: &str = "live_state";
+    const LIVE_SESSION_TABLE: &str = "live_session";
+    const GUILD_MESSAGE_TABLE: &str = "guild_message";
+    const VERIFICATION_TABLE: &str = "member_verification";
+    const POINTS_LEDGER_TABLE: &str = "points_ledger";
+    const SIGN_IN_TABLE: &str = "sign_in";
+    const LEVEL_TABLE: &str = "levels";
+    const WORD_STRIKE_TABLE: &str = "word_strike";
+    const REMINDER_TABLE: &str = "reminder";
+    const IDIOM_GAME_TABLE: &str = "idiom_game";
+    const IDIOM_SCORE_TABLE: &str = "idiom_score";
+    const FEED_SUBSCRIPTION_TABLE: &str = "feed_subscription";
+    const FEED_SEEN_TABLE: &str = "feed_seen";
+    const ANNOUNCEMENT_TABLE: &str = "announcement";
+    const MENTION_TABLE: &str = "mention";
+    const SPEND_TABLE: &str = "openai_spend";
+    const AB_REPLY_TABLE: &str = "ab_reply";
+    const AGENT_REPLY_TABLE: &str = "agent_reply";
+    const FEEDBACK_TABLE: &str = "feedback";
+    const PENDING_REPLY_TABLE: &str = "pending_reply";
+    const STICKER_TABLE: &str = "sticker";
+    const IMAGE_HASH_TABLE: &str = "image_hash";
+    const QUOTA_TABLE: &str = "quota";
+    const FEATURE_USAGE_TABLE: &str = "feature_usage";
+    const DB_CHECK_TABLE: &str = "db_check";
+    const RUN_HISTORY_TABLE: &str = "run_history";
+    const REACTION_TABLE: &str = "reaction";
+    const GROUP_MSG_SCHEMA: &str = indoc!(
+        "
+        (
+            auto_id INTEGER PRIMARY KEY,
+            message_id INTEGER,
+            time TEXT,
+            sender_id INTEGER,
+            sender_name TEXT,
+            type TEXT,
+            content TEXT,
+            interpret TEXT
+        )
+        "
+    );
+    pub const INSERT_GROUP_MSG_SCHEMA: &str = indoc!(
+        "
+        (message_id, time, sender_id, sender_name, type, content, interpret)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "
+    );
+
+    pub fn create_log_table() -> String {
+        let config = CONFIG.get().unwrap();
+        let table_name = &config.database.log_table_name;
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {table_name}(
+                auto_id INTEGER PRIMARY KEY,
+                time TEXT,
+                level TEXT,
+                content TEXT
+            );
+            {CREATE_INDEX_IF_NOT_EXISTS} log_time
+            ON {table_name}(time);
+            "
+        )
+    }
+
+    pub fn insert_log() -> String {
         let config = CONFIG.get().unwrap();
         let table_name = &config.database.log_table_name;
         formatdoc!(
             "
-            INSERT INTO {table_name} (time, level, content)
-            VALUES($1, $2, $3);
+            INSERT INTO {table_name} (time, level, content)
+            VALUES($1, $2, $3);
+            "
+        )
+    }
+
+    pub fn create_error_stat_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {ERROR_STAT_TABLE}(
+                day TEXT,
+                hash TEXT,
+                template TEXT,
+                count INTEGER,
+                last_content TEXT,
+                PRIMARY KEY (day, hash)
+            );
+            "
+        )
+    }
+
+    pub fn upsert_error_stat() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {ERROR_STAT_TABLE} (day, hash, template, count, last_content)
+            VALUES ($1, $2, $3, 1, $4)
+            ON CONFLICT(day, hash) DO UPDATE SET
+                count = count + 1,
+                last_content = excluded.last_content;
+            "
+        )
+    }
+
+    pub fn load_error_stat() -> String {
+        formatdoc!(
+            "
+            SELECT
+                day,
+                template,
+                count,
+                last_content
+            FROM {ERROR_STAT_TABLE}
+            WHERE day = $1
+            ORDER BY count DESC;
+            "
+        )
+    }
+
+    pub fn create_private_msg_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {PRIVATE_MSG_TABLE}(
+                auto_id INTEGER PRIMARY KEY,
+                message_id INTEGER,
+                time TEXT,
+                user_id INTEGER,
+                role TEXT,
+                content TEXT
+            );
+            {CREATE_INDEX_IF_NOT_EXISTS} private_msg_user
+            ON {PRIVATE_MSG_TABLE}(user_id);
+            "
+        )
+    }
+
+    pub fn insert_private_msg() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {PRIVATE_MSG_TABLE} (message_id, time, user_id, role, content)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        )
+    }
+
+    pub fn load_n_latest_private_msg() -> String {
+        formatdoc!(
+            "
+            SELECT
+                message_id,
+                time,
+                user_id,
+                role,
+                content
+            FROM (
+                SELECT *
+                FROM {PRIVATE_MSG_TABLE}
+                WHERE user_id = $1
+                ORDER BY auto_id DESC
+                LIMIT $2
+            )
+            ORDER BY auto_id ASC;
+            "
+        )
+    }
+
+    pub fn create_live_subscription_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {LIVE_SUBSCRIPTION_TABLE}(
+                user_id INTEGER,
+                room_id TEXT,
+                time TEXT,
+                PRIMARY KEY (user_id, room_id)
+            );
+            "
+        )
+    }
+
+    pub fn insert_live_subscription() -> String {
+        formatdoc!(
+            "
+            INSERT OR IGNORE INTO {LIVE_SUBSCRIPTION_TABLE} (user_id, room_id, time)
+            VALUES ($1, $2, $3);
+            "
+        )
+    }
+
+    pub fn delete_live_subscription() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {LIVE_SUBSCRIPTION_TABLE}
+            WHERE user_id = $1 AND room_id = $2;
+            "
+        )
+    }
+
+    pub fn count_live_subscription() -> String {
+        formatdoc!(
+            "
+            SELECT COUNT(*)
+            FROM {LIVE_SUBSCRIPTION_TABLE}
+            WHERE user_id = $1;
+            "
+        )
+    }
+
+    pub fn load_live_subscriptions() -> String {
+        formatdoc!(
+            "
+            SELECT user_id, room_id
+            FROM {LIVE_SUBSCRIPTION_TABLE}
+            ORDER BY room_id;
+            "
+        )
+    }
+
+    pub fn load_room_subscribers() -> String {
+        formatdoc!(
+            "
+            SELECT user_id
+            FROM {LIVE_SUBSCRIPTION_TABLE}
+            WHERE room_id = $1;
+            "
+        )
+    }
+
+    pub fn create_live_state_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {LIVE_STATE_TABLE}(
+                group_id INTEGER,
+                room_id TEXT,
+                streaming INTEGER,
+                time TEXT,
+                PRIMARY KEY (group_id, room_id)
+            );
+            "
+        )
+    }
+
+    pub fn upsert_live_state() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {LIVE_STATE_TABLE} (group_id, room_id, streaming, time)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn load_live_states() -> String {
+        formatdoc!(
+            "
+            SELECT room_id, streaming
+            FROM {LIVE_STATE_TABLE}
+            WHERE group_id = $1;
+            "
+        )
+    }
+
+    pub fn create_live_session_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {LIVE_SESSION_TABLE}(
+                group_id INTEGER,
+                room_id TEXT,
+                title TEXT,
+                start_time TEXT,
+                end_time TEXT
+            );
+            {CREATE_INDEX_IF_NOT_EXISTS} live_session_start
+            ON {LIVE_SESSION_TABLE}(start_time);
+            "
+        )
+    }
+
+    /// At most one open session per room and poller.
+    pub fn open_live_session() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {LIVE_SESSION_TABLE} (group_id, room_id, title, start_time, end_time)
+            SELECT $1, $2, $3, $4, NULL
+            WHERE NOT EXISTS (
+                SELECT 1 FROM {LIVE_SESSION_TABLE}
+                WHERE group_id = $1 AND room_id = $2 AND end_time IS NULL
+            );
+            "
+        )
+    }
+
+    pub fn close_live_session() -> String {
+        formatdoc!(
+            "
+            UPDATE {LIVE_SESSION_TABLE}
+            SET end_time = $3
+            WHERE group_id = $1 AND room_id = $2 AND end_time IS NULL;
+            "
+        )
+    }
+
+    pub fn load_live_sessions() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, room_id, title, start_time, end_time
+            FROM {LIVE_SESSION_TABLE}
+            WHERE start_time < $2 AND (end_time IS NULL OR end_time >= $1)
+            ORDER BY start_time, group_id;
+            "
+        )
+    }
+
+    /// Message id of history rows is the negated `id`.
+    pub fn create_guild_message_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {GUILD_MESSAGE_TABLE}(
+                id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                guild_id TEXT,
+                channel_id TEXT,
+                guild_message_id TEXT
+            );
+            "
+        )
+    }
+
+    pub fn insert_guild_message() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {GUILD_MESSAGE_TABLE} (group_id, guild_id, channel_id, guild_message_id)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn create_verification_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {VERIFICATION_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                deadline INTEGER,
+                status TEXT,
+                PRIMARY KEY (group_id, user_id)
+            );
+            "
+        )
+    }
+
+    pub fn upsert_pending_verification() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {VERIFICATION_TABLE} (group_id, user_id, deadline, status)
+            VALUES ($1, $2, $3, 'pending');
+            "
+        )
+    }
+
+    pub fn find_pending_verification() -> String {
+        formatdoc!(
+            "
+            SELECT deadline
+            FROM {VERIFICATION_TABLE}
+            WHERE group_id = $1 AND user_id = $2 AND status = 'pending';
+            "
+        )
+    }
+
+    pub fn update_verification_status() -> String {
+        formatdoc!(
+            "
+            UPDATE {VERIFICATION_TABLE}
+            SET status = $1
+            WHERE group_id = $2 AND user_id = $3;
+            "
+        )
+    }
+
+    pub fn load_overdue_verifications() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, user_id, deadline
+            FROM {VERIFICATION_TABLE}
+            WHERE status = 'pending' AND deadline < $1;
+            "
+        )
+    }
+
+    pub fn create_points_tables() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {POINTS_LEDGER_TABLE}(
+                auto_id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                user_id INTEGER,
+                time TEXT,
+                delta INTEGER,
+                reason TEXT
+            );
+            {CREATE_INDEX_IF_NOT_EXISTS} points_ledger_member
+            ON {POINTS_LEDGER_TABLE}(group_id, user_id);
+            {CREATE_TABLE_IF_NOT_EXISTS} {SIGN_IN_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                last_day TEXT,
+                streak INTEGER,
+                PRIMARY KEY (group_id, user_id)
+            );
+            "
+        )
+    }
+
+    pub fn insert_points() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {POINTS_LEDGER_TABLE} (group_id, user_id, time, delta, reason)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        )
+    }
+
+    pub fn points_balance() -> String {
+        formatdoc!(
+            "
+            SELECT COALESCE(SUM(delta), 0)
+            FROM {POINTS_LEDGER_TABLE}
+            WHERE group_id = $1 AND user_id = $2;
+            "
+        )
+    }
+
+    pub fn points_of_day() -> String {
+        formatdoc!(
+            "
+            SELECT COALESCE(SUM(delta), 0)
+            FROM {POINTS_LEDGER_TABLE}
+            WHERE group_id = $1 AND user_id = $2 AND reason = $3 AND time LIKE $4;
+            "
+        )
+    }
+
+    pub fn points_leaderboard() -> String {
+        formatdoc!(
+            "
+            SELECT user_id, SUM(delta) AS points
+            FROM {POINTS_LEDGER_TABLE}
+            WHERE group_id = $1
+            GROUP BY user_id
+            ORDER BY points DESC
+            LIMIT $2;
+            "
+        )
+    }
+
+    pub fn load_sign_in() -> String {
+        formatdoc!(
+            "
+            SELECT last_day, streak
+            FROM {SIGN_IN_TABLE}
+            WHERE group_id = $1 AND user_id = $2;
+            "
+        )
+    }
+
+    pub fn upsert_sign_in() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {SIGN_IN_TABLE} (group_id, user_id, last_day, streak)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn create_level_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {LEVEL_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                xp INTEGER,
+                level INTEGER,
+                day TEXT,
+                day_xp INTEGER,
+                last_award INTEGER,
+                PRIMARY KEY (group_id, user_id)
+            );
+            "
+        )
+    }
+
+    pub fn load_level() -> String {
+        formatdoc!(
+            "
+            SELECT user_id, xp, level, day, day_xp, last_award
+            FROM {LEVEL_TABLE}
+            WHERE group_id = $1 AND user_id = $2;
+            "
+        )
+    }
+
+    pub fn upsert_level() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {LEVEL_TABLE}
+            (group_id, user_id, xp, level, day, day_xp, last_award)
+            VALUES ($1, $2, $3, $4, $5, $6, $7);
+            "
+        )
+    }
+
+    pub fn count_level_above() -> String {
+        formatdoc!(
+            "
+            SELECT COUNT(*)
+            FROM {LEVEL_TABLE}
+            WHERE group_id = $1 AND xp > $2;
+            "
+        )
+    }
+
+    pub fn level_ranking() -> String {
+        formatdoc!(
+            "
+            SELECT user_id, xp, level, day, day_xp, last_award
+            FROM {LEVEL_TABLE}
+            WHERE group_id = $1
+            ORDER BY xp DESC
+            LIMIT $2;
+            "
+        )
+    }
+
+    pub fn create_word_strike_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {WORD_STRIKE_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                strikes INTEGER,
+                last_time TEXT,
+                PRIMARY KEY (group_id, user_id)
+            );
+            "
+        )
+    }
+
+    pub fn upsert_word_strike() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {WORD_STRIKE_TABLE} (group_id, user_id, strikes, last_time)
+            VALUES ($1, $2, 1, $3)
+            ON CONFLICT(group_id, user_id) DO UPDATE SET
+                strikes = strikes + 1,
+                last_time = excluded.last_time;
+            "
+        )
+    }
+
+    pub fn load_word_strikes() -> String {
+        formatdoc!(
+            "
+            SELECT strikes
+            FROM {WORD_STRIKE_TABLE}
+            WHERE group_id = $1 AND user_id = $2;
+            "
+        )
+    }
+
+    pub fn create_reminder_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {REMINDER_TABLE}(
+                auto_id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                user_id INTEGER,
+                due INTEGER,
+                content TEXT
+            );
+            "
+        )
+    }
+
+    pub fn insert_reminder() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {REMINDER_TABLE} (group_id, user_id, due, content)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn count_reminders() -> String {
+        formatdoc!(
+            "
+            SELECT COUNT(*)
+            FROM {REMINDER_TABLE}
+            WHERE group_id = $1 AND user_id = $2;
+            "
+        )
+    }
+
+    pub fn load_due_reminders() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, user_id, due, content
+            FROM {REMINDER_TABLE}
+            WHERE due <= $1
+            ORDER BY due;
+            "
+        )
+    }
+
+    pub fn delete_due_reminders() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {REMINDER_TABLE}
+            WHERE due <= $1;
+            "
+        )
+    }
+
+    pub fn create_idiom_tables() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {IDIOM_GAME_TABLE}(
+                group_id INTEGER PRIMARY KEY,
+                current TEXT,
+                used TEXT,
+                last_player INTEGER
+            );
+            {CREATE_TABLE_IF_NOT_EXISTS} {IDIOM_SCORE_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                score INTEGER,
+                PRIMARY KEY (group_id, user_id)
+            );
+            "
+        )
+    }
+
+    pub fn load_idiom_game() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, current, used, last_player
+            FROM {IDIOM_GAME_TABLE}
+            WHERE group_id = $1;
+            "
+        )
+    }
+
+    pub fn upsert_idiom_game() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {IDIOM_GAME_TABLE} (group_id, current, used, last_player)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn delete_idiom_game() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {IDIOM_GAME_TABLE} WHERE group_id = $1;
+            DELETE FROM {IDIOM_SCORE_TABLE} WHERE group_id = $1;
+            "
+        )
+    }
+
+    pub fn upsert_idiom_score() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {IDIOM_SCORE_TABLE} (group_id, user_id, score)
+            VALUES ($1, $2, 1)
+            ON CONFLICT(group_id, user_id) DO UPDATE SET score = score + 1;
+            "
+        )
+    }
+
+    pub fn load_idiom_scores() -> String {
+        formatdoc!(
+            "
+            SELECT user_id, score
+            FROM {IDIOM_SCORE_TABLE}
+            WHERE group_id = $1
+            ORDER BY score DESC;
+            "
+        )
+    }
+
+    pub fn create_feed_tables() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {FEED_SUBSCRIPTION_TABLE}(
+                group_id INTEGER,
+                url TEXT,
+                title TEXT,
+                interval_sec INTEGER,
+                next_poll INTEGER,
+                PRIMARY KEY (group_id, url)
+            );
+            {CREATE_TABLE_IF_NOT_EXISTS} {FEED_SEEN_TABLE}(
+                group_id INTEGER,
+                url TEXT,
+                entry_id TEXT,
+                PRIMARY KEY (group_id, url, entry_id)
+            );
+            "
+        )
+    }
+
+    pub fn create_announcement_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {ANNOUNCEMENT_TABLE}(
+                id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                sender_id INTEGER,
+                content TEXT,
+                time TEXT,
+                pinned INTEGER
+            );
+            "
+        )
+    }
+
+    pub fn insert_announcement() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {ANNOUNCEMENT_TABLE}
+            (group_id, sender_id, content, time, pinned)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        )
+    }
+
+    pub fn load_announcements() -> String {
+        formatdoc!(
+            "
+            SELECT id, group_id, sender_id, content, time, pinned
+            FROM {ANNOUNCEMENT_TABLE}
+            WHERE group_id = $1
+            ORDER BY id DESC
+            LIMIT $2;
+            "
+        )
+    }
+
+    pub fn create_mention_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {MENTION_TABLE}(
+                id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                sender_id INTEGER,
+                target_id INTEGER,
+                time TEXT
+            );
+            {CREATE_INDEX_IF_NOT_EXISTS} mention_group_time
+            ON {MENTION_TABLE}(group_id, time);
+            "
+        )
+    }
+
+    pub fn insert_mention() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {MENTION_TABLE}
+            (group_id, sender_id, target_id, time)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn top_mentioners() -> String {
+        formatdoc!(
+            "
+            SELECT sender_id, COUNT(*) AS count
+            FROM {MENTION_TABLE}
+            WHERE group_id = $1 AND target_id = $2 AND sender_id != $2 AND time >= $3
+            GROUP BY sender_id
+            ORDER BY count DESC, MAX(time) DESC
+            LIMIT $4;
+            "
+        )
+    }
+
+    pub fn top_mentioned() -> String {
+        formatdoc!(
+            "
+            SELECT target_id, COUNT(*) AS count
+            FROM {MENTION_TABLE}
+            WHERE group_id = $1 AND sender_id != target_id AND time >= $2
+            GROUP BY target_id
+            ORDER BY count DESC, MAX(time) DESC
+            LIMIT $3;
+            "
+        )
+    }
+
+    pub fn create_spend_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {SPEND_TABLE}(
+                month TEXT PRIMARY KEY,
+                tokens INTEGER,
+                cost REAL
+            );
+            "
+        )
+    }
+
+    pub fn upsert_spend() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {SPEND_TABLE} (month, tokens, cost)
+            VALUES ($1, $2, $3)
+            ON CONFLICT(month) DO UPDATE SET
+                tokens = tokens + excluded.tokens,
+                cost = cost + excluded.cost;
+            "
+        )
+    }
+
+    pub fn load_spend() -> String {
+        formatdoc!(
+            "
+            SELECT cost
+            FROM {SPEND_TABLE}
+            WHERE month = $1;
+            "
+        )
+    }
+
+    pub fn load_spend_range() -> String {
+        formatdoc!(
+            "
+            SELECT month, tokens, cost
+            FROM {SPEND_TABLE}
+            WHERE month >= $1 AND month <= $2
+            ORDER BY month;
+            "
+        )
+    }
+
+    /// Messages are told apart by id, those without one are counted by time.
+    pub fn count_messages_in_range(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT
+                sender_id,
+                MAX(sender_name) AS sender_name,
+                COUNT(DISTINCT CASE WHEN message_id != 0 THEN message_id ELSE time END) AS messages
+            FROM {table_name}
+            WHERE time >= $1 AND time < $2 AND sender_name != 'RECALL_INDICATOR'
+            GROUP BY sender_id
+            ORDER BY messages DESC, sender_id;
+            "
+        )
+    }
+
+    pub fn create_reaction_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {REACTION_TABLE}(
+                group_id INTEGER,
+                message_id INTEGER,
+                user_id INTEGER,
+                emoji TEXT,
+                time TEXT,
+                PRIMARY KEY (group_id, message_id, user_id, emoji)
+            );
+            "
+        )
+    }
+
+    pub fn insert_reaction() -> String {
+        formatdoc!(
+            "
+            INSERT OR IGNORE INTO {REACTION_TABLE} (group_id, message_id, user_id, emoji, time)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        )
+    }
+
+    pub fn delete_reaction() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {REACTION_TABLE}
+            WHERE group_id = $1 AND message_id = $2 AND user_id = $3 AND emoji = $4;
+            "
+        )
+    }
+
+    pub fn load_reaction_counts() -> String {
+        formatdoc!(
+            "
+            SELECT message_id, COUNT(*)
+            FROM {REACTION_TABLE}
+            WHERE group_id = $1 AND time >= $2
+            GROUP BY message_id;
+            "
+        )
+    }
+
+    pub fn create_ab_reply_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {AB_REPLY_TABLE}(
+                id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                variant TEXT,
+                asker_id INTEGER,
+                time TEXT
+            );
+            "
+        )
+    }
+
+    pub fn insert_ab_reply() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {AB_REPLY_TABLE} (group_id, variant, asker_id, time)
+            VALUES ($1, $2, $3, $4);
+            "
+        )
+    }
+
+    pub fn create_agent_reply_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {AGENT_REPLY_TABLE}(
+                group_id INTEGER,
+                message_id INTEGER,
+                time TEXT,
+                asker_id INTEGER,
+                persona TEXT,
+                model TEXT,
+                prompt TEXT,
+                response TEXT,
+                PRIMARY KEY (group_id, message_id)
+            );
+            "
+        )
+    }
+
+    pub fn insert_agent_reply() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {AGENT_REPLY_TABLE}
+            (group_id, message_id, time, asker_id, persona, model, prompt, response)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8);
+            "
+        )
+    }
+
+    pub fn create_feedback_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {FEEDBACK_TABLE}(
+                group_id INTEGER,
+                message_id INTEGER,
+                user_id INTEGER,
+                good INTEGER,
+                time TEXT,
+                PRIMARY KEY (group_id, message_id, user_id)
+            );
+            "
+        )
+    }
+
+    /// Nothing is inserted unless the message is a recorded reply.
+    pub fn upsert_feedback() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {FEEDBACK_TABLE} (group_id, message_id, user_id, good, time)
+            SELECT $1, $2, $3, $4, $5
+            WHERE EXISTS (
+                SELECT 1 FROM {AGENT_REPLY_TABLE} WHERE group_id = $1 AND message_id = $2
+            )
+            ON CONFLICT(group_id, message_id, user_id) DO UPDATE SET
+                good = excluded.good,
+                time = excluded.time;
+            "
+        )
+    }
+
+    pub fn create_pending_reply_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {PENDING_REPLY_TABLE}(
+                id INTEGER PRIMARY KEY,
+                group_id INTEGER,
+                message_id INTEGER,
+                asker_id INTEGER,
+                persona TEXT,
+                model TEXT,
+                prompt TEXT,
+                response TEXT,
+                time TEXT,
+                status TEXT DEFAULT 'pending',
+                posted TEXT
+            );
+            "
+        )
+    }
+
+    pub fn insert_pending_reply() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {PENDING_REPLY_TABLE}
+            (group_id, message_id, asker_id, persona, model, prompt, response, time)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8);
+            "
+        )
+    }
+
+    pub fn load_pending_reply() -> String {
+        formatdoc!(
+            "
+            SELECT id, group_id, message_id, asker_id, persona, model, prompt, response, time
+            FROM {PENDING_REPLY_TABLE}
+            WHERE id = $1;
+            "
+        )
+    }
+
+    pub fn resolve_pending_reply() -> String {
+        formatdoc!(
+            "
+            UPDATE {PENDING_REPLY_TABLE} SET status = $2, posted = $3
+            WHERE id = $1 AND status = 'pending';
+            "
+        )
+    }
+
+    pub fn create_sticker_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {STICKER_TABLE}(
+                group_id INTEGER,
+                tag TEXT,
+                path TEXT,
+                url TEXT,
+                adder_id INTEGER,
+                time TEXT,
+                PRIMARY KEY (group_id, tag)
+            );
+            "
+        )
+    }
+
+    pub fn insert_sticker() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {STICKER_TABLE} (group_id, tag, path, url, adder_id, time)
+            VALUES ($1, $2, $3, $4, $5, $6);
+            "
+        )
+    }
+
+    pub fn delete_sticker() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {STICKER_TABLE} WHERE group_id = $1 AND tag = $2;
+            "
+        )
+    }
+
+    pub fn load_stickers() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, tag, path, url, adder_id, time
+            FROM {STICKER_TABLE}
+            WHERE group_id = $1
+            ORDER BY tag;
+            "
+        )
+    }
+
+    pub fn find_sticker() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, tag, path, url, adder_id, time
+            FROM {STICKER_TABLE}
+            WHERE group_id = $1 AND tag = $2;
+            "
+        )
+    }
+
+    pub fn create_image_hash_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {IMAGE_HASH_TABLE}(
+                group_id INTEGER,
+                hash TEXT,
+                message_id INTEGER,
+                sender_id INTEGER,
+                sender_name TEXT,
+                time TEXT,
+                PRIMARY KEY (group_id, hash)
+            );
+            "
+        )
+    }
+
+    pub fn insert_image_post() -> String {
+        formatdoc!(
+            "
+            INSERT OR REPLACE INTO {IMAGE_HASH_TABLE}
+            (group_id, hash, message_id, sender_id, sender_name, time)
+            VALUES ($1, $2, $3, $4, $5, $6);
+            "
+        )
+    }
+
+    pub fn find_image_post() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, hash, message_id, sender_id, sender_name, time
+            FROM {IMAGE_HASH_TABLE}
+            WHERE group_id = $1 AND hash = $2;
+            "
+        )
+    }
+
+    pub fn create_quota_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {QUOTA_TABLE}(
+                group_id INTEGER,
+                user_id INTEGER,
+                kind TEXT,
+                day TEXT,
+                used INTEGER,
+                extra INTEGER,
+                PRIMARY KEY (group_id, user_id, kind, day)
+            );
+            "
+        )
+    }
+
+    pub fn load_quota() -> String {
+        formatdoc!(
+            "
+            SELECT used, extra
+            FROM {QUOTA_TABLE}
+            WHERE group_id = $1 AND user_id = $2 AND kind = $3 AND day = $4;
+            "
+        )
+    }
+
+    pub fn add_quota() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {QUOTA_TABLE} (group_id, user_id, kind, day, used, extra)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT(group_id, user_id, kind, day) DO UPDATE SET
+                used = used + excluded.used,
+                extra = extra + excluded.extra;
+            "
+        )
+    }
+
+    pub fn create_feature_usage_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {FEATURE_USAGE_TABLE}(
+                group_id INTEGER,
+                day TEXT,
+                feature TEXT,
+                count INTEGER,
+                PRIMARY KEY (group_id, day, feature)
+            );
+            "
+        )
+    }
+
+    pub fn add_feature_use() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {FEATURE_USAGE_TABLE} (group_id, day, feature, count)
+            VALUES ($1, $2, $3, 1)
+            ON CONFLICT(group_id, day, feature) DO UPDATE SET count = count + 1;
+            "
+        )
+    }
+
+    pub fn feature_usage_since() -> String {
+        formatdoc!(
+            "
+            SELECT feature, SUM(count) AS total
+            FROM {FEATURE_USAGE_TABLE}
+            WHERE group_id = $1 AND day >= $2
+            GROUP BY feature
+            ORDER BY total DESC, feature;
+            "
+        )
+    }
+
+    pub fn create_db_check_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {DB_CHECK_TABLE}(
+                id INTEGER PRIMARY KEY,
+                time TEXT,
+                ok INTEGER,
+                detail TEXT
+            );
+            "
+        )
+    }
+
+    pub fn insert_db_check() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {DB_CHECK_TABLE} (time, ok, detail)
+            VALUES ($1, $2, $3);
+            "
+        )
+    }
+
+    pub fn load_last_db_check() -> String {
+        formatdoc!(
+            "
+            SELECT time, ok, detail
+            FROM {DB_CHECK_TABLE}
+            ORDER BY id DESC
+            LIMIT 1;
+            "
+        )
+    }
+
+    pub fn create_run_history_table() -> String {
+        formatdoc!(
+            "
+            {CREATE_TABLE_IF_NOT_EXISTS} {RUN_HISTORY_TABLE}(
+                id INTEGER PRIMARY KEY,
+                start_time TEXT,
+                stop_time TEXT,
+                init_error TEXT
+            );
+            "
+        )
+    }
+
+    /// Failed starts stop at once, so they are never taken as still running.
+    pub fn insert_run() -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {RUN_HISTORY_TABLE} (start_time, stop_time, init_error)
+            VALUES ($1, CASE WHEN $2 IS NULL THEN NULL ELSE $1 END, $2);
+            "
+        )
+    }
+
+    pub fn stop_run() -> String {
+        formatdoc!(
+            "
+            UPDATE {RUN_HISTORY_TABLE}
+            SET stop_time = $1
+            WHERE id = $2;
+            "
+        )
+    }
+
+    /// Runs without stop time were killed or crashed, failed starts are unclean too.
+    pub fn load_run_stats() -> String {
+        formatdoc!(
+            "
+            SELECT
+                COUNT(*),
+                COALESCE(SUM(
+                    CASE WHEN id != $2 AND (stop_time IS NULL OR init_error IS NOT NULL)
+                    THEN 1 ELSE 0 END
+                ), 0)
+            FROM {RUN_HISTORY_TABLE}
+            WHERE start_time >= $1;
+            "
+        )
+    }
+
+    pub fn load_last_init_error() -> String {
+        formatdoc!(
+            "
+            SELECT start_time, init_error
+            FROM {RUN_HISTORY_TABLE}
+            WHERE init_error IS NOT NULL
+            ORDER BY id DESC
+            LIMIT 1;
+            "
+        )
+    }
+
+    pub fn load_feedback_stats() -> String {
+        formatdoc!(
+            "
+            SELECT
+                r.persona AS persona,
+                r.model AS model,
+                COUNT(DISTINCT r.message_id) AS replies,
+                COALESCE(SUM(f.good), 0) AS good,
+                COUNT(f.good) - COALESCE(SUM(f.good), 0) AS bad
+            FROM {AGENT_REPLY_TABLE} r
+            LEFT JOIN {FEEDBACK_TABLE} f
+                ON f.group_id = r.group_id AND f.message_id = r.message_id
+            WHERE r.group_id = $1
+            GROUP BY r.persona, r.model
+            ORDER BY CAST(bad AS REAL) / MAX(good + bad, 1) DESC, r.persona, r.model;
+            "
+        )
+    }
+
+    /// Follow-ups are messages of members other than the bot, asker rate is the fraction of replies
+    /// after which the asker speaks again.
+    pub fn load_ab_stats(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT
+                r.variant AS variant,
+                COUNT(*) AS replies,
+                AVG((
+                    SELECT COUNT(DISTINCT m.message_id)
+                    FROM {table_name} m
+                    WHERE m.time > r.time AND m.time <= datetime(r.time, $2) AND m.sender_id != $3
+                )) AS follow_ups,
+                AVG(EXISTS (
+                    SELECT 1
+                    FROM {table_name} m
+                    WHERE m.time > r.time AND m.time <= datetime(r.time, $2)
+                        AND m.sender_id = r.asker_id
+                )) AS asker_rate
+            FROM {AB_REPLY_TABLE} r
+            WHERE r.group_id = $1
+            GROUP BY r.variant
+            ORDER BY r.variant;
+            "
+        )
+    }
+
+    pub fn insert_feed_subscription() -> String {
+        formatdoc!(
+            "
+            INSERT OR IGNORE INTO {FEED_SUBSCRIPTION_TABLE}
+            (group_id, url, title, interval_sec, next_poll)
+            VALUES ($1, $2, $3, $4, $5);
+            "
+        )
+    }
+
+    pub fn delete_feed_subscription() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {FEED_SUBSCRIPTION_TABLE}
+            WHERE group_id = $1 AND url = $2;
+            "
+        )
+    }
+
+    pub fn delete_feed_seen() -> String {
+        formatdoc!(
+            "
+            DELETE FROM {FEED_SEEN_TABLE}
+            WHERE group_id = $1 AND url = $2;
+            "
+        )
+    }
+
+    pub fn load_feed_subscriptions() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, url, title, interval_sec, next_poll
+            FROM {FEED_SUBSCRIPTION_TABLE}
+            WHERE group_id = $1
+            ORDER BY url;
+            "
+        )
+    }
+
+    pub fn load_due_feeds() -> String {
+        formatdoc!(
+            "
+            SELECT group_id, url, title, interval_sec, next_poll
+            FROM {FEED_SUBSCRIPTION_TABLE}
+            WHERE next_poll <= $1;
+            "
+        )
+    }
+
+    pub fn update_feed_next_poll() -> String {
+        formatdoc!(
+            "
+            UPDATE {FEED_SUBSCRIPTION_TABLE}
+            SET next_poll = $3
+            WHERE group_id = $1 AND url = $2;
+            "
+        )
+    }
+
+    pub fn insert_feed_seen() -> String {
+        formatdoc!(
+            "
+            INSERT OR IGNORE INTO {FEED_SEEN_TABLE} (group_id, url, entry_id)
+            VALUES ($1, $2, $3);
             "
         )
     }
@@ -308,10 +3541,236 @@ mod sql_query {
         )
     }
 
+    /// Text and OCR segments are indexed by trigram, which matches substring of CJK text. The
+    /// trigger is recreated, as older ones also indexed compressed texts.
+    pub fn create_group_msg_fts(table_name: &str) -> String {
+        formatdoc!(
+            "
+            CREATE VIRTUAL TABLE IF NOT EXISTS {table_name}_fts
+            USING fts5(content, tokenize = 'trigram');
+            DROP TRIGGER IF EXISTS {table_name}_fts_insert;
+            CREATE TRIGGER {table_name}_fts_insert
+            AFTER INSERT ON {table_name}
+            WHEN new.type = 'ocr' OR (new.type = 'text' AND typeof(new.content) = 'text')
+            BEGIN
+                INSERT INTO {table_name}_fts (rowid, content)
+                VALUES (
+                    new.auto_id,
+                    CASE new.type WHEN 'ocr' THEN new.interpret ELSE new.content END
+                );
+            END;
+            INSERT INTO {table_name}_fts (rowid, content)
+            SELECT auto_id, CASE type WHEN 'ocr' THEN interpret ELSE content END
+            FROM {table_name}
+            WHERE (type = 'ocr' OR (type = 'text' AND typeof(content) = 'text'))
+            AND auto_id NOT IN (SELECT rowid FROM {table_name}_fts);
+            "
+        )
+    }
+
+    /// Compressed texts are indexed by the caller.
+    pub fn index_group_msg_fts(table_name: &str) -> String {
+        format!("INSERT INTO {table_name}_fts (rowid, content) VALUES ($1, $2);")
+    }
+
+    /// Texts are matched in the index, which keeps compressed ones as text.
+    pub fn search_fts_condition(table_name: &str) -> String {
+        formatdoc!(
+            "
+            type = 'text'
+            AND auto_id IN (
+                SELECT rowid FROM {table_name}_fts
+                WHERE {table_name}_fts MATCH $1 AND content NOT LIKE $2
+            )
+            AND sender_id != $3
+            "
+        )
+    }
+
+    /// Scans the index, for keywords too short for trigrams.
+    pub fn search_instr_condition(table_name: &str) -> String {
+        formatdoc!(
+            "
+            type = 'text'
+            AND auto_id IN (
+                SELECT rowid FROM {table_name}_fts
+                WHERE instr(content, $1) > 0 AND content NOT LIKE $2
+            )
+            AND sender_id != $3
+            "
+        )
+    }
+
+    pub fn count_search_group_msg(table_name: &str, condition: &str) -> String {
+        formatdoc!(
+            "
+            SELECT COUNT(*)
+            FROM {table_name}
+            WHERE {condition};
+            "
+        )
+    }
+
+    pub fn search_group_msg(table_name: &str, condition: &str, order: &str) -> String {
+        formatdoc!(
+            "
+            SELECT message_id, time, sender_id, sender_name, type, content, interpret
+            FROM {table_name}
+            WHERE {condition}
+            ORDER BY time {order}, auto_id {order}
+            LIMIT 1;
+            "
+        )
+    }
+
+    pub fn related_group_msg(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT m.message_id, m.time, m.sender_id, m.sender_name, m.type, m.content,
+                m.interpret
+            FROM {table_name}_fts f
+            JOIN {table_name} m ON m.auto_id = f.rowid
+            WHERE {table_name}_fts MATCH $1
+            AND m.content NOT LIKE $2
+            AND m.sender_id != $3
+            ORDER BY f.rank
+            LIMIT $4;
+            "
+        )
+    }
+
+    pub fn count_messages_by_year(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT sender_id, COUNT(DISTINCT message_id) AS count
+            FROM {table_name}
+            WHERE time LIKE $1 AND message_id != 0 AND sender_id != $2
+            GROUP BY sender_id
+            ORDER BY count DESC;
+            "
+        )
+    }
+
+    pub fn activity_by_hour(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT
+                CAST(strftime('%w', time) AS INTEGER) AS weekday,
+                CAST(substr(time, 12, 2) AS INTEGER) AS hour,
+                COUNT(DISTINCT CASE WHEN message_id != 0 THEN message_id ELSE time END) AS count
+            FROM {table_name}
+            WHERE time >= $1 AND sender_id != $2 AND sender_name != 'RECALL_INDICATOR'
+            GROUP BY weekday, hour;
+            "
+        )
+    }
+
+    pub fn most_active_hour(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT substr(time, 12, 2) AS hour, COUNT(DISTINCT message_id) AS count
+            FROM {table_name}
+            WHERE sender_id = $1 AND time LIKE $2
+            GROUP BY hour
+            ORDER BY count DESC
+            LIMIT 1;
+            "
+        )
+    }
+
+    pub fn most_at(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT MAX(interpret), COUNT(*) AS count
+            FROM {table_name}
+            WHERE sender_id = $1 AND time LIKE $2 AND type = 'at'
+            GROUP BY content
+            ORDER BY count DESC
+            LIMIT 1;
+            "
+        )
+    }
+
+    pub fn load_texts_by_year(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT content
+            FROM {table_name}
+            WHERE sender_id = $1 AND time LIKE $2 AND type = 'text';
+            "
+        )
+    }
+
     pub fn insert_group_msg(table_name: &str) -> String {
         format!("{INSERT_INTO} {table_name} {INSERT_GROUP_MSG_SCHEMA};")
     }
 
+    pub fn import_group_msg(table_name: &str) -> String {
+        formatdoc!(
+            "
+            {INSERT_INTO} {table_name}
+            (message_id, time, sender_id, sender_name, type, content, interpret)
+            SELECT $1, $2, $3, $4, $5, $6, $7
+            WHERE NOT EXISTS (
+                SELECT 1
+                FROM {table_name}
+                WHERE time = $2 AND sender_id = $3 AND content = $6
+            );
+            "
+        )
+    }
+
+    pub fn load_table_names() -> String {
+        formatdoc!(
+            "
+            SELECT name
+            FROM sqlite_master
+            WHERE type = 'table';
+            "
+        )
+    }
+
+    pub fn load_recent_images(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT message_id, time, sender_id, sender_name, type, content, interpret
+            FROM {table_name}
+            WHERE type = 'image'
+            ORDER BY auto_id DESC
+            LIMIT $1 OFFSET $2;
+            "
+        )
+    }
+
+    pub fn load_media(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT time, type, content, interpret
+            FROM {table_name}
+            WHERE type IN ('image', 'record')
+            ORDER BY auto_id;
+            "
+        )
+    }
+
+    pub fn load_msg_since(table_name: &str) -> String {
+        formatdoc!(
+            "
+            SELECT 
+                message_id, 
+                time, 
+                sender_id, 
+                sender_name, 
+                type, 
+                content, 
+                interpret
+            FROM {table_name}
+            WHERE time >= $1
+            ORDER BY time ASC;
+            "
+        )
+    }
+
     pub fn load_n_latest_msg(table_name: &str) -> String {
         formatdoc!(
             "
@@ -374,18 +3833,43 @@ mod sql_query {
     }
 }
 
-#[derive(FromRow, Debug)]
+/// File of an image or record segment, url is the upload result or empty.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MediaEntry {
+    pub group_id: i64,
+    pub time: String,
+    #[serde(rename = "type")]
+    pub media_type: String,
+    pub path: String,
+    pub url: String,
+}
+
+#[derive(Debug)]
 pub struct GroupChatSegment {
     pub message_id: i32,
     pub time: String,
     pub sender_id: i64,
     pub sender_name: String,
-    #[sqlx(rename = "type")]
     pub seg_type: String,
     pub content: String,
     pub interpret: String,
 }
 
+// content may be stored compressed
+impl<'r> FromRow<'r, SqliteRow> for GroupChatSegment {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            message_id: row.try_get("message_id")?,
+            time: row.try_get("time")?,
+            sender_id: row.try_get("sender_id")?,
+            sender_name: row.try_get("sender_name")?,
+            seg_type: row.try_get("type")?,
+            content: unpack(row.try_get("content")?),
+            interpret: row.try_get("interpret")?,
+        })
+    }
+}
+
 impl GroupChatSegment {
     pub async fn db_store(&self, group_id: i64) -> PluginResult<()> {
         db_write_group_msg(
@@ -401,3 +3885,184 @@ impl GroupChatSegment {
         .await
     }
 }
+
+#[derive(FromRow, Debug)]
+pub struct PrivateChatMessage {
+    pub message_id: i32,
+    pub time: String,
+    pub user_id: i64,
+    pub role: String,
+    pub content: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct ErrorStat {
+    pub day: String,
+    pub template: String,
+    pub count: i64,
+    pub last_content: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct AbStat {
+    pub variant: String,
+    pub replies: i64,
+    pub follow_ups: f64,
+    pub asker_rate: f64,
+}
+
+/// Answer of the agent waiting for review, `message_id` is the message it answers.
+#[derive(FromRow, Debug)]
+pub struct PendingReply {
+    pub id: i64,
+    pub group_id: i64,
+    pub message_id: i32,
+    pub asker_id: i64,
+    pub persona: String,
+    pub model: String,
+    pub prompt: String,
+    pub response: String,
+    pub time: String,
+}
+
+/// OpenAI usage of a month, `YYYY-MM`.
+#[derive(FromRow, Serialize, Debug)]
+pub struct SpendStat {
+    pub month: String,
+    pub tokens: i64,
+    pub cost: f64,
+}
+
+/// Streaming of a room watched by a group, `end_time` is None while ongoing.
+#[derive(FromRow, Serialize, Debug)]
+pub struct LiveSession {
+    pub group_id: i64,
+    pub room_id: String,
+    pub title: String,
+    pub start_time: String,
+    pub end_time: Option<String>,
+}
+
+#[derive(FromRow, Serialize, Debug)]
+pub struct MemberStat {
+    pub sender_id: i64,
+    pub sender_name: String,
+    pub messages: i64,
+}
+
+/// Result of a daily check, `detail` lists problems or the error that stopped the check.
+#[derive(FromRow, Debug)]
+pub struct DbCheck {
+    pub time: String,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Image of the group's history saved under a tag, `url` is the upload result or empty.
+#[derive(FromRow, Debug)]
+pub struct Sticker {
+    pub group_id: i64,
+    pub tag: String,
+    pub path: String,
+    pub url: String,
+    pub adder_id: i64,
+    pub time: String,
+}
+
+/// Posting of an image in a group, `hash` is of the image bytes.
+#[derive(FromRow, Debug)]
+pub struct ImagePost {
+    pub group_id: i64,
+    pub hash: String,
+    pub message_id: i32,
+    pub sender_id: i64,
+    pub sender_name: String,
+    pub time: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct FeedbackStat {
+    pub persona: String,
+    pub model: String,
+    pub replies: i64,
+    pub good: i64,
+    pub bad: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct LiveSubscription {
+    pub user_id: i64,
+    pub room_id: String,
+}
+
+#[derive(FromRow, Debug)]
+pub struct MemberVerification {
+    pub group_id: i64,
+    pub user_id: i64,
+    pub deadline: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct PointsRank {
+    pub user_id: i64,
+    pub points: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct SignIn {
+    pub last_day: String,
+    pub streak: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct Reminder {
+    pub group_id: i64,
+    pub user_id: i64,
+    /// Unix timestamp.
+    pub due: i64,
+    pub content: String,
+}
+
+/// Experience of a member, `day_xp` is earned on `day` for the daily cap.
+#[derive(FromRow, Debug)]
+pub struct MemberLevel {
+    pub user_id: i64,
+    pub xp: i64,
+    pub level: i64,
+    pub day: String,
+    pub day_xp: i64,
+    /// Unix timestamp of the last award, for the cooldown.
+    pub last_award: i64,
+}
+
+/// State of an idiom solitaire game, `used` holds idioms separated by comma.
+#[derive(FromRow, Debug, Clone)]
+pub struct IdiomGame {
+    pub group_id: i64,
+    pub current: String,
+    pub used: String,
+    /// 0 if the bot starts the chain.
+    pub last_player: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct FeedSubscription {
+    pub group_id: i64,
+    pub url: String,
+    pub title: String,
+    pub interval_sec: i64,
+    /// Unix timestamp in seconds.
+    pub next_poll: i64,
+}
+
+#[derive(FromRow, Debug)]
+pub struct Announcement {
+    pub id: i64,
+    pub group_id: i64,
+    pub sender_id: i64,
+    pub content: String,
+    /// ISO8601 `YYYY-MM-DD HH:MM:SS`.
+    pub time: String,
+    /// Whether it is also posted as a group notice.
+    pub pinned: bool,
+}
@@ -0,0 +1,56 @@
+//! Optional speech to text of voice messages.
+
+use std::time::Duration;
+
+use kovi::tokio::time::timeout;
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    global_state::SttSetting,
+    http::{Body, Request},
+    std_error, util, CONFIG,
+};
+
+#[derive(serde::Deserialize, Debug)]
+struct SttResponse {
+    text: String,
+}
+
+/// Text of a record segment by its `file`, None if STT is disabled, failed, or nothing is heard.
+pub async fn transcribe(file: &str) -> Option<String> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no stt config
+    let stt = config.stt.as_ref()?;
+    let text = match timeout(Duration::from_secs(stt.timeout_sec), request(stt, file)).await {
+        Ok(Ok(text)) => text,
+        Ok(Err(err)) => {
+            std_error!("STT {file} failed: {err}");
+            return None;
+        }
+        Err(_) => {
+            std_error!("STT {file} timed out");
+            return None;
+        }
+    };
+    // recognizers tend to end a sentence with punctuation
+    let text = text
+        .trim()
+        .trim_end_matches(['。', '，', '！', '？', '.', ',', '!', '?'])
+        .to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+async fn request(stt: &SttSetting, file: &str) -> PluginResult<String> {
+    let api = util::get_record(file, "mp3")
+        .await
+        .map_err(|err| PluginError::PathNotAvailable(format!("record {file}: {err}")))?;
+    let Some(path) = api.data["file"].as_str() else {
+        return Err(PluginError::PathNotAvailable(format!("record {file}")));
+    };
+    let mut req = Request::post(&stt.endpoint).body(Body::File(path.to_string()));
+    if let Some(ref key) = stt.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp: SttResponse = req.send().await?.json()?;
+    Ok(resp.text)
+}
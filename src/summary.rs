@@ -0,0 +1,148 @@
+//! Summarize recent chat history on demand.
+
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    agent,
+    event::GroupMsg,
+    global_state::{AgentSetting, SummarySetting},
+    heat, std_db_error, std_info, store, tr, util, CONFIG,
+};
+
+/// `<trigger> [hours]` replies a summary of history in the last hours.
+pub async fn summary_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    // no-op if no summary or agent config
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    let Some(group) = groups.iter().find(|&g| g.id == group_id) else {
+        return;
+    };
+    let (Some(ref setting), Some(ref agent)) = (&group.summary, &group.agent) else {
+        return;
+    };
+    let Some(arg) = text.trim().strip_prefix(&setting.trigger) else {
+        return;
+    };
+    if agent.is_mute() || heat::is_heated(group_id) {
+        return;
+    }
+    let arg = arg.trim();
+    let hours = if arg.is_empty() {
+        setting.hours
+    } else {
+        match arg.parse::<i64>() {
+            Ok(hours) if (1..=setting.max_hours).contains(&hours) => hours,
+            _ => {
+                e.reply(tr!(
+                    group_id,
+                    "summary.usage",
+                    command = setting.trigger,
+                    max = setting.max_hours,
+                ))
+                .await;
+                return;
+            }
+        }
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let since = match util::iso8601_from_timestamp(Some(group_id), now - hours * 3600) {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Summary time error: {err}");
+            return;
+        }
+    };
+    let history = match store::db_load_group_segment_since(group_id, &since).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load summary history failed: {err}");
+            return;
+        }
+    };
+    let history = agent::format_history(&history);
+    if history.is_empty() {
+        e.reply(tr!(group_id, "summary.empty", hours = hours)).await;
+        return;
+    }
+    if let Some(summary) = summarize(agent, setting, &history).await {
+        e.reply(tr!(
+            group_id,
+            "summary.result",
+            hours = hours,
+            summary = summary.trim(),
+        ))
+        .await;
+    }
+}
+
+/// Summarize chunks separately if history is too long, then merge the partial summaries.
+async fn summarize(
+    agent: &AgentSetting,
+    setting: &SummarySetting,
+    history: &str,
+) -> Option<String> {
+    let model = agent.get_model().await;
+    let complete = |text: String| {
+        let model = model.clone();
+        async move {
+            agent::complete(
+                &agent.api_url,
+                &agent.api_key,
+                &model,
+                &setting.prompt,
+                &text,
+            )
+            .await
+        }
+    };
+    let chunks = chunk_lines(history, setting.chunk_chars);
+    if chunks.len() == 1 {
+        return complete(history.to_string()).await;
+    }
+    std_info!("Summarize history in {} chunks", chunks.len());
+    let mut partials = Vec::with_capacity(chunks.len());
+    for chunk in chunks {
+        partials.push(complete(chunk).await?);
+    }
+    let merged = partials
+        .iter()
+        .enumerate()
+        .map(|(i, p)| format!("第{}段总结:\n{p}", i + 1))
+        .collect::<Vec<_>>()
+        .join("\n");
+    complete(merged).await
+}
+
+/// Split by lines into chunks of at most `max_chars` characters, a longer line is a chunk itself.
+fn chunk_lines(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    let mut chars = 0;
+    for line in text.lines() {
+        let len = line.chars().count() + 1;
+        if chars + len > max_chars && !chunk.is_empty() {
+            chunks.push(std::mem::take(&mut chunk));
+            chars = 0;
+        }
+        chunk.push_str(line);
+        chunk.push('\n');
+        chars += len;
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
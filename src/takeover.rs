@@ -0,0 +1,188 @@
+//! Hold sensitive answers of the agent until a command admin reviews them in private chat.
+//!
+//! Pending answers are kept in database, so reviews still work after restart. The first review of an
+//! answer decides, later ones are told it has been reviewed.
+
+use std::sync::{Arc, OnceLock};
+
+use regex::Regex;
+
+use crate::{
+    agent::{self, GroupAnswer},
+    event::PrivateMsg,
+    global_state::{GroupSetting, TakeoverSetting},
+    std_db_error, std_db_info,
+    store::{self, PendingReply},
+    tr, util, ADMIN_QQ, CONFIG,
+};
+
+/// Whether the answer contains enough sensitive words to be reviewed.
+pub fn needs_review(setting: &TakeoverSetting, text: &str) -> bool {
+    let text = text.to_ascii_lowercase();
+    let hits = setting
+        .sensitive_words
+        .iter()
+        .filter(|w| !w.is_empty() && text.contains(&w.to_ascii_lowercase()))
+        .count();
+    hits >= setting.threshold
+}
+
+/// Hold an answer to message `message_id` and send it to command admins of the group.
+pub async fn submit(
+    group: &GroupSetting,
+    message_id: i32,
+    asker_id: i64,
+    prompt: &str,
+    answer: &GroupAnswer,
+) {
+    let Some(ref setting) = group.takeover else {
+        return;
+    };
+    let group_id = group.id;
+    let pending = PendingReply {
+        id: 0,
+        group_id,
+        message_id,
+        asker_id,
+        persona: answer.persona.clone(),
+        model: answer.model.clone(),
+        prompt: prompt.to_string(),
+        response: answer.text.clone(),
+        time: util::cur_time_iso8601(Some(group_id)),
+    };
+    let id = match store::db_add_pending_reply(&pending).await {
+        Ok(id) => id,
+        Err(err) => {
+            std_db_error!("Hold agent answer for review failed: {err}");
+            return;
+        }
+    };
+    let msg = tr!(
+        group_id,
+        "takeover.pending",
+        group = group_id,
+        id = id,
+        prompt = prompt,
+        response = answer.text,
+        approve = setting.approve,
+        reject = setting.reject,
+        edit = setting.edit,
+    );
+    for admin_id in reviewers(group).await {
+        util::send_private_msg(admin_id, msg.as_str());
+    }
+}
+
+/// Command admins of the group, or the main admin if there are none.
+async fn reviewers(group: &GroupSetting) -> Vec<i64> {
+    let admins = match group.command {
+        Some(ref command) => command.admins(group.id).await,
+        None => vec![],
+    };
+    if admins.is_empty() {
+        vec![*ADMIN_QQ.get().unwrap()]
+    } else {
+        admins
+    }
+}
+
+/// Review a pending answer by `<approve|reject> <id>` or `<edit> <id> <content>`.
+///
+/// Returns true if the message is taken as a review.
+pub async fn review_handler(e: Arc<PrivateMsg>) -> bool {
+    static REVIEW_REGEX: OnceLock<Regex> = OnceLock::new();
+    let Some(text) = e.borrow_text() else {
+        return false;
+    };
+    let regex = REVIEW_REGEX.get_or_init(|| {
+        Regex::new(r"(?s)^(?<action>\S+)\s+(?<id>\d+)(\s+(?<content>.+))?$").unwrap()
+    });
+    let Some(caps) = regex.captures(text.trim()) else {
+        return false;
+    };
+    let Ok(id) = caps["id"].parse::<i64>() else {
+        return false;
+    };
+    let pending = match store::db_load_pending_reply(id).await {
+        Ok(Some(pending)) => pending,
+        Ok(None) => return false,
+        Err(err) => {
+            std_db_error!("Load pending reply failed: {err}");
+            return false;
+        }
+    };
+    let group_id = pending.group_id;
+    let config = CONFIG.get().unwrap();
+    let group = config
+        .groups
+        .as_ref()
+        .and_then(|groups| groups.iter().find(|g| g.id == group_id));
+    let Some(group) = group else {
+        return false;
+    };
+    let Some(ref setting) = group.takeover else {
+        return false;
+    };
+    let action = &caps["action"];
+    let content = caps.name("content").map(|c| c.as_str().trim());
+    let (status, posted) = match (action, content) {
+        (a, None) if a == setting.approve => ("approved", Some(pending.response.as_str())),
+        (a, None) if a == setting.reject => ("rejected", None),
+        (a, Some(content)) if a == setting.edit => ("edited", Some(content)),
+        _ => return false,
+    };
+    let is_main_admin = e.user_id == *ADMIN_QQ.get().unwrap();
+    if !is_main_admin && !reviewers(group).await.contains(&e.user_id) {
+        return false;
+    }
+
+    match store::db_resolve_pending_reply(id, status, posted).await {
+        Ok(true) => {}
+        Ok(false) => {
+            e.reply(tr!(group_id, "takeover.reviewed", id = id));
+            return true;
+        }
+        Err(err) => {
+            std_db_error!("Resolve pending reply failed: {err}");
+            return true;
+        }
+    }
+    std_db_info!(
+        "Pending reply {id} of group {group_id} {status} by {}.",
+        e.user_id
+    );
+    if let Some(posted) = posted {
+        let answer = GroupAnswer {
+            text: posted.to_string(),
+            persona: pending.persona,
+            model: pending.model,
+        };
+        agent::post_answer(
+            group,
+            pending.message_id,
+            pending.asker_id,
+            &pending.prompt,
+            &answer,
+        )
+        .await;
+    }
+    e.reply(tr!(group_id, "takeover.done", id = id, status = action));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_review() {
+        let setting = TakeoverSetting::default();
+        assert!(needs_review(&setting, "我们不聊政治"));
+        assert!(!needs_review(&setting, "今天天气不错"));
+        let review_all = TakeoverSetting {
+            threshold: 0,
+            ..Default::default()
+        };
+        assert!(needs_review(&review_all, "今天天气不错"));
+    }
+}
@@ -0,0 +1,4227 @@
+//! Mock bot test harness.
+//!
+//! Available with `testing` feature and in unit tests of this crate. OneBot actions, live room and
+//! HTTP requests are served by [MockBot] through [MockApi], [MockLiveApi] and [MockHttp], injected
+//! in place of the real clients by [init], as are free disk space and exits for restart. Database
+//! is an in-memory SQLite.
+//!
+//! # Examples
+//! ```ignore
+//! testing::run(async {
+//!     testing::init();
+//!     let e = GroupMsgBuilder::new(1).sender(1234).text("禁用聊天回复").build();
+//!     command::act(e).await;
+//!     assert_eq!(testing::sent_to_group(1).len(), 1);
+//! });
+//! ```
+//!
+//! Pitfalls
+//! 1. Global states can be initialized only once per process, use a distinct group per test
+//! 2. Handlers must be driven by [run], database connections are bound to its runtime
+
+use kovi::{
+    tokio::runtime::{Builder, Runtime},
+    ApiReturn, Message, Segment,
+};
+use serde_json::{json, Value};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    path::Path,
+    sync::{
+        atomic::{AtomicI32, Ordering},
+        Arc, Mutex, MutexGuard, Once, OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    agent::{Answer, Choice, FunctionCall, GptResponse, ToolCall, Usage},
+    disk::{self, SpaceFuture, SpaceProbe},
+    event::{GroupMsg, PrivateMsg},
+    exception::{PluginError, PluginResult},
+    global_state::{self, Config, GroupSetting},
+    http::{self, Body, HttpClient, HttpFuture, Method, Request, Response},
+    live::{self, LiveApi, LiveFuture},
+    maintenance,
+    onebot::{ApiFuture, OneBotApi},
+    store, ADMIN_QQ, BOT_QQ, CONFIG, DATA_PATH, DB_POOL,
+};
+
+pub const BOT_ID: i64 = 10000;
+pub const ADMIN_ID: i64 = 10001;
+/// Groups 1 to [GROUP_COUNT] are configured with default settings.
+pub const GROUP_COUNT: i64 = 57;
+
+static MOCK: OnceLock<Mutex<MockBot>> = OnceLock::new();
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static INIT: Once = Once::new();
+
+/// Records everything the plugin sends, and serves canned API responses.
+#[derive(Default, Debug)]
+pub struct MockBot {
+    pub sent: Vec<Sent>,
+    /// (group_id, user_id) -> card
+    pub members: HashMap<(i64, i64), String>,
+    pub agent_answer: String,
+    /// keyword in prompts -> answer instead of `agent_answer`
+    pub agent_answers: HashMap<String, String>,
+    pub agent_payloads: Vec<Value>,
+    /// keyword in user prompt -> function the agent calls before answering
+    pub agent_tool_calls: HashMap<String, String>,
+    /// room_id -> raw response of bilibili room api
+    pub live_rooms: HashMap<String, Value>,
+    /// (group_id, user_id) -> role, "member" if absent
+    pub roles: HashMap<(i64, i64), String>,
+    /// (group_id, user_id)
+    pub kicked: Vec<(i64, i64)>,
+    /// (group_id, user_id, duration_sec)
+    pub banned: Vec<(i64, i64, usize)>,
+    /// url -> feed document
+    pub feeds: HashMap<String, String>,
+    /// path of GitHub api -> response
+    pub github: HashMap<String, Value>,
+    /// url -> html
+    pub pages: HashMap<String, String>,
+    /// (group_id, content) of group notices
+    pub group_notices: Vec<(i64, String)>,
+    /// url -> image bytes
+    pub images: HashMap<String, Vec<u8>>,
+    /// url of [MockBot::images] or `file://` path -> safety score, matched by the image bytes, 0
+    /// if absent
+    pub image_scores: HashMap<String, f64>,
+    /// file of record segment in [MockBot::media] -> transcript
+    pub transcripts: HashMap<String, String>,
+    /// local image path -> caption
+    pub captions: HashMap<String, String>,
+    /// group_id -> data of `get_group_root_files`, failed if absent
+    pub group_files: HashMap<i64, Value>,
+    /// user_id -> QQ level, unknown if absent
+    pub levels: HashMap<i64, i64>,
+    /// (flag, approve) of answered join requests
+    pub join_answers: Vec<(String, bool)>,
+    /// free bytes of the data path, plenty if absent
+    pub free_space: Option<u64>,
+    /// (message_id, emoji_id) of reactions
+    pub emoji_likes: Vec<(i32, String)>,
+    /// urls of OpenAI endpoints failing requests
+    pub down_urls: HashSet<String>,
+    /// response of crates.io api for this crate, failed if absent
+    pub crate_info: Option<Value>,
+    /// times the plugin exited for restart
+    pub restarts: usize,
+    /// local paths of downloadable images and records by file id
+    pub media: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Group(i64),
+    Private(i64),
+    /// (guild_id, channel_id)
+    Channel(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Sent {
+    pub target: Target,
+    pub message: Message,
+}
+
+pub fn mock() -> MutexGuard<'static, MockBot> {
+    let mock = MOCK.get_or_init(|| {
+        Mutex::new(MockBot {
+            agent_answer: String::from("mock answer"),
+            ..Default::default()
+        })
+    });
+    mock.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Block on a future with the runtime shared by all tests.
+pub fn run<F: Future>(future: F) -> F::Output {
+    let runtime = RUNTIME.get_or_init(|| {
+        Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("build test runtime")
+    });
+    runtime.block_on(future)
+}
+
+/// Initialize global states with groups 1 to [GROUP_COUNT], each has default settings and
+/// [ADMIN_ID] as command admin.
+pub fn init() {
+    let mut config = Config {
+        object_storage: None,
+        error_digest: None,
+        ..Default::default()
+    };
+    config.global.max_sleep_sec = 0;
+    config.global.analytics = Some(true);
+    let groups = (1..=GROUP_COUNT).map(|id| {
+        let mut group = GroupSetting {
+            id,
+            ..Default::default()
+        };
+        if let Some(ref mut command) = group.command {
+            command.admin_ids.push(ADMIN_ID);
+        }
+        // answers are sent at once, outbox is tested by holding explicitly
+        if let Some(ref mut outbox) = group.outbox {
+            outbox.delay_sec = 0;
+        }
+        // each group handles its own channel
+        if let Some(ref mut guild) = group.guild {
+            for channel in guild.channels.iter_mut() {
+                channel.channel_id = id.to_string();
+            }
+        }
+        // answers are taken as they are, language is tested explicitly
+        if let Some(ref mut agent) = group.agent {
+            agent.language = None;
+        }
+        group
+    });
+    config.groups = Some(groups.collect());
+    init_with(config);
+}
+
+/// Initialize global states with given config. Only the first call in a process takes effect.
+pub fn init_with(config: Config) {
+    INIT.call_once(|| {
+        run(async move {
+            let mut config = config;
+            global_state::prepare_config(&mut config).await;
+            let data_path = std::env::temp_dir().join("momo-bot-testing");
+            std::fs::create_dir_all(&data_path).expect("create test data path");
+            let _ = DATA_PATH.set(data_path);
+            let _ = BOT_QQ.set(BOT_ID);
+            let _ = global_state::BOT.set(Arc::new(MockApi));
+            live::set_api(MockLiveApi);
+            http::set_client(MockHttp);
+            disk::set_probe(MockDisk);
+            maintenance::set_exit(record_restart);
+            let _ = ADMIN_QQ.set(ADMIN_ID);
+            let _ = CONFIG.set(config);
+
+            // a single connection that never expires keeps the in-memory database alive
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect("sqlite::memory:")
+                .await
+                .expect("connect in-memory database");
+            let _ = DB_POOL.set(pool);
+            store::init_predefined_tables()
+                .await
+                .expect("initialize pre-defined tables");
+            store::init_group_tables()
+                .await
+                .expect("initialize group tables");
+        });
+    });
+}
+
+/// Messages sent to a group so far.
+pub fn sent_to_group(group_id: i64) -> Vec<Message> {
+    sent_to(Target::Group(group_id))
+}
+
+/// Messages sent to a user privately so far.
+pub fn sent_to_private(user_id: i64) -> Vec<Message> {
+    sent_to(Target::Private(user_id))
+}
+
+/// Messages sent to a guild channel so far.
+pub fn sent_to_channel(guild_id: &str, channel_id: &str) -> Vec<Message> {
+    sent_to(Target::Channel(
+        guild_id.to_string(),
+        channel_id.to_string(),
+    ))
+}
+
+fn sent_to(target: Target) -> Vec<Message> {
+    mock()
+        .sent
+        .iter()
+        .filter(|s| s.target == target)
+        .map(|s| s.message.clone())
+        .collect()
+}
+
+/// Register a live room, raw response fields not provided are filled with defaults.
+pub fn set_liveroom(room_id: &str, is_streaming: bool, title: &str) {
+    let room = raw_liveroom(0, is_streaming, title);
+    mock().live_rooms.insert(room_id.to_string(), room);
+}
+
+fn raw_liveroom(code: i32, is_streaming: bool, title: &str) -> Value {
+    json!({
+        "code": code,
+        "data": {
+            "live_status": if is_streaming { 1 } else { 0 },
+            "online": 0,
+            "attention": 0,
+            "keyframe": "",
+            "user_cover": "",
+            "area_name": "",
+            "description": "",
+            "title": title,
+        }
+    })
+}
+
+/// Room api variants seen in the wild, by what they test.
+pub fn raw_liveroom_variant(variant: &str) -> Value {
+    match variant {
+        // risk control answering with a captcha voucher in place of room info
+        "v_voucher" => json!({
+            "code": 0,
+            "message": "0",
+            "data": { "v_voucher": "voucher_7d1c0a4e-0b5d-4bd8-9c8c-3c1e0e6b8f01" }
+        }),
+        "expired_login" => json!({ "code": -101, "message": "账号未登录", "data": {} }),
+        "risk_control" => json!({ "code": -352, "message": "风控校验失败", "data": {} }),
+        "missing_room" => json!({
+            "code": 1,
+            "msg": "未找到该房间",
+            "message": "未找到该房间",
+            "data": []
+        }),
+        "missing_keyframe" => {
+            let mut room = raw_liveroom(0, true, "无画面");
+            let data = room["data"].as_object_mut().unwrap();
+            data.remove("keyframe");
+            data.remove("user_cover");
+            room
+        }
+        _ => panic!("unknown live room variant {variant}"),
+    }
+}
+
+/// Serves OneBot actions of the plugin from [MockBot].
+pub struct MockApi;
+
+impl OneBotApi for MockApi {
+    fn send_api(&self, action: &str, params: Value) {
+        let _ = serve_action(action, &params);
+    }
+
+    fn send_api_return<'a>(&'a self, action: &'a str, params: Value) -> ApiFuture<'a> {
+        let res = serve_action(action, &params);
+        Box::pin(async move { res })
+    }
+}
+
+fn api_return(data: Option<Value>) -> Result<ApiReturn, ApiReturn> {
+    let ok = data.is_some();
+    let api = ApiReturn {
+        status: String::from(if ok { "ok" } else { "failed" }),
+        retcode: if ok { 0 } else { 100 },
+        data: data.unwrap_or(Value::Null),
+        echo: String::new(),
+    };
+    if ok {
+        Ok(api)
+    } else {
+        Err(api)
+    }
+}
+
+fn serve_action(action: &str, params: &Value) -> Result<ApiReturn, ApiReturn> {
+    let id = |key: &str| params[key].as_i64().unwrap_or_default();
+    let text = |key: &str| params[key].as_str().unwrap_or_default().to_string();
+    let message = |key: &str| serde_json::from_value(params[key].clone()).unwrap_or_default();
+    let mut mock = mock();
+    let data = match action {
+        "send_group_msg" | "send_group_forward_msg" => {
+            let key = if action == "send_group_msg" {
+                "message"
+            } else {
+                "messages"
+            };
+            mock.sent.push(Sent {
+                target: Target::Group(id("group_id")),
+                message: message(key),
+            });
+            // id is the number of messages sent so far
+            Some(json!({ "message_id": mock.sent.len() }))
+        }
+        "send_private_msg" | "send_private_forward_msg" => {
+            let key = if action == "send_private_msg" {
+                "message"
+            } else {
+                "messages"
+            };
+            mock.sent.push(Sent {
+                target: Target::Private(id("user_id")),
+                message: message(key),
+            });
+            Some(json!({ "message_id": mock.sent.len() }))
+        }
+        "send_guild_channel_msg" => {
+            mock.sent.push(Sent {
+                target: Target::Channel(text("guild_id"), text("channel_id")),
+                message: message("message"),
+            });
+            Some(json!({ "message_id": mock.sent.len().to_string() }))
+        }
+        "_send_group_notice" => {
+            mock.group_notices.push((id("group_id"), text("content")));
+            Some(Value::Null)
+        }
+        "set_group_kick" => {
+            mock.kicked.push((id("group_id"), id("user_id")));
+            Some(Value::Null)
+        }
+        "set_group_card" => {
+            let member = (id("group_id"), id("user_id"));
+            mock.members.insert(member, text("card"));
+            Some(Value::Null)
+        }
+        "set_group_ban" => {
+            let duration_sec = id("duration") as usize;
+            mock.banned
+                .push((id("group_id"), id("user_id"), duration_sec));
+            Some(Value::Null)
+        }
+        "set_msg_emoji_like" => {
+            let message_id = id("message_id") as i32;
+            mock.emoji_likes.push((message_id, text("emoji_id")));
+            Some(Value::Null)
+        }
+        "set_group_add_request" => {
+            let approve = params["approve"].as_bool().unwrap_or_default();
+            mock.join_answers.push((text("flag"), approve));
+            Some(Value::Null)
+        }
+        "get_stranger_info" => mock
+            .levels
+            .get(&id("user_id"))
+            .map(|level| json!({ "user_id": id("user_id"), "level": level })),
+        "get_group_member_info" => Some(member_info(&mock, id("group_id"), id("user_id"))),
+        "get_group_member_list" => Some(member_list(&mock, id("group_id"))),
+        "get_group_root_files" => mock.group_files.get(&id("group_id")).cloned(),
+        "get_image" | "get_record" => mock
+            .media
+            .get(&text("file"))
+            .map(|path| json!({ "file": path })),
+        _ => None,
+    };
+    api_return(data)
+}
+
+fn member_info(mock: &MockBot, group_id: i64, user_id: i64) -> Value {
+    let card = mock
+        .members
+        .get(&(group_id, user_id))
+        .cloned()
+        .unwrap_or_default();
+    let role = mock
+        .roles
+        .get(&(group_id, user_id))
+        .cloned()
+        .unwrap_or_else(|| String::from("member"));
+    json!({
+        "group_id": group_id,
+        "user_id": user_id,
+        "nickname": "",
+        "card": card,
+        "sex": "unknown",
+        "age": 0,
+        "area": "",
+        "join_time": 0,
+        "last_sent_time": 0,
+        "level": "1",
+        "role": role,
+        "unfriendly": false,
+        "title": "",
+        "title_expire_time": 0,
+        "card_changeable": false,
+    })
+}
+
+fn member_list(mock: &MockBot, group_id: i64) -> Value {
+    let members = mock.members.keys().chain(mock.roles.keys());
+    let user_ids: HashSet<i64> = members
+        .filter(|&&(g, _)| g == group_id)
+        .map(|&(_, u)| u)
+        .collect();
+    let list = user_ids
+        .into_iter()
+        .map(|user_id| member_info(mock, group_id, user_id))
+        .collect();
+    Value::Array(list)
+}
+
+fn agent_response(mock: &mut MockBot, payload: &Value) -> GptResponse {
+    mock.agent_payloads.push(payload.clone());
+    let messages = payload["messages"].as_array().cloned().unwrap_or_default();
+    // call once, then answer with the function result in messages
+    let answered = messages.iter().any(|m| m["role"] == "tool");
+    let prompt = messages
+        .iter()
+        .filter(|m| m["role"] == "user")
+        .filter_map(|m| m["content"].as_str())
+        .collect::<String>();
+    let tool_call = mock
+        .agent_tool_calls
+        .iter()
+        .find(|(keyword, _)| prompt.contains(keyword.as_str()))
+        .map(|(_, name)| name.clone())
+        .filter(|_| !answered && payload.get("tools").is_some());
+    let (message, finish_reason) = match tool_call {
+        Some(name) => {
+            let call = ToolCall {
+                id: String::from("call_mock"),
+                call_type: String::from("function"),
+                function: FunctionCall {
+                    name,
+                    arguments: String::from("{}"),
+                },
+            };
+            let message = Answer {
+                content: None,
+                tool_calls: vec![call],
+            };
+            (message, "tool_calls")
+        }
+        None => {
+            let answer = messages
+                .iter()
+                .filter_map(|m| m["content"].as_str())
+                .find_map(|content| {
+                    mock.agent_answers
+                        .iter()
+                        .find(|(keyword, _)| content.contains(keyword.as_str()))
+                })
+                .map_or(&mock.agent_answer, |(_, answer)| answer);
+            let message = Answer {
+                content: Some(answer.clone()),
+                tool_calls: vec![],
+            };
+            (message, "stop")
+        }
+    };
+    GptResponse {
+        id: String::from("mock"),
+        model: payload["model"].as_str().unwrap_or_default().to_string(),
+        choices: vec![Choice {
+            message,
+            finish_reason: String::from(finish_reason),
+        }],
+        usage: Usage::default(),
+    }
+}
+
+/// Live api answering with fixtures in [MockBot::live_rooms], a missing room otherwise.
+pub struct MockLiveApi;
+
+impl LiveApi for MockLiveApi {
+    fn get_info<'a>(&'a self, room_id: &'a str) -> LiveFuture<'a> {
+        let room = mock()
+            .live_rooms
+            .get(room_id)
+            .cloned()
+            .unwrap_or_else(|| raw_liveroom(1, false, ""));
+        Box::pin(async move { Ok(room) })
+    }
+}
+
+/// Serves HTTP requests of features from fixtures in [MockBot]: agent completions, GitHub and
+/// crates.io api, STT, caption and image safety endpoints of the config, then feeds, pages and
+/// images by url.
+pub struct MockHttp;
+
+impl HttpClient for MockHttp {
+    fn send(&self, request: Request) -> HttpFuture<'_> {
+        Box::pin(async move { serve_http(&request) })
+    }
+}
+
+fn serve_http(request: &Request) -> PluginResult<Response> {
+    let config = CONFIG.get().unwrap();
+    let url = request.url.as_str();
+    let endpoint_of = |endpoint: Option<&String>| endpoint.is_some_and(|e| e == url);
+    let mut mock = mock();
+    let not_found = || PluginError::PathNotAvailable(url.to_string());
+    let json = |value: Value| Response {
+        content_type: Some(String::from("application/json")),
+        body: value.to_string().into_bytes(),
+    };
+    if request.method == Method::Post {
+        let path = match request.body {
+            Some(Body::File(ref path)) => path.as_str(),
+            _ => "",
+        };
+        let bytes = match request.body {
+            Some(Body::Bytes(ref bytes)) => bytes.as_slice(),
+            _ => &[],
+        };
+        if endpoint_of(config.stt.as_ref().map(|s| &s.endpoint)) {
+            let (file, _) = mock
+                .media
+                .iter()
+                .find(|(_, p)| *p == path)
+                .ok_or_else(not_found)?;
+            let text = mock.transcripts.get(file).ok_or_else(not_found)?;
+            return Ok(json(json!({ "text": text })));
+        }
+        if endpoint_of(config.caption.as_ref().map(|c| &c.endpoint)) {
+            let text = mock.captions.get(path).ok_or_else(not_found)?;
+            return Ok(json(json!({ "text": text })));
+        }
+        if endpoint_of(config.image_safety.as_ref().map(|s| &s.endpoint)) {
+            let score = mock
+                .image_scores
+                .iter()
+                .find(|(src, _)| match src.strip_prefix("file://") {
+                    Some(path) => std::fs::read(path).is_ok_and(|b| b == bytes),
+                    None => mock.images.get(*src).is_some_and(|b| b == bytes),
+                })
+                .map_or(0.0, |(_, &score)| score);
+            return Ok(json(json!({ "score": score })));
+        }
+        if mock.down_urls.contains(url) {
+            return Err(PluginError::AgentRequest(format!("{url} is down")));
+        }
+        let payload: Value = serde_json::from_slice(bytes)?;
+        let response = agent_response(&mut mock, &payload);
+        return Ok(json(serde_json::to_value(response)?));
+    }
+    if let Some(path) = url.strip_prefix("https://api.github.com/") {
+        return Ok(json(mock.github.get(path).cloned().unwrap_or_default()));
+    }
+    if url.starts_with("https://crates.io/api/v1/crates/") {
+        return Ok(json(mock.crate_info.clone().ok_or_else(not_found)?));
+    }
+    let (content_type, body) = if let Some(feed) = mock.feeds.get(url) {
+        ("application/atom+xml", feed.clone().into_bytes())
+    } else if let Some(page) = mock.pages.get(url) {
+        ("text/html", page.clone().into_bytes())
+    } else if let Some(image) = mock.images.get(url) {
+        ("image/png", image.clone())
+    } else {
+        return Err(not_found());
+    };
+    Ok(Response {
+        content_type: Some(content_type.to_string()),
+        body,
+    })
+}
+
+/// Free space of [MockBot::free_space].
+pub struct MockDisk;
+
+impl SpaceProbe for MockDisk {
+    fn free_bytes<'a>(&'a self, _path: &'a Path) -> SpaceFuture<'a> {
+        let free = mock().free_space.unwrap_or(u64::MAX / 2);
+        Box::pin(async move { Ok(free) })
+    }
+}
+
+fn record_restart() {
+    mock().restarts += 1;
+}
+
+/// Builds a [GroupMsg] as if received from OneBot.
+pub struct GroupMsgBuilder {
+    group_id: i64,
+    message_id: i32,
+    time: i64,
+    sender_id: i64,
+    message: Message,
+}
+
+impl GroupMsgBuilder {
+    /// Message ids are unique unless set, as replies are claimed per message.
+    pub fn new(group_id: i64) -> Self {
+        static NEXT_ID: AtomicI32 = AtomicI32::new(1);
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Self {
+            group_id,
+            message_id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            time,
+            sender_id: ADMIN_ID,
+            message: Message::new(),
+        }
+    }
+
+    pub fn message_id(mut self, message_id: i32) -> Self {
+        self.message_id = message_id;
+        self
+    }
+
+    pub fn time(mut self, time: i64) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn sender(mut self, sender_id: i64) -> Self {
+        self.sender_id = sender_id;
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.message = self.message.add_text(text);
+        self
+    }
+
+    pub fn at(mut self, user_id: i64) -> Self {
+        self.message = self.message.add_at(&user_id.to_string());
+        self
+    }
+
+    pub fn reply(mut self, message_id: i32) -> Self {
+        self.message = self.message.add_reply(message_id);
+        self
+    }
+
+    /// Voice message, transcribed from [MockBot::transcripts] if its file is in [MockBot::media].
+    pub fn record(mut self, file: &str) -> Self {
+        let segment = Segment::new("record", json!({ "file": file }));
+        self.message.push(segment);
+        self
+    }
+
+    /// Image downloadable from `file` as url, served from [MockBot::images].
+    pub fn image(mut self, file: &str) -> Self {
+        let segment = Segment::new("image", json!({ "file": file, "url": file }));
+        self.message.push(segment);
+        self
+    }
+
+    pub fn build(self) -> Arc<GroupMsg> {
+        let texts: Vec<String> = self
+            .message
+            .get("text")
+            .iter()
+            .filter_map(|seg| seg.data["text"].as_str().map(str::to_string))
+            .collect();
+        let text = if texts.is_empty() {
+            None
+        } else {
+            Some(texts.join("\n"))
+        };
+        Arc::new(GroupMsg {
+            group_id: self.group_id,
+            message_id: self.message_id,
+            time: self.time,
+            sender_id: self.sender_id,
+            message: self.message,
+            text,
+        })
+    }
+}
+
+/// Builds a [PrivateMsg] from friend as if received from OneBot.
+pub struct PrivateMsgBuilder {
+    user_id: i64,
+    sub_type: String,
+    source_group: Option<i64>,
+    message_id: i32,
+    time: i64,
+    message: Message,
+}
+
+impl PrivateMsgBuilder {
+    pub fn new(user_id: i64) -> Self {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        Self {
+            user_id,
+            sub_type: String::from("friend"),
+            source_group: None,
+            message_id: 1,
+            time,
+            message: Message::new(),
+        }
+    }
+
+    /// "friend" by default, "group" for temporary session.
+    pub fn sub_type(mut self, sub_type: &str) -> Self {
+        self.sub_type = sub_type.to_string();
+        self
+    }
+
+    /// Temporary session started from the group.
+    pub fn temp(mut self, group_id: i64) -> Self {
+        self.sub_type = String::from("group");
+        self.source_group = Some(group_id);
+        self
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.message = self.message.add_text(text);
+        self
+    }
+
+    pub fn build(self) -> Arc<PrivateMsg> {
+        let text = Some(text_of(&self.message)).filter(|x| !x.is_empty());
+        Arc::new(PrivateMsg {
+            user_id: self.user_id,
+            sub_type: self.sub_type,
+            source_group: self.source_group,
+            sender_name: self.user_id.to_string(),
+            message_id: self.message_id,
+            time: self.time,
+            message: self.message,
+            text,
+        })
+    }
+}
+
+/// Concatenated text segments of a message.
+pub fn text_of(message: &Message) -> String {
+    message
+        .get("text")
+        .iter()
+        .filter_map(|seg| seg.data["text"].as_str())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ab_test, activity, agent, annual_report, calendar, caption, card, command, db_check, disk,
+        endpoint, event, feed, feedback, games, github,
+        global_state::{
+            AdminSource, AgentSetting, ApiUrl, CardSetting, DiskSetting, Language, LanguageAction,
+            LanguageSetting, LevelSetting, LiveSwitch, PipelineStage, SpendSetting, WeatherSetting,
+        },
+        group_notice, guild, heat, history_import, join_request, language, level, live, locale,
+        maintenance, meme, mention, migration, moderation, news, outbox, pipeline, points, private,
+        quota, reminder, report, repost, search, self_check, spend, stats_api, sticker, summary,
+        takeover, unfurl, uptime, util, verify, word_filter,
+    };
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_command_mute_unmute() {
+        init();
+        run(async {
+            let group_id = 1;
+            let mute = GroupMsgBuilder::new(group_id).text("禁用聊天回复").build();
+            command::act(mute).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("冷暴力"));
+
+            // muted agent tells it is muted once a minute instead of answering
+            for _ in 0..2 {
+                let at = GroupMsgBuilder::new(group_id)
+                    .at(BOT_ID)
+                    .text("在吗")
+                    .build();
+                agent::logger(Arc::clone(&at)).await;
+                agent::at_me_handler(at).await;
+            }
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 2);
+            assert!(text_of(&sent[1]).contains("主人不让我说话"));
+
+            let unmute = GroupMsgBuilder::new(group_id).text("启用聊天回复").build();
+            command::act(unmute).await;
+            assert_eq!(sent_to_group(group_id).len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_feedback() {
+        init();
+        run(async {
+            let group_id = 35;
+            let at = GroupMsgBuilder::new(group_id)
+                .sender(76)
+                .at(BOT_ID)
+                .text("讲个笑话")
+                .build();
+            agent::logger(Arc::clone(&at)).await;
+            agent::at_me_handler(at).await;
+            // ids of mock messages are their positions among all sent
+            let answer_id = {
+                let mock = mock();
+                let pos = mock
+                    .sent
+                    .iter()
+                    .rposition(|s| s.target == Target::Group(group_id));
+                pos.unwrap() as i32 + 1
+            };
+            let rate = |sender_id, text: &str, reply_to: Option<i32>| {
+                let builder = GroupMsgBuilder::new(group_id).sender(sender_id).text(text);
+                match reply_to {
+                    Some(id) => builder.reply(id).build(),
+                    None => builder.build(),
+                }
+            };
+            feedback::feedback_handler(rate(77, "反馈 差", None)).await;
+            feedback::feedback_handler(rate(77, "反馈 差", Some(answer_id + 100_000))).await;
+            feedback::feedback_handler(rate(77, "反馈 好", Some(answer_id))).await;
+            // the latest rating of a member counts
+            feedback::feedback_handler(rate(77, "反馈 差", Some(answer_id))).await;
+            feedback::feedback_handler(rate(78, "反馈 好", Some(answer_id))).await;
+            feedback::feedback_handler(rate(78, "反馈 一般", Some(answer_id))).await;
+            let texts: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(texts.len(), 6);
+            assert!(texts[1].starts_with("请回复助理的消息"));
+            assert!(texts[2].ends_with("只能评价助理的回复"));
+            assert!(texts[3..].iter().all(|t| t.ends_with("感谢反馈")));
+
+            let report = feedback::report(group_id).await.unwrap();
+            assert!(report.ends_with(": 回复1次, 好评1, 差评1"), "{report}");
+            assert_eq!(
+                feedback::report(GROUP_COUNT + 1).await.unwrap(),
+                "还没有助理回复的记录"
+            );
+        });
+    }
+
+    #[test]
+    fn test_heat() {
+        init();
+        run(async {
+            let group_id = 36;
+            for i in 0..30 {
+                let text = if i % 3 == 0 {
+                    "你闭嘴"
+                } else {
+                    "你说什么"
+                };
+                let e = GroupMsgBuilder::new(group_id)
+                    .sender(79 + i % 2)
+                    .text(text)
+                    .build();
+                heat::heat_handler(e).await;
+            }
+            assert!(heat::is_heated(group_id));
+            let at = GroupMsgBuilder::new(group_id)
+                .at(BOT_ID)
+                .text("你评评理")
+                .build();
+            agent::logger(Arc::clone(&at)).await;
+            agent::at_me_handler(at).await;
+            let notice = serde_json::from_value(serde_json::json!({
+                "notice_type": "group_admin",
+                "sub_type": "set",
+                "time": 1234,
+                "self_id": BOT_ID,
+                "group_id": group_id,
+                "user_id": 79
+            }))
+            .unwrap();
+            group_notice::dispatch(notice).await;
+            assert!(sent_to_group(group_id).is_empty());
+            assert!(!heat::is_heated(1));
+        });
+    }
+
+    #[test]
+    fn test_takeover() {
+        init();
+        run(async {
+            let group_id = 37;
+            let config = CONFIG.get().unwrap();
+            let groups = config.groups.as_ref().unwrap();
+            let group = groups.iter().find(|g| g.id == group_id).unwrap();
+            let answer = agent::GroupAnswer {
+                text: String::from("聊聊政治吧"),
+                persona: String::from("base"),
+                model: String::from("gpt-4o-mini"),
+            };
+            takeover::submit(group, 555, 81, "聊点什么", &answer).await;
+            assert!(sent_to_group(group_id).is_empty());
+            let pending: Vec<_> = sent_to_private(ADMIN_ID)
+                .iter()
+                .map(text_of)
+                .filter(|t| t.starts_with("群37的助理回复待审核"))
+                .collect();
+            assert_eq!(pending.len(), 1);
+            let id: String = pending[0]
+                .split_once('#')
+                .unwrap()
+                .1
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+
+            let review =
+                |user_id, text: String| PrivateMsgBuilder::new(user_id).text(&text).build();
+            // members of the group cannot review
+            assert!(!takeover::review_handler(review(82, format!("通过 {id}"))).await);
+            assert!(
+                takeover::review_handler(review(ADMIN_ID, format!("修改 {id} 换个话题吧"))).await
+            );
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(sent[0].contains("reply"));
+            assert_eq!(text_of(&sent[0]), "换个话题吧");
+            assert!(takeover::review_handler(review(ADMIN_ID, format!("通过 {id}"))).await);
+            assert_eq!(sent_to_group(group_id).len(), 1);
+            let replies: Vec<_> = sent_to_private(ADMIN_ID).iter().map(text_of).collect();
+            assert!(replies.contains(&format!("#{id}已修改")));
+            assert!(replies.contains(&format!("#{id}已经审核过了")));
+        });
+    }
+
+    #[test]
+    fn test_answer_message() {
+        init();
+        run(async {
+            let config = CONFIG.get().unwrap();
+            let group = &config.groups.as_ref().unwrap()[0];
+            let mut png = std::io::Cursor::new(Vec::new());
+            image::RgbaImage::new(4, 4)
+                .write_to(&mut png, image::ImageFormat::Png)
+                .unwrap();
+            mock().images.insert(
+                String::from("https://example.com/cat.png"),
+                png.into_inner(),
+            );
+
+            let text = "[at:张三] 看这个[image:https://example.com/cat.png]\
+                [image:https://example.com/404.png][at:87654321][at:路人]好吧";
+            let message = agent::answer_message(group, text, Some(42)).await;
+            let kinds: Vec<_> = message.iter().map(|seg| seg.type_.as_str()).collect();
+            assert_eq!(kinds, ["reply", "at", "text", "image", "at", "text"]);
+            let ats: Vec<_> = message
+                .get("at")
+                .iter()
+                .map(|seg| seg.data["qq"].as_str().unwrap().to_string())
+                .collect();
+            assert_eq!(ats, ["23456789", "87654321"]);
+            assert_eq!(text_of(&message), " 看这个@路人好吧");
+
+            // a quoted message in the answer replaces the asker's
+            let message = agent::answer_message(group, "[reply:7]是这条[reply:x]", Some(42)).await;
+            let reply = message.get("reply");
+            assert_eq!(reply.len(), 1);
+            assert_eq!(reply[0].data["id"], "7");
+            assert_eq!(text_of(&message), "是这条[reply:x]");
+        });
+    }
+
+    #[test]
+    fn test_sticker() {
+        init();
+        run(async {
+            let group_id = 38;
+            let seg = store::GroupChatSegment {
+                message_id: 5,
+                time: util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("image"),
+                content: String::from("/tmp/melon.gif"),
+                interpret: String::from("https://example.com/melon.gif"),
+            };
+            seg.db_store(group_id).await.unwrap();
+
+            let add = |sender| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .reply(5)
+                    .text("添加表情 吃瓜")
+                    .build()
+            };
+            // members other than command admins cannot add
+            sticker::sticker_handler(add(42)).await;
+            assert!(sent_to_group(group_id).is_empty());
+            sticker::sticker_handler(add(ADMIN_ID)).await;
+            let list = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .text("表情列表")
+                .build();
+            sticker::sticker_handler(list).await;
+            let sent: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(sent, ["已添加表情[吃瓜]", "表情: 吃瓜"]);
+
+            let config = CONFIG.get().unwrap();
+            let groups = config.groups.as_ref().unwrap();
+            let group = groups.iter().find(|g| g.id == group_id).unwrap();
+            let message = agent::answer_message(group, "[sticker:吃瓜][sticker:无]", None).await;
+            let images = message.get("image");
+            assert_eq!(images.len(), 1);
+            assert_eq!(images[0].data["file"], "https://example.com/melon.gif");
+
+            let remove = || {
+                GroupMsgBuilder::new(group_id)
+                    .sender(ADMIN_ID)
+                    .text("删除表情 吃瓜")
+                    .build()
+            };
+            sticker::sticker_handler(remove()).await;
+            sticker::sticker_handler(remove()).await;
+            let sent: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(sent[2..], ["已删除表情[吃瓜]", "没有表情[吃瓜]"]);
+            assert!(sticker::tags(group_id).await.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_db_check() {
+        init();
+        run(async {
+            let group_id = 39;
+            db_check::check().await;
+            let last = store::db_last_check().await.unwrap().unwrap();
+            assert!(last.ok);
+            assert!(last.detail.is_empty());
+
+            let e = GroupMsgBuilder::new(group_id).text("数据库状态").build();
+            command::act(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            let text = text_of(&sent[0]);
+            assert!(text.starts_with("数据库大小: "));
+            assert!(text.ends_with(&format!("上次检查: {} 正常", last.time)));
+        });
+    }
+
+    #[test]
+    fn test_disk() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 14;
+            let dir = std::env::temp_dir().join("momo-bot-testing-disk");
+            std::fs::create_dir_all(&dir).unwrap();
+            let mut media = Vec::new();
+            for (message_id, url) in [(1, "https://example.com/old.png"), (2, "")] {
+                let path = dir.join(format!("{message_id}.png"));
+                std::fs::write(&path, vec![0; 2 * 1024 * 1024]).unwrap();
+                let seg = store::GroupChatSegment {
+                    message_id,
+                    time: util::cur_time_iso8601(None),
+                    sender_id: 42,
+                    sender_name: String::from("张三"),
+                    seg_type: String::from("image"),
+                    content: path.to_string_lossy().to_string(),
+                    interpret: url.to_string(),
+                };
+                seg.db_store(group_id).await.unwrap();
+                media.push(path);
+            }
+            let setting = DiskSetting {
+                min_free_mb: 100,
+                prune: true,
+                ..Default::default()
+            };
+            let low_notices = || {
+                sent_to_private(ADMIN_ID)
+                    .iter()
+                    .map(text_of)
+                    .filter(|t| t.starts_with("数据目录所在磁盘"))
+                    .collect::<Vec<_>>()
+            };
+
+            // pruning the uploaded image is enough, the other one is the only copy
+            mock().free_space = Some(99 * 1024 * 1024);
+            disk::check(&setting).await;
+            assert!(!disk::is_low());
+            assert!(!media[0].exists());
+            assert!(media[1].exists());
+            assert!(low_notices().is_empty());
+
+            let setting = DiskSetting {
+                prune: false,
+                ..setting
+            };
+            mock().free_space = Some(10 * 1024 * 1024);
+            disk::check(&setting).await;
+            disk::check(&setting).await;
+            assert!(disk::is_low());
+            assert_eq!(
+                low_notices(),
+                ["数据目录所在磁盘剩余10MB, 低于100MB, 已暂停下载聊天记录中的图片和语音"]
+            );
+            // the image is not downloaded
+            store::write_group_msg(group_id, 3, None, 42, Message::new().add_image("abc.image"))
+                .await;
+            let segs = store::db_find_segment_by_id(group_id, 3).await.unwrap();
+            assert_eq!(segs[0].content, "abc.image");
+            assert!(segs[0].interpret.is_empty());
+
+            mock().free_space = None;
+            disk::check(&setting).await;
+            assert!(!disk::is_low());
+            assert_eq!(low_notices().len(), 2);
+            std::fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn test_bot_message_id() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 15;
+            util::send_group_and_log(group_id, "你好").await;
+            // ids of the mock are positions in sent messages
+            let position = mock()
+                .sent
+                .iter()
+                .position(|s| s.target == Target::Group(group_id))
+                .unwrap();
+            let message_id = position as i32 + 1;
+            let segs = store::db_find_segment_by_id(group_id, message_id)
+                .await
+                .unwrap();
+            assert_eq!(segs.len(), 1);
+            assert_eq!(segs[0].sender_id, BOT_ID);
+            assert_eq!(segs[0].content, "你好");
+        });
+    }
+
+    #[test]
+    fn test_outbox() {
+        init();
+        run(async {
+            let group_id = 40;
+            let cancel = || {
+                GroupMsgBuilder::new(group_id)
+                    .sender(ADMIN_ID)
+                    .text("撤掉")
+                    .build()
+            };
+            let held = kovi::tokio::spawn(outbox::hold(group_id, 1));
+            let kept = kovi::tokio::spawn(outbox::hold(group_id + 1, 1));
+            kovi::tokio::time::sleep(Duration::from_millis(100)).await;
+            command::act(cancel()).await;
+            assert!(!held.await.unwrap());
+            assert!(kept.await.unwrap());
+            command::act(cancel()).await;
+            let sent: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(sent, ["已撤掉待发送的回复", "没有待发送的回复"]);
+        });
+    }
+
+    #[test]
+    fn test_range_stats() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 16;
+            let segs = [
+                (1, "2026-01-01 08:00:00", 42, "text"),
+                (1, "2026-01-01 08:00:00", 42, "image"),
+                (2, "2026-01-02 08:00:00", 42, "text"),
+                (3, "2026-01-02 09:00:00", 43, "text"),
+                (4, "2026-01-03 08:00:00", 43, "text"),
+            ];
+            for (message_id, time, sender_id, seg_type) in segs {
+                let seg = store::GroupChatSegment {
+                    message_id,
+                    time: time.to_string(),
+                    sender_id,
+                    sender_name: format!("成员{sender_id}"),
+                    seg_type: seg_type.to_string(),
+                    content: String::from("内容"),
+                    interpret: String::new(),
+                };
+                seg.db_store(group_id).await.unwrap();
+            }
+            let stats = store::db_message_stats(group_id, "2026-01-01", "2026-01-03")
+                .await
+                .unwrap();
+            let counts: Vec<_> = stats.iter().map(|s| (s.sender_id, s.messages)).collect();
+            assert_eq!(counts, [(42, 2), (43, 1)]);
+            assert_eq!(
+                serde_json::to_value(&stats[1]).unwrap(),
+                json!({ "sender_id": 43, "sender_name": "成员43", "messages": 1 })
+            );
+
+            for month in ["2001-01", "2001-02", "2001-03"] {
+                store::db_add_spend(month, 1000, 0.5).await.unwrap();
+            }
+            let spend = store::db_spend_stats("2001-01", "2001-02").await.unwrap();
+            let months: Vec<_> = spend.iter().map(|s| s.month.as_str()).collect();
+            assert_eq!(months, ["2001-01", "2001-02"]);
+            assert_eq!(spend[0].tokens, 1000);
+        });
+    }
+
+    #[test]
+    fn test_stats_api() {
+        init();
+        run(async {
+            let group_id = 56;
+            let auth = Some("Bearer xxx");
+            let e = GroupMsgBuilder::new(group_id).sender(42).text("早").build();
+            agent::logger(e).await;
+            let today = util::cur_date_iso8601(None);
+            let tomorrow = util::date_iso8601(None, 1);
+
+            let target = format!("/groups/{group_id}/messages?from={today}&to={tomorrow}");
+            let res = stats_api::respond(&target, auth).await;
+            assert_eq!(res.status, 200);
+            assert_eq!(res.body["data"][0]["sender_id"], 42);
+            assert_eq!(stats_api::respond(&target, None).await.status, 401);
+            let res = stats_api::respond(&target, Some("Bearer yyy")).await;
+            assert_eq!(res.status, 401);
+            let target = format!("/groups/{group_id}/messages?from={today}");
+            assert_eq!(stats_api::respond(&target, auth).await.status, 400);
+            let target = format!("/groups/{}/messages?from=2026&to=2027", GROUP_COUNT + 1);
+            assert_eq!(stats_api::respond(&target, auth).await.status, 404);
+
+            store::db_add_spend("2002-05", 2000, 1.5).await.unwrap();
+            let res = stats_api::respond("/spend?from=2002-05&to=2002-05", auth).await;
+            assert_eq!(res.body["data"][0]["tokens"], 2000);
+
+            // sessions are recorded by the live poller, the ongoing one has no end
+            let room_id = "20056";
+            let live = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                ..Default::default()
+            };
+            for streaming in [true, false, true] {
+                set_liveroom(room_id, streaming, "开播了");
+                *live.last_notified.lock().unwrap() = None;
+                live::poll_room(group_id, &live).await;
+            }
+
+            // percent-encoded space
+            let target = format!("/live/sessions?from={today}%2000:00:00&to={tomorrow}");
+            let res = stats_api::respond(&target, auth).await;
+            let sessions: Vec<&Value> = res.body["data"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|s| s["room_id"] == room_id)
+                .collect();
+            assert_eq!(sessions.len(), 2);
+            assert!(sessions[0]["end_time"].is_string());
+            assert!(sessions[1]["end_time"].is_null());
+        });
+    }
+
+    #[test]
+    fn test_announce() {
+        init();
+        run(async {
+            let group_id = 23;
+            let e = GroupMsgBuilder::new(group_id)
+                .text("公告 明天停电\n注意安全")
+                .build();
+            command::act(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "【公告】\n明天停电\n注意安全");
+            assert!(mock()
+                .group_notices
+                .contains(&(group_id, String::from("明天停电\n注意安全"))));
+
+            let rows = store::db_load_announcements(group_id, 10).await.unwrap();
+            assert_eq!(rows.len(), 1);
+            assert_eq!(rows[0].sender_id, ADMIN_ID);
+            assert!(rows[0].pinned);
+
+            // mentioning the command mid-sentence is not an announcement
+            let e = GroupMsgBuilder::new(group_id).text("看群公告 好的").build();
+            command::act(e).await;
+            assert_eq!(sent_to_group(group_id).len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_command_requires_admin() {
+        init();
+        run(async {
+            let group_id = 2;
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .text("禁用聊天回复")
+                .build();
+            command::act(e).await;
+            assert!(sent_to_group(group_id).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_platform_admins() {
+        init();
+        run(async {
+            let group_id = 22;
+            {
+                let mut mock = mock();
+                mock.roles.insert((group_id, 81), String::from("owner"));
+                mock.roles.insert((group_id, 82), String::from("admin"));
+                mock.roles.insert((group_id, BOT_ID), String::from("admin"));
+                mock.members.insert((group_id, 83), String::from("群员"));
+            }
+            let mut command = global_state::CommandSetting::default();
+            command.admin_ids = vec![ADMIN_ID];
+            assert_eq!(command.admins(group_id).await, vec![ADMIN_ID]);
+
+            command.admin_source = Some(AdminSource::Platform);
+            let mut admins = command.admins(group_id).await;
+            admins.sort();
+            assert_eq!(admins, vec![81, 82]);
+            assert!(!command.is_admin(group_id, ADMIN_ID).await);
+            assert!(!command.is_admin(group_id, 83).await);
+
+            command.admin_source = Some(AdminSource::Both);
+            assert!(command.is_admin(group_id, ADMIN_ID).await);
+            assert!(command.is_admin(group_id, 82).await);
+
+            // platform roles grant commands of the group only
+            assert!(!command.is_global_admin(82));
+            assert!(command.is_global_admin(ADMIN_ID));
+            assert!(global_state::GroupCommand::ReloadConfig.is_global());
+            assert!(!global_state::GroupCommand::Mute.is_global());
+        });
+    }
+
+    #[test]
+    fn test_agent_at_me() {
+        init();
+        run(async {
+            let group_id = 3;
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .at(BOT_ID)
+                .text("你好")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            agent::at_me_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "mock answer");
+        });
+    }
+
+    #[test]
+    fn test_unknown_group() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 2;
+            assert!(!global_state::is_configured_group(group_id));
+            let plain = GroupMsgBuilder::new(group_id).text("你好").build();
+            agent::unknown_group_handler(plain).await;
+            assert!(sent_to_group(group_id).is_empty());
+
+            let at = GroupMsgBuilder::new(group_id)
+                .at(BOT_ID)
+                .text("你好")
+                .build();
+            agent::unknown_group_handler(at).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "该群聊未配置");
+        });
+    }
+
+    #[test]
+    fn test_duplicate_event() {
+        init();
+        let msg = GroupMsgBuilder::new(GROUP_COUNT + 4).text("hi").build();
+        assert!(event::first_seen(msg.dedup_key()));
+        // replayed after reconnect
+        assert!(!event::first_seen(msg.dedup_key()));
+
+        let poke = |time: i64| {
+            json!({
+                "notice_type": "notify",
+                "sub_type": "poke",
+                "time": time,
+                "group_id": GROUP_COUNT + 4,
+                "user_id": 42,
+                "target_id": BOT_ID
+            })
+        };
+        assert!(event::first_seen(event::notice_key(&poke(1))));
+        assert!(!event::first_seen(event::notice_key(&poke(1))));
+        assert!(event::first_seen(event::notice_key(&poke(2))));
+    }
+
+    #[test]
+    fn test_bot_banned() {
+        init();
+        run(async {
+            let ban = |group_id: i64, sub_type: &str, user_id: i64, duration: i64| {
+                serde_json::from_value::<group_notice::NoticeResponse>(json!({
+                    "notice_type": "group_ban",
+                    "sub_type": sub_type,
+                    "time": 1234,
+                    "self_id": BOT_ID,
+                    "group_id": group_id,
+                    "operator_id": 42,
+                    "user_id": user_id,
+                    "duration": duration
+                }))
+                .unwrap()
+            };
+            let group_id = 24;
+            group_notice::dispatch(ban(group_id, "ban", BOT_ID, 600)).await;
+            assert!(util::is_bot_banned(group_id));
+            let at = GroupMsgBuilder::new(group_id)
+                .at(BOT_ID)
+                .text("你好")
+                .build();
+            agent::logger(Arc::clone(&at)).await;
+            agent::at_me_handler(Arc::clone(&at)).await;
+            util::send_group_msg(group_id, "scheduled");
+            assert!(sent_to_group(group_id).is_empty());
+
+            group_notice::dispatch(ban(group_id, "lift_ban", BOT_ID, 0)).await;
+            agent::at_me_handler(at).await;
+            assert_eq!(sent_to_group(group_id).len(), 1);
+
+            // whole group ban does not affect admin bot
+            let group_id = GROUP_COUNT + 3;
+            group_notice::dispatch(ban(group_id, "ban", 0, -1)).await;
+            assert!(util::is_bot_banned(group_id));
+            group_notice::dispatch(ban(group_id, "lift_ban", 0, 0)).await;
+            assert!(!util::is_bot_banned(group_id));
+            mock()
+                .roles
+                .insert((group_id, BOT_ID), String::from("admin"));
+            group_notice::dispatch(ban(group_id, "ban", 0, -1)).await;
+            assert!(!util::is_bot_banned(group_id));
+            assert!(sent_to_group(group_id).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_group_notice_admin() {
+        init();
+        run(async {
+            let group_id = 4;
+            mock().members.insert((group_id, 42), String::from("张三"));
+            let notice = serde_json::from_value(json!({
+                "notice_type": "group_admin",
+                "sub_type": "set",
+                "time": 1234,
+                "self_id": BOT_ID,
+                "group_id": group_id,
+                "user_id": 42
+            }))
+            .unwrap();
+            group_notice::dispatch(notice).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).starts_with("张三"));
+        });
+    }
+
+    #[test]
+    fn test_live_local_query() {
+        init();
+        run(async {
+            let group_id = 5;
+            set_liveroom("12345678", true, "测试标题");
+            let e = GroupMsgBuilder::new(group_id).text("查询直播间").build();
+            live::local_query_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("测试标题"));
+        });
+    }
+
+    #[test]
+    fn test_live_api_variants() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 17;
+            {
+                let mut mock = mock();
+                for (room_id, variant) in [
+                    ("11", "v_voucher"),
+                    ("12", "expired_login"),
+                    ("13", "risk_control"),
+                    ("14", "missing_room"),
+                    ("15", "missing_keyframe"),
+                ] {
+                    mock.live_rooms
+                        .insert(room_id.to_string(), raw_liveroom_variant(variant));
+                }
+            }
+            for room_id in ["11", "12", "13"] {
+                let err = live::fetch_room(&MockLiveApi, room_id).await.unwrap_err();
+                assert!(matches!(err, PluginError::BilibiliRejected(_)), "{err}");
+            }
+            let err = live::fetch_room(&MockLiveApi, "11").await.unwrap_err();
+            assert!(err.to_string().contains("v_voucher"));
+            assert!(!live::fetch_room(&MockLiveApi, "14").await.unwrap().exist);
+            // unregistered rooms are missing
+            assert!(!live::fetch_room(&MockLiveApi, "16").await.unwrap().exist);
+            let room = live::fetch_room(&MockLiveApi, "15").await.unwrap();
+            assert!(room.exist && room.data.is_streaming);
+            assert!(room.data.keyframe.is_empty());
+
+            // rejected queries are not answered as missing rooms
+            for room_id in ["11", "14", "15"] {
+                let e = GroupMsgBuilder::new(group_id)
+                    .text(&format!("查询直播间{room_id}"))
+                    .build();
+                live::general_query_handler(e).await;
+            }
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 2);
+            assert!(text_of(&sent[0]).contains("14"));
+            assert!(text_of(&sent[1]).contains("无画面"));
+            assert!(!sent[1].contains("image"));
+        });
+    }
+
+    #[test]
+    fn test_image_safety() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 5;
+            let cover = |url: &str| {
+                let mut room = raw_liveroom(0, true, "封面");
+                room["data"]["user_cover"] = json!(url);
+                room
+            };
+            // scores are matched by bytes, so images differ in size
+            let png = |size: u32| {
+                let mut png = std::io::Cursor::new(Vec::new());
+                image::RgbaImage::new(size, size)
+                    .write_to(&mut png, image::ImageFormat::Png)
+                    .unwrap();
+                png.into_inner()
+            };
+            {
+                let mut mock = mock();
+                for (url, size) in [
+                    ("https://i0.hdslb.com/safe.jpg", 4),
+                    ("https://i0.hdslb.com/nsfw.jpg", 5),
+                ] {
+                    mock.images.insert(url.to_string(), png(size));
+                }
+                mock.image_scores
+                    .insert(String::from("https://i0.hdslb.com/nsfw.jpg"), 0.99);
+                mock.live_rooms
+                    .insert(String::from("1"), cover("https://i0.hdslb.com/safe.jpg"));
+                mock.live_rooms
+                    .insert(String::from("2"), cover("https://i0.hdslb.com/nsfw.jpg"));
+                // not fetched
+                mock.live_rooms
+                    .insert(String::from("3"), cover("https://i0.hdslb.com/404.jpg"));
+            }
+            for room_id in 1..=3 {
+                let e = GroupMsgBuilder::new(group_id)
+                    .text(&format!("查询直播间{room_id}"))
+                    .build();
+                live::general_query_handler(e).await;
+            }
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 3);
+            assert!(sent.iter().all(|m| text_of(m).contains("封面")));
+            let images: Vec<usize> = sent.iter().map(|m| m.get("image").len()).collect();
+            assert_eq!(images, [1, 0, 0]);
+        });
+    }
+
+    #[test]
+    fn test_voice_command() {
+        init();
+        run(async {
+            let group_id = 25;
+            {
+                let mut mock = mock();
+                mock.transcripts
+                    .insert(String::from("mute.amr"), String::from("禁用聊天回复。"));
+                mock.transcripts
+                    .insert(String::from("chat.amr"), String::from("今天天气不错"));
+                for file in ["mute.amr", "chat.amr"] {
+                    mock.media.insert(file.to_string(), format!("/tmp/{file}"));
+                }
+            }
+            // not admin
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .record("mute.amr")
+                .build();
+            command::act(e).await;
+            let e = GroupMsgBuilder::new(group_id).record("chat.amr").build();
+            command::act(e).await;
+            assert!(sent_to_group(group_id).is_empty());
+
+            let e = GroupMsgBuilder::new(group_id).record("mute.amr").build();
+            command::act(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("冷暴力"));
+        });
+    }
+
+    #[test]
+    fn test_live_fan_out() {
+        init();
+        run(async {
+            let (owner, other) = (GROUP_COUNT + 6, GROUP_COUNT + 7);
+            let room_id = "20000";
+            let live = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                notify_group_ids: Some(vec![other, owner]),
+                notify_subscribers: Some(true),
+                notify_window_sec: None,
+                ..Default::default()
+            };
+            let time = util::cur_time_iso8601(None);
+            store::db_add_live_subscription(77, room_id, &time)
+                .await
+                .unwrap();
+
+            // first observation only records status
+            set_liveroom(room_id, false, "");
+            live::poll_room(owner, &live).await;
+            assert!(sent_to_group(owner).is_empty());
+
+            set_liveroom(room_id, true, "开播标题");
+            live::poll_room(owner, &live).await;
+            set_liveroom(room_id, false, "");
+            live::poll_room(owner, &live).await;
+            for group_id in [owner, other] {
+                let sent: Vec<String> = sent_to_group(group_id).iter().map(text_of).collect();
+                assert_eq!(sent.len(), 2);
+                assert!(sent[0].starts_with("XX开播了") && sent[0].contains("开播标题"));
+                assert_eq!(sent[1], "XX下播了");
+            }
+            let sent = sent_to_private(77);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).starts_with("你订阅的直播间开播了"));
+        });
+    }
+
+    #[test]
+    fn test_live_state_restore() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 8;
+            let room_id = "20001";
+            let live = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                ..Default::default()
+            };
+            set_liveroom(room_id, false, "");
+            live::poll_room(group_id, &live).await;
+
+            // restarted poller resumes from persisted status instead of Init
+            let restarted = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                ..Default::default()
+            };
+            live::restore_switch(group_id, &restarted).await;
+            assert!(matches!(restarted.get_switch(), LiveSwitch::Off));
+            set_liveroom(room_id, true, "重启后开播");
+            live::poll_room(group_id, &restarted).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("重启后开播"));
+
+            // never observed by this group
+            let other = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                ..Default::default()
+            };
+            live::restore_switch(GROUP_COUNT + 9, &other).await;
+            assert!(matches!(other.get_switch(), LiveSwitch::Init));
+        });
+    }
+
+    #[test]
+    fn test_live_notify_window() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 10;
+            let room_id = "20002";
+            let live = global_state::LiveSetting {
+                room_id: String::from(room_id),
+                ..Default::default()
+            };
+            set_liveroom(room_id, false, "");
+            live::poll_room(group_id, &live).await;
+
+            // flapping within the window is notified once
+            for streaming in [true, false, true, false] {
+                set_liveroom(room_id, streaming, "");
+                live::poll_room(group_id, &live).await;
+            }
+            assert_eq!(sent_to_group(group_id).len(), 1);
+
+            // pending change is notified once the window passes
+            *live.last_notified.lock().unwrap() =
+                Instant::now().checked_sub(Duration::from_secs(301));
+            live::poll_room(group_id, &live).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 2);
+            assert_eq!(text_of(&sent[1]), "XX下播了");
+
+            let private_live = global_state::PrivateLiveSetting::default();
+            assert!(private_live.try_notify(room_id));
+            assert!(!private_live.try_notify(room_id));
+            assert!(private_live.try_notify("20003"));
+        });
+    }
+
+    #[test]
+    fn test_write_buffer() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 11;
+            let row = |message_id: i32| store::PendingRow {
+                group_id,
+                message_id,
+                time: util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("text"),
+                content: format!("离线消息{message_id}"),
+                interpret: String::from("text"),
+            };
+            // oldest rows are dropped beyond capacity
+            let mut buffer = store::WriteBuffer::default();
+            for message_id in 0..10002 {
+                buffer.push(row(message_id));
+            }
+            assert_eq!(buffer.rows.len(), 10000);
+            assert_eq!(buffer.dropped, 2);
+            assert_eq!(buffer.rows[0].message_id, 2);
+
+            // the shared breaker stays closed, so other writes are not held back
+            {
+                let mut buffer = store::write_buffer();
+                buffer.push(row(1));
+                buffer.push(row(2));
+            }
+            let (written, _) = store::flush_write_buffer().await.unwrap();
+            assert!(written >= 2);
+            assert!(!store::write_buffer().is_open());
+            let segs = store::db_find_segment_by_id(group_id, 2).await.unwrap();
+            assert_eq!(segs[0].content, "离线消息2");
+        });
+    }
+
+    #[test]
+    fn test_self_check() {
+        init();
+        run(async {
+            let config = CONFIG.get().unwrap();
+            let checks = self_check::run_checks(config).await;
+            assert!(checks.iter().all(|c| c.error.is_none()), "{checks:?}");
+            assert!(checks.iter().any(|c| c.name == "database"));
+            assert!(checks.iter().any(|c| c.name.starts_with("OpenAI")));
+            let report = self_check::format_report(config, &checks);
+            assert!(report.contains("中0项失败"));
+            // groups of test config are complete
+            assert!(!report.contains("[群"));
+
+            let mut group = GroupSetting {
+                id: 1,
+                agent: None,
+                ..Default::default()
+            };
+            assert!(self_check::group_problems(&group)
+                .contains(&String::from("指令正则无效, 指令不可用")));
+            group.command.as_mut().unwrap().init_regex().unwrap();
+            group.moderation.as_mut().unwrap().init_regex().unwrap();
+            let problems = self_check::group_problems(&group);
+            assert!(problems.contains(&String::from("群聊总结需要agent配置")));
+            assert!(!problems.contains(&String::from("指令正则无效, 指令不可用")));
+            group.command = None;
+            assert!(
+                self_check::group_problems(&group).contains(&String::from("举报需要command配置"))
+            );
+        });
+    }
+
+    #[test]
+    fn test_spend_limit() {
+        init();
+        run(async {
+            let mut setting = SpendSetting {
+                monthly_limit: 0.01,
+                default_price: 10.0,
+                prices: HashMap::from([(String::from("cheap"), 1.0)]),
+                fallback_model: Some(String::from("cheap")),
+            };
+            let model = spend::choose_model(&setting, "gpt-4o").await;
+            assert_eq!(model.as_deref(), Some("gpt-4o"));
+            spend::charge(&setting, "cheap", 5000).await;
+            let model = spend::choose_model(&setting, "gpt-4o").await;
+            assert_eq!(model.as_deref(), Some("gpt-4o"));
+
+            // over the limit, switch to fallback and notify admin once
+            spend::charge(&setting, "gpt-4o", 1000).await;
+            let model = spend::choose_model(&setting, "gpt-4o").await;
+            assert_eq!(model.as_deref(), Some("cheap"));
+            spend::choose_model(&setting, "gpt-4o").await;
+            let notified = sent_to_private(ADMIN_ID)
+                .iter()
+                .filter(|m| text_of(m).contains("已达上限"))
+                .count();
+            assert_eq!(notified, 1);
+            setting.fallback_model = None;
+            assert!(spend::choose_model(&setting, "gpt-4o").await.is_none());
+
+            let month = &util::cur_date_iso8601(None)[..7];
+            let cost = store::db_load_spend(month).await.unwrap();
+            assert!((cost - 0.015).abs() < 1e-9);
+        });
+    }
+
+    #[test]
+    fn test_private_agent_rate_limit() {
+        init();
+        run(async {
+            // allowed by default config
+            let user_id = 12345678;
+            let e = PrivateMsgBuilder::new(user_id).text("你好").build();
+            private::agent_handler(e).await;
+            let sent = sent_to_private(user_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "mock answer");
+
+            let e = PrivateMsgBuilder::new(user_id).text("还在吗").build();
+            private::agent_handler(e).await;
+            assert_eq!(sent_to_private(user_id).len(), 1);
+
+            // not in allowlist
+            let e = PrivateMsgBuilder::new(42).text("你好").build();
+            private::agent_handler(e).await;
+            assert!(sent_to_private(42).is_empty());
+        });
+    }
+
+    #[test]
+    fn test_temp_session() {
+        init();
+        run(async {
+            // forwarded to admin by default config
+            let user_id = 5001;
+            let forwarded = || {
+                sent_to_private(ADMIN_ID)
+                    .iter()
+                    .map(text_of)
+                    .filter(|t| t.contains("(5001)通过群7"))
+                    .count()
+            };
+            let e = PrivateMsgBuilder::new(user_id)
+                .temp(7)
+                .text("加我好友")
+                .build();
+            assert!(private::temp_session_handler(e).await);
+            assert_eq!(forwarded(), 1);
+            assert!(sent_to_private(user_id).is_empty());
+
+            // rate limited
+            let e = PrivateMsgBuilder::new(user_id).temp(7).text("在吗").build();
+            assert!(private::temp_session_handler(e).await);
+            assert_eq!(forwarded(), 1);
+
+            // friends are left to other handlers
+            let e = PrivateMsgBuilder::new(user_id).text("你好").build();
+            assert!(!private::temp_session_handler(e).await);
+        });
+    }
+
+    #[test]
+    fn test_history_import() {
+        init();
+        run(async {
+            let group_id = 32;
+            let txt = "\u{feff}消息记录（此消息记录为文本格式，不支持重新导入）\n\
+                ================================================================\n\
+                消息对象:测试群\n\
+                ================================================================\n\
+                \n\
+                2020-01-02 8:05:03 张三(42)\n\
+                早上好\n\
+                今天爬山吗\n\
+                \n\
+                2020-01-02 8:06:00 李四<lisi@example.com>\n\
+                [图片]\n\
+                \n\
+                2020-01-02 8:07:00 王五(43)\n\
+                \n";
+            let rows = history_import::parse_txt(group_id, txt);
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].time, "2020-01-02 08:05:03");
+            assert_eq!(rows[0].content, "早上好\n今天爬山吗");
+            assert_eq!(rows[1].sender_id, 0);
+            assert_eq!(rows[1].sender_name, "李四");
+
+            let json = r#"{"messages": [
+                {"time": 1577923200, "sender_id": "44", "content": "新年快乐"},
+                {"time": "2020-01-02 9:00:00", "sender_id": 45, "sender_name": "赵六", "content": "hi"},
+                {"time": "yesterday", "sender_id": 46, "content": "skipped"}
+            ]}"#;
+            let rows = history_import::parse_json(group_id, json).unwrap();
+            assert_eq!(rows.len(), 2);
+            assert_eq!(rows[0].time, "2020-01-02 08:00:00");
+            assert_eq!(rows[0].sender_name, "44");
+
+            let dir = DATA_PATH.get().unwrap().join("imports");
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("history32.txt"), txt).unwrap();
+            for _ in 0..2 {
+                let e = PrivateMsgBuilder::new(ADMIN_ID)
+                    .text("群32 导入聊天记录 history32.txt")
+                    .build();
+                assert!(command::private_console(e).await);
+            }
+            let sent = sent_to_private(ADMIN_ID);
+            let replies: Vec<String> = sent
+                .iter()
+                .map(text_of)
+                .filter(|t| t.starts_with("导入了"))
+                .collect();
+            assert!(replies.contains(&String::from("导入了2条聊天记录, 跳过0条已有记录")));
+            assert!(replies.contains(&String::from("导入了0条聊天记录, 跳过2条已有记录")));
+            let segs = store::db_load_group_segment_since(group_id, "2020-01-01")
+                .await
+                .unwrap();
+            assert_eq!(segs.len(), 2);
+            assert_eq!(segs[0].sender_name, "张三");
+
+            let e = PrivateMsgBuilder::new(ADMIN_ID)
+                .text("群32 导入聊天记录 ../config.toml")
+                .build();
+            assert!(command::private_console(e).await);
+            let failed = sent_to_private(ADMIN_ID)
+                .iter()
+                .any(|m| text_of(m).starts_with("导入聊天记录失败"));
+            assert!(failed);
+        });
+    }
+
+    #[test]
+    fn test_group_tables() {
+        init();
+        run(async {
+            // created at startup, before any message
+            let ids = store::db_group_table_ids().await.unwrap();
+            assert!((1..=GROUP_COUNT).all(|id| ids.contains(&id)));
+        });
+    }
+
+    #[test]
+    fn test_state_migration() {
+        init();
+        run(async {
+            // the in-memory database cannot be snapshotted, so export is checked by its parts
+            let group_id = GROUP_COUNT + 12;
+            let seg = store::GroupChatSegment {
+                message_id: 1,
+                time: util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("image"),
+                content: String::from("/tmp/cat.png"),
+                interpret: String::from("https://example.com/cat.png"),
+            };
+            seg.db_store(group_id).await.unwrap();
+            assert!(store::db_group_table_ids()
+                .await
+                .unwrap()
+                .contains(&group_id));
+            let media = store::db_load_media(group_id).await.unwrap();
+            assert_eq!(media.len(), 1);
+            assert_eq!(media[0].url, "https://example.com/cat.png");
+
+            let data_path = std::env::temp_dir().join("momo-bot-testing-migration");
+            let _ = std::fs::remove_dir_all(&data_path);
+            let state_dir = data_path.join("export").join("state");
+            std::fs::create_dir_all(&state_dir).unwrap();
+            std::fs::write(state_dir.join("store.db"), "new db").unwrap();
+            std::fs::write(state_dir.join("config.toml"), "new").unwrap();
+            let archive = data_path.join(migration::IMPORT_ARCHIVE);
+            let status = std::process::Command::new("tar")
+                .arg("-czf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(data_path.join("export"))
+                .arg("state")
+                .status()
+                .unwrap();
+            assert!(status.success());
+
+            std::fs::write(data_path.join("config.toml"), "old").unwrap();
+            assert!(migration::import_state(&data_path).unwrap());
+            let read = |name: &str| std::fs::read_to_string(data_path.join(name)).unwrap();
+            assert_eq!(read("store.db"), "new db");
+            assert_eq!(read("config.toml"), "new");
+            assert_eq!(read("config.toml.before-import"), "old");
+            // imported only once
+            assert!(!archive.exists());
+            assert!(!migration::import_state(&data_path).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_ab_test_report() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT + 13;
+            assert_eq!(
+                ab_test::report(group_id).await.unwrap(),
+                "还没有人设对比的记录"
+            );
+
+            let mut agent = AgentSetting {
+                dev_prompt: String::from("base <!members!>"),
+                ..Default::default()
+            };
+            agent.load_members();
+            let prompts: Vec<_> = (0..64).map(|_| agent.pick_prompts()).collect();
+            let butler = prompts.iter().find(|(v, ..)| *v == "butler").unwrap();
+            assert!(butler.1.contains("butler"));
+            // the variant has no user prompt of its own
+            assert_eq!(butler.2, agent.user_prompt);
+            assert!(prompts.iter().any(|(v, ..)| *v == ab_test::BASE_VARIANT));
+
+            let add = |variant: &'static str, time: &'static str| async move {
+                store::db_add_ab_reply(group_id, variant, 42, time)
+                    .await
+                    .unwrap();
+            };
+            add("base", "2025-01-01 10:00:00").await;
+            add("base", "2025-01-01 12:00:00").await;
+            add("butler", "2025-01-01 14:00:00").await;
+            let msg = |message_id: i32, time: &str, sender_id: i64| store::GroupChatSegment {
+                message_id,
+                time: time.to_string(),
+                sender_id,
+                sender_name: String::new(),
+                seg_type: String::from("text"),
+                content: String::from("hi"),
+                interpret: String::from("text"),
+            };
+            let segs = [
+                msg(1, "2025-01-01 10:00:01", BOT_ID),
+                msg(2, "2025-01-01 10:01:00", 43),
+                msg(3, "2025-01-01 14:01:00", 42),
+                msg(4, "2025-01-01 14:02:00", 43),
+                // out of the window
+                msg(5, "2025-01-01 14:06:00", 42),
+            ];
+            for seg in segs {
+                seg.db_store(group_id).await.unwrap();
+            }
+            let stats = store::db_ab_stats(group_id, ab_test::FOLLOW_UP_SEC)
+                .await
+                .unwrap();
+            assert_eq!(stats.len(), 2);
+            assert_eq!((stats[0].replies, stats[0].follow_ups), (2, 0.5));
+            assert_eq!(stats[0].asker_rate, 0.0);
+            assert_eq!((stats[1].replies, stats[1].follow_ups), (1, 2.0));
+            assert_eq!(stats[1].asker_rate, 1.0);
+            let report = ab_test::report(group_id).await.unwrap();
+            assert!(report.contains("butler: 回复1次, 平均2.0条后续消息, 100%的提问者继续发言"));
+        });
+    }
+
+    #[test]
+    fn test_private_console() {
+        init();
+        run(async {
+            let group_id = 6;
+            let e = PrivateMsgBuilder::new(ADMIN_ID)
+                .text("群6 禁用聊天回复")
+                .build();
+            assert!(command::private_console(e).await);
+            assert!(sent_to_group(group_id).is_empty());
+            let sent = sent_to_private(ADMIN_ID);
+            assert!(sent.iter().any(|m| text_of(m).contains("冷暴力")));
+
+            // only admin has the console
+            let e = PrivateMsgBuilder::new(42).text("群6 启用聊天回复").build();
+            assert!(!command::private_console(e).await);
+        });
+    }
+
+    #[test]
+    fn test_private_live_subscription() {
+        init();
+        run(async {
+            let user_id = 43;
+            set_liveroom("23456789", false, "订阅标题");
+            let subscribe = || {
+                PrivateMsgBuilder::new(user_id)
+                    .text("订阅直播间 23456789")
+                    .build()
+            };
+            assert!(live::private_subscribe_handler(subscribe()).await);
+            assert!(live::private_subscribe_handler(subscribe()).await);
+            let sent = sent_to_private(user_id);
+            assert_eq!(sent.len(), 2);
+            assert!(text_of(&sent[0]).starts_with("已订阅"));
+            assert!(text_of(&sent[1]).starts_with("已经订阅过"));
+
+            let e = PrivateMsgBuilder::new(user_id)
+                .text("取消订阅直播间 23456789")
+                .build();
+            assert!(live::private_subscribe_handler(e).await);
+            assert!(text_of(&sent_to_private(user_id)[2]).starts_with("已取消订阅"));
+        });
+    }
+
+    #[test]
+    fn test_report_forward() {
+        init();
+        run(async {
+            let group_id = 7;
+            let offending = GroupMsgBuilder::new(group_id)
+                .message_id(70)
+                .sender(44)
+                .text("违规内容")
+                .build();
+            agent::logger(offending).await;
+
+            let e = GroupMsgBuilder::new(group_id)
+                .message_id(71)
+                .sender(45)
+                .reply(70)
+                .text("举报")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            report::report_handler(e).await;
+            let sent = sent_to_private(ADMIN_ID);
+            assert!(sent.iter().any(|m| text_of(m).contains("违规内容")));
+            assert_eq!(sent_to_group(group_id).len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_recap() {
+        init();
+        run(async {
+            let group_id = 26;
+            let history = [(1, 44, "早"), (2, 45, "吃了吗"), (3, 44, "还没")];
+            for (message_id, sender_id, text) in history {
+                let e = GroupMsgBuilder::new(group_id)
+                    .message_id(message_id)
+                    .time(1_700_000_000 + message_id as i64)
+                    .sender(sender_id)
+                    .at(BOT_ID)
+                    .text(text)
+                    .build();
+                agent::logger(e).await;
+            }
+            let e = GroupMsgBuilder::new(group_id).text("回顾 2").build();
+            command::act(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            let nodes = sent[0].get("node");
+            assert_eq!(nodes.len(), 2);
+            assert_eq!(nodes[0].data["uin"], "45");
+            let content = nodes[0].data["content"].as_array().unwrap();
+            assert_eq!(content.len(), 2);
+            assert_eq!(content[1]["data"]["text"], "吃了吗");
+        });
+    }
+
+    #[test]
+    fn test_member_verification() {
+        init();
+        run(async {
+            let group_id = 8;
+            let increase = |user_id: i64| {
+                serde_json::from_value(json!({
+                    "notice_type": "group_increase",
+                    "sub_type": "approve",
+                    "time": 1234,
+                    "self_id": BOT_ID,
+                    "group_id": group_id,
+                    "operator_id": ADMIN_ID,
+                    "user_id": user_id
+                }))
+                .unwrap()
+            };
+            group_notice::dispatch(increase(46)).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[1]).contains("直播间号"));
+
+            let answer = |text: &str| GroupMsgBuilder::new(group_id).sender(46).text(text).build();
+            verify::answer_handler(answer("不知道")).await;
+            verify::answer_handler(answer("12345678")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[2]).contains("回答错误"));
+            assert!(text_of(&sent[3]).contains("验证通过"));
+
+            // passed member is left alone, pending one is reported
+            group_notice::dispatch(increase(47)).await;
+            verify::expire_overdue(i64::MAX).await;
+            let reported: Vec<String> = sent_to_private(ADMIN_ID)
+                .iter()
+                .map(text_of)
+                .filter(|x| x.contains("未在时限内完成验证"))
+                .collect();
+            assert_eq!(reported.len(), 1);
+            assert!(reported[0].contains("(47)"));
+        });
+    }
+
+    #[test]
+    fn test_moderation_repeat() {
+        init();
+        run(async {
+            let group_id = 9;
+            for _ in 0..3 {
+                let e = GroupMsgBuilder::new(group_id)
+                    .sender(48)
+                    .text("广告")
+                    .build();
+                moderation::spam_handler(e).await;
+            }
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("重复发送相同内容"));
+
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(48)
+                .text("https://t.cn/abc")
+                .build();
+            moderation::spam_handler(e).await;
+            assert!(text_of(&sent_to_group(group_id)[1]).contains("可疑链接"));
+        });
+    }
+
+    #[test]
+    fn test_points_sign_in() {
+        init();
+        run(async {
+            let group_id = 10;
+            mock().members.insert((group_id, 49), String::from("李四"));
+            let msg = |text: &str| GroupMsgBuilder::new(group_id).sender(49).text(text).build();
+            points::points_handler(msg("签到")).await;
+            points::points_handler(msg("签到")).await;
+            points::points_handler(msg("积分排行")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[0]).contains("获得10积分"));
+            assert!(text_of(&sent[1]).contains("已经签到"));
+            assert!(text_of(&sent[2]).contains("李四: 10"));
+
+            assert!(!points::try_spend(group_id, 49, 11, "test").await.unwrap());
+            assert!(points::try_spend(group_id, 49, 10, "test").await.unwrap());
+        });
+    }
+
+    #[test]
+    fn test_quiz_rounds() {
+        init();
+        run(async {
+            let group_id = 11;
+            let questions = indoc::indoc! {r#"
+                [[questions]]
+                question = "1+1=?"
+                answers = ["2", "二"]
+            "#};
+            let path = DATA_PATH.get().unwrap().join("quiz.toml");
+            std::fs::write(path, questions).unwrap();
+
+            let msg = |text: &str| GroupMsgBuilder::new(group_id).sender(50).text(text).build();
+            games::game_handler(msg("开始答题")).await;
+            games::game_handler(msg("3")).await;
+            games::game_handler(msg("二")).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 3);
+            assert!(text_of(&sent[0]).contains("1+1=?"));
+            assert!(text_of(&sent[1]).contains("答对了"));
+            assert!(text_of(&sent[2]).contains("1. 50: 1"));
+
+            // round times out
+            games::game_handler(msg("开始答题")).await;
+            games::tick(Instant::now() + Duration::from_secs(3600)).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[4]).contains("时间到"));
+            assert!(text_of(&sent[5]).contains("没有人答对"));
+        });
+    }
+
+    #[test]
+    fn test_idiom_chain() {
+        init();
+        run(async {
+            let group_id = 12;
+            let game = store::IdiomGame {
+                group_id,
+                current: String::from("一心一意"),
+                used: String::from("一心一意"),
+                last_player: 0,
+            };
+            store::db_save_idiom_game(&game).await.unwrap();
+
+            let msg = |sender: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .text(text)
+                    .build()
+            };
+            games::game_handler(msg(51, "意气风发")).await;
+            games::game_handler(msg(51, "发扬光大")).await;
+            games::game_handler(msg(52, "一心一意")).await;
+            games::game_handler(msg(52, "发扬光大")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[0]).contains("接龙成功"));
+            assert!(text_of(&sent[1]).contains("不能连续接龙"));
+            assert!(text_of(&sent[2]).contains("要接「发」开头"));
+            assert!(text_of(&sent[3]).contains("接龙成功"));
+
+            // state is read from database
+            let game = store::db_load_idiom_game(group_id).await.unwrap().unwrap();
+            assert_eq!(game.current, "发扬光大");
+            games::game_handler(msg(51, "结束接龙")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[4]).contains("51: 1"));
+            assert!(store::db_load_idiom_game(group_id).await.unwrap().is_none());
+        });
+    }
+
+    #[test]
+    fn test_feed_subscription() {
+        init();
+        run(async {
+            let group_id = 13;
+            let url = "https://example.com/atom.xml";
+            let atom = |ids: &[&str]| {
+                let entries: String = ids
+                    .iter()
+                    .map(|id| {
+                        format!(
+                            r#"<entry><id>{id}</id><title>post {id}</title>
+                            <link rel="alternate" href="https://example.com/{id}"/></entry>"#
+                        )
+                    })
+                    .collect();
+                format!(
+                    r#"<?xml version="1.0"?>
+                    <feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title>{entries}</feed>"#
+                )
+            };
+            mock().feeds.insert(url.to_string(), atom(&["1"]));
+
+            let msg = |text: &str| GroupMsgBuilder::new(group_id).text(text).build();
+            feed::feed_handler(msg(&format!("订阅RSS {url} 5"))).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[0]).contains("已订阅Blog"));
+
+            // not due yet
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+            mock().feeds.insert(url.to_string(), atom(&["3", "2", "1"]));
+            feed::poll_due(now).await;
+            assert_eq!(sent_to_group(group_id).len(), 1);
+
+            // existing entry is not notified, new entries in chronological order
+            feed::poll_due(now + 300).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 3);
+            assert_eq!(
+                text_of(&sent[1]),
+                "Blog更新了: post 2\nhttps://example.com/2"
+            );
+            assert!(text_of(&sent[2]).contains("post 3"));
+            feed::poll_due(now + 600).await;
+            assert_eq!(sent_to_group(group_id).len(), 3);
+
+            // non-admin is ignored
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(53)
+                .text("RSS列表")
+                .build();
+            feed::feed_handler(e).await;
+            assert_eq!(sent_to_group(group_id).len(), 3);
+            feed::feed_handler(msg(&format!("取消订阅RSS {url}"))).await;
+            feed::feed_handler(msg("RSS列表")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[3]).contains("已取消订阅"));
+            assert!(text_of(&sent[4]).contains("未订阅RSS"));
+        });
+    }
+
+    #[test]
+    fn test_news_digest() {
+        init();
+        run(async {
+            let url = "https://example.com/news.xml";
+            let rss = indoc::indoc! {r#"
+                <rss version="2.0"><channel><title>新闻</title>
+                <item><title>头条一</title><guid>1</guid></item>
+                <item><title>头条二</title><guid>2</guid></item>
+                <item><title><![CDATA[头条三]]></title><guid>3</guid></item>
+                </channel></rss>
+            "#};
+            mock().feeds.insert(url.to_string(), rss.to_string());
+            let mut news = global_state::NewsSetting {
+                sources: vec![url.to_string(), String::from("https://example.com/404")],
+                headlines_per_source: 2,
+                group_ids: vec![14],
+                ..Default::default()
+            };
+            news::post_digest(&news).await;
+            let sent = sent_to_group(14);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).starts_with("早报"));
+            assert!(text_of(&sent[0]).contains("mock answer"));
+
+            // plain headlines without summary
+            news.summary_prompt = None;
+            news.group_ids = vec![15];
+            news::post_digest(&news).await;
+            let sent = sent_to_group(15);
+            assert!(text_of(&sent[0]).ends_with("【新闻】\n1. 头条一\n2. 头条二"));
+        });
+    }
+
+    #[test]
+    fn test_github_events() {
+        init();
+        run(async {
+            let group_id = 16;
+            let repo = global_state::GithubRepoSetting {
+                repo: String::from("octo/bot"),
+                group_ids: vec![group_id],
+                ..Default::default()
+            };
+            let release = |id: u64, tag: &str| json!({"id": id, "tag_name": tag, "name": null, "html_url": "https://github.com/octo/bot/releases"});
+            let issue = |number: u64, pr: bool| {
+                let mut issue = json!({
+                    "number": number,
+                    "title": format!("issue {number}"),
+                    "html_url": "https://github.com/octo/bot/issues",
+                    "user": {"login": "alice"},
+                });
+                if pr {
+                    issue["pull_request"] = json!({});
+                }
+                issue
+            };
+            let set = |releases: Value, issues: Value, stars: u64| {
+                let mut mock = mock();
+                mock.github
+                    .insert(String::from("repos/octo/bot/releases"), releases);
+                mock.github
+                    .insert(String::from("repos/octo/bot/issues"), issues);
+                let repository = json!({ "stargazers_count": stars });
+                mock.github
+                    .insert(String::from("repos/octo/bot"), repository);
+            };
+
+            // first poll records state only
+            set(json!([release(1, "v0.1")]), json!([issue(1, false)]), 3);
+            github::poll_repo(None, &repo).await;
+            assert!(sent_to_group(group_id).is_empty());
+
+            let releases = json!([release(2, "v0.2"), release(1, "v0.1")]);
+            let issues = json!([issue(3, false), issue(2, true), issue(1, false)]);
+            set(releases, issues, 5);
+            github::poll_repo(None, &repo).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 3);
+            assert!(text_of(&sent[0]).contains("发布了 v0.2"));
+            assert!(text_of(&sent[1]).contains("新issue #3"));
+            assert!(text_of(&sent[2]).contains("收获了2个star, 共5个"));
+
+            // filtered by event type
+            let repo = global_state::GithubRepoSetting {
+                repo: String::from("octo/bot"),
+                group_ids: vec![group_id],
+                events: vec![global_state::GithubEvent::Star],
+                ..Default::default()
+            };
+            github::poll_repo(None, &repo).await;
+            set(json!([release(3, "v0.3")]), json!([]), 6);
+            github::poll_repo(None, &repo).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 4);
+            assert!(text_of(&sent[3]).contains("共6个"));
+        });
+    }
+
+    #[test]
+    fn test_meme_arguments() {
+        init();
+        run(async {
+            let group_id = 17;
+            let msg = |text: &str| GroupMsgBuilder::new(group_id).text(text).build();
+            meme::meme_handler(msg("做表情")).await;
+            meme::meme_handler(msg("做表情 不存在 你好")).await;
+            meme::meme_handler(msg("做表情 ../喜报 你好")).await;
+            // no font under test data path
+            meme::meme_handler(msg("做表情 喜报 你好")).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[0]).contains("可用模板: 喜报, 悲报, 警告"));
+            assert!(text_of(&sent[1]).starts_with("没有模板不存在"));
+            assert!(text_of(&sent[2]).starts_with("没有模板"));
+            assert_eq!(text_of(&sent[3]), "未配置表情字体");
+        });
+    }
+
+    #[test]
+    fn test_ocr_segment_in_agent_history() {
+        init();
+        run(async {
+            let group_id = 18;
+            let seg = store::GroupChatSegment {
+                message_id: 7,
+                time: crate::util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("ocr"),
+                content: String::from("/tmp/screenshot.png"),
+                interpret: String::from("今晚八点开播"),
+            };
+            seg.db_store(group_id).await.unwrap();
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .at(BOT_ID)
+                .text("图里写了什么")
+                .build();
+            agent::at_me_handler(e).await;
+            let payloads = mock().agent_payloads.clone();
+            assert!(payloads
+                .iter()
+                .any(|p| p.to_string().contains("张三: [图片文字]今晚八点开播")));
+        });
+    }
+
+    #[test]
+    fn test_image_caption_in_agent_history() {
+        init();
+        run(async {
+            let group_id = 30;
+            let path = "/tmp/cat_meme.png";
+            mock().captions.insert(
+                String::from(path),
+                String::from("一只猫的表情包，  它正在\n打哈欠并且看起来非常困倦的样子哈哈哈哈"),
+            );
+            let caption = caption::describe(path).await.unwrap();
+            assert_eq!(
+                caption,
+                "一只猫的表情包， 它正在 打哈欠并且看起来非常困倦的样子哈哈"
+            );
+            assert!(caption::describe("/tmp/missing.png").await.is_none());
+
+            let seg = store::GroupChatSegment {
+                message_id: 8,
+                time: crate::util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("caption"),
+                content: String::from(path),
+                interpret: String::from("一只猫的表情包"),
+            };
+            seg.db_store(group_id).await.unwrap();
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .at(BOT_ID)
+                .text("我发了什么")
+                .build();
+            agent::at_me_handler(e).await;
+            let payloads = mock().agent_payloads.clone();
+            assert!(payloads
+                .iter()
+                .any(|p| p.to_string().contains("张三: [图片]一只猫的表情包")));
+        });
+    }
+
+    #[test]
+    fn test_reply_thread_in_agent_context() {
+        init();
+        run(async {
+            let group_id = 31;
+            let chain = [
+                (1, None, 44, "今晚吃什么"),
+                (2, Some(1), 45, "火锅吧"),
+                (3, Some(2), 44, "哪家火锅"),
+            ];
+            for (message_id, reply, sender_id, text) in chain {
+                let mut builder = GroupMsgBuilder::new(group_id)
+                    .message_id(message_id)
+                    .sender(sender_id);
+                if let Some(reply) = reply {
+                    builder = builder.reply(reply);
+                }
+                agent::logger(builder.text(text).build()).await;
+            }
+            let thread = store::db_load_thread(group_id, 3).await.unwrap();
+            let texts: Vec<&str> = thread
+                .iter()
+                .filter(|s| s.seg_type == "text")
+                .map(|s| s.content.as_str())
+                .collect();
+            assert_eq!(texts, ["今晚吃什么", "火锅吧", "哪家火锅"]);
+            // missing message ends the chain
+            assert!(store::db_load_thread(group_id, 99)
+                .await
+                .unwrap()
+                .is_empty());
+
+            let e = GroupMsgBuilder::new(group_id)
+                .message_id(4)
+                .sender(45)
+                .reply(3)
+                .at(BOT_ID)
+                .text("你推荐一家")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            agent::at_me_handler(e).await;
+            let payloads = mock().agent_payloads.clone();
+            let payload = payloads
+                .iter()
+                .map(|p| p.to_string())
+                .find(|p| p.contains("你推荐一家"))
+                .unwrap();
+            let thread_at = payload.find("回复的对话").unwrap();
+            let first = payload[thread_at..].find("今晚吃什么").unwrap();
+            let last = payload[thread_at..].find("哪家火锅").unwrap();
+            assert!(first < last);
+        });
+    }
+
+    #[test]
+    fn test_summary() {
+        init();
+        run(async {
+            let group_id = 27;
+            let e = GroupMsgBuilder::new(group_id).text("总结一下").build();
+            summary::summary_handler(e).await;
+            assert_eq!(
+                text_of(&sent_to_group(group_id)[0]),
+                "最近6小时没有聊天记录"
+            );
+
+            for text in ["周末去爬山吗", "好啊，去香山"] {
+                let e = GroupMsgBuilder::new(group_id).sender(42).text(text).build();
+                agent::logger(e).await;
+            }
+            let e = GroupMsgBuilder::new(group_id).text("总结一下 2").build();
+            summary::summary_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(text_of(&sent[1]), "最近2小时总结:\nmock answer");
+            let payloads = mock().agent_payloads.clone();
+            assert!(payloads
+                .iter()
+                .any(|p| p.to_string().contains("周末去爬山吗") && p.to_string().contains("香山")));
+
+            let e = GroupMsgBuilder::new(group_id).text("总结一下 100").build();
+            summary::summary_handler(e).await;
+            assert!(text_of(&sent_to_group(group_id)[2]).starts_with("用法"));
+        });
+    }
+
+    #[test]
+    fn test_agent_live_status() {
+        init();
+        run(async {
+            let group_id = 29;
+            set_liveroom("12345678", true, "测试标题");
+            mock()
+                .agent_tool_calls
+                .insert(String::from("她开播了吗"), String::from("live_status"));
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .at(BOT_ID)
+                .text("她开播了吗")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            agent::at_me_handler(e).await;
+            let payloads: Vec<Value> = mock()
+                .agent_payloads
+                .iter()
+                .filter(|p| p.to_string().contains("她开播了吗"))
+                .cloned()
+                .collect();
+            assert_eq!(payloads.len(), 2);
+            // placeholder in prompt and function offered
+            let first = payloads[0].to_string();
+            assert!(first.contains("直播间12345678: 直播中") && first.contains("live_status"));
+            let messages = payloads[1]["messages"].as_array().unwrap();
+            let result = messages.last().unwrap();
+            assert_eq!(result["role"], "tool");
+            assert!(result["content"].as_str().unwrap().contains("测试标题"));
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert!(text_of(&sent[0]).contains("mock answer"));
+        });
+    }
+
+    #[test]
+    fn test_reaction_tracking() {
+        init();
+        run(async {
+            let group_id = 33;
+            let reaction = |sub_type: &str, operator_id: i64, code: &str| {
+                serde_json::from_value::<group_notice::NoticeResponse>(json!({
+                    "notice_type": "reaction",
+                    "sub_type": sub_type,
+                    "time": 1234,
+                    "self_id": BOT_ID,
+                    "group_id": group_id,
+                    "message_id": 7001,
+                    "operator_id": operator_id,
+                    "code": code,
+                    "count": 1
+                }))
+                .unwrap()
+            };
+            let reply = store::GroupChatSegment {
+                message_id: 7001,
+                time: String::from("1970-01-01 08:00:00"),
+                sender_id: BOT_ID,
+                sender_name: String::from("momo"),
+                seg_type: String::from("text"),
+                content: String::from("冷笑话"),
+                interpret: String::from("text"),
+            };
+            reply.db_store(group_id).await.unwrap();
+            for operator_id in [42, 43, 44, BOT_ID] {
+                group_notice::dispatch(reaction("add", operator_id, "76")).await;
+            }
+            group_notice::dispatch(reaction("add", 42, "76")).await;
+            group_notice::dispatch(reaction("remove", 44, "76")).await;
+            let counts = store::db_reaction_counts(group_id, "1970-01-01")
+                .await
+                .unwrap();
+            assert_eq!(counts.get(&7001), Some(&2));
+
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .at(BOT_ID)
+                .text("再讲一个")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            agent::at_me_handler(e).await;
+            let payload = mock()
+                .agent_payloads
+                .iter()
+                .find(|p| p.to_string().contains("再讲一个"))
+                .cloned()
+                .unwrap();
+            assert!(payload.to_string().contains("momo: 冷笑话 [收到2个回应]"));
+        });
+    }
+
+    #[test]
+    fn test_album_commands() {
+        init();
+        run(async {
+            let group_id = 33;
+            let image = |message_id: i32, interpret: &str| store::GroupChatSegment {
+                message_id,
+                time: util::cur_time_iso8601(None),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("image"),
+                content: format!("/tmp/{message_id}.png"),
+                interpret: interpret.to_string(),
+            };
+            image(8001, "https://example.com/1.png")
+                .db_store(group_id)
+                .await
+                .unwrap();
+            image(8002, "").db_store(group_id).await.unwrap();
+            let console = |text: &str| PrivateMsgBuilder::new(ADMIN_ID).text(text).build();
+            let nodes_of = |text: &str| {
+                sent_to_private(ADMIN_ID)
+                    .into_iter()
+                    .rev()
+                    .find(|m| m.iter().any(|s| s.data.to_string().contains(text)))
+            };
+            assert!(command::private_console(console("群33 最近图片 1 2")).await);
+            let nodes = nodes_of("https://example.com/1.png").unwrap();
+            assert_eq!(nodes.iter().len(), 1);
+            assert_eq!(nodes.iter().next().unwrap().data["uin"], "42");
+            assert!(command::private_console(console("群33 最近图片 1")).await);
+            assert!(nodes_of("file:///tmp/8002.png").is_some());
+
+            assert!(command::private_console(console("群33 群文件")).await);
+            assert!(sent_to_private(ADMIN_ID)
+                .iter()
+                .any(|m| text_of(m).starts_with("获取群文件失败")));
+            mock().group_files.insert(
+                group_id,
+                json!({
+                    "files": [
+                        {"file_name": "old.txt", "file_size": 10, "upload_time": 0, "uploader_name": "李四"},
+                        {"file_name": "new.zip", "file_size": 1572864, "upload_time": 86400, "uploader_name": "张三"}
+                    ],
+                    "folders": null
+                }),
+            );
+            assert!(command::private_console(console("群33 群文件")).await);
+            let nodes = nodes_of("new.zip").unwrap();
+            let lines: Vec<String> = nodes
+                .iter()
+                .map(|n| n.data["content"][0]["data"]["text"].to_string())
+                .collect();
+            assert_eq!(lines.len(), 2);
+            assert!(lines[0].contains("new.zip (1.5MB), 张三上传于1970-01-02 08:00:00"));
+            assert!(lines[1].contains("old.txt (10B)"));
+        });
+    }
+
+    #[test]
+    fn test_language() {
+        init();
+        run(async {
+            let agent_with = |action: LanguageAction, instruction: &str| AgentSetting {
+                language: Some(LanguageSetting {
+                    expect: Language::Zh,
+                    min_ratio: 0.6,
+                    action,
+                    instruction: instruction.to_string(),
+                }),
+                ..Default::default()
+            };
+            {
+                let mut mock = mock();
+                let answers = &mut mock.agent_answers;
+                answers.insert("retry-test".into(), "好的我用中文回答".into());
+                answers.insert("translate-test".into(), "这是翻译后的回答".into());
+            }
+            let english = || String::from("Sure, here you go my friend");
+            let prompts = ("dev", "question");
+
+            let agent = agent_with(LanguageAction::Retry, "只用中文回答 retry-test");
+            let answer = language::enforce(&agent, "gpt-4o-mini", prompts, english()).await;
+            assert_eq!(answer, "好的我用中文回答");
+            let answer =
+                language::enforce(&agent, "gpt-4o-mini", prompts, "本来就是中文".into()).await;
+            assert_eq!(answer, "本来就是中文");
+
+            let agent = agent_with(LanguageAction::Translate, "翻译成中文 translate-test");
+            let answer = language::enforce(&agent, "gpt-4o-mini", prompts, english()).await;
+            assert_eq!(answer, "这是翻译后的回答");
+            let payload = mock()
+                .agent_payloads
+                .iter()
+                .map(|p| p.to_string())
+                .find(|p| p.contains("translate-test"))
+                .unwrap();
+            assert!(payload.contains("Sure, here you go my friend"));
+        });
+    }
+
+    #[test]
+    fn test_endpoint_rotation() {
+        init();
+        #[derive(serde::Deserialize)]
+        struct Wrapper {
+            api_url: ApiUrl,
+        }
+        let parse = |s: &str| toml::from_str::<Wrapper>(s).unwrap().api_url;
+        let one = parse(r#"api_url = "https://a.test/v1""#);
+        assert_eq!(one.urls(), ["https://a.test/v1"]);
+        let many = parse(r#"api_url = ["https://a.test/v1", "https://b.test/v1"]"#);
+        assert_eq!(many.urls().len(), 2);
+
+        run(async {
+            mock().down_urls.insert(String::from("https://a.test/v1"));
+            for _ in 0..2 {
+                let answer = agent::complete(&many, "key", "gpt-4o-mini", "dev", "user").await;
+                assert_eq!(answer.as_deref(), Some("mock answer"));
+            }
+            // the failing endpoint is benched
+            assert_eq!(
+                endpoint::order(&many),
+                ["https://b.test/v1", "https://a.test/v1"]
+            );
+
+            mock().down_urls.clear();
+            endpoint::report("https://a.test/v1", true);
+            let firsts: HashSet<_> = (0..2).map(|_| endpoint::order(&many)[0]).collect();
+            assert_eq!(firsts.len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_calendar_variables() {
+        init();
+        run(async {
+            assert!(calendar::today(1).starts_with(&util::cur_date_iso8601(None)));
+
+            let setting = WeatherSetting {
+                city: String::from("testcity"),
+                api_url: Some(String::from("https://weather.example.com/<!city!>")),
+            };
+            assert_eq!(calendar::weather(&setting).await, "");
+            mock().pages.insert(
+                String::from("https://weather.example.com/testcity"),
+                String::from("晴 +25°C\n"),
+            );
+            assert_eq!(calendar::weather(&setting).await, "晴 +25°C");
+            // cached
+            mock().pages.remove("https://weather.example.com/testcity");
+            assert_eq!(calendar::weather(&setting).await, "晴 +25°C");
+        });
+    }
+
+    #[test]
+    fn test_bot_card() {
+        init();
+        run(async {
+            let group_id = 33;
+            let card_of_bot = || mock().members.get(&(group_id, BOT_ID)).cloned();
+            let e = PrivateMsgBuilder::new(ADMIN_ID)
+                .text("群33 设置群名片 小桃")
+                .build();
+            assert!(command::private_console(e).await);
+            assert_eq!(card_of_bot().as_deref(), Some("小桃"));
+            assert!(sent_to_private(ADMIN_ID)
+                .iter()
+                .any(|m| text_of(m) == "群名片已改为小桃"));
+
+            let setting = CardSetting {
+                names: vec![String::from("早"), String::from("晚")],
+                rotate_interval_sec: Some(60),
+            };
+            let rotated: Vec<_> = (0..3)
+                .filter_map(|_| card::rotate(group_id, &setting))
+                .collect();
+            assert_eq!(rotated, ["早", "晚", "早"]);
+            assert_eq!(card_of_bot().as_deref(), Some("早"));
+            let empty = CardSetting {
+                names: vec![],
+                rotate_interval_sec: Some(60),
+            };
+            assert!(card::rotate(group_id, &empty).is_none());
+        });
+    }
+
+    #[test]
+    fn test_join_request() {
+        init();
+        run(async {
+            let group_id = 33;
+            let request = |user_id: i64, comment: &str| join_request::JoinRequest {
+                group_id,
+                user_id,
+                comment: comment.to_string(),
+                flag: format!("flag{user_id}"),
+            };
+            let answer_of = |flag: &str| {
+                let mock = mock();
+                let answer = mock.join_answers.iter().find(|(f, _)| f == flag);
+                answer.map(|(_, approve)| *approve)
+            };
+            join_request::handle(request(61, "12345678")).await;
+            assert_eq!(answer_of("flag61"), None);
+
+            mock()
+                .roles
+                .insert((group_id, BOT_ID), String::from("admin"));
+            mock().levels.insert(62, 30);
+            join_request::handle(request(62, "")).await;
+            join_request::handle(request(63, "邀请码12345678")).await;
+            join_request::handle(request(64, "路过")).await;
+            assert_eq!(answer_of("flag62"), Some(true));
+            assert_eq!(answer_of("flag63"), Some(true));
+            assert_eq!(answer_of("flag64"), None);
+            let announced = sent_to_group(group_id);
+            assert!(announced
+                .iter()
+                .any(|m| text_of(m) == "已通过62的入群申请, QQ等级达到要求"));
+            assert!(announced
+                .iter()
+                .any(|m| text_of(m) == "已通过63的入群申请, 申请信息包含关键词"));
+            assert!(sent_to_private(ADMIN_ID)
+                .iter()
+                .any(|m| text_of(m) == "群33收到64的入群申请, 需要人工审核: 路过"));
+        });
+    }
+
+    #[test]
+    fn test_levels() {
+        init();
+        run(async {
+            let group_id = 33;
+            let setting = LevelSetting {
+                xp_per_message: 60,
+                daily_cap: 150,
+                ..Default::default()
+            };
+            mock().members.insert((group_id, 71), String::from("阿七"));
+            let award = |text: &'static str, now: i64| {
+                let setting = setting.clone();
+                async move {
+                    level::award(group_id, 71, text, &setting, now)
+                        .await
+                        .unwrap()
+                }
+            };
+            assert_eq!(award("嗯", 0).await, None);
+            assert_eq!(award("早上好", 1000).await, None);
+            assert_eq!(award("又来了", 1010).await, None);
+            assert_eq!(award("吃了吗", 1060).await, Some(2));
+            assert_eq!(award("还在吗", 1120).await, None);
+            assert_eq!(award("睡了吗", 1180).await, None);
+            let member = store::db_load_level(group_id, 71).await.unwrap().unwrap();
+            assert_eq!((member.xp, member.day_xp), (150, 150));
+
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(71)
+                .text("我的等级")
+                .build();
+            level::level_handler(e).await;
+            let e = GroupMsgBuilder::new(group_id).text("等级排行").build();
+            level::level_handler(e).await;
+            let texts: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert!(texts
+                .iter()
+                .any(|t| t.ends_with("你当前2级, 经验150/300, 群内排名第1")));
+            assert!(texts.iter().any(|t| t == "等级排行\n1. 阿七: 2级, 150经验"));
+        });
+    }
+
+    #[test]
+    fn test_word_filter() {
+        init();
+        run(async {
+            let group_id = 33;
+            util::send_group_and_log(group_id, "废物点心").await;
+            assert!(sent_to_group(group_id)
+                .iter()
+                .any(|m| text_of(m) == "**点心"));
+
+            mock().members.insert((group_id, 72), String::from("小八"));
+            for text in ["你是废物", "好的", "傻逼"] {
+                let e = GroupMsgBuilder::new(group_id).sender(72).text(text).build();
+                word_filter::warn_handler(e).await;
+            }
+            let warnings: Vec<_> = sent_to_group(group_id)
+                .iter()
+                .map(text_of)
+                .filter(|t| t.contains("小八请注意文明用语"))
+                .collect();
+            assert_eq!(warnings.len(), 2);
+            assert!(warnings[1].ends_with("这是第2次警告"));
+        });
+    }
+
+    #[test]
+    fn test_live_screenshot() {
+        init();
+        run(async {
+            let group_id = 33;
+            let (room_id, keyframe) = ("87654321", "https://i0.hdslb.com/keyframe.jpg");
+            let message = live::screenshot(group_id, room_id, "下播了").await;
+            assert_eq!(text_of(&message), "直播间87654321不存在");
+            set_liveroom(room_id, false, "");
+            let message = live::screenshot(group_id, room_id, "下播了").await;
+            assert_eq!(text_of(&message), "下播了");
+
+            let mut room = json!({
+                "code": 0,
+                "data": {
+                    "live_status": 1,
+                    "online": 42,
+                    "attention": 0,
+                    "keyframe": keyframe,
+                    "user_cover": "",
+                    "area_name": "单机游戏",
+                    "description": "",
+                    "title": "通关",
+                    "live_time": "2026-01-01 20:00:00",
+                }
+            });
+            mock().images.insert(keyframe.to_string(), vec![0; 16]);
+            mock().live_rooms.insert(room_id.to_string(), room.clone());
+            let message = live::screenshot(group_id, room_id, "下播了").await;
+            assert!(message.contains("image"));
+            assert_eq!(
+                text_of(&message),
+                "通关\n分区:单机游戏, 热度:42, 开播于2026-01-01 20:00:00"
+            );
+
+            room["data"]["keyframe"] = json!("");
+            mock().live_rooms.insert(room_id.to_string(), room);
+            let message = live::screenshot(group_id, room_id, "下播了").await;
+            assert!(!message.contains("image"));
+            assert!(text_of(&message).ends_with("暂时没有直播画面"));
+        });
+    }
+
+    #[test]
+    fn test_timezone() {
+        init();
+        assert_eq!(
+            util::iso8601_from_timestamp(Some(1), 0).unwrap(),
+            "1970-01-01 08:00:00"
+        );
+        let group = GroupSetting {
+            timezone: Some(String::from("UTC+8")),
+            ..Default::default()
+        };
+        assert!(
+            self_check::group_problems(&group).contains(&String::from("时区格式无效, 应为+08:00"))
+        );
+    }
+
+    #[test]
+    fn test_reminder() {
+        init();
+        run(async {
+            let group_id = 33;
+            let remind = |text: &str| GroupMsgBuilder::new(group_id).sender(73).text(text).build();
+            reminder::reminder_handler(remind("提醒我 喝水")).await;
+            reminder::reminder_handler(remind("提醒我 1小时后 喝水")).await;
+            let texts: Vec<_> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert!(texts
+                .iter()
+                .any(|t| t.ends_with("例如提醒我 明天下午三点 开会")));
+            assert!(texts.iter().any(|t| t.ends_with("提醒你: 喝水")));
+            assert_eq!(store::db_count_reminders(group_id, 73).await.unwrap(), 1);
+
+            reminder::fire_due(0).await;
+            assert_eq!(store::db_count_reminders(group_id, 73).await.unwrap(), 1);
+            reminder::fire_due(i64::MAX).await;
+            assert_eq!(store::db_count_reminders(group_id, 73).await.unwrap(), 0);
+            let fired = sent_to_group(group_id);
+            let fired = fired.last().unwrap();
+            assert!(fired.contains("at") && text_of(fired).ends_with("提醒: 喝水"));
+        });
+    }
+
+    #[test]
+    fn test_mention_statistics() {
+        init();
+        run(async {
+            let group_id = 28;
+            {
+                let mut mock = mock();
+                for (id, name) in [(44, "张三"), (45, "李四"), (46, "王五")] {
+                    mock.members.insert((group_id, id), String::from(name));
+                }
+            }
+            for (sender_id, target_id) in [(44, 45), (44, 45), (46, 45), (45, 44), (45, 45)] {
+                let e = GroupMsgBuilder::new(group_id)
+                    .sender(sender_id)
+                    .at(target_id)
+                    .text("在吗")
+                    .build();
+                agent::logger(e).await;
+            }
+            let e = GroupMsgBuilder::new(group_id)
+                .sender(45)
+                .text("谁最关心我")
+                .build();
+            mention::mention_handler(e).await;
+            let e = GroupMsgBuilder::new(group_id).text("艾特排行 7").build();
+            mention::mention_handler(e).await;
+            let e = GroupMsgBuilder::new(group_id).text("艾特排行 上周").build();
+            mention::mention_handler(e).await;
+            // would overflow the lower bound
+            let e = GroupMsgBuilder::new(group_id)
+                .text("艾特排行 999999999999999999")
+                .build();
+            mention::mention_handler(e).await;
+            let sent: Vec<String> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(
+                sent[0],
+                "有史以来最关心李四的人:\n1. 张三 艾特了2次\n2. 王五 艾特了1次"
+            );
+            assert_eq!(
+                sent[1],
+                "最近7天艾特排行:\n1. 李四 被艾特3次\n2. 张三 被艾特1次"
+            );
+            assert!(sent[2].starts_with("用法"));
+            assert!(sent[3].contains("3650"));
+        });
+    }
+
+    #[test]
+    fn test_unfurl_links() {
+        init();
+        run(async {
+            let group_id = 19;
+            let page = |title: &str| {
+                format!(
+                    r#"<html><head><title>{title}</title>
+                    <meta content="A &amp; B" property="og:description"></head></html>"#
+                )
+            };
+            {
+                let mut mock = mock();
+                mock.pages.insert(
+                    String::from("https://github.com/octo/bot"),
+                    page("octo/bot"),
+                );
+                mock.pages
+                    .insert(String::from("https://blog.test/post"), page("Post"));
+                mock.pages
+                    .insert(String::from("http://127.0.0.1/admin"), page("Admin"));
+            }
+            let e = GroupMsgBuilder::new(group_id)
+                .text("看看https://github.com/octo/bot，还有 https://blog.test/post http://127.0.0.1/admin")
+                .build();
+            agent::logger(Arc::clone(&e)).await;
+            unfurl::preview_handler(e).await;
+
+            // only whitelisted domain is previewed
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "octo/bot\nA & B");
+
+            // private host is never fetched
+            let segs = store::db_load_n_group_segment(group_id, 10).await.unwrap();
+            let links: Vec<(&str, &str)> = segs
+                .iter()
+                .filter(|s| s.seg_type == "link")
+                .map(|s| (s.content.as_str(), s.interpret.as_str()))
+                .collect();
+            assert_eq!(
+                links,
+                vec![
+                    ("https://github.com/octo/bot", "octo/bot - A & B"),
+                    ("https://blog.test/post", "Post - A & B"),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_who_said() {
+        init();
+        run(async {
+            let group_id = 20;
+            let msg = |sender: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .text(text)
+                    .build()
+            };
+            for (sender, text) in [
+                (61, "周末去爬山吗"),
+                (62, "今天好热"),
+                (63, "我也想周末去爬山"),
+            ] {
+                agent::logger(msg(sender, text)).await;
+            }
+
+            for text in ["谁说过 周末去爬山", "谁说过 爬山", "谁说过 下雨"] {
+                let e = msg(64, text);
+                agent::logger(Arc::clone(&e)).await;
+                search::search_handler(e).await;
+            }
+            let sent = sent_to_group(group_id);
+            let reply = text_of(&sent[0]);
+            assert!(reply.starts_with("「周末去爬山」共出现2次"));
+            assert!(reply.contains("61: 周末去爬山吗"));
+            assert!(reply.contains("最近: ") && reply.contains("63: 我也想周末去爬山"));
+            assert!(text_of(&sent[1]).starts_with("「爬山」共出现2次"));
+            assert!(text_of(&sent[2]).contains("没有人说过「下雨」"));
+        });
+    }
+
+    #[test]
+    fn test_history_question() {
+        init();
+        run(async {
+            let group_id = 42;
+            let msg = |sender: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .text(text)
+                    .build()
+            };
+            for (sender, text) in [
+                (71, "下周六去黄山团建"),
+                (72, "今天好热"),
+                (73, "黄山团建记得带外套"),
+            ] {
+                agent::logger(msg(sender, text)).await;
+            }
+
+            let e = msg(74, "群史问答 黄山团建是哪天");
+            agent::logger(Arc::clone(&e)).await;
+            search::search_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(text_of(&sent[0]), "mock answer");
+            let payload = mock()
+                .agent_payloads
+                .iter()
+                .map(|p| p.to_string())
+                .find(|p| p.contains("黄山团建是哪天"))
+                .unwrap();
+            assert!(payload.contains("71: 下周六去黄山团建"));
+            assert!(payload.contains("73: 黄山团建记得带外套"));
+            assert!(!payload.contains("今天好热"));
+            // the question itself is not history
+            assert!(!payload.contains("74: 群史问答"));
+
+            let e = msg(74, "群史问答 有人养猫吗");
+            search::search_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert!(text_of(&sent[1]).contains("没有找到相关内容"));
+        });
+    }
+
+    #[test]
+    fn test_repost() {
+        init();
+        run(async {
+            let group_id = 43;
+            let big = "big.jpg";
+            let small = "small.gif";
+            let dir = DATA_PATH.get().unwrap().join("repost");
+            std::fs::create_dir_all(&dir).unwrap();
+            {
+                let mut mock = mock();
+                for (file, bytes) in [(big, vec![7; 30 * 1024]), (small, vec![8; 1024])] {
+                    let path = dir.join(file);
+                    std::fs::write(&path, bytes).unwrap();
+                    let path = path.to_string_lossy().to_string();
+                    mock.media.insert(file.to_string(), path);
+                }
+                mock.members.insert((group_id, 81), String::from("张三"));
+            }
+            let post = |sender: i64, file: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .image(file)
+                    .build()
+            };
+
+            repost::repost_handler(post(81, big)).await;
+            // own repost and small images are not teased
+            repost::repost_handler(post(81, big)).await;
+            repost::repost_handler(post(81, small)).await;
+            repost::repost_handler(post(82, small)).await;
+            assert!(sent_to_group(group_id).is_empty());
+
+            repost::repost_handler(post(82, big)).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            let today = crate::util::cur_date_iso8601(Some(group_id));
+            assert!(text_of(&sent[0]).contains(&format!("张三在{today}就发过这张图")));
+        });
+    }
+
+    #[test]
+    fn test_activity_heatmap() {
+        init();
+        run(async {
+            let group_id = 44;
+            let msg = || GroupMsgBuilder::new(group_id).text("活跃热力图").build();
+            activity::activity_handler(msg()).await;
+            assert_eq!(text_of(&sent_to_group(group_id)[0]), "最近28天没有聊天记录");
+
+            let now = crate::util::cur_time_iso8601(Some(group_id));
+            let segs = [
+                (1, 91, now.as_str()),
+                (2, 92, now.as_str()),
+                (3, BOT_ID, now.as_str()),
+                (4, 91, "2020-01-01 10:00:00"),
+            ];
+            for (message_id, sender_id, time) in segs {
+                let seg = store::GroupChatSegment {
+                    message_id,
+                    time: time.to_string(),
+                    sender_id,
+                    sender_name: sender_id.to_string(),
+                    seg_type: String::from("text"),
+                    content: String::from("早"),
+                    interpret: String::new(),
+                };
+                seg.db_store(group_id).await.unwrap();
+            }
+            activity::activity_handler(msg()).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 2);
+            let local = crate::util::now_in(Some(group_id));
+            let day = local.weekday().number_days_from_monday() as u32;
+            let hour = local.hour() as u32;
+            let weekday = ["一", "二", "三", "四", "五", "六", "日"][day as usize];
+            assert_eq!(
+                text_of(&sent[1]),
+                format!("最近28天共2条消息, 最活跃: 周{weekday}{hour}点")
+            );
+
+            let images = sent[1].get("image");
+            let file = images[0].data["file"].as_str().unwrap();
+            let image = image::open(file.trim_start_matches("file://"))
+                .unwrap()
+                .to_rgba8();
+            assert_eq!(image.dimensions(), (828, 272));
+            let cell =
+                |day: u32, hour: u32| image.get_pixel(48 + hour * 32 + 16, 36 + day * 32 + 16).0;
+            assert_eq!(cell(day, hour), [33, 110, 57, 255]);
+            assert_eq!(cell((day + 1) % 7, hour), [235, 237, 240, 255]);
+        });
+    }
+
+    #[test]
+    fn test_quota() {
+        init();
+        run(async {
+            let group_id = 45;
+            let msg = |sender: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .text(text)
+                    .build()
+            };
+            for _ in 0..20 {
+                search::search_handler(msg(61, "谁说过 火锅")).await;
+            }
+            assert_eq!(sent_to_group(group_id).len(), 20);
+            search::search_handler(msg(61, "谁说过 火锅")).await;
+            // quota is per member
+            search::search_handler(msg(62, "谁说过 火锅")).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(text_of(&sent[20]), "今日搜索聊天记录次数已用完, 每天20次");
+            assert_eq!(text_of(&sent[21]), "没有人说过「火锅」");
+
+            command::act(msg(ADMIN_ID, "加额度 61 search 1")).await;
+            assert_eq!(
+                text_of(&sent_to_group(group_id)[22]),
+                "已为61增加今日search额度1次"
+            );
+            search::search_handler(msg(61, "谁说过 火锅")).await;
+            search::search_handler(msg(61, "谁说过 火锅")).await;
+            quota::query_handler(msg(61, "我的额度")).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(text_of(&sent[23]), "没有人说过「火锅」");
+            assert!(text_of(&sent[24]).starts_with("今日搜索聊天记录次数已用完"));
+            assert_eq!(
+                text_of(&sent[25]),
+                "今日剩余次数\n做表情: 10/10\n搜索聊天记录: 0/21\n导出: 3/3"
+            );
+        });
+    }
+
+    #[test]
+    fn test_annual_report() {
+        init();
+        run(async {
+            let group_id = 21;
+            mock().members.insert((group_id, 72), String::from("李四"));
+            let segs = [
+                (1, 71, "2025-03-01 23:10:00", "text", "今晚打游戏吗"),
+                (1, 71, "2025-03-01 23:10:00", "at", "72"),
+                (2, 71, "2025-05-02 23:40:00", "text", "打游戏打游戏"),
+                (3, 71, "2025-06-03 09:00:00", "text", "早上好, rust yyds"),
+                (4, 72, "2025-06-03 09:01:00", "text", "早"),
+                (5, 71, "2024-12-31 23:00:00", "text", "打游戏"),
+            ];
+            for (message_id, sender_id, time, seg_type, content) in segs {
+                let seg = store::GroupChatSegment {
+                    message_id,
+                    time: time.to_string(),
+                    sender_id,
+                    sender_name: sender_id.to_string(),
+                    seg_type: seg_type.to_string(),
+                    content: content.to_string(),
+                    interpret: String::from("李四"),
+                };
+                seg.db_store(group_id).await.unwrap();
+            }
+
+            let msg = |sender: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender)
+                    .text(text)
+                    .build()
+            };
+            annual_report::report_handler(msg(71, "年度报告 2025")).await;
+            annual_report::report_handler(msg(73, "年度报告 2025")).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(
+                text_of(&sent[0]),
+                indoc::indoc! {"
+                    71的2025年度报告
+                    发言3条, 本群第1/2
+                    最活跃的时段: 23点, 发言2条
+                    最常@的人: 李四, 1次
+                    口头禅: 「打游」2次, 「游戏」2次"
+                }
+            );
+            assert!(text_of(&sent[1]).contains("还没有在本群发言"));
+        });
+    }
+
+    #[test]
+    fn test_pipeline() {
+        init();
+        // configured groups run all stages by default
+        assert!(global_state::pipeline_runs(1, PipelineStage::Logger));
+        assert!(global_state::pipeline_runs(
+            GROUP_COUNT + 1,
+            PipelineStage::Agent
+        ));
+    }
+
+    #[test]
+    fn test_pipeline_middleware() {
+        init();
+        run(async {
+            let group_id = 34;
+            let stages = [
+                PipelineStage::Blacklist,
+                PipelineStage::RateLimit,
+                PipelineStage::Keyword,
+            ];
+            let greet = |sender_id| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender_id)
+                    .text("大家早上好")
+                    .build()
+            };
+            // blacklisted by default
+            pipeline::run_stages(greet(12345678), &stages).await;
+            assert!(sent_to_group(group_id).is_empty());
+            pipeline::run_stages(greet(74), &stages).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "早上好呀");
+
+            // 20 messages per minute by default
+            for _ in 0..21 {
+                pipeline::run_stages(greet(75), &stages).await;
+            }
+            assert_eq!(sent_to_group(group_id).len(), 21);
+            // admins are exempted
+            pipeline::run_stages(greet(ADMIN_ID), &stages).await;
+            assert_eq!(sent_to_group(group_id).len(), 22);
+        });
+    }
+
+    #[test]
+    fn test_ignore() {
+        init();
+        run(async {
+            let group_id = 41;
+            let stages = [
+                PipelineStage::Ignore,
+                PipelineStage::Logger,
+                PipelineStage::Keyword,
+            ];
+            let msgs = [
+                // another bot by default
+                GroupMsgBuilder::new(group_id)
+                    .message_id(1)
+                    .sender(34567890)
+                    .text("早上好"),
+                GroupMsgBuilder::new(group_id)
+                    .message_id(2)
+                    .sender(74)
+                    .text(" /早上好"),
+                (0..4).fold(
+                    GroupMsgBuilder::new(group_id).message_id(3).sender(74),
+                    |msg, i| msg.image(&format!("{i}.image")),
+                ),
+                GroupMsgBuilder::new(group_id)
+                    .message_id(4)
+                    .sender(74)
+                    .text("早上好"),
+            ];
+            for msg in msgs {
+                pipeline::run_stages(msg.build(), &stages).await;
+            }
+            for message_id in 1..=3 {
+                let segs = store::db_find_segment_by_id(group_id, message_id)
+                    .await
+                    .unwrap();
+                assert!(segs.is_empty());
+            }
+            let segs = store::db_find_segment_by_id(group_id, 4).await.unwrap();
+            assert_eq!(segs.len(), 1);
+            let sent = sent_to_group(group_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(text_of(&sent[0]), "早上好呀");
+        });
+    }
+
+    #[test]
+    fn test_locale_override() {
+        init();
+        let dir = DATA_PATH.get().unwrap().join("locales");
+        std::fs::create_dir_all(&dir).unwrap();
+        let content = indoc::indoc! {r#"
+            "command.mute" = "Muted"
+
+            [live]
+            room_not_found = "Room <!room!> not found"
+        "#};
+        std::fs::write(dir.join("test-en.toml"), content).unwrap();
+
+        let en = Some("test-en");
+        assert_eq!(locale::format_in(en, "command.mute", &[]), "Muted");
+        let room = locale::format_in(en, "live.room_not_found", &[("room", &42)]);
+        assert_eq!(room, "Room 42 not found");
+        // missing keys and missing files fall back to zh-CN
+        let unmute = "我勉为其难地同意和你们聊天";
+        assert_eq!(locale::format_in(en, "command.unmute", &[]), unmute);
+        assert_eq!(
+            locale::format_in(Some("nope"), "command.unmute", &[]),
+            unmute
+        );
+        assert_eq!(
+            locale::format_in(Some("../x"), "command.unmute", &[]),
+            unmute
+        );
+        assert_eq!(
+            crate::tr!(1, "live.room_not_found", room = 7),
+            "直播间7不存在"
+        );
+    }
+
+    #[test]
+    fn test_hooks() {
+        struct Recorder(Arc<Mutex<Vec<String>>>);
+        impl crate::Hook for Recorder {
+            fn on_agent_reply(
+                &self,
+                group_id: i64,
+                _reply_id: i32,
+                prompt: &str,
+                answer: &agent::GroupAnswer,
+            ) {
+                let line = format!("{group_id} reply {prompt} {}", answer.text);
+                self.0.lock().unwrap().push(line);
+            }
+
+            fn on_command(
+                &self,
+                group_id: i64,
+                requester: i64,
+                command: &global_state::GroupCommand,
+            ) {
+                let line = format!("{group_id} command {requester} {command:?}");
+                self.0.lock().unwrap().push(line);
+            }
+
+            fn on_live_status_change(&self, group_id: i64, room_id: &str, room: &live::LiveRoom) {
+                let line = format!("{group_id} live {room_id} {}", room.data.is_streaming);
+                self.0.lock().unwrap().push(line);
+            }
+        }
+
+        init();
+        run(async {
+            let group_id = 46;
+            let events = Arc::new(Mutex::new(Vec::new()));
+            crate::register_hook(Recorder(Arc::clone(&events)));
+            // hooks are process wide, so only events of this group count
+            let of_group = || -> Vec<String> {
+                let prefix = format!("{group_id} ");
+                let events = events.lock().unwrap();
+                events
+                    .iter()
+                    .filter_map(|e| e.strip_prefix(&prefix).map(str::to_string))
+                    .collect()
+            };
+
+            let at = GroupMsgBuilder::new(group_id)
+                .at(BOT_ID)
+                .text("你好")
+                .build();
+            agent::logger(Arc::clone(&at)).await;
+            agent::at_me_handler(at).await;
+            let mute = GroupMsgBuilder::new(group_id).text("禁用聊天回复").build();
+            command::act(mute).await;
+
+            let live = global_state::LiveSetting {
+                room_id: String::from("20046"),
+                notify_window_sec: None,
+                ..Default::default()
+            };
+            // first observation is not a change
+            set_liveroom("20046", false, "");
+            live::poll_room(group_id, &live).await;
+            set_liveroom("20046", true, "开播标题");
+            live::poll_room(group_id, &live).await;
+
+            assert_eq!(
+                of_group(),
+                [
+                    String::from("reply 你好 mock answer"),
+                    format!("command {ADMIN_ID} Mute"),
+                    String::from("live 20046 true"),
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_usage_stat() {
+        init();
+        run(async {
+            let group_id = 47;
+            let stat = || GroupMsgBuilder::new(group_id).text("功能使用统计").build();
+            command::act(stat()).await;
+            // the command counts itself
+            assert_eq!(
+                text_of(&sent_to_group(group_id)[0]),
+                "最近30天功能使用次数:\ncommand.usage_stat: 1"
+            );
+
+            for _ in 0..2 {
+                let at = GroupMsgBuilder::new(group_id)
+                    .at(BOT_ID)
+                    .text("你好")
+                    .build();
+                agent::logger(Arc::clone(&at)).await;
+                agent::at_me_handler(at).await;
+            }
+            set_liveroom("20047", false, "");
+            let query = GroupMsgBuilder::new(group_id)
+                .text("查询直播间 20047")
+                .build();
+            live::general_query_handler(query).await;
+            command::act(stat()).await;
+            let sent = sent_to_group(group_id);
+            assert_eq!(
+                text_of(&sent[4]),
+                "最近30天功能使用次数:\nagent: 2\ncommand.usage_stat: 2\nlive_query: 1"
+            );
+        });
+    }
+
+    #[test]
+    fn test_reply_once() {
+        init();
+        run(async {
+            let group_id = 48;
+            let stages = [
+                PipelineStage::Logger,
+                PipelineStage::Keyword,
+                PipelineStage::Agent,
+            ];
+            let at = |text: &str| GroupMsgBuilder::new(group_id).at(BOT_ID).text(text).build();
+            // the rule does not stop the pipeline, but the agent does not answer again
+            pipeline::run_stages(at("早上好"), &stages).await;
+            pipeline::run_stages(at("你好"), &stages).await;
+            let sent: Vec<String> = sent_to_group(group_id).iter().map(text_of).collect();
+            assert_eq!(sent, ["早上好呀", "mock answer"]);
+        });
+    }
+
+    #[test]
+    fn test_long_message() {
+        init();
+        run(async {
+            let group_id = 49;
+            let article = "喵呜".repeat(600);
+            mock()
+                .agent_answers
+                .insert(article.clone(), String::from("一篇关于猫叫的文章"));
+            let pasted = GroupMsgBuilder::new(group_id)
+                .sender(42)
+                .text(&article)
+                .build();
+            agent::logger(pasted).await;
+            // raw text is kept along with the summary
+            let history = store::db_load_n_group_segment(group_id, 1).await.unwrap();
+            assert_eq!(history[0].content, article);
+            assert_eq!(history[0].interpret, "一篇关于猫叫的文章");
+
+            let at = GroupMsgBuilder::new(group_id)
+                .sender(43)
+                .at(BOT_ID)
+                .text("上面说了什么")
+                .build();
+            agent::at_me_handler(at).await;
+            let payloads: Vec<String> = mock()
+                .agent_payloads
+                .iter()
+                .map(|p| p.to_string())
+                .filter(|p| p.contains("上面说了什么"))
+                .collect();
+            assert_eq!(payloads.len(), 1);
+            assert!(payloads[0].contains("[长消息摘要]一篇关于猫叫的文章"));
+            assert!(!payloads[0].contains(&article));
+
+            // the message itself is summarized once for both history and prompt
+            let asked = GroupMsgBuilder::new(group_id)
+                .sender(44)
+                .at(BOT_ID)
+                .text(&article)
+                .build();
+            agent::logger(Arc::clone(&asked)).await;
+            agent::at_me_handler(asked).await;
+            let summarized = mock()
+                .agent_payloads
+                .iter()
+                .filter(|p| p.to_string().contains(&article))
+                .count();
+            assert_eq!(summarized, 1);
+        });
+    }
+
+    #[test]
+    fn test_show_config() {
+        init();
+        run(async {
+            let group_id = 50;
+            let cmd = |text: &str| GroupMsgBuilder::new(group_id).text(text).build();
+            command::act(cmd("禁用聊天回复")).await;
+            command::act(cmd("更换模型 o1-mini")).await;
+            command::act(cmd("查看配置")).await;
+            let sent = sent_to_group(group_id);
+            let nodes = sent[2].get("node");
+            let text = |i: usize| {
+                nodes[i].data["content"][0]["data"]["text"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            };
+            let state = text(0);
+            assert!(state.contains("助理: 已禁用, 当前模型: o1-mini (配置为chatgpt-4o-latest)"));
+            assert!(state.contains(&format!("命令管理员: 1234, 5678, {ADMIN_ID}")));
+            assert!(text(1).starts_with("id = 50"));
+
+            let agent = (1..nodes.len())
+                .map(text)
+                .find(|t| t.starts_with("[agent]"))
+                .unwrap();
+            assert!(agent.contains("api_key = \"******\""));
+            assert!(!agent.contains("API KEY"));
+        });
+    }
+
+    #[test]
+    fn test_run_status() {
+        init();
+        run(async {
+            let group_id = 51;
+            // killed an hour ago, then failed to start once
+            let killed = crate::util::iso8601_from_timestamp(
+                None,
+                crate::util::now_in(None).unix_timestamp() - 3600,
+            )
+            .unwrap();
+            store::db_start_run(&killed).await.unwrap();
+            let error = crate::exception::PluginError::InitGlobalState(String::from("坏配置"));
+            uptime::save_init_error(&error);
+            uptime::record_start().await;
+
+            let e = GroupMsgBuilder::new(group_id).text("运行状态").build();
+            command::act(e).await;
+            let sent = sent_to_group(group_id);
+            let lines: Vec<String> = text_of(&sent[0]).lines().map(str::to_string).collect();
+            assert!(lines[0].starts_with("已运行0天0小时0分钟, 启动于"));
+            assert_eq!(
+                lines[1],
+                "最近7天启动3次, 其中此前有2次非正常退出或初始化失败"
+            );
+            assert!(lines[2].starts_with("上次初始化失败: ") && lines[2].contains("坏配置"));
+
+            // a clean stop does not count as unclean
+            uptime::record_stop().await;
+            let (starts, unclean) = store::db_run_stats(&killed, 0).await.unwrap();
+            assert_eq!((starts, unclean), (3, 2));
+        });
+    }
+
+    #[test]
+    fn test_compress_content() {
+        init();
+        run(async {
+            let group_id = 52;
+            let seg = |message_id: i32, content: &str| store::GroupChatSegment {
+                message_id,
+                time: String::from("2025-01-01 00:00:00"),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("text"),
+                content: content.to_string(),
+                interpret: String::from("text"),
+            };
+            let long = format!("压缩测试{}", "很长的一句话，".repeat(1000));
+            seg(1, &long).db_store(group_id).await.unwrap();
+            seg(2, "短消息").db_store(group_id).await.unwrap();
+
+            let pool = DB_POOL.get().unwrap();
+            let kinds: Vec<(String,)> =
+                sqlx::query_as("SELECT typeof(content) FROM message52 ORDER BY auto_id")
+                    .fetch_all(pool)
+                    .await
+                    .unwrap();
+            assert_eq!(
+                kinds,
+                vec![(String::from("blob"),), (String::from("text"),)]
+            );
+
+            let segs = store::db_load_n_group_segment(group_id, 10).await.unwrap();
+            let contents: Vec<&str> = segs.iter().map(|s| s.content.as_str()).collect();
+            assert_eq!(contents, vec![long.as_str(), "短消息"]);
+
+            // compressed texts are still indexed
+            let (count, first, _) = store::db_search_group_msg(group_id, "压缩测试", "/")
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(first.unwrap().content, long);
+            // keywords too short for trigrams match compressed texts too
+            let (count, first, _) = store::db_search_group_msg(group_id, "压缩", "/")
+                .await
+                .unwrap();
+            assert_eq!(count, 1);
+            assert_eq!(first.unwrap().content, long);
+            let (count, _, _) = store::db_search_group_msg(group_id, "压缩", "压缩测试")
+                .await
+                .unwrap();
+            assert_eq!(count, 0);
+
+            // the same compressed content counts as a duplicate
+            let row = store::PendingRow {
+                group_id,
+                message_id: 1,
+                time: String::from("2025-01-01 00:00:00"),
+                sender_id: 42,
+                sender_name: String::from("张三"),
+                seg_type: String::from("text"),
+                content: long.clone(),
+                interpret: String::from("text"),
+            };
+            assert_eq!(
+                store::db_import_group_rows(group_id, &[row]).await.unwrap(),
+                0
+            );
+
+            let path = store::dump_history_csv(group_id, "compress52.csv", 10)
+                .await
+                .unwrap();
+            let csv = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert!(csv.contains(&long));
+        });
+    }
+
+    #[test]
+    fn test_sanitize_output() {
+        init();
+        run(async {
+            let group_id = 53;
+            mock().agent_answers.insert(
+                String::from("注入测试"),
+                String::from("[CQ:at,qq=all]@全体成员 [at:all]收到"),
+            );
+            let e = GroupMsgBuilder::new(group_id)
+                .at(BOT_ID)
+                .text("注入测试")
+                .build();
+            agent::at_me_handler(e).await;
+            let sent = sent_to_group(group_id);
+            assert!(sent[0].get("at").is_empty());
+            assert_eq!(text_of(&sent[0]), "全体成员 all收到");
+        });
+    }
+
+    #[test]
+    fn test_sender_role() {
+        init();
+        run(async {
+            let group_id = 54;
+            mock().roles.insert((group_id, 42), String::from("owner"));
+            let ask = |sender_id: i64, text: &str| {
+                GroupMsgBuilder::new(group_id)
+                    .sender(sender_id)
+                    .at(BOT_ID)
+                    .text(text)
+                    .build()
+            };
+            let payload_of = |text: &str| {
+                mock()
+                    .agent_payloads
+                    .iter()
+                    .map(|p| p.to_string())
+                    .find(|p| p.contains(text))
+                    .unwrap()
+            };
+            agent::at_me_handler(ask(42, "群主提问")).await;
+            agent::at_me_handler(ask(43, "群员提问")).await;
+            assert!(payload_of("群主提问").contains("语气要格外恭敬"));
+            assert!(!payload_of("群员提问").contains("语气要格外恭敬"));
+
+            // cached until the role changes
+            mock().roles.insert((group_id, 42), String::from("member"));
+            assert_eq!(
+                util::get_cached_role(group_id, 42).await.as_deref(),
+                Some("owner")
+            );
+            let notice = serde_json::from_value(serde_json::json!({
+                "notice_type": "group_admin",
+                "sub_type": "unset",
+                "time": 1234,
+                "self_id": BOT_ID,
+                "group_id": group_id,
+                "user_id": 42
+            }))
+            .unwrap();
+            group_notice::dispatch(notice).await;
+            agent::at_me_handler(ask(42, "卸任后提问")).await;
+            assert!(!payload_of("卸任后提问").contains("语气要格外恭敬"));
+        });
+    }
+
+    #[test]
+    fn test_maintenance() {
+        init();
+        run(async {
+            let current = env!("CARGO_PKG_VERSION");
+            let admin = |text: &str| PrivateMsgBuilder::new(ADMIN_ID).text(text).build();
+            assert!(maintenance::check_update()
+                .await
+                .starts_with("检查更新失败"));
+            mock().crate_info = Some(json!({
+                "crate": { "max_version": "99.0.0-rc.1", "max_stable_version": "98.0.0" }
+            }));
+            assert!(maintenance::private_handler(admin("检查更新")).await);
+            let sent = sent_to_private(ADMIN_ID);
+            assert!(text_of(sent.last().unwrap()).starts_with("发现新版本98.0.0"));
+            mock().crate_info = Some(json!({
+                "crate": { "max_version": current, "max_stable_version": current }
+            }));
+            assert_eq!(
+                maintenance::check_update().await,
+                format!("当前版本{current}已是最新")
+            );
+
+            // answers in the outbox are waited for until timeout
+            let held = kovi::tokio::spawn(outbox::hold(GROUP_COUNT + 12, 1));
+            kovi::tokio::time::sleep(Duration::from_millis(100)).await;
+            let (answers, _) = maintenance::drain(Duration::from_millis(200)).await;
+            assert!(answers >= 1);
+            assert!(held.await.unwrap());
+
+            assert!(
+                !maintenance::private_handler(PrivateMsgBuilder::new(42).text("重启").build())
+                    .await
+            );
+            assert_eq!(mock().restarts, 0);
+            assert!(maintenance::private_handler(admin("重启")).await);
+            assert_eq!(mock().restarts, 1);
+            let sent = sent_to_private(ADMIN_ID);
+            assert!(text_of(sent.last().unwrap()).contains("正在重启"));
+        });
+    }
+
+    #[test]
+    fn test_media_limits() {
+        init();
+        run(async {
+            let group_id = 55;
+            let dir = DATA_PATH.get().unwrap().join("media_limits");
+            std::fs::create_dir_all(&dir).unwrap();
+            let small = dir.join("small.amr");
+            std::fs::write(&small, b"voice").unwrap();
+            // sparse, larger than the default 20MB
+            let huge = dir.join("huge.amr");
+            std::fs::File::create(&huge)
+                .unwrap()
+                .set_len(21 * 1024 * 1024)
+                .unwrap();
+            // reported larger than the limit, never downloaded
+            let reported = dir.join("reported.amr");
+            std::fs::write(&reported, b"voice").unwrap();
+            for (file, path) in [
+                ("small.amr", &small),
+                ("huge.amr", &huge),
+                ("reported.amr", &reported),
+            ] {
+                let path = path.to_string_lossy().to_string();
+                mock().media.insert(file.to_string(), path);
+            }
+
+            let mut message = Message::new();
+            for file in ["small.amr", "huge.amr", "missing.amr"] {
+                message.push(Segment::new("record", json!({ "file": file })));
+            }
+            let size = (30 * 1024 * 1024).to_string();
+            message.push(Segment::new(
+                "record",
+                json!({ "file": "reported.amr", "file_size": size }),
+            ));
+            store::write_group_msg(group_id, 1, None, 42, message).await;
+            let segs = store::db_find_segment_by_id(group_id, 1).await.unwrap();
+            let contents: Vec<&str> = segs.iter().map(|s| s.content.as_str()).collect();
+            assert_eq!(
+                contents,
+                [
+                    small.to_str().unwrap(),
+                    "huge.amr",
+                    "missing.amr",
+                    "reported.amr"
+                ]
+            );
+            // downloaded files over the limit are deleted
+            assert!(small.exists());
+            assert!(!huge.exists());
+        });
+    }
+
+    #[test]
+    fn test_guild_channel() {
+        init();
+        run(async {
+            let group_id = GROUP_COUNT;
+            let guild_id = "12345678901234567";
+            let channel_id = group_id.to_string();
+            let raw = |channel_id: &str, message_id: &str, message: Value| {
+                json!({
+                    "post_type": "message",
+                    "message_type": "guild",
+                    "sub_type": "channel",
+                    "time": 1767225600,
+                    "self_id": BOT_ID,
+                    "self_tiny_id": "144115218676000000",
+                    "guild_id": guild_id,
+                    "channel_id": channel_id,
+                    "message_id": message_id,
+                    "user_id": "144115218677000000",
+                    "sender": { "user_id": 144115218677000000_i64, "nickname": "频道用户" },
+                    "message": message,
+                })
+            };
+            let text = |s: &str| json!([{ "type": "text", "data": { "text": s } }]);
+            let at_bot = json!([
+                { "type": "at", "data": { "qq": "144115218676000000" } },
+                { "type": "text", "data": { "text": " 频道提问" } },
+            ]);
+
+            // group messages and unmapped channels are not guild messages of the group
+            assert!(event::GuildMsg::from_json(&json!({ "post_type": "message" })).is_none());
+            let unmapped = event::GuildMsg::from_json(&raw("0", "m0", text("无关"))).unwrap();
+            guild::handler(Arc::new(unmapped)).await;
+
+            let chat = event::GuildMsg::from_json(&raw(&channel_id, "m1", text("大家好"))).unwrap();
+            assert!(!chat.is_at_me());
+            guild::handler(Arc::new(chat)).await;
+            let ask = event::GuildMsg::from_json(&raw(&channel_id, "m2", at_bot)).unwrap();
+            assert!(ask.is_at_me());
+            guild::handler(Arc::new(ask)).await;
+
+            let segs = store::db_load_n_group_segment(group_id, 10).await.unwrap();
+            let texts: Vec<(&str, &str)> = segs
+                .iter()
+                .filter(|s| s.seg_type == "text")
+                .map(|s| (s.sender_name.as_str(), s.content.as_str()))
+                .collect();
+            assert_eq!(texts, [("频道用户", "大家好"), ("频道用户", " 频道提问")]);
+            assert!(segs.iter().all(|s| s.message_id < 0));
+
+            let pool = DB_POOL.get().unwrap();
+            let channels: Vec<(i64, String, String)> = sqlx::query_as(
+                "SELECT group_id, channel_id, guild_message_id FROM guild_message ORDER BY id",
+            )
+            .fetch_all(pool)
+            .await
+            .unwrap();
+            assert_eq!(
+                channels,
+                [
+                    (group_id, channel_id.clone(), String::from("m1")),
+                    (group_id, channel_id.clone(), String::from("m2")),
+                ]
+            );
+
+            // only the at is answered, quoting it in the channel
+            assert!(sent_to_channel(guild_id, "0").is_empty());
+            let sent = sent_to_channel(guild_id, &channel_id);
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].get("reply")[0].data["id"], "m2");
+            assert_eq!(text_of(&sent[0]), "mock answer");
+        });
+    }
+}
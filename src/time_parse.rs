@@ -0,0 +1,420 @@
+//! Parse times written by humans, e.g. "明天下午三点", "周五 20:00", "3月5日" or "in 2 hours".
+//!
+//! Times without a day are the next occurrence, so "八点" at 9:00 means tomorrow. A day without a
+//! time falls on the start of its period of day, or 9:00.
+
+use time::{Date, Duration, Month, OffsetDateTime, Time};
+
+const DEFAULT_HOUR: u8 = 9;
+
+/// Time at the start of `input` relative to `now`, and the rest of the input.
+pub fn parse(input: &str, now: OffsetDateTime) -> Option<(OffsetDateTime, &str)> {
+    let mut cursor = Cursor(input.trim_start());
+    if let Some(at) = cursor.clone().relative(now) {
+        return Some(at);
+    }
+    let date = cursor.date(now);
+    cursor.skip_space();
+    let mut period = cursor.period();
+    if date.is_some_and(|d| d.evening) {
+        period = period.or(Some(Period::Evening));
+    }
+    cursor.skip_space();
+    let clock = cursor.clock();
+    if clock.is_some() {
+        cursor.skip_space();
+        period = cursor.english_period().or(period);
+    }
+    if date.is_none() && period.is_none() && clock.is_none() {
+        return None;
+    }
+
+    let (hour, minute) = match clock {
+        Some((hour, minute)) => (period.map_or(hour, |p| p.adjust(hour)), minute),
+        None => (period.map_or(DEFAULT_HOUR, Period::start), 0),
+    };
+    let time = Time::from_hms(hour, minute, 0).ok()?;
+    let mut at = match date {
+        Some(DayOf { date, .. }) => date.with_time(time).assume_offset(now.offset()),
+        None => now.replace_time(time),
+    };
+    let roll = match date {
+        None => Some(Duration::DAY),
+        Some(DayOf { roll, .. }) => roll,
+    };
+    if let Some(roll) = roll {
+        if at <= now {
+            at += roll;
+        }
+    }
+    Some((at, cursor.0.trim_start()))
+}
+
+/// `n` units of `unit_sec` seconds after `now`, None if out of range.
+fn after(now: OffsetDateTime, n: i64, unit_sec: i64) -> Option<OffsetDateTime> {
+    now.checked_add(Duration::seconds(n.checked_mul(unit_sec)?))
+}
+
+/// Day parsed from input.
+#[derive(Clone, Copy)]
+struct DayOf {
+    date: Date,
+    /// Added once if the time has passed, e.g. a week for weekdays.
+    roll: Option<Duration>,
+    /// "今晚" implies the evening.
+    evening: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Period {
+    EarlyMorning,
+    Morning,
+    Noon,
+    Afternoon,
+    Evening,
+}
+
+impl Period {
+    fn adjust(self, hour: u8) -> u8 {
+        match self {
+            Self::Noon if hour < 11 => hour + 12,
+            Self::Afternoon | Self::Evening if hour < 12 => hour + 12,
+            Self::EarlyMorning | Self::Morning if hour == 12 => 0,
+            _ => hour,
+        }
+    }
+
+    fn start(self) -> u8 {
+        match self {
+            Self::EarlyMorning => 3,
+            Self::Morning => DEFAULT_HOUR,
+            Self::Noon => 12,
+            Self::Afternoon => 15,
+            Self::Evening => 20,
+        }
+    }
+}
+
+const WEEKDAYS_ZH: [&str; 7] = ["一", "二", "三", "四", "五", "六", "日"];
+const WEEKDAYS_EN: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+#[derive(Clone)]
+struct Cursor<'a>(&'a str);
+
+impl<'a> Cursor<'a> {
+    fn eat(&mut self, prefix: &str) -> bool {
+        let matched = self
+            .0
+            .get(..prefix.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(prefix));
+        if matched {
+            self.0 = &self.0[prefix.len()..];
+        }
+        matched
+    }
+
+    /// Index of the first prefix eaten, longer alternatives should come first.
+    fn eat_any(&mut self, prefixes: &[&str]) -> Option<usize> {
+        prefixes.iter().position(|p| self.eat(p))
+    }
+
+    fn skip_space(&mut self) {
+        self.0 = self.0.trim_start();
+    }
+
+    /// Arabic numerals within i64, or Chinese numerals up to 99.
+    fn number(&mut self) -> Option<i64> {
+        let digits = self.0.len()
+            - self
+                .0
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        if digits > 0 {
+            let n = self.0[..digits].parse().ok()?;
+            self.0 = &self.0[digits..];
+            return Some(n);
+        }
+        let mut value = None;
+        let mut tens = false;
+        let mut rest = self.0;
+        while let Some(c) = rest.chars().next() {
+            let digit = match c {
+                '零' | '〇' => 0,
+                '一' => 1,
+                '二' | '两' => 2,
+                '三' => 3,
+                '四' => 4,
+                '五' => 5,
+                '六' => 6,
+                '七' => 7,
+                '八' => 8,
+                '九' => 9,
+                '十' if !tens => {
+                    value = Some(value.unwrap_or(1) * 10);
+                    tens = true;
+                    rest = &rest[c.len_utf8()..];
+                    continue;
+                }
+                _ => break,
+            };
+            value = Some(match value {
+                Some(v) if tens => v + digit,
+                Some(v) => v * 10 + digit,
+                None => digit,
+            });
+            rest = &rest[c.len_utf8()..];
+            if tens {
+                break;
+            }
+        }
+        if value.is_some() {
+            self.0 = rest;
+        }
+        value
+    }
+
+    /// "in 2 hours", "2小时后", "半小时后".
+    fn relative(&mut self, now: OffsetDateTime) -> Option<(OffsetDateTime, &'a str)> {
+        if self.eat("in ") {
+            self.skip_space();
+            let n = self.number()?;
+            self.skip_space();
+            let unit = self.eat_any(&[
+                "minutes", "minute", "mins", "min", "m", "hours", "hour", "hrs", "hr", "h", "days",
+                "day", "d",
+            ])?;
+            let unit_sec = match unit {
+                0..=4 => 60,
+                5..=9 => 3600,
+                _ => 86400,
+            };
+            return Some((after(now, n, unit_sec)?, self.0.trim_start()));
+        }
+        let at = if self.eat("半") {
+            self.eat("个");
+            self.eat_any(&["小时", "钟头"])?;
+            after(now, 30, 60)?
+        } else {
+            let n = self.number()?;
+            self.skip_space();
+            self.eat("个");
+            let unit_sec = match self.eat_any(&["分钟", "分", "小时", "钟头", "天", "日"])?
+            {
+                0 | 1 => 60,
+                2 | 3 => 3600,
+                _ => 86400,
+            };
+            after(now, n, unit_sec)?
+        };
+        self.eat_any(&["以后", "之后", "后"])?;
+        Some((at, self.0.trim_start()))
+    }
+
+    fn date(&mut self, now: OffsetDateTime) -> Option<DayOf> {
+        let today = now.date();
+        let day = |shift: i64, evening: bool| DayOf {
+            date: today + Duration::days(shift),
+            roll: None,
+            evening,
+        };
+        if let Some(i) = self.eat_any(&[
+            "大后天",
+            "后天",
+            "明天",
+            "明日",
+            "今天",
+            "今日",
+            "今晚",
+            "tomorrow",
+            "today",
+            "tonight",
+        ]) {
+            return Some(match i {
+                0 => day(3, false),
+                1 => day(2, false),
+                2 | 3 | 7 => day(1, false),
+                4 | 5 | 8 => day(0, false),
+                _ => day(0, true),
+            });
+        }
+        let cur = today.weekday().number_days_from_monday() as i64;
+        let mut next_week = self.clone();
+        if next_week.eat_any(&["下周", "下星期", "下礼拜"]).is_some() {
+            let wd = next_week
+                .eat_any(&WEEKDAYS_ZH)
+                .or_else(|| next_week.eat_any(&["天"]).map(|_| 6))?;
+            *self = next_week;
+            return Some(day(7 - cur + wd as i64, false));
+        }
+        let mut weekday = self.clone();
+        if weekday.eat_any(&["周", "星期", "礼拜"]).is_some() {
+            if let Some(wd) = weekday
+                .eat_any(&WEEKDAYS_ZH)
+                .or_else(|| weekday.eat_any(&["天"]).map(|_| 6))
+            {
+                *self = weekday;
+                return Some(this_week(today, cur, wd));
+            }
+        }
+        if let Some(wd) = self.eat_any(&WEEKDAYS_EN) {
+            // "fri" or "friday"
+            let suffix = ["day", "sday", "nesday", "rsday", "day", "urday", "day"][wd];
+            self.eat(suffix);
+            return Some(this_week(today, cur, wd));
+        }
+        self.calendar_date(today)
+    }
+
+    /// "2026-03-05", "03-05", "3月5日" or "3月5号".
+    fn calendar_date(&mut self, today: Date) -> Option<DayOf> {
+        let mut probe = self.clone();
+        let first = probe.number_digits()?;
+        let (year, month, day) = if probe.eat("-") {
+            let second = probe.number_digits()?;
+            if probe.eat("-") {
+                (
+                    Some(i32::try_from(first).ok()?),
+                    second,
+                    probe.number_digits()?,
+                )
+            } else {
+                (None, first, second)
+            }
+        } else if probe.eat("月") {
+            let day = probe.number()?;
+            probe.eat_any(&["日", "号"])?;
+            (None, first, day)
+        } else {
+            return None;
+        };
+        let month = Month::try_from(u8::try_from(month).ok()?).ok()?;
+        let day = u8::try_from(day).ok()?;
+        let date = Date::from_calendar_date(year.unwrap_or(today.year()), month, day).ok()?;
+        *self = probe;
+        let date = match year {
+            // month and day only, the next occurrence
+            None if date < today => date.replace_year(today.year() + 1).ok()?,
+            _ => date,
+        };
+        Some(DayOf {
+            date,
+            roll: None,
+            evening: false,
+        })
+    }
+
+    fn number_digits(&mut self) -> Option<i64> {
+        self.0
+            .starts_with(|c: char| c.is_ascii_digit())
+            .then_some(())?;
+        self.number()
+    }
+
+    fn period(&mut self) -> Option<Period> {
+        let i = self.eat_any(&[
+            "凌晨", "早上", "早晨", "上午", "中午", "下午", "傍晚", "晚上", "夜里", "今晚",
+        ])?;
+        Some(match i {
+            0 => Period::EarlyMorning,
+            1..=3 => Period::Morning,
+            4 => Period::Noon,
+            5 => Period::Afternoon,
+            _ => Period::Evening,
+        })
+    }
+
+    fn english_period(&mut self) -> Option<Period> {
+        match self.eat_any(&["am", "pm"])? {
+            0 => Some(Period::Morning),
+            _ => Some(Period::Afternoon),
+        }
+    }
+
+    /// "3点", "三点半", "3点15分", "三点一刻", "15:30" or "3pm".
+    fn clock(&mut self) -> Option<(u8, u8)> {
+        let mut probe = self.clone();
+        let hour = u8::try_from(probe.number()?).ok()?;
+        let minute = if probe.eat(":") {
+            probe.number_digits()?
+        } else if probe.eat_any(&["点", "时"]).is_some() {
+            probe.eat("钟");
+            if probe.eat("半") {
+                30
+            } else if probe.eat("一刻") {
+                15
+            } else if probe.eat("三刻") {
+                45
+            } else {
+                let minute = probe.number().unwrap_or_default();
+                probe.eat("分");
+                minute
+            }
+        } else if probe.clone().english_period().is_some() {
+            0
+        } else {
+            return None;
+        };
+        if hour > 23 || !(0..60).contains(&minute) {
+            return None;
+        }
+        *self = probe;
+        Some((hour, minute as u8))
+    }
+}
+
+/// Weekday `wd` of this week, or of the next week once it has passed.
+fn this_week(today: Date, cur: i64, wd: usize) -> DayOf {
+    let shift = (wd as i64 - cur).rem_euclid(7);
+    DayOf {
+        date: today + Duration::days(shift),
+        roll: Some(Duration::weeks(1)),
+        evening: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_time_parse() {
+        use time::macros::datetime;
+        // a Wednesday
+        let now = datetime!(2026-10-14 10:00 +8);
+        let parse = |input| parse(input, now);
+        let cases = [
+            ("明天下午三点 开会", datetime!(2026-10-15 15:00 +8), "开会"),
+            ("周五 20:00 聚餐", datetime!(2026-10-16 20:00 +8), "聚餐"),
+            ("周三 9点", datetime!(2026-10-21 09:00 +8), ""),
+            ("下周一", datetime!(2026-10-19 09:00 +8), ""),
+            ("八点", datetime!(2026-10-15 08:00 +8), ""),
+            ("今晚8点半 看直播", datetime!(2026-10-14 20:30 +8), "看直播"),
+            ("中午1点", datetime!(2026-10-14 13:00 +8), ""),
+            ("十二点十五分", datetime!(2026-10-14 12:15 +8), ""),
+            ("三点一刻", datetime!(2026-10-15 03:15 +8), ""),
+            ("3月5日 交房租", datetime!(2027-03-05 09:00 +8), "交房租"),
+            ("2026-12-25 18:30", datetime!(2026-12-25 18:30 +8), ""),
+            ("半小时后喝水", datetime!(2026-10-14 10:30 +8), "喝水"),
+            ("两个小时后", datetime!(2026-10-14 12:00 +8), ""),
+            ("三天后", datetime!(2026-10-17 10:00 +8), ""),
+            (
+                "in 2 hours stretch",
+                datetime!(2026-10-14 12:00 +8),
+                "stretch",
+            ),
+            ("in 15 min", datetime!(2026-10-14 10:15 +8), ""),
+            ("tomorrow 3pm", datetime!(2026-10-15 15:00 +8), ""),
+            ("Friday 9:30am", datetime!(2026-10-16 09:30 +8), ""),
+        ];
+        for (input, at, rest) in cases {
+            assert_eq!(parse(input), Some((at, rest)), "{input}");
+        }
+        assert!(parse("你好").is_none());
+        assert!(parse("25点").is_none());
+        assert!(parse("2月30日").is_none());
+        // out of range instead of overflow
+        assert!(parse("9999999天后 喝水").is_none());
+        assert!(parse("in 9223372036854775807 min").is_none());
+        assert!(parse("99999999999-01-01").is_none());
+    }
+}
@@ -0,0 +1,236 @@
+//! Fetch title and description of links in messages.
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    net::IpAddr,
+    sync::{Arc, Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
+
+use regex::Regex;
+
+use crate::{
+    event::GroupMsg, exception::PluginResult, global_state::UnfurlSetting, http::Request,
+    std_error, CONFIG,
+};
+
+const CACHE_TTL: Duration = Duration::from_secs(600);
+const CACHE_CAPACITY: usize = 256;
+const MAX_DESCRIPTION_CHARS: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct Preview {
+    pub title: String,
+    pub description: String,
+}
+
+impl Display for Preview {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.description.is_empty() {
+            write!(f, "{}", self.title)
+        } else {
+            write!(f, "{} - {}", self.title, self.description)
+        }
+    }
+}
+
+/// url -> (fetched at, preview)
+type Cache = HashMap<String, (Instant, Option<Preview>)>;
+
+/// Results are cached, so the logger and preview handler fetch a link only once.
+fn cache() -> MutexGuard<'static, Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Links in text, at most `max_urls_per_message` if unfurl is enabled, otherwise empty.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    let config = CONFIG.get().unwrap();
+    let Some(ref unfurl) = config.unfurl else {
+        return vec![];
+    };
+    let regex =
+        URL_REGEX.get_or_init(|| Regex::new(r#"https?://[^\s<>"'，。！？、（）【】]+"#).unwrap());
+    regex
+        .find_iter(text)
+        .map(|m| m.as_str().to_string())
+        .take(unfurl.max_urls_per_message)
+        .collect()
+}
+
+/// Title and description of a page, None if disabled, blocked, failed, or the page has no title.
+pub async fn unfurl(url: &str) -> Option<Preview> {
+    let config = CONFIG.get().unwrap();
+    // no-op if no unfurl config
+    let unfurl = config.unfurl.as_ref()?;
+    let host = host_of(url)?;
+    if is_private_host(&host) || matches_domain(&host, &unfurl.blocklist) {
+        return None;
+    }
+    if let Some((at, preview)) = cache().get(url) {
+        if at.elapsed() < CACHE_TTL {
+            return preview.clone();
+        }
+    }
+
+    let preview = match fetch_html(url, unfurl).await {
+        Ok(html) => parse_preview(&html),
+        Err(err) => {
+            std_error!("Unfurl {url} failed: {err}");
+            None
+        }
+    };
+    let mut cache = cache();
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+    }
+    if cache.len() < CACHE_CAPACITY {
+        cache.insert(url.to_string(), (Instant::now(), preview.clone()));
+    }
+    preview
+}
+
+/// Reply previews of links under whitelisted domains.
+pub async fn preview_handler(e: Arc<GroupMsg>) {
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    let config = CONFIG.get().unwrap();
+    // no-op if no unfurl config
+    let Some(ref unfurl) = config.unfurl else {
+        return;
+    };
+    for url in extract_urls(text) {
+        let Some(host) = host_of(&url) else {
+            continue;
+        };
+        if !matches_domain(&host, &unfurl.preview_domains) {
+            continue;
+        }
+        if let Some(preview) = self::unfurl(&url).await {
+            let message = format!("{}\n{}", preview.title, preview.description);
+            e.reply(message.trim_end()).await;
+        }
+    }
+}
+
+async fn fetch_html(url: &str, setting: &UnfurlSetting) -> PluginResult<String> {
+    let resp = Request::get(url)
+        .timeout(Duration::from_secs(setting.timeout_sec))
+        // redirects are checked against private hosts as well
+        .redirect_guard(|host| !is_private_host(host))
+        .user_agent("Mozilla/5.0 (compatible; momo-bot)")
+        // head of page is enough
+        .max_bytes(setting.max_bytes)
+        .send()
+        .await?;
+    let is_html = resp
+        .content_type
+        .as_deref()
+        .is_some_and(|v| v.contains("html"));
+    if !is_html {
+        return Ok(String::new());
+    }
+    Ok(resp.text())
+}
+
+fn parse_preview(html: &str) -> Option<Preview> {
+    static TITLE_REGEX: OnceLock<Regex> = OnceLock::new();
+    static META_REGEX: OnceLock<Regex> = OnceLock::new();
+    static ATTR_REGEX: OnceLock<Regex> = OnceLock::new();
+    let title_regex =
+        TITLE_REGEX.get_or_init(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+    let meta_regex = META_REGEX.get_or_init(|| Regex::new(r"(?is)<meta\s[^>]*>").unwrap());
+    let attr_regex = ATTR_REGEX
+        .get_or_init(|| Regex::new(r#"(?is)([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+
+    // property or name -> content
+    let mut metas = HashMap::new();
+    for tag in meta_regex.find_iter(html) {
+        let mut key = None;
+        let mut content = None;
+        for cap in attr_regex.captures_iter(tag.as_str()) {
+            let value = cap.get(2).or(cap.get(3)).map_or("", |m| m.as_str());
+            match cap[1].to_lowercase().as_str() {
+                "property" | "name" => key = Some(value.to_lowercase()),
+                "content" => content = Some(value),
+                _ => {}
+            }
+        }
+        if let (Some(key), Some(content)) = (key, content) {
+            metas.entry(key).or_insert(content);
+        }
+    }
+
+    let title = metas
+        .get("og:title")
+        .copied()
+        .or_else(|| {
+            title_regex
+                .captures(html)
+                .and_then(|c| c.get(1))
+                .map(|m| m.as_str())
+        })
+        .map(clean)
+        .filter(|t| !t.is_empty())?;
+    let description = metas
+        .get("og:description")
+        .or_else(|| metas.get("description"))
+        .map(|d| clean(d))
+        .unwrap_or_default();
+    let description = match description.char_indices().nth(MAX_DESCRIPTION_CHARS) {
+        Some((i, _)) => format!("{}...", &description[..i]),
+        None => description,
+    };
+    Some(Preview { title, description })
+}
+
+/// Decode common entities and collapse whitespace.
+fn clean(text: &str) -> String {
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn host_of(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = match host_port.strip_prefix('[') {
+        // ipv6 literal
+        Some(v6) => v6.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    Some(host.to_lowercase()).filter(|h| !h.is_empty())
+}
+
+fn is_private_host(host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    if host == "localhost" || host.ends_with(".localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => {
+            ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()
+        }
+        Ok(IpAddr::V6(ip)) => ip.is_loopback() || ip.is_unspecified(),
+        Err(_) => false,
+    }
+}
+
+/// Domain matches itself and its subdomains.
+fn matches_domain(host: &str, domains: &[String]) -> bool {
+    domains
+        .iter()
+        .any(|d| host == d || host.ends_with(&format!(".{d}")))
+}
@@ -0,0 +1,115 @@
+//! Uptime and restart history of the plugin, reported by the `run_status` command.
+//!
+//! Each start is a row of `run_history` that gets a stop time when the plugin is dropped, so runs
+//! without one were killed or crashed. Initialization may abort before the database is ready, so
+//! its error is kept in a file of the data path and recorded on the next start.
+
+use std::{sync::OnceLock, time::Instant};
+
+use crate::{
+    exception::{PluginError, PluginResult},
+    std_db_error, std_error, store, tr, util, DATA_PATH,
+};
+
+const INIT_ERROR_FILE: &str = "init_error.txt";
+const WEEK_SECS: i64 = 7 * 86400;
+
+/// The current run.
+struct Run {
+    id: i64,
+    start_time: String,
+    started: Instant,
+}
+
+static RUN: OnceLock<Run> = OnceLock::new();
+
+/// Record the start of this run, and the failed start before it if any.
+pub async fn record_start() {
+    // recorded first, as it happened before this run
+    if let Some((time, error)) = take_init_error() {
+        if let Err(err) = store::db_add_failed_run(&time, &error).await {
+            std_db_error!("Record failed start failed: {err}");
+        }
+    }
+    let start_time = util::cur_time_iso8601(None);
+    match store::db_start_run(&start_time).await {
+        Ok(id) => {
+            let _ = RUN.set(Run {
+                id,
+                start_time,
+                started: Instant::now(),
+            });
+        }
+        Err(err) => std_db_error!("Record start failed: {err}"),
+    }
+}
+
+/// Record a clean shutdown of this run.
+pub async fn record_stop() {
+    let Some(run) = RUN.get() else {
+        return;
+    };
+    let time = util::cur_time_iso8601(None);
+    if let Err(err) = store::db_stop_run(run.id, &time).await {
+        std_error!("Record stop failed: {err}");
+    }
+}
+
+/// Keep the error aborting initialization for the next start, no-op if the data path is unknown.
+pub fn save_init_error(error: &PluginError) {
+    let Some(data_path) = DATA_PATH.get() else {
+        return;
+    };
+    let content = format!("{}\n{error}", util::cur_time_iso8601(None));
+    if let Err(err) = std::fs::write(data_path.join(INIT_ERROR_FILE), content) {
+        std_error!("Save init error failed: {err}");
+    }
+}
+
+/// (time, error) saved by [save_init_error], removed once taken.
+fn take_init_error() -> Option<(String, String)> {
+    let path = DATA_PATH.get()?.join(INIT_ERROR_FILE);
+    let content = std::fs::read_to_string(&path).ok()?;
+    if let Err(err) = std::fs::remove_file(&path) {
+        std_error!("Remove init error file failed: {err}");
+    }
+    let (time, error) = content.split_once('\n')?;
+    Some((time.to_string(), error.to_string()))
+}
+
+/// Uptime, starts in the last week, and the last failed initialization.
+pub async fn status(group_id: i64) -> PluginResult<String> {
+    let mut lines = Vec::new();
+    let current = match RUN.get() {
+        Some(run) => {
+            let secs = run.started.elapsed().as_secs();
+            lines.push(tr!(
+                group_id,
+                "uptime.running",
+                days = secs / 86400,
+                hours = secs % 86400 / 3600,
+                minutes = secs % 3600 / 60,
+                start = run.start_time,
+            ));
+            run.id
+        }
+        None => {
+            lines.push(tr!(group_id, "uptime.unknown"));
+            0
+        }
+    };
+    let since =
+        util::iso8601_from_timestamp(None, util::now_in(None).unix_timestamp() - WEEK_SECS)?;
+    let (starts, unclean) = store::db_run_stats(&since, current).await?;
+    lines.push(tr!(
+        group_id,
+        "uptime.restarts",
+        starts = starts,
+        unclean = unclean,
+    ));
+    lines.push(match store::db_last_init_error().await? {
+        Some((time, error)) => tr!(group_id, "uptime.init_error", time = time, error = error),
+        None => tr!(group_id, "uptime.no_init_error"),
+    });
+    Ok(lines.join("\n"))
+}
@@ -2,18 +2,28 @@
 
 use kovi::{
     tokio::time::{interval, sleep},
-    Message,
+    ApiReturn, Message,
 };
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
-use std::{future::Future, path::PathBuf, time::Duration};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::{Duration, Instant},
+};
 use time::{
     macros::{format_description, offset},
-    OffsetDateTime,
+    OffsetDateTime, Time, UtcOffset,
 };
 
 use crate::{
-    db_warn, exception::PluginResult, global_state, std_db_error, std_info, store, BOT_QQ, CONFIG,
+    db_warn,
+    exception::PluginResult,
+    global_state::{get_bot, GuildChannel},
+    std_db_error, std_error, std_info, store, word_filter, ADMIN_QQ, BOT_QQ, CONFIG,
 };
 
 /// Schedule a periodic task that blocks current task forever.
@@ -29,8 +39,32 @@ where
     }
 }
 
+/// Schedule a daily task at the given hour that blocks current task forever.
+pub async fn schedule_daily_blocking<F, Fut>(hour: u8, mut task: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        let now = now_in(None);
+        let Ok(at) = Time::from_hms(hour, 0, 0) else {
+            std_error!("Schedule daily task with invalid hour: {hour}");
+            return;
+        };
+        let mut next = now.replace_time(at);
+        if next <= now {
+            next += time::Duration::DAY;
+        }
+        sleep((next - now).unsigned_abs()).await;
+        task().await;
+    }
+}
+
 pub async fn sleep_rand_time() {
     let config = CONFIG.get().unwrap();
+    if config.global.max_sleep_sec == 0 {
+        return;
+    }
     let max_sleep_sec = config.global.max_sleep_sec as f64;
     let rand_time = {
         let mut rng = thread_rng();
@@ -40,6 +74,15 @@ pub async fn sleep_rand_time() {
     sleep(Duration::from_secs_f64(rand_time)).await;
 }
 
+/// Id of the message being replied to.
+pub fn reply_id(message: &Message) -> Option<i32> {
+    let seg = message.get("reply").into_iter().next()?;
+    let id = &seg.data["id"];
+    id.as_str()
+        .and_then(|x| x.parse().ok())
+        .or_else(|| id.as_i64().map(|x| x as i32))
+}
+
 pub async fn extract_text(msg: &Message) -> String {
     let text_segs = msg.get("text");
     let mut buf = String::new();
@@ -60,7 +103,8 @@ pub async fn extract_text(msg: &Message) -> String {
     buf
 }
 
-pub async fn extract_segments<T>(msg: T) -> Vec<(String, String)>
+/// (type, content, data) of known segments, content is the text, file, target or id by type.
+pub async fn extract_segments<T>(msg: T) -> Vec<(String, String, Value)>
 where
     T: Into<Message>,
 {
@@ -89,23 +133,91 @@ where
             );
             continue;
         };
-        list.push((seg_type, content));
+        list.push((seg_type, content, seg.data.clone()));
     }
     list
 }
 
+/// Parse a fixed offset like "+08:00", "-05:30" or "+8".
+pub fn parse_utc_offset(s: &str) -> Option<UtcOffset> {
+    let s = s.trim();
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':').unwrap_or((rest, "0"));
+    // parse would accept a second sign, as in "+-5"
+    let is_digits = |x: &str| !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit());
+    if !is_digits(hours) || !is_digits(minutes) {
+        return None;
+    }
+    let hours = hours.parse::<i8>().ok()?;
+    let minutes = minutes.parse::<i8>().ok()?;
+    if !(0..60).contains(&minutes) {
+        return None;
+    }
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// Offset of the group, falling back to the global one, then Beijing time.
+pub fn utc_offset(group_id: Option<i64>) -> UtcOffset {
+    let Some(config) = CONFIG.get() else {
+        return offset!(+8);
+    };
+    let group = group_id.and_then(|id| {
+        let groups = config.groups.as_ref()?;
+        groups.iter().find(|g| g.id == id)?.timezone.as_deref()
+    });
+    resolve_offset(group, config.global.timezone.as_deref())
+}
+
+/// Group time zone wins over the global one, invalid ones are skipped.
+pub fn resolve_offset(group: Option<&str>, global: Option<&str>) -> UtcOffset {
+    group
+        .and_then(parse_utc_offset)
+        .or(global.and_then(parse_utc_offset))
+        .unwrap_or(offset!(+8))
+}
+
+/// Current time in the time zone of the group.
+pub fn now_in(group_id: Option<i64>) -> OffsetDateTime {
+    OffsetDateTime::now_utc().to_offset(utc_offset(group_id))
+}
+
 /// Obtain "[year-month-day hour:minute:second]".
-pub fn cur_time_iso8601() -> String {
-    let offset = offset!(+8);
-    let datetime = OffsetDateTime::now_utc().to_offset(offset);
+pub fn cur_time_iso8601(group_id: Option<i64>) -> String {
     let desc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    now_in(group_id).format(desc).unwrap()
+}
+
+/// Obtain "[year-month-day]", shifted by the given number of days.
+pub fn date_iso8601(group_id: Option<i64>, shift_days: i64) -> String {
+    let datetime = now_in(group_id) + time::Duration::days(shift_days);
+    let desc = format_description!("[year]-[month]-[day]");
     datetime.format(desc).unwrap()
 }
 
+/// Obtain "[year-month-day]" of today.
+pub fn cur_date_iso8601(group_id: Option<i64>) -> String {
+    date_iso8601(group_id, 0)
+}
+
+/// 64-bit FNV-1a, stable across builds unlike [std::hash::DefaultHasher].
+pub fn fnv1a_hash(s: &str) -> u64 {
+    fnv1a_hash_bytes(s.as_bytes())
+}
+
+pub fn fnv1a_hash_bytes(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
 /// Convert unix timestamp to "[year-month-day hour:minute:second]".  
 /// This may fail if the timestamp passed in is before 1970.
-pub fn iso8601_from_timestamp(timestamp: i64) -> PluginResult<String> {
-    let offset = offset!(+8);
+pub fn iso8601_from_timestamp(group_id: Option<i64>, timestamp: i64) -> PluginResult<String> {
+    let offset = utc_offset(group_id);
     let datetime = OffsetDateTime::from_unix_timestamp(timestamp)?.to_offset(offset);
     let desc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
     Ok(datetime.format(desc)?)
@@ -120,10 +232,10 @@ pub enum TimeRepr {
 
 impl TimeRepr {
     /// Silently log time error and return None on failure.
-    pub async fn to_iso8601(&self) -> Option<String> {
+    pub async fn to_iso8601(&self, group_id: Option<i64>) -> Option<String> {
         match self {
             Self::Iso8601(t) => Some(t.clone()),
-            Self::UnixTimeStamp(t) => match iso8601_from_timestamp(*t) {
+            Self::UnixTimeStamp(t) => match iso8601_from_timestamp(group_id, *t) {
                 Ok(t) => Some(t),
                 Err(err) => {
                     std_db_error!("{err}");
@@ -136,7 +248,7 @@ impl TimeRepr {
 
 impl Default for TimeRepr {
     fn default() -> Self {
-        Self::Iso8601(cur_time_iso8601())
+        Self::Iso8601(cur_time_iso8601(None))
     }
 }
 
@@ -163,8 +275,7 @@ pub async fn get_name_in_group(group_id: i64, user_id: i64) -> String {
     }
 
     // fallback to 1, 2, 3
-    let bot = global_state::get_bot();
-    let group_member_api = bot.get_group_member_info(group_id, user_id, false).await;
+    let group_member_api = get_group_member_info(group_id, user_id).await;
 
     match group_member_api {
         Ok(api) => {
@@ -214,18 +325,407 @@ pub async fn get_name_in_group(group_id: i64, user_id: i64) -> String {
     }
 }
 
-/// For somewhat reason [bot.send_group_msg][kovi::RuntimeBot::send_group_msg] invokes [From] thus
-/// clone is inevitable here.
+async fn get_group_member_info(group_id: i64, user_id: i64) -> Result<ApiReturn, ApiReturn> {
+    let params = json!({ "group_id": group_id, "user_id": user_id, "no_cache": false });
+    get_bot()
+        .send_api_return("get_group_member_info", params)
+        .await
+}
+
+/// Files and folders in the root directory of group files.
+pub async fn get_group_root_files(group_id: i64) -> Result<ApiReturn, ApiReturn> {
+    // extension of go-cqhttp, also implemented by NapCat and Lagrange
+    let params = json!({ "group_id": group_id });
+    get_bot()
+        .send_api_return("get_group_root_files", params)
+        .await
+}
+
+/// Download an image of a received message, `data.file` of the response is the local path.
+pub async fn get_image(file: &str) -> Result<ApiReturn, ApiReturn> {
+    get_bot()
+        .send_api_return("get_image", json!({ "file": file }))
+        .await
+}
+
+/// Download a record of a received message converted to `out_format`, e.g. "mp3".
+pub async fn get_record(file: &str, out_format: &str) -> Result<ApiReturn, ApiReturn> {
+    let params = json!({ "file": file, "out_format": out_format });
+    get_bot().send_api_return("get_record", params).await
+}
+
+/// group_id -> when the ban of bot expires, None if it lasts until lifted
+type BanState = HashMap<i64, Option<Instant>>;
+
+fn bans() -> MutexGuard<'static, BanState> {
+    static BANS: OnceLock<Mutex<BanState>> = OnceLock::new();
+    BANS.get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Record that the bot cannot speak in a group, for `duration` or until lifted if None.
+pub fn set_bot_banned(group_id: i64, duration: Option<Duration>) {
+    let until = duration.map(|d| Instant::now() + d);
+    bans().insert(group_id, until);
+}
+
+pub fn lift_bot_ban(group_id: i64) {
+    bans().remove(&group_id);
+}
+
+pub fn is_bot_banned(group_id: i64) -> bool {
+    let mut bans = bans();
+    match bans.get(&group_id) {
+        Some(Some(until)) if Instant::now() >= *until => {
+            bans.remove(&group_id);
+            false
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Send group message through bot.
+///
+/// All outgoing group messages should go through here. Dropped while the bot is banned in the
+/// group, sending would fail anyway.
+pub fn send_group_msg<T>(group_id: i64, message: T)
+where
+    Message: From<T>,
+    T: Serialize,
+{
+    if is_bot_banned(group_id) {
+        std_info!("Bot is banned in group {group_id}, drop message.");
+        return;
+    }
+    let message = word_filter::sanitize(group_id, Message::from(message));
+    if dry_run(|| format!("send group {group_id}: {}", message.to_human_string())) {
+        return;
+    }
+    let params = json!({ "group_id": group_id, "message": message });
+    get_bot().send_api("send_group_msg", params);
+}
+
+/// [send_group_msg] and wait for the message id, None if the message is not sent.
+pub async fn send_group_msg_return(group_id: i64, message: Message) -> Option<i32> {
+    if is_bot_banned(group_id) {
+        std_info!("Bot is banned in group {group_id}, drop message.");
+        return None;
+    }
+    let message = word_filter::sanitize(group_id, message);
+    send_sanitized_return(group_id, message).await
+}
+
+async fn send_sanitized_return(group_id: i64, message: Message) -> Option<i32> {
+    if dry_run(|| format!("send group {group_id}: {}", message.to_human_string())) {
+        return None;
+    }
+    let params = json!({ "group_id": group_id, "message": message });
+    match get_bot().send_api_return("send_group_msg", params).await {
+        Ok(api) => message_id_of(&api),
+        Err(err) => {
+            std_error!("Send group message to {group_id} failed: {err}");
+            None
+        }
+    }
+}
+
+fn message_id_of(api: &ApiReturn) -> Option<i32> {
+    let message_id = api.data["message_id"]
+        .as_i64()
+        .and_then(|id| i32::try_from(id).ok());
+    if message_id.is_none() {
+        std_error!("Message id missing in response: {}", api.data);
+    }
+    message_id
+}
+
+/// Returns true if outgoing actions are suppressed by [dry run][GlobalSetting::dry_run], after
+/// logging the action to stdout and database.
+///
+/// [GlobalSetting::dry_run]: crate::global_state::GlobalSetting::dry_run
+fn dry_run(action: impl FnOnce() -> String) -> bool {
+    let config = CONFIG.get().unwrap();
+    if !config.global.dry_run.unwrap_or(false) {
+        return false;
+    }
+    let content = format!("[dry run] {}", action());
+    kovi::log::info!("{content}");
+    kovi::spawn(store::db_write_bot_log(
+        cur_time_iso8601(None),
+        String::from("INFO"),
+        content,
+    ));
+    true
+}
+
+/// Send `node` segments as a forward message, which is folded in clients.
+pub fn send_group_forward_msg(group_id: i64, nodes: Message) {
+    if is_bot_banned(group_id) {
+        std_info!("Bot is banned in group {group_id}, drop message.");
+        return;
+    }
+    if dry_run(|| {
+        format!(
+            "send group {group_id} forward of {} nodes",
+            nodes.iter().len()
+        )
+    }) {
+        return;
+    }
+    let params = json!({ "group_id": group_id, "messages": nodes });
+    get_bot().send_api("send_group_forward_msg", params);
+}
+
+/// Send `node` segments as a forward message in private chat.
+pub fn send_private_forward_msg(user_id: i64, nodes: Message) {
+    if dry_run(|| {
+        format!(
+            "send private {user_id} forward of {} nodes",
+            nodes.iter().len()
+        )
+    }) {
+        return;
+    }
+    let params = json!({ "user_id": user_id, "messages": nodes });
+    get_bot().send_api("send_private_forward_msg", params);
+}
+
+/// Send private message through bot.
+///
+/// All outgoing private messages should go through here.
+pub fn send_private_msg<T>(user_id: i64, message: T)
+where
+    Message: From<T>,
+    T: Serialize,
+{
+    let message = Message::from(message);
+    if dry_run(|| format!("send private {user_id}: {}", message.to_human_string())) {
+        return;
+    }
+    let params = json!({ "user_id": user_id, "message": message });
+    get_bot().send_api("send_private_msg", params);
+}
+
+/// [send_private_msg] and wait for the message id, None if the message is not sent.
+pub async fn send_private_msg_return(user_id: i64, message: Message) -> Option<i32> {
+    if dry_run(|| format!("send private {user_id}: {}", message.to_human_string())) {
+        return None;
+    }
+    let params = json!({ "user_id": user_id, "message": message });
+    match get_bot().send_api_return("send_private_msg", params).await {
+        Ok(api) => message_id_of(&api),
+        Err(err) => {
+            std_error!("Send private message to {user_id} failed: {err}");
+            None
+        }
+    }
+}
+
+/// Send to a guild (频道) channel handled as group, see [crate::guild]. Filtered by words of the
+/// group.
+pub fn send_guild_channel_msg(group_id: i64, channel: &GuildChannel, message: Message) {
+    let (guild_id, channel_id) = (&channel.guild_id, &channel.channel_id);
+    let message = word_filter::sanitize(group_id, message);
+    if dry_run(|| {
+        format!(
+            "send guild {guild_id} channel {channel_id}: {}",
+            message.to_human_string()
+        )
+    }) {
+        return;
+    }
+    let params = json!({ "guild_id": guild_id, "channel_id": channel_id, "message": message });
+    get_bot().send_api("send_guild_channel_msg", params);
+}
+
+/// Remove a member from group through bot.
+pub fn kick_group_member(group_id: i64, user_id: i64) {
+    if dry_run(|| format!("kick {user_id} from group {group_id}")) {
+        return;
+    }
+    let params = json!({ "group_id": group_id, "user_id": user_id, "reject_add_request": false });
+    get_bot().send_api("set_group_kick", params);
+}
+
+/// Set group card of a member through bot.
+pub fn set_group_card(group_id: i64, user_id: i64, card: &str) {
+    if dry_run(|| format!("set card of {user_id} in group {group_id} to {card}")) {
+        return;
+    }
+    let params = json!({ "group_id": group_id, "user_id": user_id, "card": card });
+    get_bot().send_api("set_group_card", params);
+}
+
+/// React to a message with a face through bot.
+pub fn set_msg_emoji_like(message_id: i32, emoji_id: &str) {
+    if dry_run(|| format!("react to message {message_id} with {emoji_id}")) {
+        return;
+    }
+    let params = json!({ "message_id": message_id, "emoji_id": emoji_id });
+    get_bot().send_api("set_msg_emoji_like", params);
+}
+
+/// Answer a group join request through bot.
+pub fn set_group_add_request(flag: &str, approve: bool, reason: &str) {
+    if dry_run(|| format!("answer join request {flag}, approve = {approve}, reason = {reason}")) {
+        return;
+    }
+    let params = json!({ "flag": flag, "sub_type": "add", "approve": approve, "reason": reason });
+    get_bot().send_api("set_group_add_request", params);
+}
+
+/// QQ level of a user, None on failure.
+pub async fn get_user_level(user_id: i64) -> Option<i64> {
+    let params = json!({ "user_id": user_id, "no_cache": true });
+    let api = get_bot()
+        .send_api_return("get_stranger_info", params)
+        .await
+        .ok()?;
+    // go-cqhttp uses level, NapCat uses qqLevel
+    api.data["level"].as_i64().or(api.data["qqLevel"].as_i64())
+}
+
+/// Mute a member through bot.
+pub fn ban_group_member(group_id: i64, user_id: i64, duration_sec: usize) {
+    if dry_run(|| format!("ban {user_id} in group {group_id} for {duration_sec}s")) {
+        return;
+    }
+    let params = json!({ "group_id": group_id, "user_id": user_id, "duration": duration_sec });
+    get_bot().send_api("set_group_ban", params);
+}
+
+/// Post a group notice, which is pinned by most clients. Returns false if the implementation does
+/// not support it.
+pub async fn send_group_notice(group_id: i64, content: &str) -> bool {
+    if dry_run(|| format!("post group notice in {group_id}: {content}")) {
+        return false;
+    }
+    // extension of go-cqhttp, also implemented by NapCat and Lagrange
+    let params = json!({ "group_id": group_id, "content": content });
+    match get_bot()
+        .send_api_return("_send_group_notice", params)
+        .await
+    {
+        Ok(_) => true,
+        Err(err) => {
+            std_info!("Send group notice failed, possibly unsupported: {err}");
+            false
+        }
+    }
+}
+
+/// Role of a member in group, one of "owner", "admin" and "member". None on failure.
+pub async fn get_role_in_group(group_id: i64, user_id: i64) -> Option<String> {
+    let api = get_group_member_info(group_id, user_id).await.ok()?;
+    let info = serde_json::from_value::<GroupMemberInfoResponse>(api.data).ok()?;
+    Some(info.role)
+}
+
+/// (group_id, user_id) -> (fetched at, role)
+type RoleCache = HashMap<(i64, i64), (Instant, String)>;
+
+fn role_cache() -> MutexGuard<'static, RoleCache> {
+    static CACHE: OnceLock<Mutex<RoleCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    cache.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// [get_role_in_group] cached for a while, for prompts rather than permission checks.
+pub async fn get_cached_role(group_id: i64, user_id: i64) -> Option<String> {
+    const TTL: Duration = Duration::from_secs(600);
+    if let Some((at, role)) = role_cache().get(&(group_id, user_id)) {
+        if at.elapsed() < TTL {
+            return Some(role.clone());
+        }
+    }
+    let role = get_role_in_group(group_id, user_id).await?;
+    role_cache().insert((group_id, user_id), (Instant::now(), role.clone()));
+    Some(role)
+}
+
+/// Drop the cached role of a member, e.g. once it is set or unset as admin.
+pub fn forget_role(group_id: i64, user_id: i64) {
+    role_cache().remove(&(group_id, user_id));
+}
+
+/// group_id -> (fetched at, admins)
+type AdminCache = HashMap<i64, (Instant, Vec<i64>)>;
+
+/// Owner and admins of a group except the bot, cached for a while. Empty on failure.
+pub async fn get_group_admins(group_id: i64) -> Vec<i64> {
+    const TTL: Duration = Duration::from_secs(600);
+    static CACHE: OnceLock<Mutex<AdminCache>> = OnceLock::new();
+    let cache = CACHE.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some((at, admins)) = cache.lock().unwrap().get(&group_id) {
+        if at.elapsed() < TTL {
+            return admins.clone();
+        }
+    }
+
+    let api = match get_group_member_list(group_id).await {
+        Ok(api) => api,
+        Err(err) => {
+            std_db_error!("GroupMemberList api request of {group_id} failed.\nCause: {err}");
+            return vec![];
+        }
+    };
+    let members = match serde_json::from_value::<Vec<GroupMemberInfoResponse>>(api.data) {
+        Ok(members) => members,
+        Err(err) => {
+            std_db_error!("GroupMemberList deserialize failed.\nCause: {err}");
+            return vec![];
+        }
+    };
+    let bot_qq = *BOT_QQ.get().unwrap();
+    let admins: Vec<i64> = members
+        .into_iter()
+        .filter(|m| matches!(m.role.as_str(), "owner" | "admin") && m.user_id != bot_qq)
+        .map(|m| m.user_id)
+        .collect();
+    cache
+        .lock()
+        .unwrap()
+        .insert(group_id, (Instant::now(), admins.clone()));
+    admins
+}
+
+async fn get_group_member_list(group_id: i64) -> Result<ApiReturn, ApiReturn> {
+    let params = json!({ "group_id": group_id });
+    get_bot()
+        .send_api_return("get_group_member_list", params)
+        .await
+}
+
+/// Send a group message and write it to history with the message id returned by the send API, so
+/// that recalls and replies of it can be matched. The id is 0 if the message is not sent, e.g. in
+/// dry run.
 pub async fn send_group_and_log<T>(group_id: i64, message: T)
 where
     T: Into<Message>,
     T: Serialize,
 {
-    let bot = global_state::get_bot();
-    let message: Message = message.into();
+    if is_bot_banned(group_id) {
+        std_info!("Bot is banned in group {group_id}, drop message.");
+        return;
+    }
+    let message = word_filter::sanitize(group_id, message.into());
     let sender_id = *BOT_QQ.get().unwrap();
-    bot.send_group_msg(group_id, message.clone());
-    store::write_group_msg(group_id, 0, None, sender_id, message).await;
+    let message_id = send_sanitized_return(group_id, message.clone())
+        .await
+        .unwrap_or(0);
+    store::write_group_msg(group_id, message_id, None, sender_id, message).await;
+}
+
+/// Send a private message to the main admin of bot.
+pub fn notify_admin<T>(message: T)
+where
+    Message: From<T>,
+    T: Serialize,
+{
+    let admin_qq = *ADMIN_QQ.get().unwrap();
+    send_private_msg(admin_qq, message);
 }
 
 /// Execute the configured script to upload a file and return its stdout.  
@@ -239,6 +739,11 @@ pub async fn call_upload(file_path_str: &str) -> String {
     let Some(ref obj) = config.object_storage else {
         return file_path_str.to_string();
     };
+    #[cfg(feature = "chaos")]
+    if let Err(err) = crate::chaos::inject(crate::chaos::Point::Upload).await {
+        std_db_error!("Upload {file_path_str} failed: {err}");
+        return file_path_str.to_string();
+    }
 
     // script path
     let exec_path_str = &obj.script_path;
@@ -299,3 +804,31 @@ pub struct GroupMemberInfoResponse {
     title_expire_time: i32,
     card_changeable: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_utc_offset() {
+        let hms = |offset: Option<UtcOffset>| offset.map(|o| o.as_hms());
+        assert_eq!(hms(parse_utc_offset("+08:00")), Some((8, 0, 0)));
+        assert_eq!(hms(parse_utc_offset("-05:30")), Some((-5, -30, 0)));
+        assert_eq!(hms(parse_utc_offset("+9")), Some((9, 0, 0)));
+        assert!(parse_utc_offset("08:00").is_none());
+        assert!(parse_utc_offset("+08:60").is_none());
+        assert!(parse_utc_offset("+30").is_none());
+        assert!(parse_utc_offset("+-5").is_none());
+        assert!(parse_utc_offset("-+05:00").is_none());
+        assert!(parse_utc_offset("+05:-0").is_none());
+    }
+
+    #[test]
+    fn test_resolve_offset() {
+        let offset = resolve_offset(Some("-05:00"), Some("+08:00"));
+        assert_eq!(offset.whole_hours(), -5);
+        let offset = resolve_offset(Some("bad"), Some("+01:00"));
+        assert_eq!(offset.whole_hours(), 1);
+        assert_eq!(resolve_offset(None, None).whole_hours(), 8);
+    }
+}
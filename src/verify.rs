@@ -0,0 +1,176 @@
+//! New member verification.
+//!
+//! Pending verifications are persisted, so that a restart neither forgets nor lets members through.
+
+use std::{sync::Arc, time::Duration};
+
+use kovi::Message;
+use time::OffsetDateTime;
+
+use crate::{
+    agent,
+    event::GroupMsg,
+    global_state::{GroupSetting, VerifyFailAction, VerifySetting},
+    std_db_error, std_info,
+    store::{self, MemberVerification},
+    tr,
+    util::{self, schedule_task_blocking},
+    BOT_QQ, CONFIG,
+};
+
+const SWEEP_INTERVAL_SEC: u64 = 30;
+
+fn find_group(group_id: i64) -> Option<&'static GroupSetting> {
+    let config = CONFIG.get().unwrap();
+    config.groups.as_ref()?.iter().find(|g| g.id == group_id)
+}
+
+/// Ask a new member the question, no-op if verification is not configured.
+pub async fn start(group_id: i64, user_id: i64) {
+    if user_id == *BOT_QQ.get().unwrap() {
+        return;
+    }
+    let Some(verify) = find_group(group_id).and_then(|g| g.verify.as_ref()) else {
+        return;
+    };
+    let deadline = OffsetDateTime::now_utc().unix_timestamp() + verify.timeout_min * 60;
+    if let Err(err) = store::db_add_pending_verification(group_id, user_id, deadline).await {
+        std_db_error!("Add pending verification failed: {err}");
+        return;
+    }
+    let message = Message::new().add_at(&user_id.to_string()).add_text(tr!(
+        group_id,
+        "verify.question",
+        minutes = verify.timeout_min,
+        question = verify.question,
+    ));
+    util::send_group_and_log(group_id, message).await;
+}
+
+/// Check messages of members under verification.
+pub async fn answer_handler(e: Arc<GroupMsg>) {
+    let Some(group) = find_group(e.group_id) else {
+        return;
+    };
+    let Some(ref verify) = group.verify else {
+        return;
+    };
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    match store::db_is_verification_pending(e.group_id, e.sender_id).await {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(err) => {
+            std_db_error!("Find pending verification failed: {err}");
+            return;
+        }
+    }
+    if !check_answer(group, verify, text.trim()).await {
+        e.reply_and_quote(tr!(e.group_id, "verify.wrong")).await;
+        return;
+    }
+    if let Err(err) = store::db_set_verification_status(e.group_id, e.sender_id, "passed").await {
+        std_db_error!("Update verification status failed: {err}");
+        return;
+    }
+    e.reply_and_quote(tr!(e.group_id, "verify.passed")).await;
+}
+
+async fn check_answer(group: &GroupSetting, verify: &VerifySetting, answer: &str) -> bool {
+    let (Some(criteria), Some(agent)) = (&verify.ai_criteria, &group.agent) else {
+        return verify.answers.iter().any(|x| x.trim() == answer);
+    };
+    let dev_prompt = "You judge whether the answer of a new group member meets the criteria. \
+                      Reply with yes or no only.";
+    let user_prompt = format!(
+        "问题: {}\n标准: {criteria}\n回答: {answer}",
+        verify.question
+    );
+    let model = agent.get_model().await;
+    let judgement = agent::complete(
+        &agent.api_url,
+        &agent.api_key,
+        &model,
+        dev_prompt,
+        &user_prompt,
+    )
+    .await;
+    judgement.is_some_and(|x| x.trim().to_lowercase().starts_with("yes"))
+}
+
+/// Periodically fail overdue verifications, no-op if no group requires verification.
+pub async fn subscribe_expiry() {
+    let config = CONFIG.get().unwrap();
+    let Some(ref groups) = config.groups else {
+        return;
+    };
+    if groups.iter().all(|g| g.verify.is_none()) {
+        return;
+    }
+    kovi::spawn(async {
+        let duration = Duration::from_secs(SWEEP_INTERVAL_SEC);
+        schedule_task_blocking(duration, || {
+            expire_overdue(OffsetDateTime::now_utc().unix_timestamp())
+        })
+        .await;
+    });
+}
+
+/// Fail verifications whose deadline is before `now`, in unix timestamp.
+pub async fn expire_overdue(now: i64) {
+    let overdue = match store::db_load_overdue_verifications(now).await {
+        Ok(v) => v,
+        Err(err) => {
+            std_db_error!("Load overdue verifications failed: {err}");
+            return;
+        }
+    };
+    for row in overdue {
+        if let Err(err) =
+            store::db_set_verification_status(row.group_id, row.user_id, "failed").await
+        {
+            std_db_error!("Update verification status failed: {err}");
+            continue;
+        }
+        fail(row).await;
+    }
+}
+
+async fn fail(row: MemberVerification) {
+    let MemberVerification {
+        group_id, user_id, ..
+    } = row;
+    std_info!("Verification failed: group = {group_id}, user = {user_id}");
+    // config may have been removed since the member joined
+    let Some(group) = find_group(group_id) else {
+        return;
+    };
+    let Some(ref verify) = group.verify else {
+        return;
+    };
+    let user_name = util::get_name_in_group(group_id, user_id).await;
+    match verify.on_fail {
+        VerifyFailAction::Kick => {
+            util::kick_group_member(group_id, user_id);
+            let msg = tr!(group_id, "verify.kicked", user = user_name);
+            util::send_group_and_log(group_id, msg).await;
+        }
+        VerifyFailAction::Notify => {
+            let Some(ref command) = group.command else {
+                return;
+            };
+            let msg = tr!(
+                group_id,
+                "verify.expired",
+                group = group_id,
+                user = user_name,
+                user_id = user_id,
+            );
+
+            for admin_id in command.admins(group_id).await {
+                util::send_private_msg(admin_id, msg.as_str());
+            }
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Mask listed words in bot output, and warn members who send them.
+//!
+//! Words match case-insensitively for ASCII. Forward messages are not masked.
+
+use std::sync::Arc;
+
+use kovi::{Message, Segment};
+
+use crate::{
+    event::GroupMsg, global_state::WordFilterSetting, std_db_error, store, tr, util, BOT_QQ, CONFIG,
+};
+
+fn setting_of(group_id: i64) -> Option<&'static WordFilterSetting> {
+    let config = CONFIG.get().unwrap();
+    let groups = config.groups.as_ref()?;
+    let group = groups.iter().find(|g| g.id == group_id)?;
+    group.word_filter.as_ref()
+}
+
+/// Replace each char of listed words in `text` by `mask`, None if nothing matches.
+pub fn mask_words(text: &str, words: &[String], mask: char) -> Option<String> {
+    // ascii lowercase keeps byte offsets
+    let lower = text.to_ascii_lowercase();
+    let mut masked = vec![false; text.len()];
+    for word in words.iter().filter(|w| !w.is_empty()) {
+        for (start, _) in lower.match_indices(word.to_ascii_lowercase().as_str()) {
+            masked[start..start + word.len()].fill(true);
+        }
+    }
+    if !masked.contains(&true) {
+        return None;
+    }
+    let res = text
+        .char_indices()
+        .map(|(i, c)| if masked[i] { mask } else { c })
+        .collect();
+    Some(res)
+}
+
+/// Mask text segments of a message to the group.
+pub fn sanitize(group_id: i64, message: Message) -> Message {
+    let Some(setting) = setting_of(group_id) else {
+        return message;
+    };
+    let mut res = Message::new();
+    for seg in message.iter() {
+        let text = seg.data["text"].as_str().filter(|_| seg.type_ == "text");
+        match text.and_then(|t| mask_words(t, &setting.words, setting.mask)) {
+            Some(masked) => res.push(Segment::new("text", serde_json::json!({ "text": masked }))),
+            None => res.push(seg.clone()),
+        }
+    }
+    res
+}
+
+/// Warn the sender with the number of strikes, if [WordFilterSetting::warn] is on.
+pub async fn warn_handler(e: Arc<GroupMsg>) {
+    let group_id = e.group_id;
+    let Some(setting) = setting_of(group_id) else {
+        return;
+    };
+    if !setting.warn || e.sender_id == *BOT_QQ.get().unwrap() {
+        return;
+    }
+    let Some(text) = e.borrow_text() else {
+        return;
+    };
+    if mask_words(text, &setting.words, setting.mask).is_none() {
+        return;
+    }
+    let strikes = match store::db_add_word_strike(group_id, e.sender_id).await {
+        Ok(strikes) => strikes,
+        Err(err) => {
+            std_db_error!("Record word filter strike failed: {err}");
+            return;
+        }
+    };
+    let user = util::get_name_in_group(group_id, e.sender_id).await;
+    e.reply_and_quote(tr!(
+        group_id,
+        "word_filter.warning",
+        user = user,
+        strikes = strikes
+    ))
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mask_words() {
+        let words = [String::from("笨蛋"), String::from("Bad")];
+        assert_eq!(
+            mask_words("你这个笨蛋, so BAD", &words, '*').as_deref(),
+            Some("你这个**, so ***")
+        );
+        assert!(mask_words("挺好的", &words, '*').is_none());
+    }
+}